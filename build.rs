@@ -0,0 +1,9 @@
+//! Build script: records the target triple for `rsdu --build-info`
+
+fn main() {
+    println!(
+        "cargo:rustc-env=RSDU_TARGET={}",
+        std::env::var("TARGET").unwrap_or_else(|_| "unknown".to_string())
+    );
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -5,8 +5,11 @@
 
 use crate::config::{Config, SortColumn, SortOrder};
 use crate::error::{Result, RsduError};
-use crate::model::{Entry, EntryType, SortColumn as ModelSortColumn, SortOrder as ModelSortOrder};
-use crate::utils::{format_file_size, format_percentage};
+use crate::model::{propagate_to_ancestors, Entry, EntryType};
+use crate::navigation::{handle_navigation_key, BrowserBackend, NavOutcome};
+use crate::scanner::restat_entry;
+use crate::size::SizeFormatter;
+use crate::utils::{format_percentage, get_terminal_size};
 use crossterm::{
     cursor,
     event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
@@ -29,13 +32,18 @@ pub struct Browser {
     terminal_height: u16,
     terminal_width: u16,
     show_help: bool,
+    /// Shown in the status bar until the next key
+    message: Option<String>,
 }
 
 impl Browser {
     /// Create a new browser instance
     pub fn new(root: Arc<Entry>, config: Config) -> Result<Self> {
-        let (width, height) = terminal::size()
-            .map_err(|e| RsduError::UiError(format!("Cannot get terminal size: {}", e)))?;
+        let size = terminal::size();
+        if let Err(ref e) = size {
+            eprintln!("Warning: Cannot get terminal size ({}), using a default", e);
+        }
+        let (width, height) = terminal_size_or_default(size);
 
         Ok(Browser {
             current: root.clone(),
@@ -47,6 +55,7 @@ impl Browser {
             terminal_height: height,
             terminal_width: width,
             show_help: false,
+            message: None,
         })
     }
 
@@ -73,7 +82,7 @@ impl Browser {
     /// Main event loop
     fn main_loop(&mut self) -> Result<()> {
         loop {
-            self.update_terminal_size()?;
+            self.update_terminal_size();
             self.draw()?;
 
             // Handle events
@@ -105,6 +114,7 @@ impl Browser {
 
     /// Handle keyboard input
     fn handle_key(&mut self, key: KeyCode, modifiers: KeyModifiers) -> Result<BrowserAction> {
+        self.message = None;
         if modifiers.contains(KeyModifiers::CONTROL) {
             match key {
                 KeyCode::Char('c') => return Ok(BrowserAction::Quit),
@@ -112,127 +122,114 @@ impl Browser {
             }
         }
 
-        match key {
-            KeyCode::Char('q') | KeyCode::Esc => Ok(BrowserAction::Quit),
-            KeyCode::Char('?') | KeyCode::F(1) => {
-                self.show_help = !self.show_help;
-                Ok(BrowserAction::Continue)
-            }
-            KeyCode::Up | KeyCode::Char('k') => {
-                self.move_selection(-1);
-                Ok(BrowserAction::Continue)
-            }
-            KeyCode::Down | KeyCode::Char('j') => {
-                self.move_selection(1);
-                Ok(BrowserAction::Continue)
-            }
-            KeyCode::PageUp => {
-                self.move_selection(-(self.get_visible_height() as i32));
-                Ok(BrowserAction::Continue)
-            }
-            KeyCode::PageDown => {
-                self.move_selection(self.get_visible_height() as i32);
-                Ok(BrowserAction::Continue)
-            }
-            KeyCode::Home | KeyCode::Char('g') => {
-                self.selected_index = 0;
-                self.scroll_offset = 0;
-                Ok(BrowserAction::Continue)
-            }
-            KeyCode::End | KeyCode::Char('G') => {
-                if !self.current.children.is_empty() {
-                    self.selected_index = self.current.children.len() - 1;
-                    self.adjust_scroll();
-                }
-                Ok(BrowserAction::Continue)
-            }
-            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
-                self.enter_selected();
-                Ok(BrowserAction::Continue)
-            }
-            KeyCode::Left | KeyCode::Char('h') | KeyCode::Backspace => {
-                self.go_back();
-                Ok(BrowserAction::Continue)
-            }
-            KeyCode::Char('s') => {
-                self.toggle_sort();
-                Ok(BrowserAction::Continue)
-            }
-            KeyCode::Char('r') => {
-                self.reverse_sort();
-                Ok(BrowserAction::Continue)
-            }
-            KeyCode::Char('a') => {
-                self.toggle_apparent_size();
-                Ok(BrowserAction::Continue)
-            }
-            KeyCode::Char('d') => {
-                self.toggle_show_hidden();
-                Ok(BrowserAction::Continue)
-            }
-            _ => Ok(BrowserAction::Continue),
+        match handle_navigation_key(self, key) {
+            NavOutcome::Quit => return Ok(BrowserAction::Quit),
+            NavOutcome::Handled => return Ok(BrowserAction::Continue),
+            NavOutcome::Unhandled => {}
         }
-    }
 
-    /// Move selection by delta
-    fn move_selection(&mut self, delta: i32) {
-        if self.current.children.is_empty() {
-            return;
-        }
-
-        let max_index = self.current.children.len() - 1;
-        let new_index = if delta < 0 {
-            self.selected_index.saturating_sub((-delta) as usize)
-        } else {
-            cmp::min(self.selected_index + delta as usize, max_index)
-        };
-
-        self.selected_index = new_index;
-        self.adjust_scroll();
-    }
-
-    /// Enter the currently selected item
-    fn enter_selected(&mut self) {
-        if self.current.children.is_empty() {
-            return;
-        }
-
-        let selected = &self.current.children[self.selected_index];
-        if selected.entry_type.is_directory() && selected.entry_type != EntryType::Error {
-            self.path_stack.push(self.current.clone());
-            self.current = selected.clone();
-            self.selected_index = 0;
-            self.scroll_offset = 0;
-        }
-    }
-
-    /// Go back to parent directory
-    fn go_back(&mut self) {
-        if let Some(parent) = self.path_stack.pop() {
-            self.current = parent;
-            self.selected_index = 0;
-            self.scroll_offset = 0;
+        match key {
+            KeyCode::Char('s') => self.toggle_sort(),
+            KeyCode::Char('r') => self.reverse_sort(),
+            KeyCode::Char('a') => self.toggle_apparent_size(),
+            KeyCode::Char('d') => self.toggle_show_hidden(),
+            KeyCode::Char('f') => self.refresh_selected(),
+            _ => {}
         }
+        Ok(BrowserAction::Continue)
     }
 
     /// Toggle sort column
     fn toggle_sort(&mut self) {
-        // Cycle through sort columns
-        // Note: This is a simplified version - in a full implementation,
-        // we'd need to re-sort the current directory's children
+        self.config.sort_col = next_sort_column(self.config.sort_col, self.config.show_blocks);
+        self.resort_keeping_selection(|dir, config| {
+            Arc::make_mut(dir).sort_children(
+                config.sort_col.into(),
+                config.sort_order.into(),
+                config.sort_dirs_first,
+            )
+        });
     }
 
     /// Reverse sort order
     fn reverse_sort(&mut self) {
-        // Toggle between ascending and descending
-        // Note: This is a simplified version - in a full implementation,
-        // we'd need to re-sort the current directory's children
+        self.config.sort_order = match self.config.sort_order {
+            SortOrder::Asc => SortOrder::Desc,
+            SortOrder::Desc => SortOrder::Asc,
+        };
+        self.resort_keeping_selection(|dir, config| {
+            Arc::make_mut(dir).sort_children(
+                config.sort_col.into(),
+                config.sort_order.into(),
+                config.sort_dirs_first,
+            )
+        });
     }
 
     /// Toggle between apparent size and disk usage
     fn toggle_apparent_size(&mut self) {
-        // Toggle the display mode
-        // Note: This would affect how sizes are displayed
+        self.config.show_blocks = !self.config.show_blocks;
+        self.resort_keeping_selection(resort_for_metric);
+    }
+
+    /// Re-sort the current directory with `resort`, keeping the same entry selected
+    ///
+    /// The selection is tracked by entry id, so the cursor follows the file
+    /// to its new position instead of landing on whatever took its place.
+    fn resort_keeping_selection(&mut self, resort: impl FnOnce(&mut Arc<Entry>, &mut Config)) {
+        let selected_id = self
+            .current
+            .children
+            .get(self.selected_index)
+            .map(|entry| entry.id);
+
+        resort(&mut self.current, &mut self.config);
+        Entry::link_children(&self.current);
+
+        self.selected_index = selected_id
+            .and_then(|id| {
+                self.current
+                    .children
+                    .iter()
+                    .position(|entry| entry.id == id)
+            })
+            .unwrap_or(0);
+        self.adjust_scroll();
+    }
+
+    /// Re-read the selected entry's size, blocks and times from disk
+    ///
+    /// Meant for imported data that has gone stale. The entry is looked up at
+    /// its `full_path`; one no longer there is reported and left as it was.
+    fn refresh_selected(&mut self) {
+        let Some(target) = self.current.children.get(self.selected_index).cloned() else {
+            return;
+        };
+        if self.config.can_refresh == Some(false) {
+            self.message = Some("Refreshing is disabled".to_string());
+            return;
+        }
+        if target.entry_type == EntryType::Aggregate {
+            self.message = Some("Entries that were left out can't be refreshed".to_string());
+            return;
+        }
+
+        let path = target.full_path();
+        match restat_entry(&target, &path, &self.config) {
+            Ok(fresh) => {
+                let dir = Arc::make_mut(&mut self.current);
+                if let Some(slot) = dir.children.iter_mut().find(|c| c.id == fresh.id) {
+                    *slot = fresh.into_linked();
+                }
+                self.root = propagate_to_ancestors(&mut self.path_stack, self.current.clone());
+            }
+            Err(RsduError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                self.message = Some(format!("{} no longer exists", path.display()));
+            }
+            Err(e) => {
+                self.message = Some(format!("Cannot refresh {}: {}", path.display(), e));
+            }
+        }
     }
 
     /// Toggle showing hidden files
@@ -259,12 +256,10 @@ impl Browser {
     }
 
     /// Update terminal size
-    fn update_terminal_size(&mut self) -> Result<()> {
-        let (width, height) = terminal::size()
-            .map_err(|e| RsduError::UiError(format!("Cannot get terminal size: {}", e)))?;
+    fn update_terminal_size(&mut self) {
+        let (width, height) = terminal_size_or_default(terminal::size());
         self.terminal_width = width;
         self.terminal_height = height;
-        Ok(())
     }
 
     /// Draw the interface
@@ -386,11 +381,7 @@ impl Browser {
         }
 
         // Size column (9 chars)
-        let size_str = if entry.entry_type.is_directory() {
-            format!("{:>8} ", self.calculate_directory_size(entry))
-        } else {
-            format!("{:>8} ", format_file_size(entry.size, self.config.si))
-        };
+        let size_str = format!("{:>8} ", SizeFormatter::new(&self.config).format(entry));
 
         // Items column (7 chars) - for directories, show item count
         let items_str = if entry.entry_type.is_directory() {
@@ -436,24 +427,6 @@ impl Browser {
         Ok(())
     }
 
-    /// Calculate directory size (simplified - just sum of children)
-    fn calculate_directory_size(&self, entry: &Entry) -> String {
-        let total_size: u64 = entry
-            .children
-            .iter()
-            .map(|child| {
-                if child.entry_type.is_directory() {
-                    // For directories, we'd need to recurse, but this is simplified
-                    child.size
-                } else {
-                    child.size
-                }
-            })
-            .sum();
-
-        format_file_size(total_size, self.config.si)
-    }
-
     /// Get type indicator character and color for an entry
     fn get_type_indicator(&self, entry: &Entry) -> (char, Color) {
         match entry.entry_type {
@@ -466,6 +439,8 @@ impl Browser {
             EntryType::Excluded => ('x', Color::DarkGrey),
             EntryType::OtherFs => ('~', Color::DarkGrey),
             EntryType::KernelFs => ('#', Color::DarkGrey),
+            EntryType::BindMount => ('^', Color::DarkGrey),
+            EntryType::Aggregate => ('+', Color::DarkGrey),
         }
     }
 
@@ -474,7 +449,9 @@ impl Browser {
         let status_y = self.terminal_height - 1;
         let total_items = self.current.children.len();
 
-        let status = if total_items > 0 {
+        let status = if let Some(message) = &self.message {
+            message.clone()
+        } else if total_items > 0 {
             format!(
                 "{}/{} items, {} total | q:quit ?:help ↑↓:navigate ←→:enter/back",
                 self.selected_index + 1,
@@ -522,6 +499,7 @@ impl Browser {
             "  r          Reverse sort order",
             "  a          Toggle apparent size/disk usage",
             "  d          Toggle hidden files",
+            "  f          Re-read the selected entry from disk",
             "",
             "Other:",
             "  ?/F1       Toggle this help",
@@ -555,6 +533,55 @@ impl Browser {
     }
 }
 
+impl BrowserBackend for Browser {
+    fn row_count(&self) -> usize {
+        self.current.children.len()
+    }
+
+    fn selected(&self) -> usize {
+        self.selected_index
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected_index = index;
+        self.adjust_scroll();
+    }
+
+    fn page_height(&self) -> usize {
+        self.get_visible_height()
+    }
+
+    fn enter_selected(&mut self) {
+        if self.current.children.is_empty() {
+            return;
+        }
+
+        let selected = &self.current.children[self.selected_index];
+        if selected.entry_type.is_directory() && selected.entry_type != EntryType::Error {
+            self.path_stack.push(self.current.clone());
+            self.current = selected.clone();
+            self.selected_index = 0;
+            self.scroll_offset = 0;
+        }
+    }
+
+    fn go_back(&mut self) {
+        if let Some(parent) = self.path_stack.pop() {
+            self.current = parent;
+            self.selected_index = 0;
+            self.scroll_offset = 0;
+        }
+    }
+
+    fn help_visible(&self) -> bool {
+        self.show_help
+    }
+
+    fn set_help_visible(&mut self, visible: bool) {
+        self.show_help = visible;
+    }
+}
+
 /// Browser action result
 #[derive(Debug, PartialEq)]
 enum BrowserAction {
@@ -562,6 +589,54 @@ enum BrowserAction {
     Quit,
 }
 
+/// The terminal size if it could be read, or the default from `get_terminal_size`
+fn terminal_size_or_default(size: io::Result<(u16, u16)>) -> (u16, u16) {
+    size.unwrap_or_else(|_| {
+        let (width, height) = get_terminal_size();
+        (width as u16, height as u16)
+    })
+}
+
+/// Sort column the sort key moves on to from `current`
+///
+/// Cycles size, name, item count and modification time, with size being
+/// whichever size metric is displayed.
+fn next_sort_column(current: SortColumn, show_blocks: bool) -> SortColumn {
+    match current {
+        SortColumn::Size | SortColumn::Blocks => SortColumn::Name,
+        SortColumn::Name => SortColumn::Items,
+        SortColumn::Items => SortColumn::Mtime,
+        SortColumn::Mtime | SortColumn::Owner | SortColumn::Mode => {
+            if show_blocks {
+                SortColumn::Blocks
+            } else {
+                SortColumn::Size
+            }
+        }
+    }
+}
+
+/// Re-sort a directory after the size metric changed
+///
+/// A size-based sort is switched over to the metric now being displayed so
+/// the listing order matches the figures on screen.
+fn resort_for_metric(dir: &mut Arc<Entry>, config: &mut Config) {
+    if !matches!(config.sort_col, SortColumn::Size | SortColumn::Blocks) {
+        return;
+    }
+
+    config.sort_col = if config.show_blocks {
+        SortColumn::Blocks
+    } else {
+        SortColumn::Size
+    };
+    Arc::make_mut(dir).sort_children(
+        config.sort_col.into(),
+        config.sort_order.into(),
+        config.sort_dirs_first,
+    );
+}
+
 /// Run the interactive browser
 pub fn run_browser(root: Arc<Entry>, config: Config) -> Result<()> {
     let mut browser = Browser::new(root, config)?;
@@ -599,6 +674,169 @@ mod tests {
         // In practice, we'd mock the terminal interface for testing
     }
 
+    #[test]
+    fn test_metric_toggle_resorts() {
+        // Sparse file: large apparent size, few blocks on disk
+        let sparse = Arc::new(Entry::new(
+            generate_entry_id(),
+            EntryType::File,
+            "sparse".into(),
+            1_000_000,
+            8,
+            1,
+            1,
+            1,
+        ));
+        // Dense file: smaller apparent size, more blocks on disk
+        let dense = Arc::new(Entry::new(
+            generate_entry_id(),
+            EntryType::File,
+            "dense".into(),
+            100_000,
+            200,
+            1,
+            2,
+            1,
+        ));
+        let mut dir = Entry::new(
+            generate_entry_id(),
+            EntryType::Directory,
+            "dir".into(),
+            0,
+            0,
+            1,
+            3,
+            1,
+        );
+        dir.children = vec![sparse, dense];
+        let mut dir = Arc::new(dir);
+
+        let mut config = Config {
+            show_blocks: false,
+            sort_col: SortColumn::Size,
+            sort_order: SortOrder::Desc,
+            ..Config::default()
+        };
+
+        config.show_blocks = true;
+        resort_for_metric(&mut dir, &mut config);
+        assert_eq!(config.sort_col, SortColumn::Blocks);
+        assert_eq!(dir.children[0].name_str(), "dense");
+
+        config.show_blocks = false;
+        resort_for_metric(&mut dir, &mut config);
+        assert_eq!(config.sort_col, SortColumn::Size);
+        assert_eq!(dir.children[0].name_str(), "sparse");
+    }
+
+    #[test]
+    fn test_resort_keeps_selected_entry() {
+        let mut dir = Entry::new(
+            generate_entry_id(),
+            EntryType::Directory,
+            "dir".into(),
+            0,
+            0,
+            1,
+            1,
+            1,
+        );
+        dir.children = ["a", "b", "c"]
+            .into_iter()
+            .map(|name| create_test_entry(name, false))
+            .collect();
+        let dir = Arc::new(dir);
+        let config = Config {
+            sort_col: SortColumn::Name,
+            sort_order: SortOrder::Asc,
+            ..Config::default()
+        };
+
+        let mut browser = Browser {
+            root: dir.clone(),
+            current: dir,
+            path_stack: Vec::new(),
+            selected_index: 0,
+            scroll_offset: 0,
+            config,
+            terminal_height: 24,
+            terminal_width: 80,
+            show_help: false,
+            message: None,
+        };
+        let selected_id = browser.current.children[0].id;
+
+        browser.reverse_sort();
+        assert_eq!(browser.current.children[0].name_str(), "c");
+        assert_eq!(browser.selected_index, 2);
+        assert_eq!(
+            browser.current.children[browser.selected_index].id,
+            selected_id
+        );
+
+        browser.toggle_sort();
+        assert_eq!(browser.config.sort_col, SortColumn::Items);
+        assert_eq!(
+            browser.current.children[browser.selected_index].id,
+            selected_id
+        );
+    }
+
+    #[test]
+    fn test_refresh_selected_entry() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        std::fs::write(temp_dir.path().join("sub/grown"), vec![0u8; 5000]).unwrap();
+
+        // An imported tree whose root is named after the scanned directory
+        let mut sub = (*create_test_entry("sub", true)).clone();
+        sub.children = vec![create_test_entry("grown", false)];
+        let mut root = (*create_test_entry(&temp_dir.path().to_string_lossy(), true)).clone();
+        root.children = vec![sub.into_linked()];
+        let root = root.into_linked();
+
+        let mut browser = Browser {
+            root: root.clone(),
+            current: root,
+            path_stack: Vec::new(),
+            selected_index: 0,
+            scroll_offset: 0,
+            config: Config {
+                show_blocks: false,
+                ..Config::default()
+            },
+            terminal_height: 24,
+            terminal_width: 80,
+            show_help: false,
+            message: None,
+        };
+        browser.enter_selected();
+        browser.refresh_selected();
+        assert_eq!(browser.message, None);
+        assert_eq!(browser.current.children[0].size, 5000);
+        assert_eq!(browser.root.total_size(), 5000 + 2 * 1024);
+
+        std::fs::remove_file(temp_dir.path().join("sub/grown")).unwrap();
+        browser.refresh_selected();
+        assert!(browser
+            .message
+            .as_deref()
+            .is_some_and(|message| message.ends_with("no longer exists")));
+    }
+
+    #[test]
+    fn test_terminal_size_fallback() {
+        assert_eq!(terminal_size_or_default(Ok((120, 40))), (120, 40));
+
+        let failed = Err(io::Error::other("not a terminal"));
+        let (width, height) = terminal_size_or_default(failed);
+        let (default_width, default_height) = get_terminal_size();
+        assert_eq!(
+            (width as usize, height as usize),
+            (default_width, default_height)
+        );
+    }
+
     #[test]
     fn test_path_building() {
         let root = create_test_entry("root", true);
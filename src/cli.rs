@@ -18,6 +18,14 @@ pub struct Args {
     #[arg(short = 'f', long = "file", value_name = "FILE")]
     pub import_file: Option<String>,
 
+    /// Import a directory of JSON shards, each holding one subtree
+    #[arg(long = "import-dir", value_name = "DIR")]
+    pub import_dir: Option<PathBuf>,
+
+    /// Check an imported tree for inconsistent sizes and structure
+    #[arg(long = "verify-import")]
+    pub verify_import: bool,
+
     /// Export scanned directory to FILE in JSON format
     #[arg(short = 'o', long = "output", value_name = "FILE")]
     pub export_json: Option<String>,
@@ -26,6 +34,14 @@ pub struct Args {
     #[arg(short = 'O', long = "output-binary", value_name = "FILE")]
     pub export_binary: Option<String>,
 
+    /// Record a complete JSON snapshot of the scan to FILE, for replaying with --file
+    #[arg(long = "record", value_name = "FILE")]
+    pub record: Option<String>,
+
+    /// Write scan totals and duration to FILE as JSON
+    #[arg(long = "export-stats", value_name = "FILE")]
+    pub export_stats: Option<String>,
+
     /// Stay on same filesystem
     #[arg(short = 'x', long = "one-file-system")]
     pub same_fs: bool,
@@ -34,6 +50,10 @@ pub struct Args {
     #[arg(long = "cross-file-system")]
     pub cross_fs: bool,
 
+    /// With -x, size other filesystems by their used space without descending
+    #[arg(long = "stat-other-fs")]
+    pub stat_other_fs: bool,
+
     /// Show extended information (enables mtime, permissions, etc.)
     #[arg(short = 'e', long = "extended")]
     pub extended: bool,
@@ -42,6 +62,10 @@ pub struct Args {
     #[arg(long = "no-extended")]
     pub no_extended: bool,
 
+    /// Give directories the newest mtime found below them (implies -e)
+    #[arg(long = "newest-mtime")]
+    pub newest_mtime: bool,
+
     /// Follow symbolic links (excluding directories)
     #[arg(short = 'L', long = "follow-symlinks")]
     pub follow_symlinks: bool,
@@ -50,14 +74,46 @@ pub struct Args {
     #[arg(long = "no-follow-symlinks")]
     pub no_follow_symlinks: bool,
 
+    /// Follow only symlinks whose name or target matches PATTERN
+    #[arg(long = "follow-symlinks-matching", value_name = "PATTERN", action = clap::ArgAction::Append)]
+    pub follow_symlinks_matching: Vec<String>,
+
+    /// Dereference the scanned directory argument if it is a symlink (default)
+    #[arg(long = "follow-arg-symlinks")]
+    pub follow_arg_symlinks: bool,
+
+    /// Scan a symlinked directory argument as the link itself
+    #[arg(long = "no-follow-arg-symlinks")]
+    pub no_follow_arg_symlinks: bool,
+
+    /// Dereference symlinks given on the command line only, like `du -H`
+    #[arg(short = 'H', long = "dereference-command-line")]
+    pub dereference_command_line: bool,
+
+    /// How much an unfollowed symlink counts towards totals
+    #[arg(long = "symlink-size", value_enum)]
+    pub symlink_size: Option<SymlinkSize>,
+
     /// Exclude files matching PATTERN
     #[arg(long = "exclude", value_name = "PATTERN", action = clap::ArgAction::Append)]
     pub exclude: Vec<String>,
 
+    /// Match exclude, include, follow and .rsduignore patterns without regard to case
+    #[arg(long = "ignore-case-patterns")]
+    pub ignore_case_patterns: bool,
+
+    /// Count only files matching PATTERN, and the directories holding them
+    #[arg(long = "include-only", value_name = "PATTERN", action = clap::ArgAction::Append)]
+    pub include_only: Vec<String>,
+
     /// Exclude files matching patterns in FILE
     #[arg(short = 'X', long = "exclude-from", value_name = "FILE")]
     pub exclude_from: Option<PathBuf>,
 
+    /// Scan every path listed in FILE, one per line, under a single root
+    #[arg(long = "targets-from", value_name = "FILE")]
+    pub targets_from: Option<PathBuf>,
+
     /// Exclude directories containing CACHEDIR.TAG
     #[arg(long = "exclude-caches")]
     pub exclude_caches: bool,
@@ -66,6 +122,10 @@ pub struct Args {
     #[arg(long = "include-caches")]
     pub include_caches: bool,
 
+    /// Exclude directories containing a file named FILE
+    #[arg(long = "exclude-if-present", value_name = "FILE", action = clap::ArgAction::Append)]
+    pub exclude_if_present: Vec<String>,
+
     /// Exclude Linux pseudo filesystems (procfs, sysfs, cgroup, etc.)
     #[arg(long = "exclude-kernfs")]
     pub exclude_kernfs: bool,
@@ -78,6 +138,32 @@ pub struct Args {
     #[arg(short = 't', long = "threads", value_name = "NUM")]
     pub threads: Option<usize>,
 
+    /// Give up on metadata and directory reads that take longer than MS milliseconds
+    #[arg(long = "io-timeout", value_name = "MS")]
+    pub io_timeout: Option<u64>,
+
+    /// Time each directory and list the N slowest (default 10) after the scan
+    #[arg(
+        long = "profile-scan",
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "10"
+    )]
+    pub profile_scan: Option<usize>,
+
+    /// Count hardlinked inodes and their extra links after the scan
+    #[arg(long = "hardlink-stats")]
+    pub hardlink_stats: bool,
+
+    /// Don't count hardlinked inodes after the scan
+    #[arg(long = "no-hardlink-stats")]
+    pub no_hardlink_stats: bool,
+
+    /// Keep only the N largest children of each directory, summing up the rest
+    #[arg(long = "max-children", value_name = "N")]
+    pub max_children: Option<usize>,
+
     /// Use Zstandard compression for export
     #[arg(short = 'c', long = "compress")]
     pub compress: bool,
@@ -86,11 +172,43 @@ pub struct Args {
     #[arg(long = "no-compress")]
     pub no_compress: bool,
 
-    /// Compression level (1-22)
+    /// Compression level (1-22); implies --compress
     #[arg(long = "compress-level", value_name = "NUM")]
     pub compress_level: Option<u8>,
 
-    /// Block size for binary export in KiB (4-16000)
+    /// Codec for compressed exports; implies --compress
+    #[arg(long = "compress-format", value_enum)]
+    pub compress_format: Option<CompressFormat>,
+
+    /// Print directories larger than SIZE (e.g. 1G) instead of browsing
+    #[arg(long = "report-over", value_name = "SIZE")]
+    pub report_over: Option<String>,
+
+    /// Print the N largest files in the tree instead of browsing
+    #[arg(long = "largest", value_name = "N")]
+    pub largest: Option<usize>,
+
+    /// Print an indented tree with sizes instead of browsing
+    #[arg(long = "tree")]
+    pub tree: bool,
+
+    /// Print `du`-style "SIZE<tab>PATH" lines for every directory instead of browsing
+    #[arg(long = "du-format")]
+    pub du_format: bool,
+
+    /// With --du-format, list files as well as directories
+    #[arg(short = 'a', long = "all")]
+    pub du_all: bool,
+
+    /// Limit the --tree printout to N levels below the root
+    #[arg(long = "max-depth", value_name = "N")]
+    pub max_depth: Option<usize>,
+
+    /// Only export entries whose total size is at least SIZE (e.g. 10M)
+    #[arg(long = "export-min-size", value_name = "SIZE")]
+    pub export_min_size: Option<String>,
+
+    /// Block size for binary export in KiB (4-16000); ignored for JSON export
     #[arg(long = "export-block-size", value_name = "KIB")]
     pub export_block_size: Option<u16>,
 
@@ -104,6 +222,14 @@ pub struct Args {
     #[arg(short = '2', long = "full-ui", help = "Full ncurses UI during scan")]
     pub ui_full: bool,
 
+    /// Start browsing as soon as the top level is listed, filling in sizes as the scan goes
+    #[arg(long = "incremental")]
+    pub incremental: bool,
+
+    /// Draw the browser on the normal screen, leaving its final state visible on exit
+    #[arg(long = "no-altscreen")]
+    pub no_altscreen: bool,
+
     /// Slow UI updates (2 second interval)
     #[arg(short = 'q', long = "slow-ui-updates")]
     pub slow_updates: bool,
@@ -112,6 +238,10 @@ pub struct Args {
     #[arg(long = "fast-ui-updates")]
     pub fast_updates: bool,
 
+    /// Send scan progress at most every MS milliseconds (default 50)
+    #[arg(long = "progress-interval", value_name = "MS")]
+    pub progress_interval: Option<u64>,
+
     /// Enable shell spawning feature
     #[arg(long = "enable-shell")]
     pub enable_shell: bool,
@@ -156,6 +286,10 @@ pub struct Args {
     #[arg(long = "disk-usage")]
     pub disk_usage: bool,
 
+    /// Bytes per reported block (default 512)
+    #[arg(long = "block-unit", value_name = "BYTES")]
+    pub block_unit: Option<u64>,
+
     /// Show hidden files by default
     #[arg(long = "show-hidden")]
     pub show_hidden: bool,
@@ -196,6 +330,26 @@ pub struct Args {
     #[arg(long = "hide-percent")]
     pub hide_percent: bool,
 
+    /// Show apparent size and disk usage side by side
+    #[arg(long = "dual-size")]
+    pub dual_size: bool,
+
+    /// Show how many direct children of each directory were excluded
+    #[arg(long = "show-excluded-counts")]
+    pub show_excluded_counts: bool,
+
+    /// Show the largest file below each directory
+    #[arg(long = "show-largest-file")]
+    pub show_largest_file: bool,
+
+    /// Digit grouping and decimal separators for displayed numbers
+    #[arg(long = "locale", value_enum)]
+    pub locale: Option<NumberLocale>,
+
+    /// Fold entries smaller than PERCENT of the directory total into one row
+    #[arg(long = "fold-threshold", value_name = "PERCENT")]
+    pub fold_threshold: Option<f64>,
+
     /// Graph style for usage bars
     #[arg(long = "graph-style", value_enum)]
     pub graph_style: Option<GraphStyle>,
@@ -248,9 +402,39 @@ pub struct Args {
     #[arg(long = "color", value_enum)]
     pub color: Option<ColorScheme>,
 
+    /// Color files by extension (archives, images, code, etc.)
+    #[arg(long = "color-by-extension")]
+    pub color_by_extension: bool,
+
+    /// Color files by entry type only (default)
+    #[arg(long = "no-color-by-extension")]
+    pub no_color_by_extension: bool,
+
     /// Don't load configuration files
     #[arg(long = "ignore-config")]
     pub ignore_config: bool,
+
+    /// Don't load the system-wide configuration file
+    #[arg(long = "ignore-system-config")]
+    pub ignore_system_config: bool,
+
+    /// Don't load the per-user configuration file
+    #[arg(long = "ignore-user-config")]
+    pub ignore_user_config: bool,
+
+    /// Print version, target and feature details for bug reports, then exit
+    #[arg(long = "build-info")]
+    pub build_info: bool,
+
+    /// Scan N times (default 5) and print timings to stderr instead of browsing
+    #[arg(
+        long = "bench",
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "5"
+    )]
+    pub bench: Option<usize>,
 }
 
 #[derive(ValueEnum, Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -262,14 +446,62 @@ pub enum GraphStyle {
     EighthBlock,
 }
 
-#[derive(ValueEnum, Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum SharedColumn {
     Off,
     Shared,
     Unique,
 }
 
-#[derive(ValueEnum, Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum SymlinkSize {
+    /// Symlinks count as zero
+    None,
+    /// The link itself, i.e. the length of the path it holds (default)
+    #[value(name = "self")]
+    Link,
+    /// The file the link points to
+    Target,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CompressFormat {
+    /// Zstandard (default)
+    Zstd,
+    /// gzip, for tools that can't read zstd; levels above 9 count as 9
+    Gzip,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum NumberLocale {
+    /// `1,234,567` and `1.5` (default)
+    En,
+    /// `1.234.567` and `1,5`
+    De,
+    /// `1 234 567` and `1,5`
+    Fr,
+}
+
+impl NumberLocale {
+    /// Separator between groups of three digits
+    pub fn thousands_separator(self) -> &'static str {
+        match self {
+            NumberLocale::En => ",",
+            NumberLocale::De => ".",
+            NumberLocale::Fr => " ",
+        }
+    }
+
+    /// Separator before the fractional digits
+    pub fn decimal_separator(self) -> char {
+        match self {
+            NumberLocale::En => '.',
+            NumberLocale::De | NumberLocale::Fr => ',',
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum ColorScheme {
     Off,
     Dark,
@@ -280,6 +512,109 @@ pub enum ColorScheme {
 impl Args {
     /// Validate arguments for consistency
     pub fn validate(&self) -> Result<(), String> {
+        // Importing replaces the scan, so scan and export targets make no sense with it
+        if self.import_file.is_some() {
+            if self.export_json.is_some() || self.export_binary.is_some() {
+                return Err(
+                    "--file cannot be combined with --output or --output-binary".to_string()
+                );
+            }
+            if self.directory.is_some() {
+                return Err("--file cannot be combined with a directory to scan".to_string());
+            }
+        }
+
+        if self.import_dir.is_some() {
+            if self.import_file.is_some() {
+                return Err("--import-dir cannot be combined with --file".to_string());
+            }
+            if self.export_json.is_some() || self.export_binary.is_some() {
+                return Err(
+                    "--import-dir cannot be combined with --output or --output-binary".to_string(),
+                );
+            }
+            if self.directory.is_some() {
+                return Err("--import-dir cannot be combined with a directory to scan".to_string());
+            }
+        }
+
+        if self.record.is_some() {
+            if self.export_json.is_some() || self.export_binary.is_some() {
+                return Err(
+                    "--record cannot be combined with --output or --output-binary".to_string(),
+                );
+            }
+            if self.import_file.is_some() || self.import_dir.is_some() {
+                return Err("--record cannot be combined with --file or --import-dir".to_string());
+            }
+            // A recording leaves nothing out, so replaying it shows the same tree
+            if self.export_min_size.is_some() || self.max_children.is_some() {
+                return Err(
+                    "--record cannot be combined with --export-min-size or --max-children"
+                        .to_string(),
+                );
+            }
+            if !self.include_only.is_empty()
+                || !self.exclude.is_empty()
+                || self.exclude_from.is_some()
+                || !self.exclude_if_present.is_empty()
+                || self.exclude_caches
+            {
+                return Err(
+                    "--record cannot be combined with --include-only or excludes".to_string(),
+                );
+            }
+            if self.no_extended {
+                return Err("--record requires extended information".to_string());
+            }
+        }
+
+        if self.targets_from.is_some() {
+            if self.directory.is_some() {
+                return Err(
+                    "--targets-from cannot be combined with a directory to scan".to_string()
+                );
+            }
+            if self.import_file.is_some() || self.import_dir.is_some() {
+                return Err(
+                    "--targets-from cannot be combined with --file or --import-dir".to_string(),
+                );
+            }
+        }
+
+        if self.export_stats.is_some() && (self.import_file.is_some() || self.import_dir.is_some())
+        {
+            return Err(
+                "--export-stats cannot be combined with --file or --import-dir".to_string(),
+            );
+        }
+
+        if self.verify_import && self.import_file.is_none() && self.import_dir.is_none() {
+            return Err("--verify-import requires --file or --import-dir".to_string());
+        }
+
+        if let Some(runs) = self.bench {
+            if runs == 0 {
+                return Err("Number of benchmark runs must be greater than 0".to_string());
+            }
+            if self.import_file.is_some()
+                || self.import_dir.is_some()
+                || self.export_json.is_some()
+                || self.export_binary.is_some()
+                || self.record.is_some()
+                || self.export_stats.is_some()
+            {
+                return Err("--bench cannot be combined with importing or exporting".to_string());
+            }
+        }
+
+        // Stdin can only feed one of them
+        if self.import_file.as_deref() == Some("-")
+            && self.exclude_from.as_deref() == Some(std::path::Path::new("-"))
+        {
+            return Err("--file - and --exclude-from - cannot both read from stdin".to_string());
+        }
+
         // Check for conflicting options
         if self.ui_none as u8 + self.ui_line as u8 + self.ui_full as u8 > 1 {
             return Err("Only one UI mode can be specified".to_string());
@@ -295,12 +630,30 @@ impl Args {
             return Err("--extended and --no-extended are mutually exclusive".to_string());
         }
 
+        if self.newest_mtime && self.no_extended {
+            return Err("--newest-mtime requires extended information".to_string());
+        }
+
         if self.follow_symlinks && self.no_follow_symlinks {
             return Err(
                 "--follow-symlinks and --no-follow-symlinks are mutually exclusive".to_string(),
             );
         }
 
+        if self.follow_arg_symlinks && self.no_follow_arg_symlinks {
+            return Err(
+                "--follow-arg-symlinks and --no-follow-arg-symlinks are mutually exclusive"
+                    .to_string(),
+            );
+        }
+
+        if self.dereference_command_line && self.no_follow_arg_symlinks {
+            return Err(
+                "--dereference-command-line and --no-follow-arg-symlinks are mutually exclusive"
+                    .to_string(),
+            );
+        }
+
         if self.exclude_caches && self.include_caches {
             return Err("--exclude-caches and --include-caches are mutually exclusive".to_string());
         }
@@ -338,6 +691,12 @@ impl Args {
             );
         }
 
+        if self.hardlink_stats && self.no_hardlink_stats {
+            return Err(
+                "--hardlink-stats and --no-hardlink-stats are mutually exclusive".to_string(),
+            );
+        }
+
         if self.confirm_quit && self.no_confirm_quit {
             return Err("--confirm-quit and --no-confirm-quit are mutually exclusive".to_string());
         }
@@ -348,6 +707,13 @@ impl Args {
             );
         }
 
+        if self.color_by_extension && self.no_color_by_extension {
+            return Err(
+                "--color-by-extension and --no-color-by-extension are mutually exclusive"
+                    .to_string(),
+            );
+        }
+
         // Validate numeric ranges
         if let Some(threads) = self.threads {
             if threads == 0 {
@@ -355,12 +721,72 @@ impl Args {
             }
         }
 
+        if self.io_timeout == Some(0) {
+            return Err("I/O timeout must be greater than 0 ms".to_string());
+        }
+
+        if self.max_children == Some(0) {
+            return Err("Maximum number of children must be greater than 0".to_string());
+        }
+
+        if self.profile_scan == Some(0) {
+            return Err("Number of directories to profile must be greater than 0".to_string());
+        }
+
         if let Some(level) = self.compress_level {
             if !(1..=22).contains(&level) {
                 return Err("Compression level must be between 1 and 22".to_string());
             }
         }
 
+        if let Some(threshold) = self.fold_threshold {
+            if !(threshold > 0.0 && threshold <= 100.0) {
+                return Err("Fold threshold must be between 0 and 100 percent".to_string());
+            }
+        }
+
+        if let Some(threshold) = &self.report_over {
+            crate::utils::parse_size(threshold)
+                .map_err(|e| format!("Invalid report threshold: {}", e))?;
+        }
+
+        if self.tree && self.report_over.is_some() {
+            return Err("--tree and --report-over are mutually exclusive".to_string());
+        }
+
+        if self.du_format as u8 + self.tree as u8 + self.report_over.is_some() as u8 > 1 {
+            return Err("--du-format, --tree and --report-over are mutually exclusive".to_string());
+        }
+
+        if let Some(count) = self.largest {
+            if count == 0 {
+                return Err("Number of largest files must be greater than 0".to_string());
+            }
+            if self.du_format || self.tree || self.report_over.is_some() {
+                return Err(
+                    "--largest cannot be combined with --du-format, --tree or --report-over"
+                        .to_string(),
+                );
+            }
+        }
+
+        if self.du_all && !self.du_format {
+            return Err("--all requires --du-format".to_string());
+        }
+
+        if self.max_depth.is_some() && !self.tree {
+            return Err("--max-depth requires --tree".to_string());
+        }
+
+        if let Some(min_size) = &self.export_min_size {
+            crate::utils::parse_size(min_size)
+                .map_err(|e| format!("Invalid export minimum size: {}", e))?;
+        }
+
+        if self.block_unit == Some(0) {
+            return Err("Block unit must be greater than 0".to_string());
+        }
+
         if let Some(block_size) = self.export_block_size {
             if !(4..=16000).contains(&block_size) {
                 return Err("Export block size must be between 4 and 16000 KiB".to_string());
@@ -379,7 +805,15 @@ impl Args {
 }
 
 fn is_valid_sort_option(sort: &str) -> bool {
-    let valid_columns = ["name", "disk-usage", "apparent-size", "itemcount", "mtime"];
+    let valid_columns = [
+        "name",
+        "disk-usage",
+        "apparent-size",
+        "itemcount",
+        "mtime",
+        "owner",
+        "mode",
+    ];
     let valid_orders = ["asc", "desc"];
 
     if let Some((column, order)) = sort.rsplit_once('-') {
@@ -407,30 +841,61 @@ mod tests {
         let mut args = Args {
             directory: None,
             import_file: None,
+            import_dir: None,
+            verify_import: false,
             export_json: None,
+            export_stats: None,
             export_binary: None,
+            record: None,
             same_fs: false,
             cross_fs: false,
+            stat_other_fs: false,
             extended: false,
             no_extended: false,
+            newest_mtime: false,
             follow_symlinks: false,
             no_follow_symlinks: false,
+            follow_symlinks_matching: Vec::new(),
+            symlink_size: None,
+            follow_arg_symlinks: false,
+            no_follow_arg_symlinks: false,
+            dereference_command_line: false,
             exclude: Vec::new(),
+            ignore_case_patterns: false,
+            include_only: Vec::new(),
             exclude_from: None,
+            targets_from: None,
             exclude_caches: false,
             include_caches: false,
+            exclude_if_present: Vec::new(),
             exclude_kernfs: false,
             include_kernfs: false,
             threads: None,
+            io_timeout: None,
+            profile_scan: None,
+            hardlink_stats: false,
+            no_hardlink_stats: false,
+            max_children: None,
             compress: false,
             no_compress: false,
             compress_level: None,
+            compress_format: None,
+            report_over: None,
+            largest: None,
+            tree: false,
+            du_format: false,
+            du_all: false,
+            max_depth: None,
+            export_min_size: None,
             export_block_size: None,
             ui_none: false,
             ui_line: false,
             ui_full: false,
+            incremental: false,
+            no_altscreen: false,
             slow_updates: false,
             fast_updates: false,
+            progress_interval: None,
             enable_shell: false,
             disable_shell: false,
             enable_delete: false,
@@ -442,6 +907,7 @@ mod tests {
             no_si: false,
             apparent_size: false,
             disk_usage: false,
+            block_unit: None,
             show_hidden: false,
             hide_hidden: false,
             show_itemcount: false,
@@ -451,7 +917,12 @@ mod tests {
             show_graph: false,
             hide_graph: false,
             show_percent: false,
+            dual_size: false,
+            show_excluded_counts: false,
+            show_largest_file: false,
+            locale: None,
             hide_percent: false,
+            fold_threshold: None,
             graph_style: None,
             shared_column: None,
             sort: None,
@@ -465,7 +936,13 @@ mod tests {
             no_confirm_delete: false,
             delete_command: None,
             color: None,
+            color_by_extension: false,
+            no_color_by_extension: false,
             ignore_config: false,
+            ignore_system_config: false,
+            ignore_user_config: false,
+            build_info: false,
+            bench: None,
         };
 
         // Valid args should pass
@@ -476,4 +953,56 @@ mod tests {
         args.cross_fs = true;
         assert!(args.validate().is_err());
     }
+
+    #[test]
+    fn test_import_conflicts() {
+        let validate = |argv: &[&str]| {
+            Args::try_parse_from(std::iter::once("rsdu").chain(argv.iter().copied()))
+                .unwrap()
+                .validate()
+        };
+
+        assert!(validate(&["-f", "scan.json"]).is_ok());
+        assert!(validate(&["-o", "scan.json", "/tmp"]).is_ok());
+
+        assert!(validate(&["-f", "scan.json", "-o", "out.json"]).is_err());
+        assert!(validate(&["-f", "scan.json", "-O", "out.bin"]).is_err());
+        assert!(validate(&["-f", "scan.json", "/tmp"]).is_err());
+
+        assert!(validate(&["--import-dir", "shards"]).is_ok());
+        assert!(validate(&["--import-dir", "shards", "-f", "scan.json"]).is_err());
+        assert!(validate(&["--import-dir", "shards", "/tmp"]).is_err());
+
+        assert!(validate(&["-f", "scan.json", "--verify-import"]).is_ok());
+        assert!(validate(&["--verify-import", "/tmp"]).is_err());
+
+        assert!(validate(&["--targets-from", "list.txt"]).is_ok());
+        assert!(validate(&["--targets-from", "list.txt", "/tmp"]).is_err());
+        assert!(validate(&["--targets-from", "list.txt", "-f", "scan.json"]).is_err());
+    }
+
+    #[test]
+    fn test_stdin_conflict() {
+        let parse = |argv: &[&str]| {
+            Args::try_parse_from(std::iter::once("rsdu").chain(argv.iter().copied())).unwrap()
+        };
+
+        assert!(parse(&["-X", "-"]).validate().is_ok());
+        assert!(parse(&["-f", "-", "-X", "patterns.txt"]).validate().is_ok());
+        assert!(parse(&["-f", "-", "-X", "-"]).validate().is_err());
+    }
+
+    #[test]
+    fn test_profile_scan_count() {
+        let parse = |argv: &[&str]| {
+            Args::try_parse_from(std::iter::once("rsdu").chain(argv.iter().copied())).unwrap()
+        };
+
+        // The count is optional and never swallows the directory argument
+        let args = parse(&["--profile-scan", "/tmp"]);
+        assert_eq!(args.profile_scan, Some(10));
+        assert_eq!(args.directory, Some(PathBuf::from("/tmp")));
+        assert_eq!(parse(&["--profile-scan=3"]).profile_scan, Some(3));
+        assert!(parse(&["--profile-scan=0"]).validate().is_err());
+    }
 }
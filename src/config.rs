@@ -3,47 +3,118 @@
 //! This module handles configuration loading from command line arguments,
 //! configuration files, and environment variables.
 
-use crate::cli::{Args, ColorScheme, GraphStyle, SharedColumn};
+use crate::cli::{
+    Args, ColorScheme, CompressFormat, GraphStyle, NumberLocale, SharedColumn, SymlinkSize,
+};
+use crate::keymap::{self, Action, KeyBindings};
+use crate::model::BLOCK_SIZE;
+use crate::utils::parse_size;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-// use std::collections::HashSet; // TODO: Will be used for pattern matching
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+/// Location of the system-wide configuration file
+const SYSTEM_CONFIG_PATH: &str = "/etc/rsdu.conf";
+
+/// Time between scan progress reports unless `--progress-interval` says otherwise
+const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+
 /// Main configuration struct
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     // Scan options
     pub same_fs: bool,
+    /// With `same_fs`, size other filesystems by their used space, without descending
+    pub stat_other_fs: bool,
     pub extended: bool,
+    /// With `extended`, directories take the newest mtime of their descendants
+    pub newest_mtime: bool,
     pub follow_symlinks: bool,
+    /// Symlinks followed even without `follow_symlinks`, by name or target
+    pub follow_symlink_patterns: Vec<String>,
+    pub follow_arg_symlinks: bool,
+    pub symlink_size: SymlinkSize,
     pub exclude_caches: bool,
     pub exclude_kernfs: bool,
     pub threads: usize,
     pub exclude_patterns: Vec<String>,
+    /// Match exclude patterns regardless of case, as on case-insensitive filesystems
+    pub ignore_case_patterns: bool,
+    /// If any, only entries matching one of these (and their ancestors) are kept
+    pub include_patterns: Vec<String>,
+    /// Directories containing a file with one of these names are excluded
+    pub exclude_markers: Vec<String>,
+    /// Honor the `.rsduignore` files found while scanning
+    pub read_ignore_files: bool,
+    /// Paths to scan together under one root, from `--targets-from`
+    pub scan_targets: Vec<PathBuf>,
+    pub io_timeout: Option<Duration>,
+    /// Number of slowest directories to report after the scan, if profiling
+    pub profile_scan: Option<usize>,
+    /// Report hardlink groups and extra links after the scan
+    pub hardlink_stats: Option<bool>,
+    /// Children kept per directory; the smallest of the rest are aggregated
+    pub max_children: Option<usize>,
 
     // Export/Import options
-    pub compress: bool,
-    pub compress_level: u8,
+    /// Whether exports are compressed; unset unless a flag, level or codec was given
+    pub compress: Option<bool>,
+    /// Compression level; unset means the codec's default
+    pub compress_level: Option<u8>,
+    /// Codec used for compressed exports; unset means zstd
+    pub compress_format: Option<CompressFormat>,
     pub export_block_size: Option<usize>,
+    pub export_min_size: Option<u64>,
+    pub report_over: Option<u64>,
+    /// Print this many of the largest files instead of browsing
+    pub largest: Option<usize>,
+    pub tree: bool,
+    /// Print `du`-style lines instead of browsing, files included if `du_all`
+    pub du_format: bool,
+    pub du_all: bool,
+    pub max_depth: Option<usize>,
     pub export_json: Option<String>,
+    /// File receiving scan totals as JSON, from `--export-stats`
+    pub export_stats: Option<String>,
     pub export_binary: Option<String>,
 
     // UI options
     pub scan_ui: Option<ScanUi>,
+    pub incremental: bool,
+    /// Whether the browser runs on the terminal's alternate screen
+    pub alt_screen: bool,
     pub update_delay: Duration,
+    /// Minimum time between progress reports from the scanner
+    pub progress_interval: Duration,
     pub si: bool,
     pub color: ColorScheme,
+    pub color_by_extension: bool,
 
     // Display options
     pub show_hidden: bool,
     pub show_blocks: bool, // true for disk usage, false for apparent size
+    /// Bytes per unit of a file's block count
+    pub block_unit: u64,
     pub show_shared: SharedColumn,
     pub show_items: bool,
     pub show_mtime: bool,
     pub show_graph: bool,
     pub show_percent: bool,
+    /// Apparent size and disk usage columns instead of a single size column
+    pub dual_size: bool,
+    /// Note how many direct children of each directory were excluded
+    pub show_excluded_counts: bool,
+    /// Note the largest file anywhere below each directory
+    pub show_largest_file: bool,
+    /// Separators used when displaying sizes, counts and percentages
+    pub locale: NumberLocale,
     pub graph_style: GraphStyle,
+    pub fold_threshold: Option<f64>,
+    /// Size directories by their immediate entries only (toggled while browsing);
+    /// None leaves it to a later config file, defaulting to deep sizes
+    pub shallow_dir_sizes: Option<bool>,
 
     // Sorting options
     pub sort_col: SortColumn,
@@ -56,8 +127,13 @@ pub struct Config {
     pub can_shell: Option<bool>,
     pub can_refresh: Option<bool>,
     pub confirm_quit: bool,
+    /// Offer to save changed browser preferences to the user config on quit
+    pub save_on_exit: Option<bool>,
     pub confirm_delete: bool,
     pub delete_command: String,
+    /// Browser keys for each action, from `bind.<action>` options
+    #[serde(skip, default = "keymap::default_bindings")]
+    pub keybindings: KeyBindings,
 
     // Internal flags
     pub imported: bool,
@@ -77,6 +153,8 @@ pub enum SortColumn {
     Size,
     Items,
     Mtime,
+    Owner,
+    Mode,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -85,40 +163,96 @@ pub enum SortOrder {
     Desc,
 }
 
+/// A setting changed while browsing that can be saved back to the config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Preference {
+    /// Shallow or deep directory sizes, toggled with `S`
+    DirSizes,
+    /// The command used to delete, set with `E`
+    DeleteCommand,
+}
+
+impl Preference {
+    /// Every preference, in the order they are written to the config file
+    pub const ALL: [Preference; 2] = [Preference::DirSizes, Preference::DeleteCommand];
+
+    /// Config file options that set this preference
+    fn options(self) -> &'static [&'static str] {
+        match self {
+            Preference::DirSizes => &["shallow-sizes", "deep-sizes"],
+            Preference::DeleteCommand => &["delete-command"],
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             // Scan options
             same_fs: false,
+            stat_other_fs: false,
             extended: false,
+            newest_mtime: false,
             follow_symlinks: false,
+            follow_symlink_patterns: Vec::new(),
+            symlink_size: SymlinkSize::Link,
+            follow_arg_symlinks: true,
             exclude_caches: false,
             exclude_kernfs: false,
             threads: num_cpus::get().max(1),
             exclude_patterns: Vec::new(),
+            ignore_case_patterns: false,
+            include_patterns: Vec::new(),
+            exclude_markers: Vec::new(),
+            read_ignore_files: true,
+            scan_targets: Vec::new(),
+            io_timeout: None,
+            profile_scan: None,
+            hardlink_stats: None,
+            max_children: None,
 
             // Export/Import options
-            compress: false,
-            compress_level: 4,
+            compress: None,
+            compress_level: None,
+            compress_format: None,
             export_block_size: None,
+            export_min_size: None,
+            report_over: None,
+            largest: None,
+            tree: false,
+            du_format: false,
+            du_all: false,
+            max_depth: None,
             export_json: None,
+            export_stats: None,
             export_binary: None,
 
             // UI options
             scan_ui: None,
+            incremental: false,
+            alt_screen: true,
             update_delay: Duration::from_millis(100),
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
             si: false,
             color: ColorScheme::Off,
+            color_by_extension: false,
 
             // Display options
             show_hidden: true,
             show_blocks: true,
-            show_shared: SharedColumn::Shared,
+            block_unit: BLOCK_SIZE,
+            show_shared: SharedColumn::Off,
             show_items: false,
             show_mtime: false,
             show_graph: true,
             show_percent: false,
+            dual_size: false,
+            show_excluded_counts: false,
+            show_largest_file: false,
+            locale: NumberLocale::En,
             graph_style: GraphStyle::Hash,
+            fold_threshold: None,
+            shallow_dir_sizes: None,
 
             // Sorting options
             sort_col: SortColumn::Size,
@@ -131,8 +265,10 @@ impl Default for Config {
             can_shell: None,
             can_refresh: None,
             confirm_quit: false,
+            save_on_exit: None,
             confirm_delete: true,
             delete_command: String::new(),
+            keybindings: keymap::default_bindings(),
 
             // Internal flags
             imported: false,
@@ -150,11 +286,12 @@ impl Config {
         let mut config = if args.ignore_config {
             Self::default()
         } else {
-            Self::load_from_files()?
+            Self::load_from_files(args.ignore_system_config, args.ignore_user_config)
         };
 
         // Apply command line arguments (they override config files)
         config.apply_args(args)?;
+        config.apply_no_color(args, std::env::var_os("NO_COLOR").is_some());
 
         // Set default threads if not specified
         if config.threads == 0 {
@@ -164,24 +301,146 @@ impl Config {
         Ok(config)
     }
 
+    /// Set the compression level; a level is only useful with compression, so it enables it
+    fn set_compress_level(&mut self, level: u8) {
+        self.compress_level = Some(level);
+        self.compress = Some(true);
+    }
+
+    /// Set the compression codec; like a level, choosing one enables compression
+    fn set_compress_format(&mut self, format: CompressFormat) {
+        self.compress_format = Some(format);
+        self.compress = Some(true);
+    }
+
+    /// Set the block unit from a byte count
+    fn set_block_unit(&mut self, value: &str) -> Result<()> {
+        let unit: u64 = value.parse()?;
+        if unit == 0 {
+            return Err(anyhow::anyhow!("Block unit must be greater than 0"));
+        }
+        self.block_unit = unit;
+        Ok(())
+    }
+
+    /// Settings that are accepted but have no effect in this combination
+    ///
+    /// These are not errors, since a config file may set an option for runs
+    /// it doesn't apply to, but the user should hear about it.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        // JSON export records sizes, not blocks, so there is nothing to size
+        if self.export_block_size.is_some() && self.export_json.is_some() {
+            warnings.push(
+                "--export-block-size only applies to binary export (-O) and is ignored for JSON"
+                    .to_string(),
+            );
+        }
+        // Without a boundary no other filesystem is left unscanned
+        if self.stat_other_fs && !self.same_fs {
+            warnings.push("--stat-other-fs only applies with --one-file-system (-x)".to_string());
+        }
+        warnings.extend(keymap::conflicts(&self.keybindings));
+        warnings
+    }
+
+    /// Honor the `NO_COLOR` convention
+    ///
+    /// When the variable is set, to any value, colors are turned off even if a
+    /// config file enabled them; only an explicit `--color` wins over it.
+    fn apply_no_color(&mut self, args: &Args, no_color_set: bool) {
+        if no_color_set && args.color.is_none() {
+            self.color = ColorScheme::Off;
+        }
+    }
+
+    /// Location of the per-user configuration file, if there is a config directory
+    pub fn user_config_path() -> Option<PathBuf> {
+        get_user_config_dir().map(|dir| dir.join("rsdu").join("config"))
+    }
+
+    /// Write the `changed` preferences into the config file at `path`
+    ///
+    /// Other lines of an existing file are kept as they are.
+    pub fn save_preferences(&self, path: &Path, changed: &HashSet<Preference>) -> Result<()> {
+        let existing = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read config file: {}", path.display()))
+            }
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        std::fs::write(path, self.with_preferences(&existing, changed))
+            .with_context(|| format!("Failed to write config file: {}", path.display()))
+    }
+
+    /// Config file `content` with the lines of `changed` preferences replaced by current ones
+    fn with_preferences(&self, content: &str, changed: &HashSet<Preference>) -> String {
+        let mut updated = String::new();
+        for line in content.lines() {
+            let option = line.trim().trim_start_matches('@');
+            let option = option.split_once('=').map_or(option, |(key, _)| key).trim();
+            if !changed
+                .iter()
+                .any(|preference| preference.options().contains(&option))
+            {
+                updated.push_str(line);
+                updated.push('\n');
+            }
+        }
+
+        for preference in Preference::ALL.iter().filter(|p| changed.contains(p)) {
+            match preference {
+                Preference::DirSizes => updated.push_str(if self.shallow_dir_sizes == Some(true) {
+                    "shallow-sizes\n"
+                } else {
+                    "deep-sizes\n"
+                }),
+                Preference::DeleteCommand if !self.delete_command.is_empty() => {
+                    updated.push_str(&format!("delete-command = {}\n", self.delete_command))
+                }
+                Preference::DeleteCommand => {}
+            }
+        }
+        updated
+    }
+
     /// Load configuration from standard config file locations
-    fn load_from_files() -> Result<Self> {
+    fn load_from_files(ignore_system: bool, ignore_user: bool) -> Self {
+        let system_path = (!ignore_system).then(|| PathBuf::from(SYSTEM_CONFIG_PATH));
+        let user_path = if ignore_user {
+            None
+        } else {
+            Self::user_config_path()
+        };
+
+        Self::load_from_paths(system_path.as_deref(), user_path.as_deref())
+    }
+
+    /// Load and merge the system and user config files, either of which may be skipped
+    fn load_from_paths(system_path: Option<&Path>, user_path: Option<&Path>) -> Self {
         let mut config = Self::default();
 
         // Try to load from system config
-        if let Ok(system_config) = Self::load_config_file("/etc/rsdu.conf") {
-            config.merge(system_config);
+        if let Some(path) = system_path {
+            if let Ok(system_config) = Self::load_config_file(path) {
+                config.merge(system_config);
+            }
         }
 
         // Try to load from user config
-        if let Some(config_dir) = get_user_config_dir() {
-            let user_config_path = config_dir.join("rsdu").join("config");
-            if let Ok(user_config) = Self::load_config_file(&user_config_path) {
+        if let Some(path) = user_path {
+            if let Ok(user_config) = Self::load_config_file(path) {
                 config.merge(user_config);
             }
         }
 
-        Ok(config)
+        config
     }
 
     /// Load configuration from a specific file
@@ -236,16 +495,28 @@ impl Config {
         match flag {
             "same-fs" | "one-file-system" => self.same_fs = true,
             "cross-file-system" => self.same_fs = false,
+            "stat-other-fs" => self.stat_other_fs = true,
+            "no-stat-other-fs" => self.stat_other_fs = false,
             "extended" => self.extended = true,
             "no-extended" => self.extended = false,
+            "newest-mtime" => self.newest_mtime = true,
+            "own-mtime" => self.newest_mtime = false,
             "follow-symlinks" => self.follow_symlinks = true,
             "no-follow-symlinks" => self.follow_symlinks = false,
+            "follow-arg-symlinks" => self.follow_arg_symlinks = true,
+            "no-follow-arg-symlinks" => self.follow_arg_symlinks = false,
+            "incremental" => self.incremental = true,
+            "no-incremental" => self.incremental = false,
+            "altscreen" => self.alt_screen = true,
+            "no-altscreen" => self.alt_screen = false,
             "exclude-caches" => self.exclude_caches = true,
             "include-caches" => self.exclude_caches = false,
             "exclude-kernfs" => self.exclude_kernfs = true,
             "include-kernfs" => self.exclude_kernfs = false,
-            "compress" => self.compress = true,
-            "no-compress" => self.compress = false,
+            "ignore-case-patterns" => self.ignore_case_patterns = true,
+            "match-case-patterns" => self.ignore_case_patterns = false,
+            "compress" => self.compress = Some(true),
+            "no-compress" => self.compress = Some(false),
             "si" => self.si = true,
             "no-si" => self.si = false,
             "show-hidden" => self.show_hidden = true,
@@ -260,18 +531,32 @@ impl Config {
             "hide-graph" => self.show_graph = false,
             "show-percent" => self.show_percent = true,
             "hide-percent" => self.show_percent = false,
+            "dual-size" => self.dual_size = true,
+            "single-size" => self.dual_size = false,
+            "show-excluded-counts" => self.show_excluded_counts = true,
+            "hide-excluded-counts" => self.show_excluded_counts = false,
+            "show-largest-file" => self.show_largest_file = true,
+            "hide-largest-file" => self.show_largest_file = false,
             "group-directories-first" => self.sort_dirs_first = true,
             "no-group-directories-first" => self.sort_dirs_first = false,
             "enable-natsort" => self.sort_natural = true,
             "disable-natsort" => self.sort_natural = false,
             "confirm-quit" => self.confirm_quit = true,
             "no-confirm-quit" => self.confirm_quit = false,
+            "save-on-exit" => self.save_on_exit = Some(true),
+            "no-save-on-exit" => self.save_on_exit = Some(false),
+            "hardlink-stats" => self.hardlink_stats = Some(true),
+            "no-hardlink-stats" => self.hardlink_stats = Some(false),
+            "shallow-sizes" => self.shallow_dir_sizes = Some(true),
+            "deep-sizes" => self.shallow_dir_sizes = Some(false),
             "confirm-delete" => self.confirm_delete = true,
             "no-confirm-delete" => self.confirm_delete = false,
             "enable-shell" => self.can_shell = Some(true),
             "disable-shell" => self.can_shell = Some(false),
             "enable-delete" => self.can_delete = Some(true),
             "disable-delete" => self.can_delete = Some(false),
+            "color-by-extension" => self.color_by_extension = true,
+            "no-color-by-extension" => self.color_by_extension = false,
             "enable-refresh" => self.can_refresh = Some(true),
             "disable-refresh" => self.can_refresh = Some(false),
             _ => return Err(anyhow::anyhow!("Unknown config flag: {}", flag)),
@@ -281,15 +566,32 @@ impl Config {
 
     /// Apply a configuration key-value option
     fn apply_config_option(&mut self, key: &str, value: &str) -> Result<()> {
+        if let Some(name) = key.strip_prefix("bind.") {
+            let action = Action::from_name(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown action to bind: {}", name))?;
+            self.keybindings.insert(action, keymap::parse_keys(value)?);
+            return Ok(());
+        }
+
         match key {
             "threads" => self.threads = value.parse()?,
-            "compress-level" => self.compress_level = value.parse()?,
+            "io-timeout" => self.io_timeout = Some(Duration::from_millis(value.parse()?)),
+            "progress-interval" => self.progress_interval = Duration::from_millis(value.parse()?),
+            "profile-scan" => self.profile_scan = Some(value.parse()?),
+            "max-children" => self.max_children = Some(value.parse()?),
+            "compress-level" => self.set_compress_level(value.parse()?),
             "export-block-size" => {
                 let size: u16 = value.parse()?;
                 self.export_block_size = Some(size as usize * 1024);
             }
+            "export-min-size" => self.export_min_size = Some(parse_size(value)?),
+            "block-unit" => self.set_block_unit(value)?,
             "exclude" => self.exclude_patterns.push(value.to_string()),
+            "include-only" => self.include_patterns.push(value.to_string()),
+            "follow-symlinks-matching" => self.follow_symlink_patterns.push(value.to_string()),
+            "exclude-if-present" => self.exclude_markers.push(value.to_string()),
             "delete-command" => self.delete_command = value.to_string(),
+            "fold-threshold" => self.fold_threshold = Some(value.parse()?),
             "extended" => {
                 self.extended = match value {
                     "true" => true,
@@ -310,6 +612,14 @@ impl Config {
                     _ => return Err(anyhow::anyhow!("Invalid color scheme: {}", value)),
                 };
             }
+            "symlink-size" => {
+                self.symlink_size = match value {
+                    "none" => SymlinkSize::None,
+                    "self" => SymlinkSize::Link,
+                    "target" => SymlinkSize::Target,
+                    _ => return Err(anyhow::anyhow!("Invalid symlink size mode: {}", value)),
+                };
+            }
             "graph-style" => {
                 self.graph_style = match value {
                     "hash" => GraphStyle::Hash,
@@ -318,6 +628,21 @@ impl Config {
                     _ => return Err(anyhow::anyhow!("Invalid graph style: {}", value)),
                 };
             }
+            "compress-format" => {
+                self.set_compress_format(match value {
+                    "zstd" => CompressFormat::Zstd,
+                    "gzip" => CompressFormat::Gzip,
+                    _ => return Err(anyhow::anyhow!("Invalid compression format: {}", value)),
+                });
+            }
+            "locale" => {
+                self.locale = match value {
+                    "en" => NumberLocale::En,
+                    "de" => NumberLocale::De,
+                    "fr" => NumberLocale::Fr,
+                    _ => return Err(anyhow::anyhow!("Invalid locale: {}", value)),
+                };
+            }
             "shared-column" => {
                 self.show_shared = match value {
                     "off" => SharedColumn::Off,
@@ -347,6 +672,8 @@ impl Config {
             "apparent-size" => SortColumn::Size,
             "itemcount" => SortColumn::Items,
             "mtime" => SortColumn::Mtime,
+            "owner" => SortColumn::Owner,
+            "mode" => SortColumn::Mode,
             _ => return Err(anyhow::anyhow!("Invalid sort column: {}", column)),
         };
 
@@ -359,8 +686,10 @@ impl Config {
         } else {
             // Set default order based on column
             self.sort_order = match self.sort_col {
-                SortColumn::Name | SortColumn::Mtime => SortOrder::Asc,
-                SortColumn::Blocks | SortColumn::Size | SortColumn::Items => SortOrder::Desc,
+                SortColumn::Name | SortColumn::Mtime | SortColumn::Owner => SortOrder::Asc,
+                SortColumn::Blocks | SortColumn::Size | SortColumn::Items | SortColumn::Mode => {
+                    SortOrder::Desc
+                }
             };
         }
 
@@ -376,18 +705,39 @@ impl Config {
         if args.cross_fs {
             self.same_fs = false;
         }
+        if args.stat_other_fs {
+            self.stat_other_fs = true;
+        }
         if args.extended {
             self.extended = true;
         }
         if args.no_extended {
             self.extended = false;
         }
+        if args.newest_mtime {
+            self.newest_mtime = true;
+            self.extended = true;
+        }
         if args.follow_symlinks {
             self.follow_symlinks = true;
         }
         if args.no_follow_symlinks {
             self.follow_symlinks = false;
         }
+        if let Some(mode) = args.symlink_size {
+            self.symlink_size = mode;
+        }
+        if args.follow_arg_symlinks {
+            self.follow_arg_symlinks = true;
+        }
+        if args.no_follow_arg_symlinks {
+            self.follow_arg_symlinks = false;
+        }
+        // Overrides a configured `no-follow-arg-symlinks`, but leaves
+        // symlinks below the root to `follow_symlinks`
+        if args.dereference_command_line {
+            self.follow_arg_symlinks = true;
+        }
         if args.exclude_caches {
             self.exclude_caches = true;
         }
@@ -405,35 +755,110 @@ impl Config {
             self.threads = threads;
         }
 
+        if let Some(timeout) = args.io_timeout {
+            self.io_timeout = Some(Duration::from_millis(timeout));
+        }
+
+        if let Some(count) = args.profile_scan {
+            self.profile_scan = Some(count);
+        }
+        if args.hardlink_stats {
+            self.hardlink_stats = Some(true);
+        }
+        if args.no_hardlink_stats {
+            self.hardlink_stats = Some(false);
+        }
+
+        if let Some(max) = args.max_children {
+            self.max_children = Some(max);
+        }
+
         // Add exclude patterns
         for pattern in &args.exclude {
             self.exclude_patterns.push(pattern.clone());
         }
+        if args.ignore_case_patterns {
+            self.ignore_case_patterns = true;
+        }
+        for pattern in &args.include_only {
+            self.include_patterns.push(pattern.clone());
+        }
+        for pattern in &args.follow_symlinks_matching {
+            self.follow_symlink_patterns.push(pattern.clone());
+        }
+
+        for marker in &args.exclude_if_present {
+            self.exclude_markers.push(marker.clone());
+        }
 
         // Load exclude patterns from file
         if let Some(exclude_file) = &args.exclude_from {
             self.load_exclude_file(exclude_file)?;
         }
 
-        // Export options
-        self.export_json = args.export_json.clone();
+        if let Some(targets_file) = &args.targets_from {
+            self.load_targets_file(targets_file)?;
+        }
+
+        // Export options; a recording is a JSON export of everything
+        self.export_json = args.export_json.clone().or_else(|| args.record.clone());
+        if args.record.is_some() {
+            self.extended = true;
+        }
         self.export_binary = args.export_binary.clone();
+        self.export_stats = args.export_stats.clone();
 
         if args.compress {
-            self.compress = true;
+            self.compress = Some(true);
         }
-        if args.no_compress {
-            self.compress = false;
-        }
-
         if let Some(level) = args.compress_level {
-            self.compress_level = level;
+            self.set_compress_level(level);
+        }
+        if let Some(format) = args.compress_format {
+            self.set_compress_format(format);
+        }
+        // An explicit --no-compress wins over anything that implied compression
+        if args.no_compress {
+            self.compress = Some(false);
         }
 
         if let Some(block_size) = args.export_block_size {
             self.export_block_size = Some(block_size as usize * 1024);
         }
 
+        if let Some(min_size) = &args.export_min_size {
+            self.export_min_size = Some(parse_size(min_size)?);
+        }
+        // A recording leaves nothing out, not even for a config file
+        if args.record.is_some() {
+            self.export_min_size = None;
+            self.max_children = None;
+            self.include_patterns.clear();
+            self.exclude_patterns.clear();
+            self.exclude_markers.clear();
+            self.exclude_caches = false;
+            self.read_ignore_files = false;
+        }
+
+        if let Some(threshold) = &args.report_over {
+            self.report_over = Some(parse_size(threshold)?);
+        }
+
+        if let Some(count) = args.largest {
+            self.largest = Some(count);
+        }
+
+        if args.tree {
+            self.tree = true;
+        }
+        if args.du_format {
+            self.du_format = true;
+            self.du_all = args.du_all;
+        }
+        if let Some(depth) = args.max_depth {
+            self.max_depth = Some(depth);
+        }
+
         // UI options
         if args.ui_none {
             self.scan_ui = Some(ScanUi::None);
@@ -445,12 +870,21 @@ impl Config {
             self.scan_ui = Some(ScanUi::Full);
         }
 
+        if args.incremental {
+            self.incremental = true;
+        }
+        if args.no_altscreen {
+            self.alt_screen = false;
+        }
         if args.slow_updates {
             self.update_delay = Duration::from_secs(2);
         }
         if args.fast_updates {
             self.update_delay = Duration::from_millis(100);
         }
+        if let Some(interval) = args.progress_interval {
+            self.progress_interval = Duration::from_millis(interval);
+        }
 
         if args.si {
             self.si = true;
@@ -472,6 +906,9 @@ impl Config {
         if args.disk_usage {
             self.show_blocks = true;
         }
+        if let Some(unit) = args.block_unit {
+            self.block_unit = unit;
+        }
         if args.show_itemcount {
             self.show_items = true;
         }
@@ -496,12 +933,28 @@ impl Config {
         if args.hide_percent {
             self.show_percent = false;
         }
+        if args.dual_size {
+            self.dual_size = true;
+        }
+        if args.show_excluded_counts {
+            self.show_excluded_counts = true;
+        }
+        if args.show_largest_file {
+            self.show_largest_file = true;
+        }
+        if let Some(locale) = args.locale {
+            self.locale = locale;
+        }
+
+        if let Some(threshold) = args.fold_threshold {
+            self.fold_threshold = Some(threshold);
+        }
 
         if let Some(style) = &args.graph_style {
             self.graph_style = style.clone();
         }
         if let Some(shared) = &args.shared_column {
-            self.show_shared = shared.clone();
+            self.show_shared = *shared;
         }
 
         // Sorting options
@@ -566,16 +1019,34 @@ impl Config {
         if let Some(color) = &args.color {
             self.color = color.clone();
         }
+        if args.color_by_extension {
+            self.color_by_extension = true;
+        }
+        if args.no_color_by_extension {
+            self.color_by_extension = false;
+        }
 
         Ok(())
     }
 
     /// Load exclude patterns from a file
     fn load_exclude_file(&mut self, path: &PathBuf) -> Result<()> {
-        let content = std::fs::read_to_string(path)
+        if path.as_os_str() == "-" {
+            return self
+                .load_exclude_patterns(std::io::stdin().lock())
+                .context("Failed to read exclude patterns from stdin");
+        }
+
+        let file = std::fs::File::open(path)
             .with_context(|| format!("Failed to read exclude file: {}", path.display()))?;
+        self.load_exclude_patterns(std::io::BufReader::new(file))
+            .with_context(|| format!("Failed to read exclude file: {}", path.display()))
+    }
 
-        for line in content.lines() {
+    /// Add exclude patterns read line by line, skipping blanks and comments
+    fn load_exclude_patterns<R: std::io::BufRead>(&mut self, reader: R) -> Result<()> {
+        for line in reader.lines() {
+            let line = line?;
             let line = line.trim();
             if !line.is_empty() && !line.starts_with('#') {
                 self.exclude_patterns.push(line.to_string());
@@ -585,6 +1056,34 @@ impl Config {
         Ok(())
     }
 
+    /// Load the paths to scan from a file
+    fn load_targets_file(&mut self, path: &PathBuf) -> Result<()> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to read targets file: {}", path.display()))?;
+        self.load_scan_targets(std::io::BufReader::new(file))
+            .with_context(|| format!("Failed to read targets file: {}", path.display()))?;
+        if self.scan_targets.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Targets file {} lists no paths",
+                path.display()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Add paths to scan read line by line, skipping blanks and comments
+    fn load_scan_targets<R: std::io::BufRead>(&mut self, reader: R) -> Result<()> {
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                self.scan_targets.push(PathBuf::from(line));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Merge another configuration into this one
     fn merge(&mut self, other: Self) {
         // This is a simple merge - we could make it more sophisticated
@@ -592,12 +1091,27 @@ impl Config {
         if other.same_fs {
             self.same_fs = true;
         }
+        if other.stat_other_fs {
+            self.stat_other_fs = true;
+        }
         if other.extended {
             self.extended = true;
         }
+        if other.newest_mtime {
+            self.newest_mtime = true;
+        }
         if other.follow_symlinks {
             self.follow_symlinks = true;
         }
+        if other.symlink_size != SymlinkSize::Link {
+            self.symlink_size = other.symlink_size;
+        }
+        if !other.follow_arg_symlinks {
+            self.follow_arg_symlinks = false;
+        }
+        if other.show_shared != SharedColumn::Off {
+            self.show_shared = other.show_shared;
+        }
         if other.exclude_caches {
             self.exclude_caches = true;
         }
@@ -608,26 +1122,63 @@ impl Config {
             self.threads = other.threads;
         }
         self.exclude_patterns.extend(other.exclude_patterns);
+        if other.ignore_case_patterns {
+            self.ignore_case_patterns = true;
+        }
+        self.include_patterns.extend(other.include_patterns);
+        self.follow_symlink_patterns
+            .extend(other.follow_symlink_patterns);
+        self.exclude_markers.extend(other.exclude_markers);
+        if other.io_timeout.is_some() {
+            self.io_timeout = other.io_timeout;
+        }
+        if other.profile_scan.is_some() {
+            self.profile_scan = other.profile_scan;
+        }
+        if other.hardlink_stats.is_some() {
+            self.hardlink_stats = other.hardlink_stats;
+        }
+        if other.max_children.is_some() {
+            self.max_children = other.max_children;
+        }
 
-        if other.compress {
-            self.compress = true;
+        if other.compress.is_some() {
+            self.compress = other.compress;
         }
-        if other.compress_level != 4 {
+        if other.compress_level.is_some() {
             self.compress_level = other.compress_level;
         }
+        if other.compress_format.is_some() {
+            self.compress_format = other.compress_format;
+        }
         if other.export_block_size.is_some() {
             self.export_block_size = other.export_block_size;
         }
+        if other.export_min_size.is_some() {
+            self.export_min_size = other.export_min_size;
+        }
 
         if other.scan_ui.is_some() {
             self.scan_ui = other.scan_ui;
         }
+        if other.incremental {
+            self.incremental = true;
+        }
+        if !other.alt_screen {
+            self.alt_screen = false;
+        }
         if other.update_delay != Duration::from_millis(100) {
             self.update_delay = other.update_delay;
         }
+        if other.progress_interval != DEFAULT_PROGRESS_INTERVAL {
+            self.progress_interval = other.progress_interval;
+        }
         if other.si {
             self.si = true;
         }
+        if other.color_by_extension {
+            self.color_by_extension = true;
+        }
 
         // Display options
         if !other.show_hidden {
@@ -636,6 +1187,9 @@ impl Config {
         if !other.show_blocks {
             self.show_blocks = false;
         }
+        if other.block_unit != BLOCK_SIZE {
+            self.block_unit = other.block_unit;
+        }
         if other.show_items {
             self.show_items = true;
         }
@@ -648,6 +1202,21 @@ impl Config {
         if other.show_percent {
             self.show_percent = true;
         }
+        if other.dual_size {
+            self.dual_size = true;
+        }
+        if other.show_excluded_counts {
+            self.show_excluded_counts = true;
+        }
+        if other.show_largest_file {
+            self.show_largest_file = true;
+        }
+        if other.locale != NumberLocale::En {
+            self.locale = other.locale;
+        }
+        if other.fold_threshold.is_some() {
+            self.fold_threshold = other.fold_threshold;
+        }
 
         // Feature flags
         if other.can_delete.is_some() {
@@ -662,12 +1231,23 @@ impl Config {
         if other.confirm_quit {
             self.confirm_quit = true;
         }
+        if other.save_on_exit.is_some() {
+            self.save_on_exit = other.save_on_exit;
+        }
+        if other.shallow_dir_sizes.is_some() {
+            self.shallow_dir_sizes = other.shallow_dir_sizes;
+        }
         if !other.confirm_delete {
             self.confirm_delete = false;
         }
         if !other.delete_command.is_empty() {
             self.delete_command = other.delete_command;
         }
+        for (action, keys) in other.keybindings {
+            if keys != action.default_keys() {
+                self.keybindings.insert(action, keys);
+            }
+        }
     }
 }
 
@@ -689,6 +1269,7 @@ fn get_user_config_dir() -> Option<PathBuf> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crossterm::event::KeyCode;
 
     #[test]
     fn test_default_config() {
@@ -713,6 +1294,298 @@ exclude=*.tmp
         assert_eq!(config.exclude_patterns, vec!["*.tmp"]);
     }
 
+    #[test]
+    fn test_selectively_ignored_config_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let system_path = temp_dir.path().join("rsdu.conf");
+        let user_path = temp_dir.path().join("config");
+        std::fs::write(&system_path, "same-fs\nthreads=2\n").unwrap();
+        std::fs::write(&user_path, "extended\n").unwrap();
+
+        // --ignore-system-config: only the user config applies
+        let config = Config::load_from_paths(None, Some(&user_path));
+        assert!(!config.same_fs);
+        assert!(config.extended);
+
+        // --ignore-user-config: only the system config applies
+        let config = Config::load_from_paths(Some(&system_path), None);
+        assert!(config.same_fs);
+        assert_eq!(config.threads, 2);
+        assert!(!config.extended);
+
+        let config = Config::load_from_paths(Some(&system_path), Some(&user_path));
+        assert!(config.same_fs);
+        assert!(config.extended);
+    }
+
+    #[test]
+    fn test_exclude_patterns_from_reader() {
+        let mut config = Config::default();
+        let input = "*.tmp\n\n# comment\n  node_modules  \n";
+        config.load_exclude_patterns(input.as_bytes()).unwrap();
+        assert_eq!(config.exclude_patterns, vec!["*.tmp", "node_modules"]);
+    }
+
+    #[test]
+    fn test_scan_targets_from_reader() {
+        let mut config = Config::default();
+        let input = "# nightly audit\n/var/log\n\n  /home/shared  \n# /tmp\n";
+        config.load_scan_targets(input.as_bytes()).unwrap();
+        assert_eq!(
+            config.scan_targets,
+            vec![PathBuf::from("/var/log"), PathBuf::from("/home/shared")]
+        );
+    }
+
+    #[test]
+    fn test_compress_level_enables_compression() {
+        use clap::Parser;
+        let mut config = Config::default();
+        assert_eq!(config.compress, None);
+        let args = Args::try_parse_from(["rsdu", "--compress-level", "10"]).unwrap();
+        config.apply_args(&args).unwrap();
+        assert_eq!(config.compress, Some(true));
+        assert_eq!(config.compress_level, Some(10));
+
+        // The same rule applies to a level set in a config file
+        let config = Config::parse_config_content("compress-level = 10\n").unwrap();
+        assert_eq!(config.compress, Some(true));
+        let mut merged = Config::default();
+        merged.merge(config);
+        assert_eq!(merged.compress, Some(true));
+        assert_eq!(merged.compress_level, Some(10));
+
+        // An explicit --no-compress wins
+        let args =
+            Args::try_parse_from(["rsdu", "--compress-level", "10", "--no-compress"]).unwrap();
+        assert!(args.validate().is_ok());
+        merged.apply_args(&args).unwrap();
+        assert_eq!(merged.compress, Some(false));
+    }
+
+    #[test]
+    fn test_config_compress_format() {
+        let system = Config::parse_config_content("compress-format = gzip\n").unwrap();
+        assert_eq!(system.compress, Some(true));
+        let mut config = Config::default();
+        config.merge(system);
+        assert_eq!(config.compress_format, Some(CompressFormat::Gzip));
+
+        // A user config can switch back to the default codec
+        let user = Config::parse_config_content("compress-format = zstd\n").unwrap();
+        config.merge(user);
+        assert_eq!(config.compress_format, Some(CompressFormat::Zstd));
+        assert_eq!(config.compress, Some(true));
+    }
+
+    #[test]
+    fn test_dereference_command_line() {
+        use clap::Parser;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        std::fs::create_dir(&target).unwrap();
+        std::fs::write(target.join("data"), "data").unwrap();
+        std::os::unix::fs::symlink(target.join("data"), target.join("inner")).unwrap();
+        let link = temp_dir.path().join("link");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        // -H wins over a configured no-follow-arg-symlinks
+        let mut config = Config::parse_config_content("no-follow-arg-symlinks\n").unwrap();
+        let args = Args::try_parse_from(["rsdu", "-H", link.to_str().unwrap()]).unwrap();
+        config.apply_args(&args).unwrap();
+        assert!(config.follow_arg_symlinks);
+        assert!(!config.follow_symlinks);
+
+        // The root resolves to its target, while symlinks below stay links
+        let root = crate::utils::resolve_scan_root(&link, config.follow_arg_symlinks).unwrap();
+        assert_eq!(root, target.canonicalize().unwrap());
+        let entry = crate::scanner::scan_directory(&root, &config).unwrap();
+        assert_eq!(entry.entry_type, crate::model::EntryType::Directory);
+        let inner = entry
+            .children
+            .iter()
+            .find(|child| child.name_str() == "inner")
+            .unwrap();
+        assert_eq!(inner.entry_type, crate::model::EntryType::Symlink);
+
+        let args = Args::try_parse_from(["rsdu", "-H", "--no-follow-arg-symlinks"]).unwrap();
+        assert!(args.validate().is_err());
+    }
+
+    #[test]
+    fn test_record_keeps_everything() {
+        use clap::Parser;
+        // Options that would drop entries are refused on the command line...
+        for option in [
+            &["--max-children", "5"][..],
+            &["--include-only", "*.log"],
+            &["--exclude", "*.tmp"],
+            &["--exclude-caches"],
+        ] {
+            let mut argv = vec!["rsdu", "--record", "out.json"];
+            argv.extend_from_slice(option);
+            assert!(Args::try_parse_from(argv).unwrap().validate().is_err());
+        }
+
+        // ...and ignored when they come from a config file
+        let mut config = Config::parse_config_content(
+            "max-children = 5\ninclude-only = *.log\nexclude = *.tmp\nexclude-if-present = .nobackup\n",
+        )
+        .unwrap();
+        let args = Args::try_parse_from(["rsdu", "--record", "out.json"]).unwrap();
+        args.validate().unwrap();
+        config.apply_args(&args).unwrap();
+        assert_eq!(config.export_json.as_deref(), Some("out.json"));
+        assert_eq!(config.max_children, None);
+        assert!(config.include_patterns.is_empty());
+        assert!(config.exclude_patterns.is_empty());
+        assert!(config.exclude_markers.is_empty());
+        assert!(!config.read_ignore_files);
+    }
+
+    #[test]
+    fn test_no_color_forces_color_off() {
+        use clap::Parser;
+        let parse = |argv: &[&str]| {
+            Args::try_parse_from(std::iter::once("rsdu").chain(argv.iter().copied())).unwrap()
+        };
+
+        // A config file asked for colors, but NO_COLOR is set
+        let mut config = Config::parse_config_content("color=dark\n").unwrap();
+        config.apply_no_color(&parse(&[]), true);
+        assert_eq!(config.color, ColorScheme::Off);
+
+        // Without NO_COLOR the configured scheme stays
+        let mut config = Config::parse_config_content("color=dark\n").unwrap();
+        config.apply_no_color(&parse(&[]), false);
+        assert_eq!(config.color, ColorScheme::Dark);
+
+        // An explicit --color wins over NO_COLOR
+        let args = parse(&["--color", "dark-bg"]);
+        let mut config = Config::default();
+        config.apply_args(&args).unwrap();
+        config.apply_no_color(&args, true);
+        assert_eq!(config.color, ColorScheme::DarkBg);
+    }
+
+    #[test]
+    fn test_block_unit() {
+        let config = Config::default();
+        assert_eq!(config.block_unit, 512);
+
+        let config = Config::parse_config_content("block-unit=4096\n").unwrap();
+        assert_eq!(config.block_unit, 4096);
+
+        // st_blocks is always in 512-byte units, whatever the filesystem's block size
+        assert!(Config::parse_config_content("block-unit=auto\n").is_err());
+
+        assert!(Config::parse_config_content("block-unit=0\n").is_err());
+    }
+
+    #[test]
+    fn test_with_preferences() {
+        let config = Config {
+            shallow_dir_sizes: Some(true),
+            delete_command: "trash-put {}".to_string(),
+            ..Config::default()
+        };
+        let existing = "# my settings\ncolor = dark\ndeep-sizes\n@delete-command = rm {}\n";
+        let changed = HashSet::from(Preference::ALL);
+        let updated = config.with_preferences(existing, &changed);
+        assert_eq!(
+            updated,
+            "# my settings\ncolor = dark\nshallow-sizes\ndelete-command = trash-put {}\n"
+        );
+
+        // The saved file reads back to the same preferences
+        let reloaded = Config::parse_config_content(&updated).unwrap();
+        assert_eq!(reloaded.shallow_dir_sizes, Some(true));
+        assert_eq!(reloaded.delete_command, "trash-put {}");
+        assert_eq!(config.with_preferences(&updated, &changed), updated);
+
+        // Preferences left alone, like a delete command from the system
+        // config, are not written to the user's file
+        let changed = HashSet::from([Preference::DirSizes]);
+        assert_eq!(
+            config.with_preferences("color = dark\n", &changed),
+            "color = dark\nshallow-sizes\n"
+        );
+        assert_eq!(config.with_preferences(existing, &HashSet::new()), existing);
+    }
+
+    #[test]
+    fn test_no_hardlink_stats() {
+        use clap::Parser;
+        let mut config = Config::parse_config_content("hardlink-stats\n").unwrap();
+        assert_eq!(config.hardlink_stats, Some(true));
+        let args = Args::try_parse_from(["rsdu", "--no-hardlink-stats"]).unwrap();
+        config.apply_args(&args).unwrap();
+        assert_eq!(config.hardlink_stats, Some(false));
+
+        let mut system = Config::parse_config_content("hardlink-stats\n").unwrap();
+        system.merge(Config::parse_config_content("no-hardlink-stats\n").unwrap());
+        assert_eq!(system.hardlink_stats, Some(false));
+    }
+
+    #[test]
+    fn test_user_config_overrides_preferences() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let system = temp_dir.path().join("system");
+        let user = temp_dir.path().join("user");
+        std::fs::write(&system, "shallow-sizes\nsave-on-exit\n").unwrap();
+        std::fs::write(&user, "deep-sizes\nno-save-on-exit\n").unwrap();
+
+        let config = Config::load_from_paths(Some(&system), None);
+        assert_eq!(config.shallow_dir_sizes, Some(true));
+        assert_eq!(config.save_on_exit, Some(true));
+
+        // The user config turns both back off
+        let config = Config::load_from_paths(Some(&system), Some(&user));
+        assert_eq!(config.shallow_dir_sizes, Some(false));
+        assert_eq!(config.save_on_exit, Some(false));
+    }
+
+    #[test]
+    fn test_key_bindings() {
+        let config =
+            Config::parse_config_content("bind.quit = x\nbind.delete = D, Delete\n").unwrap();
+        assert_eq!(config.keybindings[&Action::Quit], [KeyCode::Char('x')]);
+        assert_eq!(
+            config.keybindings[&Action::Delete],
+            [KeyCode::Char('D'), KeyCode::Delete]
+        );
+        assert_eq!(
+            config.keybindings[&Action::Help],
+            Action::Help.default_keys()
+        );
+
+        assert!(Config::parse_config_content("bind.launch = x\n").is_err());
+        assert!(Config::parse_config_content("bind.quit = hyper\n").is_err());
+
+        // Taking another action's key is allowed, but warned about
+        let conflicting = Config::parse_config_content("bind.quit = d\n").unwrap();
+        assert_eq!(conflicting.warnings().len(), 1);
+
+        let mut merged = Config::default();
+        merged.merge(config);
+        assert_eq!(merged.keybindings[&Action::Quit], [KeyCode::Char('x')]);
+    }
+
+    #[test]
+    fn test_export_block_size_warning() {
+        let mut config = Config::parse_config_content("export-block-size=64\n").unwrap();
+        assert!(config.warnings().is_empty());
+
+        config.export_json = Some("scan.json".to_string());
+        let warnings = config.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("--export-block-size"));
+
+        config.export_json = None;
+        config.export_binary = Some("scan.bin".to_string());
+        assert!(config.warnings().is_empty());
+    }
+
     #[test]
     fn test_sort_parsing() {
         let mut config = Config::default();
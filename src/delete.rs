@@ -0,0 +1,114 @@
+//! File deletion module
+//!
+//! This module removes entries from disk for the browser, either directly or
+//! through the user's `delete_command`.
+
+use crate::error::{Result, RsduError};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Placeholder in `delete_command` replaced by the path being deleted
+pub const PATH_PLACEHOLDER: &str = "{}";
+
+/// Quote a path for use as a single shell word
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// Build the shell command line that deletes `path` with `command`
+///
+/// Every `{}` in the command is replaced by the quoted path; a command
+/// without a placeholder gets the path appended as its last argument.
+pub fn delete_command_line(command: &str, path: &Path) -> String {
+    let quoted = shell_quote(path);
+    if command.contains(PATH_PLACEHOLDER) {
+        command.replace(PATH_PLACEHOLDER, &quoted)
+    } else {
+        format!("{} {}", command.trim_end(), quoted)
+    }
+}
+
+/// Human-readable description of what deleting `path` will do
+pub fn describe_delete(command: &str, path: &Path) -> String {
+    if command.trim().is_empty() {
+        format!("Remove {} and everything below it", path.display())
+    } else {
+        format!("Run: {}", delete_command_line(command, path))
+    }
+}
+
+/// Delete `path`, through `command` when one is configured
+pub fn delete_path(path: &Path, command: &str) -> Result<()> {
+    if !command.trim().is_empty() {
+        // Output is captured so it can't scribble over the browser
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(delete_command_line(command, path))
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(RsduError::FileSystemError(format!(
+                "Delete command failed with {}: {}",
+                output.status,
+                stderr.trim()
+            )));
+        }
+        return Ok(());
+    }
+
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_placeholder_substitution() {
+        let path = Path::new("/data/old logs");
+        assert_eq!(
+            delete_command_line("trash-put {}", path),
+            "trash-put '/data/old logs'"
+        );
+        assert_eq!(
+            delete_command_line("mv {} /tmp/bin && echo {}", path),
+            "mv '/data/old logs' /tmp/bin && echo '/data/old logs'"
+        );
+        // No placeholder: the path goes last
+        assert_eq!(
+            delete_command_line("trash-put ", path),
+            "trash-put '/data/old logs'"
+        );
+        // Quotes in names can't break out of the shell word
+        assert_eq!(
+            delete_command_line("rm {}", Path::new("it's")),
+            r"rm 'it'\''s'"
+        );
+    }
+
+    #[test]
+    fn test_delete_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("dir");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("file"), "data").unwrap();
+
+        delete_path(&dir, "").unwrap();
+        assert!(!dir.exists());
+
+        let file = temp_dir.path().join("it's here");
+        fs::write(&file, "data").unwrap();
+        delete_path(&file, "rm {}").unwrap();
+        assert!(!file.exists());
+
+        assert!(delete_path(&file, "false").is_err());
+    }
+}
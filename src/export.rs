@@ -2,19 +2,38 @@
 //!
 //! This module handles exporting scanned directory data to JSON and binary formats.
 
+use crate::cli::CompressFormat;
+use crate::config::Config;
 use crate::error::{Result, RsduError};
-use crate::model::Entry;
+use crate::model::{
+    encode_name, generate_entry_id, Entry, EntryType, ScanStats, SerializableEntry,
+};
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
 use serde_json;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, BufWriter, Write};
-// use std::path::Path; // TODO: Will be used for path operations
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 // use std::sync::Arc; // TODO: Will be used for Arc<Entry>
 
 /// Export handler for managing output
 pub struct ExportHandler {
     writer: Box<dyn Write + Send>,
     format: ExportFormat,
-    compress: bool,
+    /// Codec the output goes through, if compressed
+    compression: Option<Compression>,
+    /// Subtrees smaller than this are folded into one aggregate entry
+    min_size: Option<u64>,
+    /// Temporary file to be renamed over the target on `finalize()`
+    pending: Option<PendingRename>,
+}
+
+/// A temporary export file and the path it replaces once complete
+struct PendingRename {
+    temp_path: PathBuf,
+    target_path: PathBuf,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -23,25 +42,126 @@ pub enum ExportFormat {
     Binary,
 }
 
+/// Codec and level of a compressed export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    pub format: CompressFormat,
+    /// 1-22; gzip only goes up to 9, so higher levels count as 9 for it
+    pub level: u8,
+}
+
+impl Compression {
+    /// Names of the codecs exports can be compressed with
+    pub fn codec_names() -> Vec<&'static str> {
+        CompressFormat::value_variants()
+            .iter()
+            .map(|format| match format {
+                CompressFormat::Zstd => "zstd",
+                CompressFormat::Gzip => "gzip",
+            })
+            .collect()
+    }
+
+    /// Level used when none is given
+    pub const DEFAULT_LEVEL: u8 = 4;
+
+    /// The compression `config` asks for, if any
+    pub fn from_config(config: &Config) -> Option<Self> {
+        (config.compress == Some(true)).then_some(Self {
+            format: config.compress_format.unwrap_or(CompressFormat::Zstd),
+            level: config.compress_level.unwrap_or(Self::DEFAULT_LEVEL),
+        })
+    }
+
+    /// `data` compressed with this codec
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.format {
+            CompressFormat::Zstd => zstd::encode_all(data, i32::from(self.level)).map_err(|e| {
+                RsduError::CompressionError(format!("zstd compression failed: {}", e))
+            }),
+            CompressFormat::Gzip => {
+                let level = flate2::Compression::new(u32::from(self.level.min(9)));
+                let mut encoder = GzEncoder::new(Vec::new(), level);
+                encoder
+                    .write_all(data)
+                    .and_then(|()| encoder.finish())
+                    .map_err(|e| {
+                        RsduError::CompressionError(format!("gzip compression failed: {}", e))
+                    })
+            }
+        }
+    }
+}
+
 impl ExportHandler {
     /// Create a new export handler for JSON format
-    pub fn json<W: Write + Send + 'static>(writer: W, compress: bool) -> Self {
+    pub fn json<W: Write + Send + 'static>(writer: W) -> Self {
         Self {
             writer: Box::new(writer),
             format: ExportFormat::Json,
-            compress,
+            compression: None,
+            min_size: None,
+            pending: None,
         }
     }
 
     /// Create a new export handler for binary format
-    pub fn binary<W: Write + Send + 'static>(writer: W, compress: bool) -> Self {
+    pub fn binary<W: Write + Send + 'static>(writer: W) -> Self {
         Self {
             writer: Box::new(writer),
             format: ExportFormat::Binary,
-            compress,
+            compression: None,
+            min_size: None,
+            pending: None,
         }
     }
 
+    /// Prune subtrees whose total size is below `min_size` from the export
+    pub fn with_min_size(mut self, min_size: Option<u64>) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Compress the output with `compression`, if any
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Write to a temporary file that replaces `target_path` on `finalize()`
+    fn with_pending_rename(mut self, temp_path: PathBuf, target_path: PathBuf) -> Self {
+        self.pending = Some(PendingRename {
+            temp_path,
+            target_path,
+        });
+        self
+    }
+
+    /// Complete the export, moving the temporary file into place
+    ///
+    /// Until this is called the previous contents of the target file are left
+    /// untouched; dropping the handler instead discards the partial output.
+    pub fn finalize(mut self) -> Result<()> {
+        self.writer
+            .flush()
+            .map_err(|e| RsduError::ExportError(format!("Flush failed: {}", e)))?;
+        // Close the temporary file before renaming it
+        self.writer = Box::new(io::sink());
+
+        if let Some(pending) = self.pending.take() {
+            fs::rename(&pending.temp_path, &pending.target_path).map_err(|e| {
+                let _ = fs::remove_file(&pending.temp_path);
+                RsduError::ExportError(format!(
+                    "Failed to move export into place at '{}': {}",
+                    pending.target_path.display(),
+                    e
+                ))
+            })?;
+        }
+
+        Ok(())
+    }
+
     /// Export an entry tree
     pub fn export(&mut self, entry: &Entry) -> Result<()> {
         match self.format {
@@ -51,21 +171,27 @@ impl ExportHandler {
     }
 
     /// Export to JSON format
+    ///
+    /// `export_block_size` has no effect here; `Config::warnings` says so.
     fn export_json(&mut self, entry: &Entry) -> Result<()> {
-        let serializable = entry.to_serializable();
+        let serializable = match self.min_size {
+            Some(min_size) => to_serializable_pruned(entry, min_size),
+            None => entry.to_serializable(),
+        };
         let json = serde_json::to_string_pretty(&serializable)
             .map_err(|e| RsduError::ExportError(format!("JSON serialization failed: {}", e)))?;
 
-        if self.compress {
-            // TODO: Implement compression
-            self.writer
-                .write_all(json.as_bytes())
-                .map_err(|e| RsduError::ExportError(format!("Write failed: {}", e)))?;
-        } else {
-            self.writer
-                .write_all(json.as_bytes())
-                .map_err(|e| RsduError::ExportError(format!("Write failed: {}", e)))?;
-        }
+        let compressed;
+        let output = match self.compression {
+            Some(compression) => {
+                compressed = compression.compress(json.as_bytes())?;
+                &compressed
+            }
+            None => json.as_bytes(),
+        };
+        self.writer
+            .write_all(output)
+            .map_err(|e| RsduError::ExportError(format!("Write failed: {}", e)))?;
 
         self.writer
             .flush()
@@ -83,38 +209,199 @@ impl ExportHandler {
     }
 }
 
+/// Convert an entry tree for export, dropping children smaller than `min_size`
+///
+/// The dropped children of each directory are replaced by a single synthetic
+/// entry carrying their combined size, so totals are unchanged after import.
+fn to_serializable_pruned(entry: &Entry, min_size: u64) -> SerializableEntry {
+    // Post-order walk as in `Entry::to_serializable`, descending only into
+    // the children that are kept
+    let mut pending: Vec<(&Entry, bool)> = vec![(entry, false)];
+    let mut done: Vec<SerializableEntry> = Vec::new();
+
+    while let Some((entry, children_done)) = pending.pop() {
+        if !children_done {
+            pending.push((entry, true));
+            pending.extend(
+                entry
+                    .children
+                    .iter()
+                    .rev()
+                    .filter(|child| child.total_size() >= min_size)
+                    .map(|child| (child.as_ref(), false)),
+            );
+            continue;
+        }
+
+        let mut kept = 0;
+        let mut pruned_items = 0u64;
+        let mut pruned_size = 0u64;
+        let mut pruned_blocks = 0u64;
+        for child in &entry.children {
+            let child_size = child.total_size();
+            if child_size < min_size {
+                pruned_items += child.total_items();
+                pruned_size += child_size;
+                pruned_blocks += child.total_blocks();
+            } else {
+                kept += 1;
+            }
+        }
+
+        let mut children = done.split_off(done.len() - kept);
+        if pruned_items > 0 {
+            children.push(SerializableEntry {
+                id: generate_entry_id(),
+                entry_type: EntryType::Aggregate,
+                name: format!("<{} smaller entries>", pruned_items),
+                raw_name: None,
+                size: pruned_size,
+                blocks: pruned_blocks,
+                device: entry.device,
+                inode: 0,
+                nlink: 1,
+                extended: None,
+                error: None,
+                children: Vec::new(),
+            });
+        }
+
+        let (name, raw_name) = encode_name(&entry.name);
+        done.push(SerializableEntry {
+            id: entry.id,
+            entry_type: entry.entry_type,
+            name,
+            raw_name,
+            size: entry.size,
+            blocks: entry.blocks,
+            device: entry.device,
+            inode: entry.inode,
+            nlink: entry.nlink,
+            extended: entry.extended.clone(),
+            error: entry.error.clone(),
+            children,
+        });
+    }
+
+    done.pop().expect("conversion yields the root entry")
+}
+
+impl Drop for ExportHandler {
+    fn drop(&mut self) {
+        // An export that was never finalized must not leave debris behind
+        if let Some(pending) = self.pending.take() {
+            self.writer = Box::new(io::sink());
+            let _ = fs::remove_file(&pending.temp_path);
+        }
+    }
+}
+
+/// Temporary path next to `target`, so the final rename stays on one filesystem
+fn temp_export_path(target: &Path) -> PathBuf {
+    let file_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "export".to_string());
+    target.with_file_name(format!(".{}.rsdu-tmp.{}", file_name, std::process::id()))
+}
+
+/// Open the temporary file backing an export to `filename`
+fn create_temp_export(filename: &str) -> Result<(BufWriter<File>, PathBuf, PathBuf)> {
+    let target_path = PathBuf::from(filename);
+    let temp_path = temp_export_path(&target_path);
+    let file = File::create(&temp_path).map_err(|e| {
+        RsduError::ExportError(format!(
+            "Failed to create export file '{}': {}",
+            filename, e
+        ))
+    })?;
+
+    Ok((BufWriter::new(file), temp_path, target_path))
+}
+
 /// Setup JSON export to a file
 pub fn setup_json_export(filename: &str) -> Result<ExportHandler> {
-    let writer: Box<dyn Write + Send> = if filename == "-" {
-        Box::new(io::stdout())
-    } else {
-        let file = File::create(filename).map_err(|e| {
-            RsduError::ExportError(format!(
-                "Failed to create export file '{}': {}",
-                filename, e
-            ))
-        })?;
-        Box::new(BufWriter::new(file))
-    };
+    if filename == "-" {
+        return Ok(ExportHandler::json(io::stdout()));
+    }
 
-    Ok(ExportHandler::json(writer, false))
+    let (writer, temp_path, target_path) = create_temp_export(filename)?;
+    Ok(ExportHandler::json(writer).with_pending_rename(temp_path, target_path))
 }
 
 /// Setup binary export to a file
 pub fn setup_binary_export(filename: &str) -> Result<ExportHandler> {
-    let writer: Box<dyn Write + Send> = if filename == "-" {
-        Box::new(io::stdout())
-    } else {
-        let file = File::create(filename).map_err(|e| {
+    if filename == "-" {
+        return Ok(ExportHandler::binary(io::stdout()));
+    }
+
+    let (writer, temp_path, target_path) = create_temp_export(filename)?;
+    Ok(ExportHandler::binary(writer).with_pending_rename(temp_path, target_path))
+}
+
+/// Scan totals written by `--export-stats`, without the tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatsExport {
+    pub total_entries: u64,
+    pub directories: u64,
+    pub files: u64,
+    pub errors: u64,
+    /// Apparent size in bytes
+    pub total_size: u64,
+    /// Disk usage in 512-byte blocks
+    pub total_blocks: u64,
+    pub duration_ms: u64,
+}
+
+impl StatsExport {
+    pub fn new(stats: &ScanStats, duration: Duration) -> Self {
+        Self {
+            total_entries: stats.get_total_entries(),
+            directories: stats.get_directories(),
+            files: stats.get_files(),
+            errors: stats.get_errors(),
+            total_size: stats.get_total_size(),
+            total_blocks: stats.get_total_blocks(),
+            duration_ms: duration.as_millis() as u64,
+        }
+    }
+}
+
+/// Write `stats` as pretty-printed JSON
+pub fn write_stats<W: Write>(mut writer: W, stats: &StatsExport) -> Result<()> {
+    serde_json::to_writer_pretty(&mut writer, stats)
+        .map_err(|e| RsduError::ExportError(format!("JSON serialization failed: {}", e)))?;
+    writeln!(writer).map_err(|e| RsduError::ExportError(format!("Write failed: {}", e)))
+}
+
+/// Write `stats` to `filename`, or to stdout for `-`
+pub fn export_stats(filename: &str, stats: &StatsExport) -> Result<()> {
+    if filename == "-" {
+        return write_stats(io::stdout().lock(), stats);
+    }
+
+    // Like the tree exports, an existing file is only replaced once the new
+    // one is complete
+    let (mut writer, temp_path, target_path) = create_temp_export(filename)?;
+    let written = write_stats(&mut writer, stats).and_then(|()| {
+        writer
+            .flush()
+            .map_err(|e| RsduError::ExportError(format!("Flush failed: {}", e)))
+    });
+    drop(writer);
+    let moved = written.and_then(|()| {
+        fs::rename(&temp_path, &target_path).map_err(|e| {
             RsduError::ExportError(format!(
-                "Failed to create export file '{}': {}",
-                filename, e
+                "Failed to move stats into place at '{}': {}",
+                target_path.display(),
+                e
             ))
-        })?;
-        Box::new(BufWriter::new(file))
-    };
-
-    Ok(ExportHandler::binary(writer, false))
+        })
+    });
+    if moved.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    moved
 }
 
 /// Export entry tree to JSON string
@@ -136,6 +423,7 @@ mod tests {
     use super::*;
     use crate::model::{generate_entry_id, EntryType};
     use std::ffi::OsString;
+    use std::sync::Arc;
 
     #[test]
     fn test_json_export() {
@@ -180,10 +468,274 @@ mod tests {
         assert!(!json.contains("  "));
     }
 
+    #[test]
+    fn test_stats_export() {
+        let stats = ScanStats::new();
+        for _ in 0..3 {
+            stats.increment_entries();
+        }
+        stats.increment_directories();
+        stats.increment_files();
+        stats.increment_files();
+        stats.record_error(crate::model::ErrorCategory::PermissionDenied);
+        stats.add_size(5000);
+        stats.add_blocks(16);
+
+        let export = StatsExport::new(&stats, Duration::from_millis(1234));
+        let mut out = Vec::new();
+        write_stats(&mut out, &export).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "total_entries": 3,
+                "directories": 1,
+                "files": 2,
+                "errors": 1,
+                "total_size": 5000,
+                "total_blocks": 16,
+                "duration_ms": 1234,
+            })
+        );
+
+        // Written to a file, the stats replace what was there in one step
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("stats.json");
+        fs::write(&path, "old").unwrap();
+        export_stats(path.to_str().unwrap(), &export).unwrap();
+        let written: StatsExport =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written, export);
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
     #[test]
     fn test_export_handler_creation() {
         let buffer = Vec::new();
-        let handler = ExportHandler::json(buffer, false);
+        let handler = ExportHandler::json(buffer);
         assert!(matches!(handler.format, ExportFormat::Json));
     }
+
+    #[test]
+    fn test_export_prunes_small_subtrees() {
+        let file = |name: &str, size: u64| {
+            Arc::new(Entry::new(
+                generate_entry_id(),
+                EntryType::File,
+                OsString::from(name),
+                size,
+                size / 512,
+                1,
+                0,
+                1,
+            ))
+        };
+        let mut small_dir = Entry::new(
+            generate_entry_id(),
+            EntryType::Directory,
+            OsString::from("small"),
+            0,
+            0,
+            1,
+            0,
+            1,
+        );
+        small_dir.children = vec![file("a", 100), file("b", 200)];
+        let mut root = Entry::new(
+            generate_entry_id(),
+            EntryType::Directory,
+            OsString::from("root"),
+            0,
+            0,
+            1,
+            0,
+            1,
+        );
+        root.children = vec![file("big", 10_000), Arc::new(small_dir), file("tiny", 50)];
+
+        let pruned = to_serializable_pruned(&root, 1000);
+        let names: Vec<&str> = pruned.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["big", "<4 smaller entries>"]);
+
+        // The dropped entries still count towards the parent's total
+        let imported = Entry::from_serializable(pruned);
+        assert_eq!(imported.total_size(), root.total_size());
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        use crate::model::ExtendedInfo;
+        use chrono::TimeZone;
+
+        let mut file = Entry::new(
+            generate_entry_id(),
+            EntryType::File,
+            OsString::from("data.bin"),
+            4096,
+            8,
+            42,
+            1001,
+            2,
+        );
+        file.extended = Some(ExtendedInfo {
+            mtime: Some(
+                chrono::Utc
+                    .timestamp_opt(1_700_000_000, 123_456_789)
+                    .unwrap(),
+            ),
+            uid: Some(1000),
+            gid: Some(100),
+            mode: Some(0o100644),
+        });
+        let error = Entry::error(
+            generate_entry_id(),
+            OsString::from("locked"),
+            "Error scanning directory: Permission denied (os error 13)".to_string(),
+        );
+        let other_fs = Entry::new(
+            generate_entry_id(),
+            EntryType::OtherFs,
+            OsString::from("mnt"),
+            0,
+            0,
+            43,
+            7,
+            1,
+        );
+        let mut root = Entry::new(
+            generate_entry_id(),
+            EntryType::Directory,
+            OsString::from("root"),
+            4096,
+            8,
+            42,
+            2,
+            3,
+        );
+        root.children = vec![Arc::new(file), Arc::new(error), Arc::new(other_fs)];
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("scan.json");
+        let mut handler = setup_json_export(target.to_str().unwrap()).unwrap();
+        handler.export(&root).unwrap();
+        handler.finalize().unwrap();
+        let replayed = crate::import::import_from_file(&target).unwrap();
+
+        // Every field of every entry comes back as it was recorded
+        let snapshot = |entry: &Entry| serde_json::to_value(entry.to_serializable()).unwrap();
+        assert_eq!(snapshot(&replayed), snapshot(&root));
+        assert_eq!(
+            replayed.children[1].error.as_deref(),
+            Some("Error scanning directory: Permission denied (os error 13)")
+        );
+        let extended = replayed.children[0].extended.as_ref().unwrap();
+        assert_eq!(
+            extended.mtime.unwrap().timestamp_subsec_nanos(),
+            123_456_789
+        );
+        assert_eq!(extended.mode, Some(0o100644));
+    }
+
+    #[test]
+    fn test_compressed_round_trip() {
+        let mut root = Entry::new(
+            generate_entry_id(),
+            EntryType::Directory,
+            OsString::from("root"),
+            4096,
+            8,
+            1,
+            1,
+            2,
+        );
+        root.children = vec![Arc::new(Entry::new(
+            generate_entry_id(),
+            EntryType::File,
+            OsString::from("data.bin"),
+            1 << 20,
+            2048,
+            1,
+            2,
+            1,
+        ))];
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cases = [
+            (CompressFormat::Gzip, &[0x1f, 0x8b][..]),
+            (CompressFormat::Zstd, &[0x28, 0xb5, 0x2f, 0xfd][..]),
+        ];
+        for (format, magic) in cases {
+            let target = temp_dir.path().join(format!("scan-{:?}.json", format));
+            let compression = Compression { format, level: 15 };
+            let mut handler = setup_json_export(target.to_str().unwrap())
+                .unwrap()
+                .with_compression(Some(compression));
+            handler.export(&root).unwrap();
+            handler.finalize().unwrap();
+
+            let written = std::fs::read(&target).unwrap();
+            assert!(written.starts_with(magic), "{:?}", format);
+
+            // Import recognizes the codec by its magic bytes
+            let imported = crate::import::import_from_file(&target).unwrap();
+            assert_eq!(imported.total_size(), root.total_size());
+            assert_eq!(imported.children[0].name_str(), "data.bin");
+        }
+    }
+
+    #[test]
+    fn test_export_finalize_replaces_target() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("out.json");
+        std::fs::write(&target, "old").unwrap();
+
+        let entry = Entry::new(
+            generate_entry_id(),
+            EntryType::File,
+            OsString::from("new.txt"),
+            1,
+            1,
+            1,
+            1,
+            1,
+        );
+        let mut handler = setup_json_export(target.to_str().unwrap()).unwrap();
+        handler.export(&entry).unwrap();
+        // Nothing is visible at the target until the export is finalized
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "old");
+
+        handler.finalize().unwrap();
+        assert!(std::fs::read_to_string(&target)
+            .unwrap()
+            .contains("new.txt"));
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_failed_export_keeps_original() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("out.json");
+        std::fs::write(&target, "previous export").unwrap();
+
+        let entry = Entry::new(
+            generate_entry_id(),
+            EntryType::File,
+            OsString::from("file"),
+            1,
+            1,
+            1,
+            1,
+            1,
+        );
+        // The new export is fully written to disk, then something fails
+        // before it is finalized
+        let mut handler = setup_json_export(target.to_str().unwrap()).unwrap();
+        handler.export(&entry).unwrap();
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+        drop(handler);
+
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "previous export");
+        // The temporary file is cleaned up as well
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
 }
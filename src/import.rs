@@ -3,12 +3,12 @@
 //! This module handles importing previously exported data from JSON and binary formats.
 
 use crate::error::{Result, RsduError};
-use crate::model::{Entry, SerializableEntry};
-// use crate::model::{generate_entry_id, EntryType}; // TODO: Will be used for entry creation
+use crate::model::{generate_entry_id, DeviceId, Entry, EntryType, SerializableEntry};
 use serde_json;
-use std::fs::File;
+use std::collections::HashSet;
+use std::fs::{self, File};
 use std::io::{self, BufReader, Read};
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 
 /// Import data from stdin
@@ -29,12 +29,14 @@ pub fn import_from_file(path: &Path) -> Result<Arc<Entry>> {
     import_from_reader(reader)
 }
 
-/// Import data from any reader
+/// Import data from any reader, compressed or not
 fn import_from_reader<R: Read>(mut reader: R) -> Result<Arc<Entry>> {
-    let mut content = String::new();
+    let mut data = Vec::new();
     reader
-        .read_to_string(&mut content)
+        .read_to_end(&mut data)
         .map_err(|e| RsduError::ImportError(format!("Failed to read import data: {}", e)))?;
+    let content = String::from_utf8(decompress(data)?)
+        .map_err(|e| RsduError::ImportError(format!("Import data is not UTF-8: {}", e)))?;
 
     // Try to parse as JSON
     if let Ok(serializable_entry) = serde_json::from_str::<SerializableEntry>(&content) {
@@ -49,6 +51,28 @@ fn import_from_reader<R: Read>(mut reader: R) -> Result<Arc<Entry>> {
     ))
 }
 
+/// Magic bytes at the start of zstd and gzip streams
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// `data` decompressed if it starts like a compressed export, else as is
+fn decompress(data: Vec<u8>) -> Result<Vec<u8>> {
+    if data.starts_with(&ZSTD_MAGIC) {
+        zstd::decode_all(data.as_slice())
+            .map_err(|e| RsduError::CompressionError(format!("zstd decompression failed: {}", e)))
+    } else if data.starts_with(&GZIP_MAGIC) {
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(data.as_slice())
+            .read_to_end(&mut decompressed)
+            .map_err(|e| {
+                RsduError::CompressionError(format!("gzip decompression failed: {}", e))
+            })?;
+        Ok(decompressed)
+    } else {
+        Ok(data)
+    }
+}
+
 /// Import from JSON string
 pub fn import_from_json(json: &str) -> Result<Arc<Entry>> {
     let serializable_entry: SerializableEntry = serde_json::from_str(json)
@@ -57,6 +81,191 @@ pub fn import_from_json(json: &str) -> Result<Arc<Entry>> {
     Ok(Entry::from_serializable(serializable_entry))
 }
 
+/// Make an imported tree browsable, even if its root is not a directory
+///
+/// An export of a single file has no children to list, so it is wrapped in a
+/// synthetic directory named `container_name` holding the file as its only
+/// entry. Directory roots are returned unchanged.
+pub fn browsable_root(root: Arc<Entry>, container_name: &str) -> Arc<Entry> {
+    if root.entry_type.is_directory() {
+        return root;
+    }
+
+    let mut container = Entry::new(
+        generate_entry_id(),
+        EntryType::Directory,
+        container_name.into(),
+        0,
+        0,
+        root.device,
+        0,
+        1,
+    );
+    container.children.push(root);
+    container.into_linked()
+}
+
+/// Check an imported tree for inconsistencies a scan could not have produced
+///
+/// Sizes are stored per entry and totals are derived by summing children,
+/// so a corrupt or hand-edited dump shows up as children that don't belong
+/// in a sum: entries listed under a file, a name listed twice in one
+/// directory, or sizes so large the totals overflow. Returns one message per
+/// problem found, naming the path it was found at.
+pub fn verify_tree(root: &Entry) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut total_size = Some(0u64);
+    let mut total_blocks = Some(0u64);
+    let mut stack = vec![(root, PathBuf::from(&root.name))];
+
+    while let Some((entry, path)) = stack.pop() {
+        total_size = total_size.and_then(|total| total.checked_add(entry.size));
+        total_blocks = total_blocks.and_then(|total| total.checked_add(entry.blocks));
+
+        if !entry.children.is_empty() && !entry.entry_type.is_directory() {
+            problems.push(format!(
+                "{}: {:?} entry lists {} children",
+                path.display(),
+                entry.entry_type,
+                entry.children.len()
+            ));
+        }
+
+        let mut names = HashSet::new();
+        for child in &entry.children {
+            if !names.insert(&child.name) {
+                problems.push(format!(
+                    "{}: '{}' is listed more than once",
+                    path.display(),
+                    child.name_str()
+                ));
+            }
+            stack.push((child.as_ref(), path.join(&child.name)));
+        }
+    }
+
+    if total_size.is_none() {
+        problems.push(format!("{}: total size overflows", root.name_str()));
+    }
+    if total_blocks.is_none() {
+        problems.push(format!("{}: total blocks overflow", root.name_str()));
+    }
+    problems
+}
+
+/// Import every `*.json` shard in `dir` and stitch them into one tree
+///
+/// Each shard holds one subtree, and the name of its root is that
+/// subtree's path relative to the shard set, so `a/b` ends up under `a`.
+/// Missing intermediate directories are created empty. Shards are applied
+/// shallowest first, so a deeper shard replaces the same subtree in a
+/// shard above it.
+pub fn import_from_shard_dir(dir: &Path) -> Result<Arc<Entry>> {
+    let read_dir = fs::read_dir(dir)
+        .map_err(|e| RsduError::ImportError(format!("Failed to read shard directory: {}", e)))?;
+
+    let mut shard_paths = Vec::new();
+    for dir_entry in read_dir {
+        let path = dir_entry
+            .map_err(|e| RsduError::ImportError(format!("Failed to read shard directory: {}", e)))?
+            .path();
+        if path.extension().is_some_and(|ext| ext == "json") && path.is_file() {
+            shard_paths.push(path);
+        }
+    }
+    if shard_paths.is_empty() {
+        return Err(RsduError::ImportError(format!(
+            "No *.json shards found in {}",
+            dir.display()
+        )));
+    }
+
+    let mut shards = Vec::with_capacity(shard_paths.len());
+    for path in &shard_paths {
+        let shard = import_from_file(path).map_err(|e| {
+            RsduError::ImportError(format!("Failed to import shard {}: {}", path.display(), e))
+        })?;
+        shards.push((shard_components(&shard.name_str()), shard));
+    }
+    shards.sort_by(|a, b| a.0.len().cmp(&b.0.len()).then_with(|| a.0.cmp(&b.0)));
+
+    let root_name = dir
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_else(|| dir.as_os_str().to_os_string());
+    let mut root = synthetic_directory(root_name, 0);
+    for (components, shard) in shards {
+        if components.is_empty() {
+            return Err(RsduError::ImportError(format!(
+                "Shard root '{}' has no usable path",
+                shard.name_str()
+            )));
+        }
+        attach_shard(&mut root, &components, shard);
+    }
+
+    // Shards and the directories above them were put together piecemeal
+    let root = Arc::new(root);
+    Entry::link_subtree(&root);
+    Ok(root)
+}
+
+/// Split a shard's recorded path into the names leading to it
+fn shard_components(recorded: &str) -> Vec<String> {
+    Path::new(recorded)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Empty directory standing in for a path no shard covers itself
+fn synthetic_directory(name: std::ffi::OsString, device: DeviceId) -> Entry {
+    Entry::new(
+        generate_entry_id(),
+        EntryType::Directory,
+        name,
+        0,
+        0,
+        device,
+        0,
+        1,
+    )
+}
+
+/// Place `shard` at `components` below `dir`, creating directories on the way
+fn attach_shard(dir: &mut Entry, components: &[String], shard: Arc<Entry>) {
+    let name = components[0].as_str();
+    let existing = dir
+        .children
+        .iter()
+        .position(|child| child.name_str() == name);
+
+    if components.len() == 1 {
+        let mut shard = Arc::unwrap_or_clone(shard);
+        shard.name = name.into();
+        let shard = shard.into_linked();
+        match existing {
+            Some(index) => dir.children[index] = shard,
+            None => dir.children.push(shard),
+        }
+        return;
+    }
+
+    let index = existing.unwrap_or_else(|| {
+        dir.children
+            .push(Arc::new(synthetic_directory(name.into(), shard.device)));
+        dir.children.len() - 1
+    });
+    attach_shard(
+        Arc::make_mut(&mut dir.children[index]),
+        &components[1..],
+        shard,
+    );
+}
+
 /// Import from binary data
 pub fn import_from_binary(_data: &[u8]) -> Result<Arc<Entry>> {
     // TODO: Implement binary format parsing
@@ -96,6 +305,156 @@ mod tests {
         assert_eq!(entry.size, 1024);
     }
 
+    #[test]
+    fn test_single_file_import_is_browsable() {
+        let json = r#"{
+            "id": 1,
+            "entry_type": "File",
+            "name": "disk.img",
+            "size": 4096,
+            "blocks": 8,
+            "device": 1,
+            "inode": 99,
+            "nlink": 1,
+            "extended": null,
+            "error": null,
+            "children": []
+        }"#;
+
+        let root = browsable_root(import_from_json(json).unwrap(), "scan.json");
+        assert_eq!(root.entry_type, EntryType::Directory);
+        assert_eq!(root.name_str(), "scan.json");
+        assert_eq!(root.children.len(), 1);
+        assert_eq!(root.children[0].name_str(), "disk.img");
+        assert_eq!(root.total_size(), 4096);
+
+        // Directory roots are browsed as they are
+        let dir = Arc::new(Entry::new(
+            2,
+            EntryType::Directory,
+            "dir".into(),
+            0,
+            0,
+            1,
+            1,
+            1,
+        ));
+        assert!(Arc::ptr_eq(&browsable_root(dir.clone(), "scan.json"), &dir));
+    }
+
+    #[test]
+    fn test_import_shard_dir() {
+        let shard = |name: &str, files: &[(&str, u64)]| {
+            let mut dir = synthetic_directory(name.into(), 1);
+            for (file, size) in files {
+                dir.add_child(Entry::new(
+                    generate_entry_id(),
+                    EntryType::File,
+                    (*file).into(),
+                    *size,
+                    0,
+                    1,
+                    0,
+                    1,
+                ));
+            }
+            crate::export::export_to_json_string(&dir).unwrap()
+        };
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let shards = temp_dir.path().join("shards");
+        fs::create_dir(&shards).unwrap();
+        fs::write(shards.join("1.json"), shard("home", &[("notes", 10)])).unwrap();
+        fs::write(shards.join("2.json"), shard("srv/www", &[("index", 20)])).unwrap();
+        fs::write(shards.join("README"), "not a shard").unwrap();
+
+        let root = import_from_shard_dir(&shards).unwrap();
+        assert_eq!(root.name_str(), "shards");
+        assert_eq!(root.total_size(), 30);
+
+        let mut names: Vec<_> = root.children.iter().map(|c| c.name_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["home", "srv"]);
+        let srv = root
+            .children
+            .iter()
+            .find(|c| c.name_str() == "srv")
+            .unwrap();
+        assert_eq!(srv.children[0].name_str(), "www");
+        assert_eq!(srv.children[0].children[0].name_str(), "index");
+        assert_eq!(
+            srv.children[0].children[0].full_path(),
+            Path::new("shards/srv/www/index")
+        );
+
+        let empty = temp_dir.path().join("empty");
+        fs::create_dir(&empty).unwrap();
+        assert!(import_from_shard_dir(&empty).is_err());
+    }
+
+    #[test]
+    fn test_verify_inconsistent_tree() {
+        let file = |name: &str, size: u64| {
+            Entry::new(
+                generate_entry_id(),
+                EntryType::File,
+                name.into(),
+                size,
+                0,
+                1,
+                0,
+                1,
+            )
+        };
+
+        let mut root = synthetic_directory("root".into(), 1);
+        root.add_child(file("a", 10));
+        assert!(verify_tree(&root).is_empty());
+
+        // A file claiming children, a duplicated name and an absurd size
+        let mut bogus = file("b", 5);
+        bogus.add_child(file("inner", 7));
+        root.add_child(bogus);
+        root.add_child(file("a", 10));
+        root.add_child(file("huge", u64::MAX));
+
+        let problems = verify_tree(&root);
+        assert_eq!(problems.len(), 3);
+        assert!(problems.contains(&"root/b: File entry lists 1 children".to_string()));
+        assert!(problems.contains(&"root: 'a' is listed more than once".to_string()));
+        assert!(problems.contains(&"root: total size overflows".to_string()));
+    }
+
+    #[test]
+    fn test_non_utf8_name_round_trip() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let name = OsString::from_vec(b"caf\xe9-\xff.txt".to_vec());
+        let mut dir = Entry::new(1, EntryType::Directory, "dir".into(), 0, 0, 1, 1, 1);
+        dir.children = vec![Arc::new(Entry::new(
+            2,
+            EntryType::File,
+            name.clone(),
+            10,
+            1,
+            1,
+            2,
+            1,
+        ))];
+
+        let json = serde_json::to_string(&dir.to_serializable()).unwrap();
+        let imported = import_from_json(&json).unwrap();
+        assert_eq!(imported.children[0].name, name);
+
+        // Valid names are exported as before, with no raw bytes alongside
+        let exported: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(exported.get("raw_name").is_none());
+        let child = &exported["children"][0];
+        assert_eq!(child["name"], "caf\u{fffd}-\u{fffd}.txt");
+        assert!(child["raw_name"].is_string());
+    }
+
     #[test]
     fn test_invalid_json() {
         let invalid_json = "{ invalid json }";
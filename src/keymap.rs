@@ -0,0 +1,324 @@
+//! Remappable browser keys
+//!
+//! Config files can rebind browser actions with `bind.<action> = <keys>`,
+//! e.g. `bind.quit = x` or `bind.delete = D, Delete`. The browser keeps
+//! matching on its built-in keys; `resolve` translates a pressed key into
+//! the built-in key of the action it is bound to.
+
+use anyhow::Result;
+use crossterm::event::KeyCode;
+use std::collections::HashMap;
+
+/// A browser action that can be bound to keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Help,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Enter,
+    Back,
+    Expand,
+    ShallowSizes,
+    Refresh,
+    RefreshEntry,
+    Delete,
+    EditDeleteCommand,
+    SaveReport,
+    Bookmark,
+    Info,
+    SharedColumn,
+    GroupCounts,
+    Filter,
+    DirsFirst,
+}
+
+/// Actions to bindings, as stored in `Config::keybindings`
+pub type KeyBindings = HashMap<Action, Vec<KeyCode>>;
+
+impl Action {
+    pub const ALL: [Action; 23] = [
+        Action::Quit,
+        Action::Help,
+        Action::Up,
+        Action::Down,
+        Action::PageUp,
+        Action::PageDown,
+        Action::Top,
+        Action::Bottom,
+        Action::Enter,
+        Action::Back,
+        Action::Expand,
+        Action::ShallowSizes,
+        Action::Refresh,
+        Action::RefreshEntry,
+        Action::Delete,
+        Action::EditDeleteCommand,
+        Action::SaveReport,
+        Action::Bookmark,
+        Action::Info,
+        Action::SharedColumn,
+        Action::GroupCounts,
+        Action::Filter,
+        Action::DirsFirst,
+    ];
+
+    /// Name used in `bind.<name>` config options
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Help => "help",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::PageUp => "page-up",
+            Action::PageDown => "page-down",
+            Action::Top => "top",
+            Action::Bottom => "bottom",
+            Action::Enter => "enter",
+            Action::Back => "back",
+            Action::Expand => "expand",
+            Action::ShallowSizes => "shallow-sizes",
+            Action::Refresh => "refresh",
+            Action::RefreshEntry => "refresh-entry",
+            Action::Delete => "delete",
+            Action::EditDeleteCommand => "edit-delete-command",
+            Action::SaveReport => "save-report",
+            Action::Bookmark => "bookmark",
+            Action::Info => "info",
+            Action::SharedColumn => "shared-column",
+            Action::GroupCounts => "group-counts",
+            Action::Filter => "filter",
+            Action::DirsFirst => "dirs-first",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    /// The keys the browser handles this action on; the first is canonical
+    pub fn default_keys(self) -> &'static [KeyCode] {
+        match self {
+            Action::Quit => &[KeyCode::Char('q'), KeyCode::Esc],
+            Action::Help => &[KeyCode::Char('?'), KeyCode::F(1)],
+            Action::Up => &[KeyCode::Up, KeyCode::Char('k')],
+            Action::Down => &[KeyCode::Down, KeyCode::Char('j')],
+            Action::PageUp => &[KeyCode::PageUp],
+            Action::PageDown => &[KeyCode::PageDown],
+            Action::Top => &[KeyCode::Home, KeyCode::Char('g')],
+            Action::Bottom => &[KeyCode::End, KeyCode::Char('G')],
+            Action::Enter => &[KeyCode::Enter, KeyCode::Right, KeyCode::Char('l')],
+            Action::Back => &[KeyCode::Left, KeyCode::Char('h'), KeyCode::Backspace],
+            Action::Expand => &[KeyCode::Char(' ')],
+            Action::ShallowSizes => &[KeyCode::Char('S')],
+            Action::Refresh => &[KeyCode::Char('R')],
+            Action::RefreshEntry => &[KeyCode::Char('r')],
+            Action::Delete => &[KeyCode::Char('d')],
+            Action::EditDeleteCommand => &[KeyCode::Char('E')],
+            Action::SaveReport => &[KeyCode::Char('c')],
+            Action::Bookmark => &[KeyCode::Char('m')],
+            Action::Info => &[KeyCode::Char('i')],
+            Action::SharedColumn => &[KeyCode::Char('u')],
+            Action::GroupCounts => &[KeyCode::Char('#')],
+            Action::Filter => &[KeyCode::Char('/')],
+            Action::DirsFirst => &[KeyCode::Char('t')],
+        }
+    }
+}
+
+/// Bindings matching the browser's built-in keys
+pub fn default_bindings() -> KeyBindings {
+    Action::ALL
+        .into_iter()
+        .map(|action| (action, action.default_keys().to_vec()))
+        .collect()
+}
+
+/// Parse a key name: a single character, or one of `Esc`, `Enter`, `Space`,
+/// `Tab`, `Backspace`, `Delete`, `Up`, `Down`, `Left`, `Right`, `PageUp`,
+/// `PageDown`, `Home`, `End` and `F1`..`F12` (case-insensitive)
+pub fn parse_key(name: &str) -> Result<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(c));
+    }
+
+    let key = match name.to_ascii_lowercase().as_str() {
+        "esc" | "escape" => KeyCode::Esc,
+        "enter" | "return" => KeyCode::Enter,
+        "space" => KeyCode::Char(' '),
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        lower => match lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+            Some(n @ 1..=12) => KeyCode::F(n),
+            _ => return Err(anyhow::anyhow!("Unknown key: {}", name)),
+        },
+    };
+    Ok(key)
+}
+
+/// Name of a key as `parse_key` accepts it, for messages
+fn key_name(key: KeyCode) -> String {
+    match key {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        KeyCode::Esc => "Esc".to_string(),
+        KeyCode::Enter => "Enter".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::Backspace => "Backspace".to_string(),
+        KeyCode::Delete => "Delete".to_string(),
+        KeyCode::Up => "Up".to_string(),
+        KeyCode::Down => "Down".to_string(),
+        KeyCode::Left => "Left".to_string(),
+        KeyCode::Right => "Right".to_string(),
+        KeyCode::PageUp => "PageUp".to_string(),
+        KeyCode::PageDown => "PageDown".to_string(),
+        KeyCode::Home => "Home".to_string(),
+        KeyCode::End => "End".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Parse a comma-separated list of keys for a `bind.*` option
+pub fn parse_keys(value: &str) -> Result<Vec<KeyCode>> {
+    // A lone comma binds the comma key itself
+    if value == "," {
+        return Ok(vec![KeyCode::Char(',')]);
+    }
+    value.split(',').map(|key| parse_key(key.trim())).collect()
+}
+
+/// The keys `action` is bound to
+fn bound_keys(bindings: &KeyBindings, action: Action) -> &[KeyCode] {
+    bindings
+        .get(&action)
+        .map(|keys| keys.as_slice())
+        .unwrap_or(action.default_keys())
+}
+
+/// Every action, those bound to keys of their own before those left on defaults
+///
+/// A key is looked up in this order, so a `bind.*` option wins over another
+/// action's default key.
+fn actions_by_precedence(bindings: &KeyBindings) -> impl Iterator<Item = Action> + '_ {
+    let rebound = |action: Action| bound_keys(bindings, action) != action.default_keys();
+    let explicit = Action::ALL
+        .into_iter()
+        .filter(move |&action| rebound(action));
+    let defaults = Action::ALL
+        .into_iter()
+        .filter(move |&action| !rebound(action));
+    explicit.chain(defaults)
+}
+
+/// Describe each key bound to more than one action, for warnings at startup
+///
+/// The key goes to the first action `actions_by_precedence` yields.
+pub fn conflicts(bindings: &KeyBindings) -> Vec<String> {
+    let mut owners: Vec<(KeyCode, Action)> = Vec::new();
+    let mut conflicts = Vec::new();
+    for action in actions_by_precedence(bindings) {
+        for &key in bound_keys(bindings, action) {
+            match owners.iter().find(|(owned, _)| *owned == key) {
+                Some((_, owner)) => conflicts.push(format!(
+                    "Key {} is bound to both {} and {}; it does {}",
+                    key_name(key),
+                    owner.name(),
+                    action.name(),
+                    owner.name()
+                )),
+                None => owners.push((key, action)),
+            }
+        }
+    }
+    conflicts
+}
+
+/// Translate a pressed key into the built-in key the browser handles
+///
+/// A key bound to an action becomes that action's canonical key, with keys
+/// given in `bind.*` options taking precedence over default ones. A built-in
+/// key whose action was bound elsewhere is dropped (`None`), and any other
+/// key (such as the bookmark slots `1`-`9`) is passed through unchanged.
+pub fn resolve(bindings: &KeyBindings, key: KeyCode) -> Option<KeyCode> {
+    if let Some(action) =
+        actions_by_precedence(bindings).find(|&action| bound_keys(bindings, action).contains(&key))
+    {
+        return Some(action.default_keys()[0]);
+    }
+    if Action::ALL
+        .into_iter()
+        .any(|action| action.default_keys().contains(&key))
+    {
+        return None;
+    }
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_remapped_keys() {
+        let mut bindings = default_bindings();
+        assert_eq!(resolve(&bindings, KeyCode::Esc), Some(KeyCode::Char('q')));
+        assert_eq!(
+            resolve(&bindings, KeyCode::Char('7')),
+            Some(KeyCode::Char('7'))
+        );
+
+        bindings.insert(Action::Quit, parse_keys("x").unwrap());
+        bindings.insert(Action::Delete, parse_keys("D, Delete").unwrap());
+        assert_eq!(
+            resolve(&bindings, KeyCode::Char('x')),
+            Some(KeyCode::Char('q'))
+        );
+        assert_eq!(resolve(&bindings, KeyCode::Char('q')), None);
+        assert_eq!(resolve(&bindings, KeyCode::Esc), None);
+        assert_eq!(
+            resolve(&bindings, KeyCode::Delete),
+            Some(KeyCode::Char('d'))
+        );
+        assert_eq!(resolve(&bindings, KeyCode::Char('d')), None);
+        assert_eq!(resolve(&bindings, KeyCode::Char('j')), Some(KeyCode::Down));
+        assert!(conflicts(&bindings).is_empty());
+
+        // A bound key wins over the default key of an action earlier in the list
+        bindings.insert(Action::DirsFirst, parse_keys("q").unwrap());
+        bindings.insert(Action::Quit, Action::Quit.default_keys().to_vec());
+        assert_eq!(
+            resolve(&bindings, KeyCode::Char('q')),
+            Some(KeyCode::Char('t'))
+        );
+        assert_eq!(resolve(&bindings, KeyCode::Esc), Some(KeyCode::Char('q')));
+        assert_eq!(
+            conflicts(&bindings),
+            vec!["Key q is bound to both dirs-first and quit; it does dirs-first"]
+        );
+    }
+
+    #[test]
+    fn test_parse_key_names() {
+        assert_eq!(parse_key("Space").unwrap(), KeyCode::Char(' '));
+        assert_eq!(parse_key("pagedown").unwrap(), KeyCode::PageDown);
+        assert_eq!(parse_key("F5").unwrap(), KeyCode::F(5));
+        assert_eq!(parse_key("Q").unwrap(), KeyCode::Char('Q'));
+        assert!(parse_key("F13").is_err());
+        assert!(parse_key("hyper").is_err());
+    }
+}
@@ -4,65 +4,173 @@
 //! fast directory scanning and an interactive terminal interface for
 //! exploring disk usage.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 mod browser;
 mod cli;
 mod config;
+mod delete;
 mod error;
 mod export;
 mod import;
+mod keymap;
 mod model;
+mod navigation;
+mod progress;
+mod report;
 mod scanner;
+mod size;
 mod tui;
 
 mod utils;
 
 use cli::Args;
 use config::Config;
-use scanner::scan_directory_with_progress;
+use error::{ResultExt, RsduError};
+use model::Entry;
+use model::ScanStats;
+use scanner::scan_directory_counting;
 use tui::TuiApp;
 
 /// Main entry point for rsdu
-fn main() -> Result<()> {
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+/// Exit status for an error that ended the run, so scripts can tell them apart
+///
+/// 2 means the path to scan was not found, 3 that it could not be read for
+/// lack of permission, 4 that importing or exporting failed, 130 that the
+/// scan was stopped with Ctrl-C, and 1 anything else. The first `RsduError`
+/// behind the error decides.
+fn exit_code(error: &anyhow::Error) -> u8 {
+    let cause = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<RsduError>());
+    match cause {
+        Some(RsduError::PathNotFound { .. }) => 2,
+        Some(RsduError::PermissionDenied { .. }) => 3,
+        Some(
+            RsduError::ImportError(_) | RsduError::ExportError(_) | RsduError::CompressionError(_),
+        ) => 4,
+        Some(RsduError::UserCancelled) => 130,
+        _ => 1,
+    }
+}
+
+/// Parse the arguments, then scan, import, report or browse as they say
+fn run() -> Result<()> {
     // Parse command line arguments
     let args = Args::parse();
 
+    if args.build_info {
+        print!("{}", build_info());
+        return Ok(());
+    }
+
     // Initialize configuration from args and config files
     let mut config = Config::from_args(&args)?;
+    for warning in config.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
 
     // Handle version and help (clap handles these automatically)
 
     // If we're importing from a file, handle that
     if let Some(import_file) = &args.import_file {
-        return handle_import(import_file, &config);
+        return handle_import(import_file, args.verify_import, &config);
+    }
+    if let Some(import_dir) = &args.import_dir {
+        let root = import::import_from_shard_dir(import_dir)?;
+        if args.verify_import {
+            verify_imported(&root)?;
+        }
+        let source_name = root.name_str();
+        return show_imported(root, &source_name, &config);
     }
 
     // If we're exporting, set up export and continue with scan
-    let _export_handler = if let Some(export_file) = &args.export_json {
-        Some(export::setup_json_export(export_file)?)
+    let export_handler = if let Some(export_file) = &config.export_json {
+        Some(
+            export::setup_json_export(export_file)?
+                .with_min_size(config.export_min_size)
+                .with_compression(export::Compression::from_config(&config)),
+        )
     } else if let Some(export_file) = &args.export_binary {
-        Some(export::setup_binary_export(export_file)?)
+        Some(
+            export::setup_binary_export(export_file)?
+                .with_min_size(config.export_min_size)
+                .with_compression(export::Compression::from_config(&config)),
+        )
     } else {
         None
     };
 
-    // Determine the directory to scan
-    let scan_path = args
-        .directory
-        .as_ref()
-        .map(|p| p.as_path())
-        .unwrap_or_else(|| std::path::Path::new("."));
+    // Determine the directories to scan, making them absolute and
+    // dereferencing symlinked arguments if requested
+    let resolve = |path: &Path| {
+        utils::resolve_scan_root(path, config.follow_arg_symlinks)
+            .with_path(path)
+            .with_context(|| format!("Cannot access directory '{}'", path.display()))
+    };
+    let (scan_paths, root_path) = if let Some(targets_file) = &args.targets_from {
+        let scan_paths = config
+            .scan_targets
+            .iter()
+            .map(|target| resolve(target))
+            .collect::<Result<Vec<_>>>()?;
+        let (scan_paths, nested) = outermost_targets(scan_paths);
+        for (target, outer) in nested {
+            eprintln!(
+                "Warning: skipping {}, which is already scanned as part of {}",
+                target.display(),
+                outer.display()
+            );
+        }
+        // A lone target is the root of the tree, so reports name paths below it
+        let root_path = match scan_paths.as_slice() {
+            [scan_path] => scan_path.clone(),
+            _ => targets_file.clone(),
+        };
+        (scan_paths, root_path)
+    } else {
+        let scan_path = args.directory.as_deref().unwrap_or_else(|| Path::new("."));
+        let scan_path = resolve(scan_path)?;
+        (vec![scan_path.clone()], scan_path)
+    };
 
-    // Canonicalize the path
-    let scan_path = scan_path
-        .canonicalize()
-        .map_err(|e| anyhow::anyhow!("Cannot access directory '{}': {}", scan_path.display(), e))?;
+    if let Some(runs) = args.bench {
+        // Timings go to stderr and nothing else is shown
+        config.scan_ui = Some(config::ScanUi::None);
+        return run_bench(
+            &scan_paths,
+            &root_path,
+            &config,
+            runs,
+            &mut std::io::stderr(),
+        );
+    }
 
     // Update config based on scan mode
-    if args.export_json.is_some() || args.export_binary.is_some() {
+    if config.report_over.is_some() || config.largest.is_some() || config.tree || config.du_format {
+        // The report goes to stdout, so keep the scan itself quiet
+        config.scan_ui = Some(config::ScanUi::None);
+    } else if config.export_json.is_some()
+        || config.export_binary.is_some()
+        || config.export_stats.is_some()
+    {
         if config.scan_ui.is_none() {
             config.scan_ui = Some(if atty::is(atty::Stream::Stdout) {
                 config::ScanUi::Line
@@ -76,56 +184,330 @@ fn main() -> Result<()> {
     }
 
     // Start the main application flow
-    run_application(scan_path, config)
+    run_application(scan_paths, root_path, config, export_handler)
+}
+
+/// Drop scan targets that lie inside another target, or repeat one
+///
+/// Scanning them as well would count their contents twice. Returns the
+/// targets kept, in their original order, and each dropped target with the
+/// one it lies in.
+fn outermost_targets(targets: Vec<PathBuf>) -> (Vec<PathBuf>, Vec<(PathBuf, PathBuf)>) {
+    let mut kept: Vec<PathBuf> = Vec::new();
+    let mut nested = Vec::new();
+    for (index, target) in targets.iter().enumerate() {
+        // A repeated target is kept at its first occurrence; the outermost
+        // target containing this one is the one that is kept
+        let outer = targets
+            .iter()
+            .enumerate()
+            .filter(|(other_index, other)| {
+                if *other == target {
+                    *other_index < index
+                } else {
+                    target.starts_with(other)
+                }
+            })
+            .min_by_key(|(_, other)| other.components().count());
+        match outer {
+            Some((_, outer)) => nested.push((target.clone(), outer.clone())),
+            None => kept.push(target.clone()),
+        }
+    }
+    (kept, nested)
 }
 
 /// Handle importing data from a file
-fn handle_import(import_file: &str, config: &Config) -> Result<()> {
-    let root = if import_file == "-" {
-        import::import_from_stdin()?
+fn handle_import(import_file: &str, verify: bool, config: &Config) -> Result<()> {
+    let (root, source_name) = if import_file == "-" {
+        (import::import_from_stdin()?, "stdin".to_string())
     } else {
         let path = PathBuf::from(import_file);
-        import::import_from_file(&path)?
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        (import::import_from_file(&path)?, name)
     };
+    if verify {
+        verify_imported(&root)?;
+    }
+    show_imported(root, &source_name, config)
+}
+
+/// Report every inconsistency in an imported tree, failing if there are any
+fn verify_imported(root: &Entry) -> Result<()> {
+    let problems = import::verify_tree(root);
+    for problem in &problems {
+        eprintln!("Import verification: {}", problem);
+    }
+    if !problems.is_empty() {
+        return Err(RsduError::ImportError(format!(
+            "Imported data is inconsistent ({} problems found)",
+            problems.len()
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Report on or browse an imported tree
+fn show_imported(root: Arc<Entry>, source_name: &str, config: &Config) -> Result<()> {
+    if let Some(threshold) = config.report_over {
+        let root_path = PathBuf::from(&root.name);
+        report::print_report_over(&root, &root_path, threshold, config)?;
+        return Ok(());
+    }
+
+    if let Some(count) = config.largest {
+        let root_path = PathBuf::from(&root.name);
+        report::print_largest(&root, &root_path, count, config)?;
+        return Ok(());
+    }
+
+    if config.tree {
+        let root_path = PathBuf::from(&root.name);
+        report::print_tree(&root, &root_path, config)?;
+        return Ok(());
+    }
+
+    if config.du_format {
+        let root_path = PathBuf::from(&root.name);
+        report::print_du(&root, &root_path, config)?;
+        return Ok(());
+    }
 
     // Start the browser with imported data
+    let root = import::browsable_root(root, source_name);
     browser::run_browser(root, config.clone()).map_err(|e| anyhow::anyhow!("{}", e))
 }
 
-/// Main application flow: scan and then browse (or export)
-fn run_application(scan_path: PathBuf, config: Config) -> Result<()> {
-    // Check if we should use TUI mode
-    let use_tui = config.scan_ui != Some(config::ScanUi::None)
+/// Scan `scan_paths`, gathering several under one synthetic root
+///
+/// A single path is scanned as usual. With more, each is scanned on its own
+/// and added to a root named `root_name`, keeping its full path as its name
+/// so reports and the browser can locate it on disk. Every scan counts into
+/// `stats`.
+fn scan_roots(
+    scan_paths: &[PathBuf],
+    root_name: &Path,
+    config: &Config,
+    mut progress: impl FnMut() -> Option<scanner::ProgressCallback>,
+    partial: Option<scanner::PartialCallback>,
+    stats: &Arc<ScanStats>,
+) -> error::Result<Arc<Entry>> {
+    if let [scan_path] = scan_paths {
+        return scan_directory_counting(scan_path, config, progress(), partial, stats.clone());
+    }
+
+    let mut root = Entry::new(
+        model::generate_entry_id(),
+        model::EntryType::Directory,
+        root_name.as_os_str().to_os_string(),
+        0,
+        0,
+        0,
+        0,
+        1,
+    );
+    for scan_path in scan_paths {
+        let mut target = Arc::unwrap_or_clone(scan_directory_counting(
+            scan_path,
+            config,
+            progress(),
+            None,
+            stats.clone(),
+        )?);
+        target.name = scan_path.as_os_str().to_os_string();
+        root.children.push(target.into_linked());
+    }
+    Ok(root.into_linked())
+}
+
+/// Why the scan root itself could not be scanned, if it could not
+///
+/// An unreadable root comes back as an error entry with nothing below it;
+/// browsing or reporting on it would only show an empty tree. The scan keeps
+/// the I/O error it hit on the root in `stats`, so its kind and message
+/// survive.
+fn root_scan_error(root: &Entry, root_path: &Path, stats: &ScanStats) -> Option<RsduError> {
+    if !root.has_error() {
+        return None;
+    }
+    Some(match stats.take_root_error() {
+        Some(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            RsduError::permission_denied(root_path, e)
+        }
+        Some(e) if e.kind() == io::ErrorKind::NotFound => RsduError::path_not_found(root_path),
+        Some(e) => RsduError::scan_error(root_path, e.to_string()),
+        None => RsduError::scan_error(root_path, root.error.as_deref().unwrap_or("Unknown error")),
+    })
+}
+
+/// Ctrl-C handling for a scan without the browser, undone when dropped
+///
+/// Instead of killing the process, Ctrl-C asks the scan to stop. It then
+/// unwinds like any failed scan, ending its progress line on the way, and
+/// the run exits with status 130. A second Ctrl-C kills the process as usual.
+struct StopOnInterrupt {
+    previous: libc::sigaction,
+}
+
+impl StopOnInterrupt {
+    fn install() -> Option<Self> {
+        extern "C" fn on_interrupt(_signal: libc::c_int) {
+            scanner::request_stop();
+        }
+
+        // SAFETY: sigaction is plain data, for which all zeroes is a valid value
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        let mut previous: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART | libc::SA_RESETHAND;
+        // SAFETY: both structs are valid for the calls, and the handler only
+        // stores to an atomic, which is async-signal-safe
+        let installed = unsafe {
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(libc::SIGINT, &action, &mut previous) == 0
+        };
+        installed.then_some(Self { previous })
+    }
+}
+
+impl Drop for StopOnInterrupt {
+    fn drop(&mut self) {
+        // SAFETY: `previous` was filled in by sigaction when installing
+        unsafe {
+            libc::sigaction(libc::SIGINT, &self.previous, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Whether the scan runs under the full-screen browser
+///
+/// Exports, scan profiles and hardlink stats are written by the plain scan
+/// path, so they keep it even on a terminal; the browser would never hand its
+/// tree back to them.
+fn uses_tui(config: &Config, stdout_is_tty: bool) -> bool {
+    config.scan_ui != Some(config::ScanUi::None)
         && config.export_json.is_none()
         && config.export_binary.is_none()
-        && atty::is(atty::Stream::Stdout);
+        && config.export_stats.is_none()
+        && config.profile_scan.is_none()
+        && config.hardlink_stats != Some(true)
+        && stdout_is_tty
+}
 
-    if use_tui {
+/// Main application flow: scan and then browse (or export)
+///
+/// `root_path` is where the scanned tree is reported to live: the scanned
+/// directory itself, or the targets file when scanning several roots. The
+/// scanned tree is written to `export` and the export finished, if given.
+fn run_application(
+    scan_paths: Vec<PathBuf>,
+    root_path: PathBuf,
+    config: Config,
+    export: Option<export::ExportHandler>,
+) -> Result<()> {
+    if uses_tui(&config, atty::is(atty::Stream::Stdout)) {
         // Use the new TUI system
         let mut app = TuiApp::new(config.clone())?;
-        let sender = app.start_scan(scan_path.display().to_string())?;
+        let sender = app.start_scan(root_path.display().to_string())?;
 
         // Start scanning in background thread
-        let scan_path_clone = scan_path.clone();
         let config_clone = config.clone();
         std::thread::spawn(move || {
-            if let Err(e) =
-                scan_directory_with_progress(&scan_path_clone, &config_clone, Some(sender.clone()))
-            {
-                let _ = sender.send(tui::ScanMessage::Error {
-                    message: format!("Scan failed: {}", e),
+            let progress = || {
+                let progress_sender = sender.clone();
+                let progress: scanner::ProgressCallback = Box::new(move |stats, path| {
+                    let _ = progress_sender.send(tui::ScanMessage::Progress {
+                        current_path: path.display().to_string(),
+                        stats: stats.clone(),
+                    });
                 });
-            }
+                Some(progress)
+            };
+
+            let partial_sender = sender.clone();
+            let partial: Option<scanner::PartialCallback> = if config_clone.incremental {
+                Some(Box::new(move |update| {
+                    let _ = partial_sender.send(tui::ScanMessage::Partial(update));
+                }))
+            } else {
+                None
+            };
+
+            let stats = Arc::new(ScanStats::new());
+            let message = match scan_roots(
+                &scan_paths,
+                &root_path,
+                &config_clone,
+                progress,
+                partial,
+                &stats,
+            ) {
+                Ok(root) => match root_scan_error(&root, &root_path, &stats) {
+                    Some(e) => tui::ScanMessage::Error {
+                        message: e.to_string(),
+                    },
+                    None => tui::ScanMessage::Complete { root },
+                },
+                Err(e) => tui::ScanMessage::Error {
+                    message: format!("Scan failed: {}", e),
+                },
+            };
+            let _ = sender.send(message);
         });
 
         // Run the TUI
         app.run()?;
     } else {
         // Use the old non-TUI mode
-        let root = scanner::scan_directory(&scan_path, &config)?;
+        let stats = Arc::new(ScanStats::new());
+        let started = Instant::now();
+        let stop_on_interrupt = StopOnInterrupt::install();
+        let root = scan_roots(&scan_paths, &root_path, &config, || None, None, &stats)?;
+        drop(stop_on_interrupt);
+        let scan_time = started.elapsed();
+        if let Some(e) = root_scan_error(&root, &root_path, &stats) {
+            return Err(e.into());
+        }
+
+        if let Some(stats_file) = &config.export_stats {
+            export::export_stats(stats_file, &export::StatsExport::new(&stats, scan_time))?;
+        }
+
+        if let Some(mut export) = export {
+            export.export(&root)?;
+            export.finalize()?;
+        }
+
+        if let Some(threshold) = config.report_over {
+            report::print_report_over(&root, &root_path, threshold, &config)?;
+            return Ok(());
+        }
+
+        if let Some(count) = config.largest {
+            report::print_largest(&root, &root_path, count, &config)?;
+            return Ok(());
+        }
+
+        if config.tree {
+            report::print_tree(&root, &root_path, &config)?;
+            return Ok(());
+        }
+
+        if config.du_format {
+            report::print_du(&root, &root_path, &config)?;
+            return Ok(());
+        }
 
         // If we're just exporting, we're done
-        if config.export_json.is_some() || config.export_binary.is_some() {
+        if config.export_json.is_some()
+            || config.export_binary.is_some()
+            || config.export_stats.is_some()
+        {
             return Ok(());
         }
 
@@ -136,8 +518,279 @@ fn run_application(scan_path: PathBuf, config: Config) -> Result<()> {
     Ok(())
 }
 
+/// Scan `scan_paths` `runs` times and write timing statistics to `out`
+///
+/// Reports the fastest, median and slowest wall-clock time, and the scan
+/// rate at the median, so runs on the same tree can be compared.
+fn run_bench(
+    scan_paths: &[PathBuf],
+    root_path: &Path,
+    config: &Config,
+    runs: usize,
+    out: &mut impl Write,
+) -> Result<()> {
+    let mut timings = Vec::with_capacity(runs);
+    let mut entries = 0;
+    for _ in 0..runs {
+        let start = Instant::now();
+        let stats = Arc::new(ScanStats::new());
+        let root = scan_roots(scan_paths, root_path, config, || None, None, &stats)?;
+        timings.push(start.elapsed());
+        entries = root.total_items();
+    }
+    timings.sort();
+
+    let mid = timings.len() / 2;
+    let median = if timings.len() % 2 == 0 {
+        (timings[mid - 1] + timings[mid]) / 2
+    } else {
+        timings[mid]
+    };
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let rate = entries as f64 / median.as_secs_f64().max(f64::EPSILON);
+
+    writeln!(
+        out,
+        "Benchmark of {}: {} runs, {} entries",
+        root_path.display(),
+        runs,
+        entries
+    )?;
+    writeln!(out, "  min:         {:.1} ms", ms(timings[0]))?;
+    writeln!(out, "  median:      {:.1} ms", ms(median))?;
+    writeln!(
+        out,
+        "  max:         {:.1} ms",
+        ms(timings[timings.len() - 1])
+    )?;
+    writeln!(out, "  entries/sec: {:.0}", rate)?;
+    Ok(())
+}
+
+/// Version and build details, for attaching to bug reports
+fn build_info() -> String {
+    format!(
+        "rsdu {}\n\
+         target: {}\n\
+         cpus: {}\n\
+         compression: {}\n\
+         profile: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        env!("RSDU_TARGET"),
+        num_cpus::get(),
+        export::Compression::codec_names().join(", "),
+        if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+    )
+}
+
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn test_build_info() {
+        let info = super::build_info();
+        assert!(info.starts_with(&format!("rsdu {}\n", env!("CARGO_PKG_VERSION"))));
+        assert!(info.contains(&format!("target: {}", env!("RSDU_TARGET"))));
+        assert!(info.contains("compression: zstd, gzip\n"));
+    }
+
+    #[test]
+    fn test_root_scan_error() {
+        use super::{root_scan_error, RsduError};
+        use crate::model::ScanStats;
+        use std::sync::Arc;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = crate::config::Config {
+            scan_ui: Some(crate::config::ScanUi::None),
+            ..crate::config::Config::default()
+        };
+        let scan = |path: &std::path::Path, stats: &Arc<ScanStats>| {
+            crate::scanner::scan_directory_counting(path, &config, None, None, stats.clone())
+                .unwrap()
+        };
+
+        let stats = Arc::new(ScanStats::new());
+        let root = scan(temp_dir.path(), &stats);
+        assert!(root_scan_error(&root, temp_dir.path(), &stats).is_none());
+
+        let missing = temp_dir.path().join("missing");
+        let root = scan(&missing, &stats);
+        assert!(matches!(
+            root_scan_error(&root, &missing, &stats),
+            Some(RsduError::PathNotFound { path }) if path == missing
+        ));
+
+        // The I/O error the scan hit is kept, not re-read from the message
+        let stats = ScanStats::new();
+        stats.record_root_error(std::io::Error::from(std::io::ErrorKind::PermissionDenied));
+        let denied = crate::model::Entry::error(
+            crate::model::generate_entry_id(),
+            "root".into(),
+            "Error scanning directory: Cannot read directory: denied".to_string(),
+        );
+        let error = root_scan_error(&denied, std::path::Path::new("/root"), &stats).unwrap();
+        match &error {
+            RsduError::PermissionDenied { path, source } => {
+                assert_eq!(path, std::path::Path::new("/root"));
+                assert_eq!(source.kind(), std::io::ErrorKind::PermissionDenied);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq!(super::exit_code(&error.into()), 3);
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        use super::{exit_code, RsduError};
+        use anyhow::Context;
+
+        let code = |error: RsduError| exit_code(&error.into());
+        assert_eq!(code(RsduError::path_not_found("/missing")), 2);
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert_eq!(code(RsduError::permission_denied("/root", denied)), 3);
+        assert_eq!(code(RsduError::ImportError("bad JSON".to_string())), 4);
+        assert_eq!(code(RsduError::ExportError("disk full".to_string())), 4);
+        assert_eq!(code(RsduError::UserCancelled), 130);
+        assert_eq!(code(RsduError::ConfigError("bad option".to_string())), 1);
+        assert_eq!(exit_code(&anyhow::anyhow!("something else")), 1);
+
+        // Context added on the way up doesn't hide the cause
+        let error = Err::<(), _>(RsduError::path_not_found("/missing"))
+            .context("Cannot access directory '/missing'")
+            .unwrap_err();
+        assert_eq!(exit_code(&error), 2);
+    }
+
+    #[test]
+    fn test_scan_exports_json() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let scan_dir = temp_dir.path().join("data");
+        std::fs::create_dir_all(scan_dir.join("sub")).unwrap();
+        std::fs::write(scan_dir.join("sub/file"), "data").unwrap();
+        let out = temp_dir.path().join("out.json");
+
+        let config = crate::config::Config {
+            scan_ui: Some(crate::config::ScanUi::None),
+            export_json: Some(out.display().to_string()),
+            ..crate::config::Config::default()
+        };
+        let export = crate::export::setup_json_export(&out.display().to_string()).unwrap();
+        super::run_application(vec![scan_dir.clone()], scan_dir, config, Some(export)).unwrap();
+
+        let json = std::fs::read_to_string(&out).unwrap();
+        assert!(!json.is_empty());
+        let exported: crate::model::SerializableEntry = serde_json::from_str(&json).unwrap();
+        assert_eq!(exported.name, "data");
+        assert_eq!(exported.children[0].name, "sub");
+        assert_eq!(exported.children[0].children[0].size, 4);
+    }
+
+    #[test]
+    fn test_export_on_terminal_uses_plain_scan() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let scan_dir = temp_dir.path().join("data");
+        std::fs::create_dir(&scan_dir).unwrap();
+        std::fs::write(scan_dir.join("file"), "data").unwrap();
+        let out = temp_dir.path().join("out.json");
+
+        // What `main` sets up for `rsdu -o out.json data` on a terminal
+        let browse = crate::config::Config {
+            scan_ui: Some(crate::config::ScanUi::Line),
+            ..crate::config::Config::default()
+        };
+        assert!(super::uses_tui(&browse, true));
+        let config = crate::config::Config {
+            export_json: Some(out.display().to_string()),
+            ..browse
+        };
+        assert!(!super::uses_tui(&config, true));
+
+        let export = crate::export::setup_json_export(&out.display().to_string()).unwrap();
+        super::run_application(vec![scan_dir.clone()], scan_dir, config, Some(export)).unwrap();
+        let exported: crate::model::SerializableEntry =
+            serde_json::from_str(&std::fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(exported.children[0].name, "file");
+    }
+
+    #[test]
+    fn test_hardlink_stats_on_terminal_uses_plain_scan() {
+        // What `main` sets up for `rsdu --hardlink-stats data` on a terminal
+        let browse = crate::config::Config {
+            scan_ui: Some(crate::config::ScanUi::Line),
+            ..crate::config::Config::default()
+        };
+        let config = crate::config::Config {
+            hardlink_stats: Some(true),
+            ..browse.clone()
+        };
+        assert!(!super::uses_tui(&config, true));
+
+        // Switched off in a config file, it doesn't keep the browser away
+        let config = crate::config::Config {
+            hardlink_stats: Some(false),
+            ..browse
+        };
+        assert!(super::uses_tui(&config, true));
+    }
+
+    #[test]
+    fn test_bench_reports_timings() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        std::fs::write(temp_dir.path().join("sub/file"), "data").unwrap();
+
+        let config = crate::config::Config {
+            scan_ui: Some(crate::config::ScanUi::None),
+            ..crate::config::Config::default()
+        };
+        let mut out = Vec::new();
+        let scan_paths = [temp_dir.path().to_path_buf()];
+        super::run_bench(&scan_paths, temp_dir.path(), &config, 3, &mut out).unwrap();
+
+        // Only the timing summary is written, with nothing drawn to the terminal
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].ends_with(": 3 runs, 3 entries"));
+        for (line, label) in lines[1..]
+            .iter()
+            .zip(["min:", "median:", "max:", "entries/sec:"])
+        {
+            assert!(line.trim_start().starts_with(label), "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_outermost_targets() {
+        use std::path::PathBuf;
+
+        let targets = [
+            "/data/sub",
+            "/data",
+            "/srv",
+            "/data",
+            "/srv2",
+            "/data/sub/deep",
+        ]
+        .map(PathBuf::from)
+        .to_vec();
+        let (kept, nested) = super::outermost_targets(targets);
+        assert_eq!(kept, ["/data", "/srv", "/srv2"].map(PathBuf::from));
+        assert_eq!(
+            nested,
+            [
+                ("/data/sub", "/data"),
+                ("/data", "/data"),
+                ("/data/sub/deep", "/data"),
+            ]
+            .map(|(target, outer)| (PathBuf::from(target), PathBuf::from(outer)))
+        );
+    }
+
     #[test]
     fn test_basic_functionality() {
         // Basic smoke test
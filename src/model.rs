@@ -4,20 +4,23 @@
 //! files, directories, and their metadata in the file system tree.
 
 // use crate::error::{Result, RsduError}; // TODO: Will be used for error handling
+use base64::Engine;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, RwLock, Weak};
 
 /// Unique identifier for entries (used for hardlink tracking)
 pub type EntryId = u64;
 
-/// Device identifier (simplified from st_dev)
-pub type DeviceId = u32;
+/// Device identifier (st_dev)
+pub type DeviceId = u64;
 
 /// Inode number
 pub type InodeId = u64;
@@ -46,14 +49,33 @@ pub enum EntryType {
     OtherFs,
     /// Kernel filesystem (proc, sys, etc.)
     KernelFs,
+    /// Directory already scanned through another path (bind mount)
+    BindMount,
+    /// Entries left out of the tree, summed into one (`--max-children`)
+    Aggregate,
 }
 
 impl EntryType {
+    /// Every entry type, in the order the help legend lists them
+    pub const ALL: [EntryType; 11] = [
+        EntryType::Directory,
+        EntryType::File,
+        EntryType::Symlink,
+        EntryType::Hardlink,
+        EntryType::Special,
+        EntryType::Error,
+        EntryType::Excluded,
+        EntryType::OtherFs,
+        EntryType::KernelFs,
+        EntryType::BindMount,
+        EntryType::Aggregate,
+    ];
+
     /// Whether this entry type represents a directory-like object
     pub fn is_directory(&self) -> bool {
         matches!(
             self,
-            EntryType::Directory | EntryType::OtherFs | EntryType::KernelFs
+            EntryType::Directory | EntryType::OtherFs | EntryType::KernelFs | EntryType::BindMount
         )
     }
 
@@ -75,6 +97,8 @@ impl fmt::Display for EntryType {
             EntryType::Excluded => write!(f, "EXCL"),
             EntryType::OtherFs => write!(f, "OTFS"),
             EntryType::KernelFs => write!(f, "KERN"),
+            EntryType::BindMount => write!(f, "BIND"),
+            EntryType::Aggregate => write!(f, "AGGR"),
         }
     }
 }
@@ -114,7 +138,11 @@ impl Default for ExtendedInfo {
 pub struct SerializableEntry {
     pub id: EntryId,
     pub entry_type: EntryType,
+    /// The name, with invalid UTF-8 replaced if it isn't valid
     pub name: String,
+    /// The name's bytes in base64, present only when `name` lost some of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_name: Option<String>,
     pub size: u64,
     pub blocks: u64,
     pub device: DeviceId,
@@ -150,8 +178,79 @@ pub struct Entry {
     pub error: Option<String>,
     /// Children (if directory)
     pub children: Vec<Arc<Entry>>,
-    /// Parent entry (weak reference to avoid cycles)
-    pub parent: Option<std::sync::Weak<Entry>>,
+    /// False while the entry is only a placeholder for a scan still in
+    /// progress, so its size and children are not final yet
+    pub complete: bool,
+    /// Parent entry (weak reference to avoid cycles), set once the parent
+    /// has been built
+    pub parent: ParentRef,
+    /// Largest files in the subtree, once asked for
+    largest_files: LargestFileCache,
+}
+
+/// Weak reference from an entry to its parent
+///
+/// Children are shared between a directory and its copy-on-write edits, so
+/// the reference is re-pointed at each new copy of the parent.
+#[derive(Debug, Default)]
+pub struct ParentRef(RwLock<Weak<Entry>>);
+
+impl ParentRef {
+    /// The parent, if one was linked and is still alive
+    fn get(&self) -> Option<Arc<Entry>> {
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .upgrade()
+    }
+
+    /// Point the reference at `parent`
+    fn set(&self, parent: Weak<Entry>) {
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = parent;
+    }
+}
+
+impl Clone for ParentRef {
+    fn clone(&self) -> Self {
+        let parent = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        Self(RwLock::new(parent.clone()))
+    }
+}
+
+/// Largest regular files in a subtree, by apparent size and by disk usage
+#[derive(Debug, Default)]
+struct LargestFiles {
+    by_size: Option<Arc<Entry>>,
+    by_blocks: Option<Arc<Entry>>,
+}
+
+impl LargestFiles {
+    /// Keep `file` wherever it beats the current largest
+    fn offer(&mut self, file: &Arc<Entry>) {
+        if self.by_size.as_ref().is_none_or(|max| file.size > max.size) {
+            self.by_size = Some(file.clone());
+        }
+        if self
+            .by_blocks
+            .as_ref()
+            .is_none_or(|max| file.blocks > max.blocks)
+        {
+            self.by_blocks = Some(file.clone());
+        }
+    }
+}
+
+/// `LargestFiles` of an entry's subtree, worked out the first time it is needed
+///
+/// A clone starts out empty, so a directory copied by `Arc::make_mut` for an
+/// edit works it out again from its new contents.
+#[derive(Debug, Default)]
+struct LargestFileCache(OnceLock<LargestFiles>);
+
+impl Clone for LargestFileCache {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
 }
 
 impl Entry {
@@ -178,7 +277,9 @@ impl Entry {
             extended: None,
             error: None,
             children: Vec::new(),
-            parent: None,
+            complete: true,
+            parent: ParentRef::default(),
+            largest_files: LargestFileCache::default(),
         }
     }
 
@@ -196,17 +297,30 @@ impl Entry {
             extended: None,
             error: Some(error),
             children: Vec::new(),
-            parent: None,
+            complete: true,
+            parent: ParentRef::default(),
+            largest_files: LargestFileCache::default(),
         }
     }
 
-    /// Get the full path of this entry
+    /// The parent entry, if it is wired up and still alive
+    pub fn parent(&self) -> Option<Arc<Entry>> {
+        self.parent.get()
+    }
+
+    /// Get the full path of this entry, following parent references up to
+    /// the topmost reachable ancestor
     pub fn full_path(&self) -> PathBuf {
-        let mut _components: Vec<&OsString> = Vec::new();
+        let mut ancestors = Vec::new();
+        let mut next = self.parent();
+        while let Some(ancestor) = next {
+            next = ancestor.parent();
+            ancestors.push(ancestor);
+        }
 
-        // For now, just return the name since parent relationship needs more work
-        // TODO: Implement proper parent traversal
-        PathBuf::from(&self.name)
+        let mut path: PathBuf = ancestors.iter().rev().map(|a| &a.name).collect();
+        path.push(&self.name);
+        path
     }
 
     /// Get the name as a string (lossy conversion)
@@ -221,9 +335,14 @@ impl Entry {
 
     /// Check if this entry has sub-errors (errors in children)
     pub fn has_sub_error(&self) -> bool {
-        self.children
-            .iter()
-            .any(|child| child.has_error() || child.has_sub_error())
+        let mut stack: Vec<&Entry> = self.children.iter().map(|c| c.as_ref()).collect();
+        while let Some(entry) = stack.pop() {
+            if entry.has_error() {
+                return true;
+            }
+            stack.extend(entry.children.iter().map(|c| c.as_ref()));
+        }
+        false
     }
 
     /// Add a child entry
@@ -234,62 +353,171 @@ impl Entry {
         child_arc
     }
 
+    /// Wrap the entry in an `Arc`, pointing its children's parent references
+    /// at it
+    pub fn into_linked(self) -> Arc<Entry> {
+        Arc::new_cyclic(|this| {
+            for child in &self.children {
+                child.parent.set(this.clone());
+            }
+            self
+        })
+    }
+
+    /// Point the parent references of `this` entry's children at it
+    ///
+    /// Needed whenever the entry was edited through `Arc::make_mut`, which
+    /// leaves it in a new allocation its children don't know about.
+    pub fn link_children(this: &Arc<Entry>) {
+        for child in &this.children {
+            child.parent.set(Arc::downgrade(this));
+        }
+    }
+
+    /// Point every parent reference below `root` at its parent
+    ///
+    /// For trees assembled from separately built parts; children are built
+    /// before their parents, so this runs top-down once the tree is whole.
+    pub fn link_subtree(root: &Arc<Entry>) {
+        let mut stack = vec![root.clone()];
+        while let Some(node) = stack.pop() {
+            Entry::link_children(&node);
+            stack.extend(node.children.iter().cloned());
+        }
+    }
+
+    /// Remove a direct child by id, returning it if present
+    pub fn remove_child(&mut self, id: EntryId) -> Option<Arc<Entry>> {
+        let index = self.children.iter().position(|c| c.id == id)?;
+        self.largest_files = LargestFileCache::default();
+        Some(self.children.remove(index))
+    }
+
     /// Get total size including all children
     pub fn total_size(&self) -> u64 {
-        self.size + self.children.iter().map(|c| c.total_size()).sum::<u64>()
+        self.sum_over_subtree(|entry| entry.size)
     }
 
     /// Get total blocks including all children
     pub fn total_blocks(&self) -> u64 {
-        self.blocks + self.children.iter().map(|c| c.total_blocks()).sum::<u64>()
+        self.sum_over_subtree(|entry| entry.blocks)
     }
 
     /// Get total item count including all children
     pub fn total_items(&self) -> u64 {
-        1 + self.children.iter().map(|c| c.total_items()).sum::<u64>()
+        self.sum_over_subtree(|_| 1)
     }
 
-    /// Calculate shared size (hardlinks that exist outside this subtree)
-    pub fn shared_size(&self, hardlink_map: &HardlinkMap) -> u64 {
-        let mut shared = 0u64;
-
-        if self.nlink > 1 {
-            let key = HardlinkKey::new(self.device, self.inode);
-            if let Some(info) = hardlink_map.get(&key) {
-                // If this hardlink appears in multiple places, count as shared
-                if info.total_links > info.links_in_tree {
-                    shared += self.size;
+    /// Sum `value` over this entry and all of its descendants
+    ///
+    /// Walks the tree with an explicit stack, so arbitrarily deep trees
+    /// cannot overflow the call stack.
+    pub fn sum_over_subtree(&self, value: impl Fn(&Entry) -> u64) -> u64 {
+        let mut total = 0u64;
+        let mut stack = vec![self];
+        while let Some(entry) = stack.pop() {
+            total += value(entry);
+            stack.extend(entry.children.iter().map(|c| c.as_ref()));
+        }
+        total
+    }
+
+    /// The largest regular file in this entry's subtree, by disk usage or
+    /// apparent size, if any
+    ///
+    /// Worked out once per entry and kept. Subdirectories that already know
+    /// theirs aren't walked again, so asking each level of a tree in turn
+    /// visits every entry about once. Of files of equal size, any may win.
+    pub fn largest_file(&self, disk_usage: bool) -> Option<&Entry> {
+        let largest = self.largest_files.0.get_or_init(|| {
+            let mut largest = LargestFiles::default();
+            let mut stack: Vec<&Arc<Entry>> = self.children.iter().collect();
+            while let Some(entry) = stack.pop() {
+                if entry.entry_type == EntryType::File {
+                    largest.offer(entry);
+                } else if let Some(known) = entry.largest_files.0.get() {
+                    for file in known.by_size.iter().chain(&known.by_blocks) {
+                        largest.offer(file);
+                    }
+                } else {
+                    stack.extend(&entry.children);
                 }
             }
+            largest
+        });
+        if disk_usage {
+            largest.by_blocks.as_deref()
+        } else {
+            largest.by_size.as_deref()
+        }
+    }
+
+    /// Whether this entry is a hardlink with links outside the scanned tree
+    fn is_shared_hardlink(&self, hardlink_map: &HardlinkMap) -> bool {
+        if self.nlink <= 1 {
+            return false;
         }
+        let key = HardlinkKey::new(self.device, self.inode);
+        hardlink_map
+            .get(&key)
+            .is_some_and(|info| info.total_links > info.links_in_tree)
+    }
 
-        shared
-            + self
-                .children
-                .iter()
-                .map(|c| c.shared_size(hardlink_map))
-                .sum::<u64>()
+    /// Calculate shared size (hardlinks that exist outside this subtree)
+    pub fn shared_size(&self, hardlink_map: &HardlinkMap) -> u64 {
+        self.sum_over_subtree(|entry| {
+            if entry.is_shared_hardlink(hardlink_map) {
+                entry.size
+            } else {
+                0
+            }
+        })
     }
 
     /// Calculate shared blocks (hardlinks that exist outside this subtree)
     pub fn shared_blocks(&self, hardlink_map: &HardlinkMap) -> u64 {
-        let mut shared = 0u64;
+        self.sum_over_subtree(|entry| {
+            if entry.is_shared_hardlink(hardlink_map) {
+                entry.blocks
+            } else {
+                0
+            }
+        })
+    }
 
-        if self.nlink > 1 {
-            let key = HardlinkKey::new(self.device, self.inode);
-            if let Some(info) = hardlink_map.get(&key) {
-                if info.total_links > info.links_in_tree {
-                    shared += self.blocks;
-                }
+    /// Compare with another entry on a single sort column, in ascending order
+    ///
+    /// Names compare byte-wise; natural name order is left to the caller.
+    pub fn cmp_by(&self, other: &Entry, sort_col: SortColumn) -> std::cmp::Ordering {
+        match sort_col {
+            SortColumn::Name => self.name.cmp(&other.name),
+            SortColumn::Size => self.total_size().cmp(&other.total_size()),
+            SortColumn::Blocks => self.total_blocks().cmp(&other.total_blocks()),
+            SortColumn::Items => self.total_items().cmp(&other.total_items()),
+            SortColumn::Mtime => {
+                let a_mtime = self.extended.as_ref().and_then(|e| e.mtime);
+                let b_mtime = other.extended.as_ref().and_then(|e| e.mtime);
+                a_mtime.cmp(&b_mtime)
+            }
+            SortColumn::Owner => {
+                let a_owner = self.extended.as_ref().map(|e| (e.uid, e.gid));
+                let b_owner = other.extended.as_ref().map(|e| (e.uid, e.gid));
+                a_owner.cmp(&b_owner)
+            }
+            SortColumn::Mode => {
+                let a_mode = self
+                    .extended
+                    .as_ref()
+                    .and_then(|e| e.mode)
+                    .map(|m| m & 0o7777);
+                let b_mode = other
+                    .extended
+                    .as_ref()
+                    .and_then(|e| e.mode)
+                    .map(|m| m & 0o7777);
+                a_mode.cmp(&b_mode)
             }
         }
-
-        shared
-            + self
-                .children
-                .iter()
-                .map(|c| c.shared_blocks(hardlink_map))
-                .sum::<u64>()
     }
 
     /// Sort children according to given criteria
@@ -310,17 +538,7 @@ impl Entry {
                 }
             }
 
-            let cmp = match sort_col {
-                SortColumn::Name => a.name.cmp(&b.name),
-                SortColumn::Size => a.total_size().cmp(&b.total_size()),
-                SortColumn::Blocks => a.total_blocks().cmp(&b.total_blocks()),
-                SortColumn::Items => a.total_items().cmp(&b.total_items()),
-                SortColumn::Mtime => {
-                    let a_mtime = a.extended.as_ref().and_then(|e| e.mtime);
-                    let b_mtime = b.extended.as_ref().and_then(|e| e.mtime);
-                    a_mtime.cmp(&b_mtime)
-                }
-            };
+            let cmp = a.cmp_by(b, sort_col);
 
             match sort_order {
                 SortOrder::Asc => cmp,
@@ -331,48 +549,150 @@ impl Entry {
 
     /// Convert to serializable format
     pub fn to_serializable(&self) -> SerializableEntry {
-        SerializableEntry {
-            id: self.id,
-            entry_type: self.entry_type,
-            name: self.name.to_string_lossy().to_string(),
-            size: self.size,
-            blocks: self.blocks,
-            device: self.device,
-            inode: self.inode,
-            nlink: self.nlink,
-            extended: self.extended.clone(),
-            error: self.error.clone(),
-            children: self.children.iter().map(|c| c.to_serializable()).collect(),
+        // Post-order walk: each entry is converted once its children are done,
+        // which leaves them as the last items on `done`
+        let mut pending: Vec<(&Entry, bool)> = vec![(self, false)];
+        let mut done: Vec<SerializableEntry> = Vec::new();
+
+        while let Some((entry, children_done)) = pending.pop() {
+            if children_done {
+                let children = done.split_off(done.len() - entry.children.len());
+                let (name, raw_name) = encode_name(&entry.name);
+                done.push(SerializableEntry {
+                    id: entry.id,
+                    entry_type: entry.entry_type,
+                    name,
+                    raw_name,
+                    size: entry.size,
+                    blocks: entry.blocks,
+                    device: entry.device,
+                    inode: entry.inode,
+                    nlink: entry.nlink,
+                    extended: entry.extended.clone(),
+                    error: entry.error.clone(),
+                    children,
+                });
+            } else {
+                pending.push((entry, true));
+                pending.extend(entry.children.iter().rev().map(|c| (c.as_ref(), false)));
+            }
         }
+
+        done.pop().expect("conversion yields the root entry")
     }
 
     /// Create from serializable format
     pub fn from_serializable(serializable: SerializableEntry) -> Arc<Self> {
-        let mut entry = Entry::new(
-            serializable.id,
-            serializable.entry_type,
-            serializable.name.into(),
-            serializable.size,
-            serializable.blocks,
-            serializable.device,
-            serializable.inode,
-            serializable.nlink,
-        );
-        entry.extended = serializable.extended;
-        entry.error = serializable.error;
+        // Post-order walk, as in `to_serializable`; a node is built once its
+        // `child_count` converted children are on top of `done`
+        enum Step {
+            Visit(SerializableEntry),
+            Build(SerializableEntry, usize),
+        }
 
-        // Convert children
-        let children: Vec<Arc<Entry>> = serializable
-            .children
-            .into_iter()
-            .map(Self::from_serializable)
-            .collect();
+        let mut pending = vec![Step::Visit(serializable)];
+        let mut done: Vec<Arc<Entry>> = Vec::new();
+
+        while let Some(step) = pending.pop() {
+            match step {
+                Step::Visit(mut node) => {
+                    let children = std::mem::take(&mut node.children);
+                    pending.push(Step::Build(node, children.len()));
+                    pending.extend(children.into_iter().rev().map(Step::Visit));
+                }
+                Step::Build(mut node, child_count) => {
+                    let name =
+                        decode_name(std::mem::take(&mut node.name), node.raw_name.as_deref());
+                    let mut entry = Entry::new(
+                        node.id,
+                        node.entry_type,
+                        name,
+                        node.size,
+                        node.blocks,
+                        node.device,
+                        node.inode,
+                        node.nlink,
+                    );
+                    entry.extended = node.extended.take();
+                    entry.error = node.error.take();
+                    entry.children = done.split_off(done.len() - child_count);
+                    done.push(entry.into_linked());
+                }
+            }
+        }
+
+        done.pop().expect("conversion yields the root entry")
+    }
+}
+
+// Trees are dropped iteratively: the default drop glue recurses once per
+// level and can overflow the stack on very deep trees.
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(child) = stack.pop() {
+            // Children still shared elsewhere just lose a reference
+            if let Ok(mut child) = Arc::try_unwrap(child) {
+                stack.append(&mut child.children);
+            }
+        }
+    }
+}
 
-        entry.children = children;
-        Arc::new(entry)
+/// An entry name for export, with its raw bytes in base64 if it isn't valid UTF-8
+///
+/// Readers that don't know about the raw bytes still get a readable name.
+pub fn encode_name(name: &OsStr) -> (String, Option<String>) {
+    match name.to_str() {
+        Some(name) => (name.to_string(), None),
+        None => (
+            name.to_string_lossy().into_owned(),
+            Some(base64::engine::general_purpose::STANDARD.encode(name.as_bytes())),
+        ),
     }
 }
 
+/// The name `encode_name` exported, from the raw bytes if they were kept
+///
+/// Raw bytes that don't decode fall back to the readable name.
+pub fn decode_name(name: String, raw_name: Option<&str>) -> OsString {
+    raw_name
+        .and_then(|raw| base64::engine::general_purpose::STANDARD.decode(raw).ok())
+        .map_or_else(|| name.into(), OsString::from_vec)
+}
+
+impl Drop for SerializableEntry {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(mut child) = stack.pop() {
+            stack.append(&mut child.children);
+        }
+    }
+}
+
+/// Re-link an edited directory into its ancestors, bottom-up
+///
+/// Children are shared `Arc`s, so editing a directory produces a new node that
+/// its ancestors do not yet point at; totals computed from them would still
+/// include the old contents. `ancestors` runs from the root down to the parent
+/// of `updated`. Each ancestor is rebuilt to reference its updated child, and
+/// the new root is returned (or `updated` itself if there are no ancestors).
+pub fn propagate_to_ancestors(ancestors: &mut [Arc<Entry>], updated: Arc<Entry>) -> Arc<Entry> {
+    let mut updated = updated;
+    Entry::link_children(&updated);
+    for ancestor in ancestors.iter_mut().rev() {
+        let node = Arc::make_mut(ancestor);
+        if let Some(slot) = node.children.iter_mut().find(|c| c.id == updated.id) {
+            *slot = updated;
+        }
+        node.largest_files = LargestFileCache::default();
+        Entry::link_children(ancestor);
+        updated = ancestor.clone();
+    }
+    updated
+}
+
 /// Sorting criteria
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SortColumn {
@@ -381,6 +701,8 @@ pub enum SortColumn {
     Blocks,
     Items,
     Mtime,
+    Owner,
+    Mode,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -389,6 +711,29 @@ pub enum SortOrder {
     Desc,
 }
 
+impl From<crate::config::SortColumn> for SortColumn {
+    fn from(column: crate::config::SortColumn) -> Self {
+        match column {
+            crate::config::SortColumn::Name => SortColumn::Name,
+            crate::config::SortColumn::Blocks => SortColumn::Blocks,
+            crate::config::SortColumn::Size => SortColumn::Size,
+            crate::config::SortColumn::Items => SortColumn::Items,
+            crate::config::SortColumn::Mtime => SortColumn::Mtime,
+            crate::config::SortColumn::Owner => SortColumn::Owner,
+            crate::config::SortColumn::Mode => SortColumn::Mode,
+        }
+    }
+}
+
+impl From<crate::config::SortOrder> for SortOrder {
+    fn from(order: crate::config::SortOrder) -> Self {
+        match order {
+            crate::config::SortOrder::Asc => SortOrder::Asc,
+            crate::config::SortOrder::Desc => SortOrder::Desc,
+        }
+    }
+}
+
 /// Key for hardlink tracking
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct HardlinkKey {
@@ -420,6 +765,49 @@ pub struct HardlinkInfo {
 /// Map for tracking hardlinks
 pub type HardlinkMap = HashMap<HardlinkKey, HardlinkInfo>;
 
+/// Summary of the hardlinked files found in a scan
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HardlinkStats {
+    /// Distinct inodes with more than one link
+    pub groups: u64,
+    /// Links beyond the first of each inode, anywhere on its filesystem
+    pub extra_links: u64,
+}
+
+impl HardlinkStats {
+    pub fn from_map(hardlink_map: &HardlinkMap) -> Self {
+        Self {
+            groups: hardlink_map.len() as u64,
+            extra_links: hardlink_map
+                .values()
+                .map(|info| u64::from(info.total_links.saturating_sub(1)))
+                .sum(),
+        }
+    }
+}
+
+/// Kind of failure behind a scan error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// Access refused; re-running with more privileges may help
+    PermissionDenied,
+    /// The entry disappeared during the scan
+    NotFound,
+    /// Any other I/O failure
+    Other,
+}
+
+impl ErrorCategory {
+    /// Classify an I/O error by its kind
+    pub fn from_io_error(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => ErrorCategory::PermissionDenied,
+            io::ErrorKind::NotFound => ErrorCategory::NotFound,
+            _ => ErrorCategory::Other,
+        }
+    }
+}
+
 /// Statistics about a scan
 #[derive(Debug, Default)]
 pub struct ScanStats {
@@ -431,10 +819,16 @@ pub struct ScanStats {
     pub files: AtomicU64,
     /// Total errors encountered
     pub errors: AtomicU64,
+    /// Errors caused by missing permissions
+    pub permission_errors: AtomicU64,
+    /// Errors caused by entries vanishing mid-scan
+    pub not_found_errors: AtomicU64,
     /// Total size in bytes
     pub total_size: AtomicU64,
     /// Total blocks
     pub total_blocks: AtomicU64,
+    /// Why the scan root itself could not be read, if it couldn't
+    pub root_error: Mutex<Option<io::Error>>,
 }
 
 impl ScanStats {
@@ -458,6 +852,30 @@ impl ScanStats {
         self.errors.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Count an error, remembering its category
+    pub fn record_error(&self, category: ErrorCategory) {
+        self.increment_errors();
+        match category {
+            ErrorCategory::PermissionDenied => {
+                self.permission_errors.fetch_add(1, Ordering::Relaxed);
+            }
+            ErrorCategory::NotFound => {
+                self.not_found_errors.fetch_add(1, Ordering::Relaxed);
+            }
+            ErrorCategory::Other => {}
+        }
+    }
+
+    /// Remember why the scan root could not be read
+    pub fn record_root_error(&self, error: io::Error) {
+        *self.root_error.lock().unwrap() = Some(error);
+    }
+
+    /// Why the scan root could not be read, if it couldn't
+    pub fn take_root_error(&self) -> Option<io::Error> {
+        self.root_error.lock().unwrap().take()
+    }
+
     pub fn add_size(&self, size: u64) {
         self.total_size.fetch_add(size, Ordering::Relaxed);
     }
@@ -482,6 +900,20 @@ impl ScanStats {
         self.errors.load(Ordering::Relaxed)
     }
 
+    pub fn get_permission_errors(&self) -> u64 {
+        self.permission_errors.load(Ordering::Relaxed)
+    }
+
+    pub fn get_not_found_errors(&self) -> u64 {
+        self.not_found_errors.load(Ordering::Relaxed)
+    }
+
+    /// Errors that are neither permission nor not-found problems
+    pub fn get_other_errors(&self) -> u64 {
+        self.get_errors()
+            .saturating_sub(self.get_permission_errors() + self.get_not_found_errors())
+    }
+
     pub fn get_total_size(&self) -> u64 {
         self.total_size.load(Ordering::Relaxed)
     }
@@ -551,6 +983,39 @@ mod tests {
         assert_eq!(stats.get_total_size(), 1024);
     }
 
+    #[test]
+    fn test_hardlink_stats() {
+        let info = |total_links: u32| HardlinkInfo {
+            total_links,
+            links_in_tree: 1,
+            size: 100,
+            blocks: 1,
+            first_entry: Arc::new(Entry::new(
+                generate_entry_id(),
+                EntryType::File,
+                OsString::from("file"),
+                100,
+                1,
+                1,
+                0,
+                total_links,
+            )),
+        };
+        let mut map = HardlinkMap::new();
+        assert_eq!(HardlinkStats::from_map(&map), HardlinkStats::default());
+
+        map.insert(HardlinkKey::new(1, 10), info(2));
+        map.insert(HardlinkKey::new(1, 11), info(4));
+        map.insert(HardlinkKey::new(2, 10), info(3));
+        assert_eq!(
+            HardlinkStats::from_map(&map),
+            HardlinkStats {
+                groups: 3,
+                extra_links: 6,
+            }
+        );
+    }
+
     #[test]
     fn test_extended_info() {
         let mut ext = ExtendedInfo::new();
@@ -559,4 +1024,242 @@ mod tests {
         ext.mtime = Some(Utc::now());
         assert!(!ext.is_empty());
     }
+
+    #[test]
+    fn test_sort_by_owner() {
+        let owned = |name: &str, uid: Option<u32>| {
+            let mut entry = Entry::new(
+                generate_entry_id(),
+                EntryType::File,
+                OsString::from(name),
+                0,
+                0,
+                1,
+                0,
+                1,
+            );
+            entry.extended = uid.map(|uid| ExtendedInfo {
+                uid: Some(uid),
+                gid: Some(100),
+                ..ExtendedInfo::new()
+            });
+            Arc::new(entry)
+        };
+
+        let mut dir = Entry::new(
+            generate_entry_id(),
+            EntryType::Directory,
+            OsString::from("dir"),
+            0,
+            0,
+            1,
+            0,
+            1,
+        );
+        dir.children = vec![
+            owned("bob", Some(1001)),
+            owned("unknown", None),
+            owned("root", Some(0)),
+            owned("alice", Some(1000)),
+        ];
+
+        dir.sort_children(SortColumn::Owner, SortOrder::Asc, false);
+        let names: Vec<String> = dir.children.iter().map(|c| c.name_str()).collect();
+        assert_eq!(names, vec!["unknown", "root", "alice", "bob"]);
+
+        dir.sort_children(SortColumn::Owner, SortOrder::Desc, false);
+        assert_eq!(dir.children[0].name_str(), "bob");
+    }
+
+    #[test]
+    fn test_very_deep_tree() {
+        const DEPTH: u64 = 200_000;
+
+        let mut node = Arc::new(Entry::new(
+            generate_entry_id(),
+            EntryType::File,
+            OsString::from("leaf"),
+            1,
+            1,
+            1,
+            0,
+            1,
+        ));
+        for _ in 0..DEPTH {
+            let mut parent = Entry::new(
+                generate_entry_id(),
+                EntryType::Directory,
+                OsString::from("d"),
+                1,
+                1,
+                1,
+                0,
+                1,
+            );
+            parent.children.push(node);
+            node = Arc::new(parent);
+        }
+
+        assert_eq!(node.total_size(), DEPTH + 1);
+        assert_eq!(node.total_blocks(), DEPTH + 1);
+        assert_eq!(node.total_items(), DEPTH + 1);
+        assert_eq!(node.shared_size(&HashMap::new()), 0);
+        assert!(!node.has_sub_error());
+
+        let serializable = node.to_serializable();
+        let imported = Entry::from_serializable(serializable);
+        assert_eq!(imported.total_items(), DEPTH + 1);
+
+        // Dropping the trees must not recurse either
+        drop(imported);
+        drop(node);
+    }
+
+    #[test]
+    fn test_imported_parent_chain() {
+        let entry = |entry_type: EntryType, name: &str| {
+            Entry::new(
+                generate_entry_id(),
+                entry_type,
+                OsString::from(name),
+                1,
+                1,
+                1,
+                0,
+                1,
+            )
+        };
+        let mut dir = entry(EntryType::Directory, "dir");
+        dir.children.push(Arc::new(entry(EntryType::File, "file")));
+        let mut root = entry(EntryType::Directory, "root");
+        root.children.push(Arc::new(dir));
+
+        let imported = Entry::from_serializable(root.to_serializable());
+        assert!(imported.parent().is_none());
+
+        let grandchild = imported.children[0].children[0].clone();
+        let parent = grandchild.parent().expect("grandchild has a parent");
+        assert_eq!(parent.id, imported.children[0].id);
+        let grandparent = parent.parent().expect("child has a parent");
+        assert_eq!(grandparent.id, imported.id);
+        assert!(grandparent.parent().is_none());
+
+        assert_eq!(grandchild.full_path(), PathBuf::from("root/dir/file"));
+    }
+
+    #[test]
+    fn test_largest_file() {
+        let entry = |entry_type: EntryType, name: &str, size: u64, children: Vec<Arc<Entry>>| {
+            let mut entry = Entry::new(
+                generate_entry_id(),
+                entry_type,
+                OsString::from(name),
+                size,
+                size / 512,
+                1,
+                0,
+                1,
+            );
+            entry.children = children;
+            entry.into_linked()
+        };
+        let nested = entry(
+            EntryType::Directory,
+            "nested",
+            4096,
+            vec![
+                entry(EntryType::File, "deep.iso", 5000, vec![]),
+                entry(EntryType::Hardlink, "link.iso", 9000, vec![]),
+            ],
+        );
+        let root = entry(
+            EntryType::Directory,
+            "root",
+            4096,
+            vec![
+                entry(EntryType::File, "top.txt", 3000, vec![]),
+                nested.clone(),
+            ],
+        );
+
+        // The deepest file wins over a bigger directory and a hardlink
+        let largest = root.largest_file(false).unwrap();
+        assert_eq!(largest.name, OsString::from("deep.iso"));
+        assert_eq!(largest.full_path(), PathBuf::from("root/nested/deep.iso"));
+        assert_eq!(root.largest_file(true).unwrap().name, largest.name);
+
+        // An edited copy works it out again
+        let mut edited = nested.clone();
+        Arc::make_mut(&mut edited).remove_child(nested.children[0].id);
+        let mut ancestors = [root.clone()];
+        let edited_root = propagate_to_ancestors(&mut ancestors, edited);
+        assert_eq!(
+            edited_root.largest_file(false).unwrap().name,
+            OsString::from("top.txt")
+        );
+        assert_eq!(root.largest_file(false).unwrap().name, largest.name);
+
+        let empty = entry(EntryType::Directory, "empty", 4096, vec![]);
+        assert!(empty.largest_file(false).is_none());
+    }
+
+    #[test]
+    fn test_propagate_removal_to_ancestors() {
+        let file = |size: u64| {
+            Arc::new(Entry::new(
+                generate_entry_id(),
+                EntryType::File,
+                OsString::from("file"),
+                size,
+                size / 512,
+                1,
+                0,
+                1,
+            ))
+        };
+        let dir = |children: Vec<Arc<Entry>>| {
+            let mut entry = Entry::new(
+                generate_entry_id(),
+                EntryType::Directory,
+                OsString::from("dir"),
+                0,
+                0,
+                1,
+                0,
+                1,
+            );
+            entry.children = children;
+            entry.into_linked()
+        };
+
+        let doomed = file(4096);
+        let leaf = dir(vec![doomed.clone(), file(1024)]);
+        let middle = dir(vec![leaf.clone(), file(512)]);
+        let root = dir(vec![middle.clone()]);
+        assert_eq!(root.total_size(), 5632);
+        assert_eq!(root.total_items(), 6);
+
+        let mut edited = leaf.clone();
+        assert!(Arc::make_mut(&mut edited).remove_child(doomed.id).is_some());
+
+        let mut ancestors = vec![root.clone(), middle.clone()];
+        let new_root = propagate_to_ancestors(&mut ancestors, edited);
+        assert_eq!(new_root.total_size(), 1536);
+        assert_eq!(new_root.total_blocks(), 3);
+        assert_eq!(new_root.total_items(), 5);
+        assert_eq!(ancestors[1].total_size(), 1536);
+
+        // Parent references follow the edited copies up to the new root
+        let new_leaf = &ancestors[1].children[0];
+        let kept = &new_leaf.children[0];
+        assert!(Arc::ptr_eq(&kept.parent().unwrap(), new_leaf));
+        assert!(Arc::ptr_eq(&new_leaf.parent().unwrap(), &ancestors[1]));
+        let sibling = &ancestors[1].children[1];
+        assert!(Arc::ptr_eq(&sibling.parent().unwrap(), &ancestors[1]));
+        assert!(Arc::ptr_eq(&ancestors[1].parent().unwrap(), &new_root));
+        assert_eq!(kept.full_path(), PathBuf::from("dir/dir/dir/file"));
+
+        // The original tree is left untouched
+        assert_eq!(root.total_size(), 5632);
+    }
 }
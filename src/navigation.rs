@@ -0,0 +1,269 @@
+//! Shared browser navigation
+//!
+//! Both interactive browsers, the ratatui `TuiApp` and the crossterm
+//! `Browser`, move through the tree the same way. This module holds that
+//! key handling once; each UI implements `BrowserBackend` for its own state
+//! and only handles the keys specific to it.
+
+use crossterm::event::KeyCode;
+
+/// Navigation primitives a browser exposes to the shared key handling
+pub trait BrowserBackend {
+    /// Number of selectable rows in the current listing
+    fn row_count(&self) -> usize;
+
+    /// Index of the selected row
+    fn selected(&self) -> usize;
+
+    /// Select the row at `index`, which is always below `row_count`
+    fn select(&mut self, index: usize);
+
+    /// Number of rows that fit on one screen
+    fn page_height(&self) -> usize;
+
+    /// Descend into the selected row if it is a directory
+    fn enter_selected(&mut self);
+
+    /// Return to the parent directory, if there is one
+    fn go_back(&mut self);
+
+    /// Whether the help screen is covering the listing
+    fn help_visible(&self) -> bool;
+
+    /// Show or hide the help screen
+    fn set_help_visible(&mut self, visible: bool);
+}
+
+/// Result of offering a key to the shared navigation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NavOutcome {
+    /// The key was a navigation key and has been applied
+    Handled,
+    /// The user asked to leave the browser
+    Quit,
+    /// Not a navigation key; the browser may handle it itself
+    Unhandled,
+}
+
+/// Apply a navigation key to `backend`
+///
+/// While help is showing, keys that move through the listing are swallowed
+/// and `q`/`Esc` close the help screen instead of quitting.
+pub fn handle_navigation_key<B: BrowserBackend + ?Sized>(
+    backend: &mut B,
+    key: KeyCode,
+) -> NavOutcome {
+    match key {
+        KeyCode::Char('q') | KeyCode::Esc => {
+            if backend.help_visible() {
+                backend.set_help_visible(false);
+                return NavOutcome::Handled;
+            }
+            return NavOutcome::Quit;
+        }
+        KeyCode::Char('?') | KeyCode::F(1) => {
+            let visible = backend.help_visible();
+            backend.set_help_visible(!visible);
+            return NavOutcome::Handled;
+        }
+        _ => {}
+    }
+
+    let page = backend.page_height().max(1) as isize;
+    let delta = match key {
+        KeyCode::Up | KeyCode::Char('k') => Some(-1),
+        KeyCode::Down | KeyCode::Char('j') => Some(1),
+        KeyCode::PageUp => Some(-page),
+        KeyCode::PageDown => Some(page),
+        KeyCode::Home | KeyCode::Char('g') => Some(isize::MIN),
+        KeyCode::End | KeyCode::Char('G') => Some(isize::MAX),
+        _ => None,
+    };
+
+    let is_navigation = delta.is_some()
+        || matches!(
+            key,
+            KeyCode::Enter
+                | KeyCode::Right
+                | KeyCode::Char('l')
+                | KeyCode::Left
+                | KeyCode::Char('h')
+                | KeyCode::Backspace
+        );
+    if !is_navigation {
+        return NavOutcome::Unhandled;
+    }
+    if backend.help_visible() {
+        return NavOutcome::Handled;
+    }
+
+    match key {
+        KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => backend.enter_selected(),
+        KeyCode::Left | KeyCode::Char('h') | KeyCode::Backspace => backend.go_back(),
+        _ => {
+            let row_count = backend.row_count();
+            if row_count > 0 {
+                let target = move_index(backend.selected(), delta.unwrap_or(0), row_count);
+                backend.select(target);
+            }
+        }
+    }
+    NavOutcome::Handled
+}
+
+/// Move `current` by `delta` rows, staying within a listing of `row_count` rows
+pub fn move_index(current: usize, delta: isize, row_count: usize) -> usize {
+    let max_index = row_count.saturating_sub(1);
+    if delta < 0 {
+        current.saturating_sub(delta.unsigned_abs())
+    } else {
+        current.saturating_add(delta as usize).min(max_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rendering-free backend over a fixed directory tree of row counts
+    struct FakeBackend {
+        /// Row count of each directory on the path from the root
+        dirs: Vec<usize>,
+        selected: usize,
+        help: bool,
+        page: usize,
+    }
+
+    impl BrowserBackend for FakeBackend {
+        fn row_count(&self) -> usize {
+            *self.dirs.last().unwrap()
+        }
+        fn selected(&self) -> usize {
+            self.selected
+        }
+        fn select(&mut self, index: usize) {
+            assert!(index < self.row_count());
+            self.selected = index;
+        }
+        fn page_height(&self) -> usize {
+            self.page
+        }
+        fn enter_selected(&mut self) {
+            // Every row is a directory holding one row per parent row selected
+            self.dirs.push(self.selected + 1);
+            self.selected = 0;
+        }
+        fn go_back(&mut self) {
+            if self.dirs.len() > 1 {
+                self.dirs.pop();
+                self.selected = 0;
+            }
+        }
+        fn help_visible(&self) -> bool {
+            self.help
+        }
+        fn set_help_visible(&mut self, visible: bool) {
+            self.help = visible;
+        }
+    }
+
+    fn backend(rows: usize) -> FakeBackend {
+        FakeBackend {
+            dirs: vec![rows],
+            selected: 0,
+            help: false,
+            page: 4,
+        }
+    }
+
+    #[test]
+    fn test_move_index() {
+        assert_eq!(move_index(0, -1, 5), 0);
+        assert_eq!(move_index(3, 1, 5), 4);
+        assert_eq!(move_index(4, 1, 5), 4);
+        assert_eq!(move_index(2, isize::MIN, 5), 0);
+        assert_eq!(move_index(2, isize::MAX, 5), 4);
+    }
+
+    #[test]
+    fn test_selection_keys() {
+        let mut b = backend(10);
+
+        assert_eq!(
+            handle_navigation_key(&mut b, KeyCode::Down),
+            NavOutcome::Handled
+        );
+        assert_eq!(
+            handle_navigation_key(&mut b, KeyCode::Char('j')),
+            NavOutcome::Handled
+        );
+        assert_eq!(b.selected, 2);
+        handle_navigation_key(&mut b, KeyCode::Char('k'));
+        assert_eq!(b.selected, 1);
+
+        handle_navigation_key(&mut b, KeyCode::PageDown);
+        assert_eq!(b.selected, 5);
+        handle_navigation_key(&mut b, KeyCode::PageDown);
+        handle_navigation_key(&mut b, KeyCode::PageDown);
+        assert_eq!(b.selected, 9);
+
+        handle_navigation_key(&mut b, KeyCode::Char('g'));
+        assert_eq!(b.selected, 0);
+        handle_navigation_key(&mut b, KeyCode::End);
+        assert_eq!(b.selected, 9);
+
+        // Moving in an empty listing is a no-op
+        let mut empty = backend(0);
+        assert_eq!(
+            handle_navigation_key(&mut empty, KeyCode::Down),
+            NavOutcome::Handled
+        );
+        assert_eq!(empty.selected, 0);
+    }
+
+    #[test]
+    fn test_enter_and_back() {
+        let mut b = backend(5);
+        b.selected = 2;
+
+        handle_navigation_key(&mut b, KeyCode::Enter);
+        assert_eq!(b.dirs, vec![5, 3]);
+        handle_navigation_key(&mut b, KeyCode::Char('l'));
+        assert_eq!(b.dirs, vec![5, 3, 1]);
+
+        handle_navigation_key(&mut b, KeyCode::Backspace);
+        handle_navigation_key(&mut b, KeyCode::Left);
+        handle_navigation_key(&mut b, KeyCode::Char('h'));
+        assert_eq!(b.dirs, vec![5]);
+    }
+
+    #[test]
+    fn test_help_and_quit() {
+        let mut b = backend(5);
+
+        assert_eq!(
+            handle_navigation_key(&mut b, KeyCode::Char('?')),
+            NavOutcome::Handled
+        );
+        assert!(b.help);
+
+        // Navigation is swallowed while help is up, and q only closes it
+        handle_navigation_key(&mut b, KeyCode::Down);
+        handle_navigation_key(&mut b, KeyCode::Enter);
+        assert_eq!((b.selected, b.dirs.len()), (0, 1));
+        assert_eq!(
+            handle_navigation_key(&mut b, KeyCode::Char('q')),
+            NavOutcome::Handled
+        );
+        assert!(!b.help);
+
+        assert_eq!(
+            handle_navigation_key(&mut b, KeyCode::Esc),
+            NavOutcome::Quit
+        );
+        assert_eq!(
+            handle_navigation_key(&mut b, KeyCode::Char('x')),
+            NavOutcome::Unhandled
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Scan progress reporting
+//!
+//! This module holds the types the scanner reports progress with. They live
+//! apart from the UIs so that the scanner depends on nothing user-facing;
+//! the TUI consumes them through `ScanMessage`.
+
+use crate::model::{Entry, ScanStats};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Point-in-time copy of the scan statistics
+#[derive(Debug, Clone)]
+pub struct ProgressStats {
+    pub total_entries: u64,
+    pub directories: u64,
+    pub files: u64,
+    pub errors: u64,
+    pub permission_errors: u64,
+    pub not_found_errors: u64,
+    pub total_size: u64,
+}
+
+impl ProgressStats {
+    pub fn from_scan_stats(stats: &ScanStats) -> Self {
+        Self {
+            total_entries: stats.get_total_entries(),
+            directories: stats.get_directories(),
+            files: stats.get_files(),
+            errors: stats.get_errors(),
+            permission_errors: stats.get_permission_errors(),
+            not_found_errors: stats.get_not_found_errors(),
+            total_size: stats.get_total_size(),
+        }
+    }
+}
+
+/// Receives the running statistics and the path about to be scanned
+///
+/// Called once per entry from whichever scan thread reached it, so it should
+/// return quickly.
+pub type ProgressCallback = Box<dyn FnMut(&ProgressStats, &Path) + Send>;
+
+/// Part of the tree finished ahead of the whole scan
+#[derive(Debug, Clone)]
+pub enum PartialUpdate {
+    /// The root with its direct children listed but not yet scanned, so
+    /// their sizes are zero and directories are empty
+    Listing(Arc<Entry>),
+    /// A direct child of the root, scanned completely
+    Subtree(Arc<Entry>),
+}
+
+/// Receives parts of the tree as they are finished, for browsing during a scan
+///
+/// The listing always comes first, followed by one subtree per direct child.
+pub type PartialCallback = Box<dyn FnMut(PartialUpdate) + Send>;
+
+/// Merge a completed subtree into a partially scanned root
+///
+/// The subtree replaces the root's child of the same name, which is its
+/// placeholder from the listing.
+pub fn merge_subtree(root: &mut Arc<Entry>, subtree: Arc<Entry>) {
+    let node = Arc::make_mut(root);
+    match node
+        .children
+        .iter_mut()
+        .find(|child| child.name == subtree.name)
+    {
+        Some(slot) => *slot = subtree,
+        None => node.children.push(subtree),
+    }
+    Entry::link_children(root);
+}
+
+/// Messages sent to a UI following a background scan
+#[derive(Debug, Clone)]
+pub enum ScanMessage {
+    Progress {
+        current_path: String,
+        stats: ProgressStats,
+    },
+    Partial(PartialUpdate),
+    Complete {
+        root: Arc<Entry>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_subtree_into_listing() {
+        use crate::model::{generate_entry_id, EntryType};
+
+        let entry = |name: &str, entry_type, size, children: Vec<Arc<Entry>>| {
+            let mut entry = Entry::new(
+                generate_entry_id(),
+                entry_type,
+                name.into(),
+                size,
+                0,
+                1,
+                0,
+                1,
+            );
+            entry.children = children;
+            Arc::new(entry)
+        };
+
+        // Listing: "docs" and "src" known by name only
+        let mut root = entry(
+            "root",
+            EntryType::Directory,
+            0,
+            vec![
+                entry("docs", EntryType::Directory, 0, vec![]),
+                entry("src", EntryType::Directory, 0, vec![]),
+            ],
+        );
+        let listing = root.clone();
+
+        let src = entry(
+            "src",
+            EntryType::Directory,
+            0,
+            vec![entry("main.rs", EntryType::File, 300, vec![])],
+        );
+        merge_subtree(&mut root, src.clone());
+
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.children[0].name_str(), "docs");
+        assert!(root.children[0].children.is_empty());
+        assert!(Arc::ptr_eq(&root.children[1], &src));
+        assert_eq!(root.total_size(), 300);
+        assert!(Arc::ptr_eq(&src.parent().unwrap(), &root));
+        assert!(Arc::ptr_eq(&root.children[0].parent().unwrap(), &root));
+
+        // The listing handed out earlier is left alone
+        assert_eq!(listing.total_size(), 0);
+    }
+
+    #[test]
+    fn test_progress_stats_snapshot() {
+        let stats = ScanStats::new();
+        stats.increment_entries();
+        stats.increment_files();
+        stats.add_size(42);
+
+        let snapshot = ProgressStats::from_scan_stats(&stats);
+        stats.add_size(8);
+        assert_eq!(snapshot.total_entries, 1);
+        assert_eq!(snapshot.files, 1);
+        assert_eq!(snapshot.total_size, 42);
+    }
+}
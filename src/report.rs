@@ -0,0 +1,473 @@
+//! Non-interactive report module
+//!
+//! This module prints plain-text summaries of a scanned tree for use in
+//! scripts and cleanup triage, without starting the browser.
+
+use crate::config::Config;
+use crate::model::{Entry, EntryType};
+use crate::scanner::sort_entries;
+use crate::size::SizeFormatter;
+use crate::utils::format_file_size;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Collect every directory whose total size exceeds `threshold`, largest first
+pub fn directories_over(
+    root: &Entry,
+    root_path: &Path,
+    threshold: u64,
+    config: &Config,
+) -> Vec<(PathBuf, u64)> {
+    let sizes = SizeFormatter::new(config);
+    let mut found = Vec::new();
+    collect_directories_over(root, root_path.to_path_buf(), threshold, &sizes, &mut found);
+    found.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    found
+}
+
+fn collect_directories_over(
+    entry: &Entry,
+    path: PathBuf,
+    threshold: u64,
+    sizes: &SizeFormatter,
+    found: &mut Vec<(PathBuf, u64)>,
+) {
+    if !entry.entry_type.is_directory() {
+        return;
+    }
+
+    // Every directory below is part of this total and so no bigger; if this
+    // one doesn't exceed the threshold, none of them can
+    let size = sizes.total_value(entry);
+    if size <= threshold {
+        return;
+    }
+
+    for child in &entry.children {
+        collect_directories_over(child, path.join(&child.name), threshold, sizes, found);
+    }
+    found.push((path, size));
+}
+
+/// Print directories over `threshold` to stdout, one per line
+pub fn print_report_over(
+    root: &Entry,
+    root_path: &Path,
+    threshold: u64,
+    config: &Config,
+) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let sizes = SizeFormatter::new(config);
+    for (path, size) in directories_over(root, root_path, threshold, config) {
+        writeln!(out, "{}  {}", sizes.format_bytes(size), path.display())?;
+    }
+    Ok(())
+}
+
+/// Collect the `count` largest files anywhere below `root`, largest first
+///
+/// Only a bounded min-heap of the current top `count` is kept, so memory
+/// grows with `count` rather than with the size of the tree. Equal sizes are
+/// ordered by path.
+pub fn largest_files(
+    root: &Entry,
+    root_path: &Path,
+    count: usize,
+    config: &Config,
+) -> Vec<(PathBuf, u64)> {
+    let sizes = SizeFormatter::new(config);
+    // The smallest kept file sits on top, ready to be displaced
+    let mut heap: BinaryHeap<Reverse<(u64, Reverse<PathBuf>)>> = BinaryHeap::new();
+    let mut stack: Vec<(&Entry, PathBuf)> = vec![(root, root_path.to_path_buf())];
+
+    while let Some((entry, path)) = stack.pop() {
+        if entry.entry_type.is_directory() {
+            for child in &entry.children {
+                stack.push((child, path.join(&child.name)));
+            }
+            continue;
+        }
+        if !matches!(entry.entry_type, EntryType::File | EntryType::Hardlink) {
+            continue;
+        }
+
+        let candidate = Reverse((sizes.own_value(entry), Reverse(path)));
+        if heap.len() < count {
+            heap.push(candidate);
+        } else if heap.peek().is_some_and(|smallest| candidate < *smallest) {
+            heap.pop();
+            heap.push(candidate);
+        }
+    }
+
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((size, Reverse(path)))| (path, size))
+        .collect()
+}
+
+/// Print the `count` largest files to stdout, one per line
+pub fn print_largest(
+    root: &Entry,
+    root_path: &Path,
+    count: usize,
+    config: &Config,
+) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let sizes = SizeFormatter::new(config);
+    for (path, size) in largest_files(root, root_path, count, config) {
+        writeln!(out, "{}  {}", sizes.format_bytes(size), path.display())?;
+    }
+    Ok(())
+}
+
+/// Write `root` as an indented tree with sizes, like `tree` with `du` figures
+///
+/// Children are ordered by the configured sort and `config.max_depth` limits
+/// how many levels below the root are printed. The walk keeps its own stack
+/// so arbitrarily deep trees can't overflow the call stack.
+pub fn write_tree<W: Write>(
+    out: &mut W,
+    root: &Entry,
+    root_path: &Path,
+    config: &Config,
+) -> io::Result<()> {
+    let sizes = SizeFormatter::new(config);
+    writeln!(
+        out,
+        "{}  {}",
+        sizes.format_bytes(sizes.total_value(root)),
+        root_path.display()
+    )?;
+
+    // (entry, indentation before its branch, whether it is the last sibling, depth)
+    let mut stack: Vec<(Arc<Entry>, String, bool, usize)> = Vec::new();
+    push_tree_children(&mut stack, root, "", 1, config);
+
+    while let Some((entry, indent, is_last, depth)) = stack.pop() {
+        let (branch, continuation) = if is_last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        writeln!(
+            out,
+            "{}  {}{}{}",
+            sizes.format_bytes(sizes.total_value(&entry)),
+            indent,
+            branch,
+            entry.name_str()
+        )?;
+        let child_indent = format!("{}{}", indent, continuation);
+        push_tree_children(&mut stack, &entry, &child_indent, depth + 1, config);
+    }
+
+    Ok(())
+}
+
+fn push_tree_children(
+    stack: &mut Vec<(Arc<Entry>, String, bool, usize)>,
+    entry: &Entry,
+    indent: &str,
+    depth: usize,
+    config: &Config,
+) {
+    if config.max_depth.is_some_and(|max| depth > max) {
+        return;
+    }
+
+    let mut children = entry.children.clone();
+    sort_entries(&mut children, config);
+
+    let last = children.len().saturating_sub(1);
+    // Pushed in reverse so the first child is popped, and printed, first
+    for (i, child) in children.into_iter().enumerate().rev() {
+        stack.push((child, indent.to_string(), i == last, depth));
+    }
+}
+
+/// Print `root` as a tree to stdout
+pub fn print_tree(root: &Entry, root_path: &Path, config: &Config) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    write_tree(&mut out, root, root_path, config)
+}
+
+/// Format a size the way `du` does: whole KiB rounded up, or human-readable with `--si`
+fn du_size(size: u64, config: &Config) -> String {
+    if config.si {
+        format_file_size(size, true).trim_start().to_string()
+    } else {
+        size.div_ceil(1024).to_string()
+    }
+}
+
+/// Write `root` as `du` would: a "SIZE<tab>PATH" line per directory
+///
+/// Directories are listed after everything inside them, in scan order, and
+/// files only with `config.du_all` (or when the root itself is a file).
+pub fn write_du<W: Write>(
+    out: &mut W,
+    root: &Entry,
+    root_path: &Path,
+    config: &Config,
+) -> io::Result<()> {
+    let sizes = SizeFormatter::new(config);
+    // (entry, its path, whether its children have been written)
+    let mut stack: Vec<(&Entry, PathBuf, bool)> = vec![(root, root_path.to_path_buf(), false)];
+
+    while let Some((entry, path, children_done)) = stack.pop() {
+        let is_dir = entry.entry_type.is_directory();
+        if is_dir && !children_done {
+            stack.push((entry, path.clone(), true));
+            for child in entry.children.iter().rev() {
+                stack.push((child, path.join(&child.name), false));
+            }
+            continue;
+        }
+
+        if is_dir || config.du_all || std::ptr::eq(entry, root) {
+            writeln!(
+                out,
+                "{}\t{}",
+                du_size(sizes.total_value(entry), config),
+                path.display()
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Print `root` in `du` format to stdout
+pub fn print_du(root: &Entry, root_path: &Path, config: &Config) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    write_du(&mut out, root, root_path, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{generate_entry_id, EntryType};
+    use std::ffi::OsString;
+    use std::sync::Arc;
+
+    fn entry(
+        entry_type: EntryType,
+        name: &str,
+        size: u64,
+        children: Vec<Arc<Entry>>,
+    ) -> Arc<Entry> {
+        let mut entry = Entry::new(
+            generate_entry_id(),
+            entry_type,
+            OsString::from(name),
+            size,
+            size / 512,
+            1,
+            0,
+            1,
+        );
+        entry.children = children;
+        Arc::new(entry)
+    }
+
+    #[test]
+    fn test_directories_over_threshold() {
+        let big = entry(
+            EntryType::Directory,
+            "big",
+            0,
+            vec![entry(EntryType::File, "video", 5000, vec![])],
+        );
+        let small = entry(
+            EntryType::Directory,
+            "small",
+            0,
+            vec![entry(EntryType::File, "note", 100, vec![])],
+        );
+        let root = entry(
+            EntryType::Directory,
+            "data",
+            0,
+            vec![
+                big,
+                small,
+                entry(EntryType::File, "huge-file", 9000, vec![]),
+            ],
+        );
+        let config = Config {
+            show_blocks: false,
+            ..Config::default()
+        };
+
+        let report = directories_over(&root, Path::new("/data"), 1000, &config);
+        assert_eq!(
+            report,
+            vec![
+                (PathBuf::from("/data"), 14100),
+                (PathBuf::from("/data/big"), 5000),
+            ]
+        );
+
+        assert!(directories_over(&root, Path::new("/data"), 20000, &config).is_empty());
+
+        // Shallow sizes are a browsing aid; reports still count everything
+        let shallow = Config {
+            shallow_dir_sizes: Some(true),
+            ..config
+        };
+        assert_eq!(
+            directories_over(&root, Path::new("/data"), 1000, &shallow),
+            report
+        );
+    }
+
+    #[test]
+    fn test_largest_files() {
+        let root = entry(
+            EntryType::Directory,
+            "data",
+            0,
+            vec![
+                entry(EntryType::File, "a", 300, vec![]),
+                entry(
+                    EntryType::Directory,
+                    "logs",
+                    0,
+                    vec![
+                        entry(EntryType::File, "b", 900, vec![]),
+                        entry(EntryType::File, "c", 100, vec![]),
+                        entry(
+                            EntryType::Directory,
+                            "old",
+                            0,
+                            vec![entry(EntryType::File, "d", 500, vec![])],
+                        ),
+                    ],
+                ),
+                entry(EntryType::File, "e", 700, vec![]),
+                entry(EntryType::File, "f", 500, vec![]),
+            ],
+        );
+        let config = Config {
+            show_blocks: false,
+            ..Config::default()
+        };
+
+        assert_eq!(
+            largest_files(&root, Path::new("/data"), 3, &config),
+            vec![
+                (PathBuf::from("/data/logs/b"), 900),
+                (PathBuf::from("/data/e"), 700),
+                (PathBuf::from("/data/f"), 500),
+            ]
+        );
+        assert_eq!(
+            largest_files(&root, Path::new("/data"), 50, &config).len(),
+            6
+        );
+        assert!(largest_files(&root, Path::new("/data"), 0, &config).is_empty());
+    }
+
+    #[test]
+    fn test_tree_printout() {
+        let root = entry(
+            EntryType::Directory,
+            "data",
+            0,
+            vec![
+                entry(EntryType::File, "notes.txt", 1024, vec![]),
+                entry(
+                    EntryType::Directory,
+                    "logs",
+                    0,
+                    vec![
+                        entry(EntryType::File, "old.log", 4096, vec![]),
+                        entry(EntryType::File, "new.log", 2048, vec![]),
+                    ],
+                ),
+            ],
+        );
+        let mut config = Config {
+            show_blocks: false,
+            sort_col: crate::config::SortColumn::Size,
+            sort_order: crate::config::SortOrder::Desc,
+            sort_dirs_first: false,
+            ..Config::default()
+        };
+
+        let print = |config: &Config| {
+            let mut out = Vec::new();
+            write_tree(&mut out, &root, Path::new("/data"), config).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            print(&config),
+            concat!(
+                "     7 KiB  /data\n",
+                "     6 KiB  ├── logs\n",
+                "     4 KiB  │   ├── old.log\n",
+                "     2 KiB  │   └── new.log\n",
+                "     1 KiB  └── notes.txt\n",
+            )
+        );
+
+        config.max_depth = Some(1);
+        assert_eq!(
+            print(&config),
+            concat!(
+                "     7 KiB  /data\n",
+                "     6 KiB  ├── logs\n",
+                "     1 KiB  └── notes.txt\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_du_format() {
+        let root = entry(
+            EntryType::Directory,
+            "data",
+            0,
+            vec![
+                entry(EntryType::File, "notes.txt", 1000, vec![]),
+                entry(
+                    EntryType::Directory,
+                    "logs",
+                    0,
+                    vec![entry(EntryType::File, "old.log", 4096, vec![])],
+                ),
+            ],
+        );
+        let mut config = Config {
+            show_blocks: false,
+            ..Config::default()
+        };
+
+        let print = |config: &Config| {
+            let mut out = Vec::new();
+            write_du(&mut out, &root, Path::new("/data"), config).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(print(&config), "4\t/data/logs\n5\t/data\n");
+
+        config.du_all = true;
+        assert_eq!(
+            print(&config),
+            "1\t/data/notes.txt\n4\t/data/logs/old.log\n4\t/data/logs\n5\t/data\n"
+        );
+
+        config.si = true;
+        config.du_all = false;
+        assert_eq!(print(&config), "4.10 kB\t/data/logs\n5.10 kB\t/data\n");
+    }
+}
@@ -9,25 +9,34 @@
 //! - Progress reporting
 //! - Various filesystem filtering options
 
-use crate::config::Config;
+use crate::cli::SymlinkSize;
+use crate::config::{Config, ScanUi};
 use crate::error::{Result, RsduError};
 use crate::model::{
-    generate_entry_id, Entry, EntryType, ExtendedInfo, HardlinkInfo, HardlinkKey, HardlinkMap,
-    ScanStats, SortColumn, SortOrder,
+    generate_entry_id, DeviceId, Entry, EntryType, ErrorCategory, ExtendedInfo, HardlinkInfo,
+    HardlinkKey, HardlinkMap, HardlinkStats, ScanStats, SortColumn, SortOrder,
 };
-use crate::tui::{ProgressStats, ScanMessage};
+pub use crate::progress::{PartialCallback, ProgressCallback};
+use crate::progress::{PartialUpdate, ProgressStats};
+use crate::size::SizeFormatter;
+use crate::utils::{filesystem_usage, format_error_breakdown, FsUsage};
 use chrono::{DateTime, Utc};
+#[cfg(target_os = "linux")]
+use nix::sys::statfs::{self, FsType};
 use rayon::prelude::*;
 use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
 use std::fs::{self, DirEntry, Metadata};
+use std::io::{self, Write};
 use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
-use std::sync::{mpsc::Sender, Arc, Mutex};
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use walkdir::{DirEntry as WalkDirEntry, WalkDir};
 
-/// Pseudo-filesystem mount points to exclude on Linux
+/// Pseudo-filesystem mount points, used when the mount table can't be read
 const PSEUDO_FS: &[&str] = &[
     "/proc",
     "/sys",
@@ -58,41 +67,455 @@ const KERNEL_FS_TYPES: &[&str] = &[
     "binfmt_misc",
 ];
 
+/// Kernel mount table consulted for `--exclude-kernfs`
+const MOUNTS_FILE: &str = "/proc/mounts";
+
+/// One line of the kernel mount table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountSource {
+    /// What is mounted, usually a block device such as `/dev/sda1`
+    pub source: String,
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+}
+
+/// Parse a mount table in `/proc/mounts` format
+///
+/// Spaces and other awkward characters in sources and mount points are
+/// written as octal escapes like `\040`, which are decoded here.
+pub fn parse_mount_sources(contents: &str) -> Vec<MountSource> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let source = fields.next()?;
+            let mount_point = fields.next()?;
+            let fs_type = fields.next()?;
+            Some(MountSource {
+                source: unescape_mount_path(source).to_string_lossy().into_owned(),
+                mount_point: unescape_mount_path(mount_point),
+                fs_type: fs_type.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parse a mount table in `/proc/mounts` format into (mount point, type) pairs
+fn parse_mount_table(contents: &str) -> Vec<(PathBuf, String)> {
+    parse_mount_sources(contents)
+        .into_iter()
+        .map(|mount| (mount.mount_point, mount.fs_type))
+        .collect()
+}
+
+/// Mount table with the device of each mount, used to look mounts up by device
+const MOUNTINFO_FILE: &str = "/proc/self/mountinfo";
+
+/// Map device ids, as stored in `Entry::device`, to what is mounted there
+///
+/// Takes a table in `/proc/self/mountinfo` format, which names the device of
+/// each mount as `major:minor`, so nothing has to be looked up on disk. When
+/// a device is mounted more than once, the last mount listed wins.
+pub fn parse_mountinfo(contents: &str) -> HashMap<DeviceId, MountSource> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            // Optional fields end at a lone "-", after which come the
+            // filesystem type and the source
+            let (mount, filesystem) = line.split_once(" - ")?;
+            let mut fields = mount.split_whitespace();
+            let (major, minor) = fields.nth(2)?.split_once(':')?;
+            let mount_point = fields.nth(1)?;
+            let mut fields = filesystem.split_whitespace();
+            let fs_type = fields.next()?;
+            let source = fields.next()?;
+
+            let device = nix::sys::stat::makedev(major.parse().ok()?, minor.parse().ok()?);
+            let mount = MountSource {
+                source: unescape_mount_path(source).to_string_lossy().into_owned(),
+                mount_point: unescape_mount_path(mount_point),
+                fs_type: fs_type.to_string(),
+            };
+            Some((device, mount))
+        })
+        .collect()
+}
+
+/// Read the system mount table, keyed by device id; empty if it can't be read
+pub fn read_mounts_by_device() -> HashMap<DeviceId, MountSource> {
+    fs::read_to_string(MOUNTINFO_FILE)
+        .map(|contents| parse_mountinfo(&contents))
+        .unwrap_or_default()
+}
+
+/// Decode the octal escapes the kernel uses in mount table paths
+fn unescape_mount_path(escaped: &str) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    let bytes = escaped.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 4 <= bytes.len() {
+            let digits = std::str::from_utf8(&bytes[i + 1..i + 4]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(digits, 8) {
+                decoded.push(byte);
+                i += 4;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    PathBuf::from(std::ffi::OsString::from_vec(decoded))
+}
+
+/// Find the type of the filesystem `path` is on: that of the deepest mount above it
+fn mount_fs_type<'a>(mounts: &'a [(PathBuf, String)], path: &Path) -> Option<&'a str> {
+    mounts
+        .iter()
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.components().count())
+        .map(|(_, fs_type)| fs_type.as_str())
+}
+
+/// Check whether a filesystem type is a kernel pseudo filesystem not worth descending into
+///
+/// Their trees are synthesized on the fly: slow to walk at best, and through
+/// links like `/proc/*/root` effectively endless. tmpfs holds real files and
+/// is deliberately not listed.
+#[cfg(target_os = "linux")]
+fn is_pathological_fs_type(fs_type: FsType) -> bool {
+    [
+        statfs::PROC_SUPER_MAGIC,
+        statfs::SYSFS_MAGIC,
+        statfs::DEBUGFS_MAGIC,
+        statfs::TRACEFS_MAGIC,
+        statfs::SECURITYFS_MAGIC,
+        statfs::SELINUX_MAGIC,
+        statfs::SMACK_MAGIC,
+        statfs::CGROUP_SUPER_MAGIC,
+        statfs::CGROUP2_SUPER_MAGIC,
+        statfs::BPF_FS_MAGIC,
+        statfs::NSFS_MAGIC,
+        statfs::DEVPTS_SUPER_MAGIC,
+    ]
+    .contains(&fs_type)
+}
+
+/// Check whether `path` is on a pathological pseudo filesystem
+#[cfg(target_os = "linux")]
+fn is_pathological_fs(path: &Path) -> bool {
+    statfs::statfs(path)
+        .map(|stat| is_pathological_fs_type(stat.filesystem_type()))
+        .unwrap_or(false)
+}
+
+/// Pseudo filesystem magic numbers are Linux-specific; elsewhere nothing matches
+#[cfg(not(target_os = "linux"))]
+fn is_pathological_fs(_path: &Path) -> bool {
+    false
+}
+
+/// Minimum time between redraws of the line-mode progress line
+const LINE_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set by `request_stop` to wind down the scans in progress
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Ask running scans to stop; they then fail with `RsduError::UserCancelled`
+///
+/// This only stores to an atomic, so it is safe to call from a signal handler.
+pub fn request_stop() {
+    STOP_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Whether `request_stop` has been called
+fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Progress for `ScanUi::Line`: a header, a line redrawn in place, a summary
+///
+/// Everything goes to `out`, which is stderr outside of tests, so reports on
+/// stdout stay clean. The live line is only drawn when `live` is set, and is
+/// always ended with a newline before anything else is printed and when the
+/// scan fails or is stopped, so the terminal is never left mid-line.
+struct LineProgress<W: Write> {
+    out: W,
+    live: bool,
+    line_open: bool,
+    last_draw: Option<Instant>,
+}
+
+impl<W: Write> LineProgress<W> {
+    fn new(mut out: W, live: bool, path: &Path) -> Self {
+        let _ = writeln!(out, "Scanning directory: {}", path.display());
+        Self {
+            out,
+            live,
+            line_open: false,
+            last_draw: None,
+        }
+    }
+
+    /// Redraw the progress line, at most every `LINE_PROGRESS_INTERVAL`
+    fn update(&mut self, stats: &ProgressStats, path: &Path) {
+        if !self.live
+            || self
+                .last_draw
+                .is_some_and(|last| last.elapsed() < LINE_PROGRESS_INTERVAL)
+        {
+            return;
+        }
+        self.last_draw = Some(Instant::now());
+
+        let status = format!(
+            "{} entries, {} bytes  {}",
+            stats.total_entries,
+            stats.total_size,
+            path.display()
+        );
+        // Some pseudo terminals report no size at all
+        let width = match crate::utils::get_terminal_size().0 {
+            0 => 80,
+            columns => columns,
+        };
+        let _ = write!(
+            self.out,
+            "\r{}\x1b[K",
+            crate::utils::truncate_string(&status, width - 1)
+        );
+        let _ = self.out.flush();
+        self.line_open = true;
+    }
+
+    /// End the progress line, if one is showing
+    fn end_line(&mut self) {
+        if self.line_open {
+            let _ = writeln!(self.out);
+            self.line_open = false;
+        }
+    }
+
+    /// End the progress line and print the final statistics
+    fn finish(&mut self, stats: &ScanStats) {
+        self.end_line();
+        let out = &mut self.out;
+        let _ = writeln!(out, "\nScan complete:");
+        let _ = writeln!(out, "  Directories: {}", stats.get_directories());
+        let _ = writeln!(out, "  Files: {}", stats.get_files());
+        let _ = writeln!(out, "  Total entries: {}", stats.get_total_entries());
+        let _ = writeln!(out, "  Errors: {}", format_error_counts(stats));
+        let _ = writeln!(out, "  Total size: {} bytes", stats.get_total_size());
+        let _ = writeln!(out, "  Total blocks: {}", stats.get_total_blocks());
+        let _ = out.flush();
+    }
+}
+
+impl<W: Write> Drop for LineProgress<W> {
+    fn drop(&mut self) {
+        // A scan that bails out with an error still leaves a clean line
+        self.end_line();
+        let _ = self.out.flush();
+    }
+}
+
 /// Cache directory tag file name
 const CACHEDIR_TAG: &str = "CACHEDIR.TAG";
 
+/// Per-directory file of patterns excluding entries below that directory
+const IGNORE_FILE: &str = ".rsduignore";
+
+/// Source of file metadata and directory listings
+///
+/// Abstracted so that calls into slow or hung filesystems can be guarded.
+pub trait MetadataSource: Send + Sync {
+    /// Get metadata for a path, optionally following symlinks
+    fn metadata(&self, path: &Path, follow_symlinks: bool) -> io::Result<Metadata>;
+
+    /// List the entries of a directory
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+
+    /// Read the target a symlink holds
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Space usage of the filesystem holding a path
+    fn filesystem_usage(&self, path: &Path) -> io::Result<FsUsage>;
+}
+
+/// Metadata read directly from the filesystem
+pub struct FsMetadata;
+
+impl MetadataSource for FsMetadata {
+    fn metadata(&self, path: &Path, follow_symlinks: bool) -> io::Result<Metadata> {
+        get_metadata(path, follow_symlinks)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        Ok(fs::read_dir(path)?.filter_map(|entry| entry.ok()).collect())
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn filesystem_usage(&self, path: &Path) -> io::Result<FsUsage> {
+        filesystem_usage(path).ok_or_else(|| io::Error::other("cannot read filesystem usage"))
+    }
+}
+
+/// Wraps another source, failing calls that don't complete within a timeout
+///
+/// Each call runs on a worker thread. A call stuck in the kernel (such as a
+/// stat on an unresponsive NFS mount) cannot be cancelled, so its thread is
+/// left behind while the scan carries on.
+pub struct TimeoutMetadata {
+    inner: Arc<dyn MetadataSource>,
+    timeout: Duration,
+}
+
+impl TimeoutMetadata {
+    pub fn new(inner: Arc<dyn MetadataSource>, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+
+    /// Run `call` against the inner source on a worker thread
+    fn run<T, F>(&self, call: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn MetadataSource) -> io::Result<T> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let inner = self.inner.clone();
+        thread::spawn(move || {
+            let _ = sender.send(call(inner.as_ref()));
+        });
+
+        receiver.recv_timeout(self.timeout).unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("timed out after {} ms", self.timeout.as_millis()),
+            ))
+        })
+    }
+}
+
+impl MetadataSource for TimeoutMetadata {
+    fn metadata(&self, path: &Path, follow_symlinks: bool) -> io::Result<Metadata> {
+        let path = path.to_path_buf();
+        self.run(move |inner| inner.metadata(&path, follow_symlinks))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let path = path.to_path_buf();
+        self.run(move |inner| inner.read_dir(&path))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = path.to_path_buf();
+        self.run(move |inner| inner.read_link(&path))
+    }
+
+    fn filesystem_usage(&self, path: &Path) -> io::Result<FsUsage> {
+        let path = path.to_path_buf();
+        self.run(move |inner| inner.filesystem_usage(&path))
+    }
+}
+
 /// Scanner context for managing scan state
 pub struct ScanContext {
     config: Config,
     stats: Arc<ScanStats>,
     hardlinks: Arc<Mutex<HardlinkMap>>,
     exclude_patterns: Vec<glob::Pattern>,
+    /// `--include-only` patterns; when present everything else is pruned
+    include_patterns: Vec<glob::Pattern>,
+    /// `--follow-symlinks-matching` patterns, for the links to dereference
+    follow_patterns: Vec<glob::Pattern>,
+    /// How every pattern above and in `.rsduignore` files is matched
+    match_options: glob::MatchOptions,
+    /// Patterns from `.rsduignore` files, by the directory holding them
+    ignore_rules: RwLock<HashMap<PathBuf, Vec<glob::Pattern>>>,
     root_device: Option<u64>,
-    progress_sender: Option<Sender<ScanMessage>>,
+    progress: Option<Mutex<ProgressCallback>>,
+    /// When the scan context was created; progress times are counted from here
+    created: Instant,
+    /// Nanoseconds after `created` at which progress is next reported, for
+    /// `Config::progress_interval`
+    next_progress: AtomicU64,
+    /// Scan root whose children are reported early for incremental browsing
+    partial_root: Option<PathBuf>,
+    partial: Option<Mutex<PartialCallback>>,
+    /// (device, inode) of every directory descended into so far
+    visited_dirs: Mutex<HashSet<(u64, u64)>>,
+    metadata_source: Arc<dyn MetadataSource>,
+    /// Device of the scan root, which is scanned whatever its filesystem type
+    scan_root_device: Option<u64>,
+    /// Path the scan started from; why it can't be read goes into the stats
+    scan_root: Option<PathBuf>,
+    /// Whether each device seen holds a pathological pseudo filesystem
+    pseudo_fs_devices: Mutex<HashMap<u64, bool>>,
+    /// Devices whose used space `--stat-other-fs` already counted, so a
+    /// filesystem mounted more than once only adds up once
+    sized_other_fs: Mutex<HashSet<u64>>,
+    /// Mount points and their types, read for `--exclude-kernfs`
+    mounts: Option<Vec<(PathBuf, String)>>,
+    /// Progress printed to stderr in line mode when no callback is listening
+    line_progress: Option<Mutex<LineProgress<io::Stderr>>>,
+    /// Time spent on each directory itself, collected with `--profile-scan`
+    dir_timings: Option<Mutex<Vec<(PathBuf, Duration)>>>,
 }
 
 impl ScanContext {
-    fn new(config: Config, progress_sender: Option<Sender<ScanMessage>>) -> Result<Self> {
-        let mut exclude_patterns = Vec::new();
-        for pattern_str in &config.exclude_patterns {
-            match glob::Pattern::new(pattern_str) {
-                Ok(pattern) => exclude_patterns.push(pattern),
-                Err(e) => {
-                    return Err(RsduError::ConfigError(format!(
-                        "Invalid exclude pattern '{}': {}",
-                        pattern_str, e
-                    )));
-                }
-            }
+    fn new(config: Config, progress: Option<ProgressCallback>) -> Result<Self> {
+        let exclude_patterns = compile_patterns(&config.exclude_patterns, "exclude")?;
+        let include_patterns = compile_patterns(&config.include_patterns, "include")?;
+        let follow_patterns = compile_patterns(&config.follow_symlink_patterns, "follow")?;
+        let match_options = glob::MatchOptions {
+            case_sensitive: !config.ignore_case_patterns,
+            ..glob::MatchOptions::new()
+        };
+
+        let mut metadata_source: Arc<dyn MetadataSource> = Arc::new(FsMetadata);
+        if let Some(timeout) = config.io_timeout {
+            metadata_source = Arc::new(TimeoutMetadata::new(metadata_source, timeout));
         }
 
+        let mounts = if config.exclude_kernfs {
+            fs::read_to_string(MOUNTS_FILE)
+                .ok()
+                .map(|contents| parse_mount_table(&contents))
+        } else {
+            None
+        };
+
+        let dir_timings = config.profile_scan.map(|_| Mutex::new(Vec::new()));
+
         Ok(Self {
             config,
             stats: Arc::new(ScanStats::new()),
             hardlinks: Arc::new(Mutex::new(HashMap::new())),
             exclude_patterns,
+            include_patterns,
+            follow_patterns,
+            match_options,
+            ignore_rules: RwLock::new(HashMap::new()),
             root_device: None,
-            progress_sender,
+            progress: progress.map(Mutex::new),
+            created: Instant::now(),
+            next_progress: AtomicU64::new(0),
+            partial_root: None,
+            partial: None,
+            visited_dirs: Mutex::new(HashSet::new()),
+            scan_root_device: None,
+            scan_root: None,
+            pseudo_fs_devices: Mutex::new(HashMap::new()),
+            sized_other_fs: Mutex::new(HashSet::new()),
+            mounts,
+            line_progress: None,
+            metadata_source,
+            dir_timings,
         })
     }
 
@@ -101,10 +524,178 @@ impl ScanContext {
         let path_str = path.to_string_lossy();
         self.exclude_patterns
             .iter()
-            .any(|pattern| pattern.matches(&path_str))
+            .any(|pattern| pattern.matches_with(&path_str, self.match_options))
+    }
+
+    /// Whether `path` or a directory above it, up to the scan root, matches
+    /// an `--include-only` pattern
+    ///
+    /// As in `.rsduignore`, a pattern with a `/` matches the path relative to
+    /// the scan root and any other pattern matches a name.
+    fn matches_include_only(&self, path: &Path) -> bool {
+        let relative = self
+            .scan_root
+            .as_deref()
+            .and_then(|root| path.strip_prefix(root).ok())
+            .unwrap_or(path);
+        relative.ancestors().any(|prefix| {
+            let Some(name) = prefix.file_name() else {
+                return false;
+            };
+            let (name, prefix) = (name.to_string_lossy(), prefix.to_string_lossy());
+            self.include_patterns.iter().any(|pattern| {
+                if pattern.as_str().contains('/') {
+                    pattern.matches_with(&prefix, self.match_options)
+                } else {
+                    pattern.matches_with(&name, self.match_options)
+                }
+            })
+        })
+    }
+
+    /// Whether `--include-only` is waiting on what lies below `path` to
+    /// decide if it is kept
+    ///
+    /// Such a directory is only counted once something below it is kept.
+    /// The scan root is always kept.
+    fn awaits_include_match(&self, path: &Path) -> bool {
+        !self.include_patterns.is_empty()
+            && self.scan_root.as_deref() != Some(path)
+            && !self.matches_include_only(path)
+    }
+
+    /// Whether `--include-only` leaves out `dir_entry` without scanning it
+    ///
+    /// Anything that is not a directory and matches nothing is skipped.
+    /// Directories are descended into, as something below may match.
+    fn skips_for_include_only(&self, dir_entry: &DirEntry) -> bool {
+        let path = dir_entry.path();
+        if !self.awaits_include_match(&path) {
+            return false;
+        }
+        let is_dir = match dir_entry.file_type() {
+            Ok(file_type) if file_type.is_symlink() && self.follows_symlink(&path) => self
+                .metadata_source
+                .metadata(&path, true)
+                .map_or(true, |metadata| metadata.is_dir()),
+            Ok(file_type) => file_type.is_dir(),
+            // Scanned anyway, so the error shows up
+            Err(_) => true,
+        };
+        !is_dir
+    }
+
+    /// Whether a scanned child survives `--include-only`
+    ///
+    /// It does if it or one of its ancestors matches, if it is a directory
+    /// still holding something that does (its children were already pruned),
+    /// or if it is an error, which may hide matches.
+    fn keeps_for_include_only(&self, path: &Path, entry: &Entry) -> bool {
+        !self.awaits_include_match(path)
+            || entry.has_error()
+            || (entry.entry_type.is_directory() && !entry.children.is_empty())
+    }
+
+    /// Whether the symlink at `path` is followed
+    ///
+    /// Without `--follow-symlinks`, only links whose name or target (as the
+    /// link holds it) matches a `--follow-symlinks-matching` pattern are.
+    fn follows_symlink(&self, path: &Path) -> bool {
+        if self.config.follow_symlinks {
+            return true;
+        }
+        if self.follow_patterns.is_empty() {
+            return false;
+        }
+        let name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        if self
+            .follow_patterns
+            .iter()
+            .any(|pattern| pattern.matches_with(&name, self.match_options))
+        {
+            return true;
+        }
+        let Ok(target) = self.metadata_source.read_link(path) else {
+            return false;
+        };
+        let target = target.to_string_lossy();
+        self.follow_patterns
+            .iter()
+            .any(|pattern| pattern.matches_with(&target, self.match_options))
     }
 
-    /// Check if a path is on a different filesystem
+    /// Metadata of the entry at `path`, of its target if it is a symlink to follow
+    ///
+    /// Links are only read when the entry turns out to be one.
+    fn entry_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        if self.config.follow_symlinks {
+            return self.metadata_source.metadata(path, true);
+        }
+        let metadata = self.metadata_source.metadata(path, false)?;
+        if metadata.file_type().is_symlink() && self.follows_symlink(path) {
+            return self.metadata_source.metadata(path, true);
+        }
+        Ok(metadata)
+    }
+
+    /// Read the `.rsduignore` patterns of `dir`, if it has the file
+    ///
+    /// One glob per line; blank lines, `#` comments and invalid globs are
+    /// skipped. A pattern containing `/` is matched against paths relative to
+    /// `dir`, any other against entry names anywhere below it.
+    fn load_ignore_file(&self, dir: &Path) {
+        if !self.config.read_ignore_files {
+            return;
+        }
+        let Ok(contents) = fs::read_to_string(dir.join(IGNORE_FILE)) else {
+            return;
+        };
+        let patterns: Vec<_> = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| glob::Pattern::new(line.trim_start_matches('/')).ok())
+            .collect();
+        if !patterns.is_empty() {
+            self.ignore_rules
+                .write()
+                .unwrap()
+                .insert(dir.to_path_buf(), patterns);
+        }
+    }
+
+    /// Check if a `.rsduignore` in one of the path's ancestors excludes it
+    fn is_ignored(&self, path: &Path) -> bool {
+        let rules = self.ignore_rules.read().unwrap();
+        if rules.is_empty() {
+            return false;
+        }
+        let name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        path.ancestors().skip(1).any(|dir| {
+            let (Some(patterns), Ok(relative)) = (rules.get(dir), path.strip_prefix(dir)) else {
+                return false;
+            };
+            let relative = relative.to_string_lossy();
+            patterns.iter().any(|pattern| {
+                if pattern.as_str().contains('/') {
+                    pattern.matches_with(&relative, self.match_options)
+                } else {
+                    pattern.matches_with(&name, self.match_options)
+                }
+            })
+        })
+    }
+
+    /// Check if an entry on `device` lies beyond the `--one-file-system` boundary
+    ///
+    /// The boundary is the scan root's device, so the root itself is always
+    /// on the near side of it.
     fn is_different_filesystem(&self, device: u64) -> bool {
         if !self.config.same_fs {
             return false;
@@ -116,12 +707,44 @@ impl ScanContext {
         }
     }
 
+    /// Size and blocks of an entry on another filesystem, for `--stat-other-fs`
+    ///
+    /// A mount point is sized by the space used on the filesystem mounted
+    /// there, which is what scanning it would roughly have added up to. Only
+    /// the first mount of each device counts it; later ones, such as bind
+    /// mounts, are sized 0. Anything else is sized by its own metadata, as is
+    /// a mount point whose filesystem can't be read in time.
+    fn other_fs_size(&self, path: &Path, metadata: &Metadata) -> (u64, u64) {
+        if !metadata.is_dir() {
+            return (metadata.len(), metadata.blocks());
+        }
+        match self.metadata_source.filesystem_usage(path) {
+            Ok(usage) => {
+                if self.sized_other_fs.lock().unwrap().insert(metadata.dev()) {
+                    (usage.used(), usage.used().div_ceil(512))
+                } else {
+                    (0, 0)
+                }
+            }
+            Err(_) => (metadata.len(), metadata.blocks()),
+        }
+    }
+
     /// Check if a path is a kernel filesystem
+    ///
+    /// Goes by the type of the filesystem it is mounted on, so a tmpfs at
+    /// `/mnt/ram` is caught while a disk-backed `/tmp` is not. Without a
+    /// readable mount table this falls back to well-known mount points.
     fn is_kernel_filesystem(&self, path: &Path) -> bool {
         if !self.config.exclude_kernfs {
             return false;
         }
 
+        if let Some(ref mounts) = self.mounts {
+            return mount_fs_type(mounts, path)
+                .is_some_and(|fs_type| KERNEL_FS_TYPES.contains(&fs_type));
+        }
+
         let path_str = path.to_string_lossy();
         PSEUDO_FS.iter().any(|&fs_path| {
             path_str.starts_with(fs_path)
@@ -130,13 +753,168 @@ impl ScanContext {
         })
     }
 
-    /// Check if a directory contains CACHEDIR.TAG
-    fn has_cachedir_tag(&self, dir_path: &Path) -> bool {
-        if !self.config.exclude_caches {
+    /// Check whether a directory lives on a pathological pseudo filesystem
+    ///
+    /// Unlike `is_kernel_filesystem` this applies even without
+    /// `--exclude-kernfs`, and goes by filesystem type so that pseudo
+    /// filesystems mounted in unusual places are caught too. The type is
+    /// looked up once per device.
+    fn is_pseudo_filesystem(&self, path: &Path, device: u64) -> bool {
+        if self.scan_root_device == Some(device) {
+            return false;
+        }
+
+        *self
+            .pseudo_fs_devices
+            .lock()
+            .unwrap()
+            .entry(device)
+            .or_insert_with(|| is_pathological_fs(path))
+    }
+
+    /// Record a directory as visited, returning false if it was seen before
+    ///
+    /// The same directory reached twice (through a bind mount or a followed
+    /// symlink) would otherwise be counted twice, or loop forever.
+    fn mark_directory_visited(&self, device: u64, inode: u64) -> bool {
+        self.visited_dirs.lock().unwrap().insert((device, inode))
+    }
+
+    /// Report progress on reaching `path`, if anyone is listening
+    ///
+    /// Reports come at most every `progress_interval`; the entries reached in
+    /// between are left out, but the final one always goes out (see
+    /// `report_final_progress`).
+    fn report_progress(&self, path: &Path) {
+        if (self.progress.is_none() && self.line_progress.is_none()) || !self.progress_due() {
+            return;
+        }
+        if let Some(ref progress) = self.progress {
+            let stats = ProgressStats::from_scan_stats(&self.stats);
+            (progress.lock().unwrap())(&stats, path);
+        } else if let Some(ref line) = self.line_progress {
+            // Skip the update rather than queue scan threads behind the terminal
+            if let Ok(mut line) = line.try_lock() {
+                line.update(&ProgressStats::from_scan_stats(&self.stats), path);
+            }
+        }
+    }
+
+    /// Report the counts of the finished scan to the progress callback
+    ///
+    /// Line progress prints its own summary when it finishes.
+    fn report_final_progress(&self, path: &Path) {
+        if let Some(ref progress) = self.progress {
+            let stats = ProgressStats::from_scan_stats(&self.stats);
+            (progress.lock().unwrap())(&stats, path);
+        }
+    }
+
+    /// Whether `progress_interval` has passed since progress was last reported
+    ///
+    /// Lock-free, so scan threads never wait on each other here; when several
+    /// find a report due at once, only the one that claims it reports.
+    fn progress_due(&self) -> bool {
+        let interval = self.config.progress_interval;
+        if interval.is_zero() {
+            return true;
+        }
+        let now = self.created.elapsed().as_nanos() as u64;
+        let next = self.next_progress.load(Ordering::Relaxed);
+        if now < next {
             return false;
         }
-        dir_path.join(CACHEDIR_TAG).exists()
+        let following = now.saturating_add(interval.as_nanos() as u64);
+        self.next_progress
+            .compare_exchange(next, following, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Whether the children of `dir_path` are reported as they finish
+    fn reports_partial(&self, dir_path: &Path) -> bool {
+        self.partial.is_some() && self.partial_root.as_deref() == Some(dir_path)
     }
+
+    /// Hand a part of the tree to the partial callback
+    fn report_partial(&self, update: PartialUpdate) {
+        if let Some(ref partial) = self.partial {
+            (partial.lock().unwrap())(update);
+        }
+    }
+
+    /// Report the scan root's children, by name and type only
+    ///
+    /// The root and its children are all marked incomplete; each child is
+    /// replaced by its finished subtree, and the root by the finished scan.
+    fn report_listing(&self, dir_path: &Path, dir_entries: &[DirEntry]) {
+        let mut listing = Entry::new(
+            generate_entry_id(),
+            EntryType::Directory,
+            dir_path
+                .file_name()
+                .unwrap_or(dir_path.as_os_str())
+                .to_os_string(),
+            0,
+            0,
+            0,
+            0,
+            1,
+        );
+        listing.complete = false;
+        for dir_entry in dir_entries {
+            let entry_type = match dir_entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => EntryType::Directory,
+                Ok(file_type) if file_type.is_symlink() => EntryType::Symlink,
+                _ => EntryType::File,
+            };
+            let mut placeholder = Entry::new(
+                generate_entry_id(),
+                entry_type,
+                dir_entry.file_name(),
+                0,
+                0,
+                0,
+                0,
+                1,
+            );
+            placeholder.complete = false;
+            listing.children.push(Arc::new(placeholder));
+        }
+        self.report_partial(PartialUpdate::Listing(listing.into_linked()));
+    }
+
+    /// Record the time spent on a directory, if profiling
+    fn record_directory_time(&self, path: &Path, elapsed: Duration) {
+        if let Some(ref timings) = self.dir_timings {
+            timings.lock().unwrap().push((path.to_path_buf(), elapsed));
+        }
+    }
+
+    /// The `count` directories that took longest, slowest first
+    fn slowest_directories(&self, count: usize) -> Vec<(PathBuf, Duration)> {
+        let mut timings = match self.dir_timings {
+            Some(ref timings) => timings.lock().unwrap().clone(),
+            None => return Vec::new(),
+        };
+        timings.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        timings.truncate(count);
+        timings
+    }
+
+    /// Check if a directory holds CACHEDIR.TAG or an `--exclude-if-present` marker
+    fn has_exclude_marker(&self, dir_path: &Path) -> bool {
+        (self.config.exclude_caches && has_marker_file(dir_path, CACHEDIR_TAG))
+            || self
+                .config
+                .exclude_markers
+                .iter()
+                .any(|name| has_marker_file(dir_path, name))
+    }
+}
+
+/// Check if `dir` contains an entry named `name`
+fn has_marker_file(dir: &Path, name: &str) -> bool {
+    dir.join(name).symlink_metadata().is_ok()
 }
 
 /// Scan a directory and return the root entry
@@ -148,53 +926,104 @@ pub fn scan_directory(path: &Path, config: &Config) -> Result<Arc<Entry>> {
 pub fn scan_directory_with_progress(
     path: &Path,
     config: &Config,
-    progress_sender: Option<Sender<ScanMessage>>,
+    progress: Option<ProgressCallback>,
 ) -> Result<Arc<Entry>> {
-    let mut context = ScanContext::new(config.clone(), progress_sender)?;
+    scan_directory_incremental(path, config, progress, None)
+}
 
-    // Get the root device for filesystem boundary checking
-    if config.same_fs {
-        match fs::metadata(path) {
-            Ok(metadata) => {
+/// Scan a directory, handing out parts of the tree before the scan completes
+///
+/// The `partial` callback first receives the root's listing and then each
+/// direct child of the root as soon as its subtree is scanned; see
+/// `PartialCallback`. The complete tree is still returned at the end.
+pub fn scan_directory_incremental(
+    path: &Path,
+    config: &Config,
+    progress: Option<ProgressCallback>,
+    partial: Option<PartialCallback>,
+) -> Result<Arc<Entry>> {
+    let stats = Arc::new(ScanStats::new());
+    scan_directory_counting(path, config, progress, partial, stats)
+}
+
+/// `scan_directory_incremental`, counting into `stats`
+///
+/// The counters are added to, not reset, so several scans can share them.
+pub fn scan_directory_counting(
+    path: &Path,
+    config: &Config,
+    progress: Option<ProgressCallback>,
+    partial: Option<PartialCallback>,
+    stats: Arc<ScanStats>,
+) -> Result<Arc<Entry>> {
+    let mut context = ScanContext::new(config.clone(), progress)?;
+    context.stats = stats;
+    context.scan_root = Some(path.to_path_buf());
+    if partial.is_some() {
+        context.partial_root = Some(path.to_path_buf());
+        context.partial = partial.map(Mutex::new);
+    }
+
+    // The root's own device is the baseline for `--one-file-system`. A root
+    // that is itself a mount point is scanned as the filesystem mounted
+    // there, and only what lies below it on other devices becomes `OtherFs`.
+    // Scanning a pseudo filesystem on purpose is allowed for the same reason.
+    match context
+        .metadata_source
+        .metadata(path, config.follow_arg_symlinks)
+    {
+        Ok(metadata) => {
+            context.scan_root_device = Some(metadata.dev());
+            if config.same_fs {
                 context.root_device = Some(metadata.dev());
             }
-            Err(e) => {
-                return Err(RsduError::scan_error(
-                    path,
-                    format!("Cannot read root directory metadata: {}", e),
-                ));
-            }
         }
+        Err(e) if config.same_fs => {
+            return Err(RsduError::scan_error(
+                path,
+                format!("Cannot read root directory metadata: {}", e),
+            ));
+        }
+        Err(e) => context.stats.record_root_error(e),
     }
 
     // Send initial progress update
-    if let Some(ref sender) = context.progress_sender {
-        let _ = sender.send(ScanMessage::Progress {
-            current_path: path.display().to_string(),
-            stats: ProgressStats::from_scan_stats(&context.stats),
-        });
-    } else {
-        println!("Scanning directory: {}", path.display());
+    if context.progress.is_some() {
+        context.report_progress(path);
+    } else if config.scan_ui != Some(ScanUi::None) {
+        let live = crate::utils::stderr_is_tty();
+        context.line_progress = Some(Mutex::new(LineProgress::new(io::stderr(), live, path)));
     }
 
-    // Perform the scan
-    let root_entry = scan_entry(path, &context)?;
+    // Perform the scan; the root argument has its own symlink setting
+    let root_entry = scan_entry_from(path, &context, |path| {
+        context
+            .metadata_source
+            .metadata(path, config.follow_arg_symlinks)
+    })?;
+    if stop_requested() {
+        return Err(RsduError::UserCancelled);
+    }
+    context.report_final_progress(path);
 
-    // Send completion message or print statistics
-    if let Some(ref sender) = context.progress_sender {
-        let _ = sender.send(ScanMessage::Complete {
-            root: root_entry.clone(),
-        });
-    } else {
-        // Print final statistics for non-TUI mode
-        let stats = &context.stats;
-        println!("\nScan complete:");
-        println!("  Directories: {}", stats.get_directories());
-        println!("  Files: {}", stats.get_files());
-        println!("  Total entries: {}", stats.get_total_entries());
-        println!("  Errors: {}", stats.get_errors());
-        println!("  Total size: {} bytes", stats.get_total_size());
-        println!("  Total blocks: {}", stats.get_total_blocks());
+    // Print statistics unless the caller is following progress itself
+    if let Some(ref line) = context.line_progress {
+        line.lock().unwrap().finish(&context.stats);
+    }
+
+    // Kept off stdout so reports stay clean; the TUI has no room for it
+    if let (Some(count), None) = (config.profile_scan, &context.progress) {
+        eprintln!("\nSlowest directories:");
+        for (dir, elapsed) in context.slowest_directories(count) {
+            eprintln!("  {:>9.3}s  {}", elapsed.as_secs_f64(), dir.display());
+        }
+    }
+    if config.hardlink_stats == Some(true) && context.progress.is_none() {
+        let stats = HardlinkStats::from_map(&context.hardlinks.lock().unwrap());
+        eprintln!(
+            "\nHardlink groups: {}, total extra links: {}",
+            stats.groups, stats.extra_links
+        );
     }
 
     Ok(root_entry)
@@ -202,18 +1031,22 @@ pub fn scan_directory_with_progress(
 
 /// Scan a single entry (file or directory)
 fn scan_entry(path: &Path, context: &ScanContext) -> Result<Arc<Entry>> {
-    // Send real-time progress update for every file for scanning screen
-    if let Some(ref sender) = context.progress_sender {
-        let _ = sender.send(ScanMessage::Progress {
-            current_path: path.display().to_string(),
-            stats: ProgressStats::from_scan_stats(&context.stats),
-        });
-    }
+    scan_entry_from(path, context, |path| context.entry_metadata(path))
+}
+
+/// Scan a single entry, with its metadata read by `read_metadata`
+fn scan_entry_from(
+    path: &Path,
+    context: &ScanContext,
+    read_metadata: impl FnOnce(&Path) -> io::Result<Metadata>,
+) -> Result<Arc<Entry>> {
+    // Report progress for every entry, for the scanning screen
+    context.report_progress(path);
     // Get metadata
-    let metadata = match get_metadata(path, context.config.follow_symlinks) {
+    let metadata = match read_metadata(path) {
         Ok(meta) => meta,
         Err(e) => {
-            context.stats.increment_errors();
+            context.stats.record_error(ErrorCategory::from_io_error(&e));
             let error_msg = format!("Cannot read metadata: {}", e);
             return Ok(Arc::new(Entry::error(
                 generate_entry_id(),
@@ -225,53 +1058,85 @@ fn scan_entry(path: &Path, context: &ScanContext) -> Result<Arc<Entry>> {
 
     // Check filesystem boundaries
     if context.is_different_filesystem(metadata.dev()) {
+        let (size, blocks) = if context.config.stat_other_fs {
+            context.other_fs_size(path, &metadata)
+        } else {
+            (0, 0)
+        };
         return Ok(Arc::new(Entry::new(
             generate_entry_id(),
             EntryType::OtherFs,
             path.file_name().unwrap_or(path.as_os_str()).to_os_string(),
-            0,
-            0,
-            metadata.dev() as u32,
+            size,
+            blocks,
+            metadata.dev(),
             metadata.ino(),
             metadata.nlink() as u32,
         )));
     }
 
     // Check for kernel filesystems
-    if context.is_kernel_filesystem(path) {
+    if context.is_kernel_filesystem(path)
+        || (metadata.is_dir() && context.is_pseudo_filesystem(path, metadata.dev()))
+    {
         return Ok(Arc::new(Entry::new(
             generate_entry_id(),
             EntryType::KernelFs,
             path.file_name().unwrap_or(path.as_os_str()).to_os_string(),
             0,
             0,
-            metadata.dev() as u32,
+            metadata.dev(),
             metadata.ino(),
             metadata.nlink() as u32,
         )));
     }
 
-    // Check exclusion patterns
-    if context.is_excluded_by_pattern(path) {
+    // Check exclusion patterns, global and from `.rsduignore` files
+    if context.is_excluded_by_pattern(path) || context.is_ignored(path) {
         return Ok(Arc::new(Entry::new(
             generate_entry_id(),
             EntryType::Excluded,
             path.file_name().unwrap_or(path.as_os_str()).to_os_string(),
             0,
             0,
-            metadata.dev() as u32,
+            metadata.dev(),
+            metadata.ino(),
+            metadata.nlink() as u32,
+        )));
+    }
+
+    // Check for directories already scanned under another path
+    if metadata.is_dir() && !context.mark_directory_visited(metadata.dev(), metadata.ino()) {
+        return Ok(Arc::new(Entry::new(
+            generate_entry_id(),
+            EntryType::BindMount,
+            path.file_name().unwrap_or(path.as_os_str()).to_os_string(),
+            0,
+            0,
+            metadata.dev(),
             metadata.ino(),
             metadata.nlink() as u32,
         )));
     }
 
     let file_type = get_entry_type(&metadata, path);
-    let size = metadata.len();
-    let blocks = metadata.blocks();
+    let (size, blocks) = if file_type == EntryType::Symlink {
+        symlink_size(path, &metadata, context)
+    } else {
+        (metadata.len(), metadata.blocks())
+    };
 
-    context.stats.increment_entries();
-    context.stats.add_size(size);
-    context.stats.add_blocks(blocks);
+    // Under `--include-only`, a directory matching nothing is only counted
+    // once something below it is kept
+    let deferred = file_type == EntryType::Directory && context.awaits_include_match(path);
+    let count_entry = || {
+        context.stats.increment_entries();
+        context.stats.add_size(size);
+        context.stats.add_blocks(blocks);
+    };
+    if !deferred {
+        count_entry();
+    }
 
     let mut entry = Entry::new(
         generate_entry_id(),
@@ -279,14 +1144,14 @@ fn scan_entry(path: &Path, context: &ScanContext) -> Result<Arc<Entry>> {
         path.file_name().unwrap_or(path.as_os_str()).to_os_string(),
         size,
         blocks,
-        metadata.dev() as u32,
+        metadata.dev(),
         metadata.ino(),
         metadata.nlink() as u32,
     );
 
     // Handle hardlinks
     if metadata.nlink() > 1 && file_type == EntryType::File {
-        let hardlink_key = HardlinkKey::new(metadata.dev() as u32, metadata.ino());
+        let hardlink_key = HardlinkKey::new(metadata.dev(), metadata.ino());
         let mut hardlinks = context.hardlinks.lock().unwrap();
 
         match hardlinks.get_mut(&hardlink_key) {
@@ -313,25 +1178,21 @@ fn scan_entry(path: &Path, context: &ScanContext) -> Result<Arc<Entry>> {
 
     // Add extended information if requested
     if context.config.extended {
-        entry.extended = Some(ExtendedInfo {
-            mtime: metadata.modified().ok().and_then(|t| {
-                DateTime::from_timestamp(
-                    t.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs() as i64,
-                    0,
-                )
-            }),
-            uid: Some(metadata.uid()),
-            gid: Some(metadata.gid()),
-            mode: Some(metadata.mode()),
-        });
+        entry.extended = Some(extended_info(&metadata, metadata.modified()));
     }
 
     // Handle directories
     if file_type == EntryType::Directory {
-        context.stats.increment_directories();
+        let count_directory = || {
+            count_entry();
+            context.stats.increment_directories();
+        };
+        if !deferred {
+            context.stats.increment_directories();
+        }
 
-        // Check for cache directory tag
-        if context.has_cachedir_tag(path) {
+        // Check for cache directory tags and other exclusion markers
+        if context.has_exclude_marker(path) {
             entry.entry_type = EntryType::Excluded;
             return Ok(Arc::new(entry));
         }
@@ -339,18 +1200,25 @@ fn scan_entry(path: &Path, context: &ScanContext) -> Result<Arc<Entry>> {
         // Scan directory contents
         match scan_directory_contents(path, context) {
             Ok(mut children) => {
+                if deferred && !children.is_empty() {
+                    count_directory();
+                }
                 // Sort children if requested
                 sort_entries(&mut children, &context.config);
 
-                // Convert to Arc and add to entry
                 let mut entry = entry;
-                for child in children {
-                    entry.children.push(child);
+                entry.children = children;
+                if context.config.newest_mtime {
+                    take_newest_child_mtime(&mut entry);
                 }
-                Ok(Arc::new(entry))
+                Ok(entry.into_linked())
             }
             Err(e) => {
-                context.stats.increment_errors();
+                // Kept whatever `--include-only` says, as it may hide matches
+                if deferred {
+                    count_directory();
+                }
+                // Already counted, by category, in scan_directory_contents
                 entry.error = Some(format!("Error scanning directory: {}", e));
                 entry.entry_type = EntryType::Error;
                 Ok(Arc::new(entry))
@@ -363,55 +1231,146 @@ fn scan_entry(path: &Path, context: &ScanContext) -> Result<Arc<Entry>> {
 }
 
 /// Scan the contents of a directory
+///
+/// For `--profile-scan` the directory is charged with reading it and its
+/// direct entries, but not with the time spent inside its subdirectories.
 fn scan_directory_contents(dir_path: &Path, context: &ScanContext) -> Result<Vec<Arc<Entry>>> {
-    let entries = match fs::read_dir(dir_path) {
+    // The partial tree is thrown away, so unwind as quickly as possible
+    if stop_requested() {
+        return Ok(Vec::new());
+    }
+
+    let started = Instant::now();
+    let entries = match context.metadata_source.read_dir(dir_path) {
         Ok(entries) => entries,
         Err(e) => {
-            return Err(RsduError::scan_error(
-                dir_path,
-                format!("Cannot read directory: {}", e),
-            ));
+            context.stats.record_error(ErrorCategory::from_io_error(&e));
+            let error = RsduError::scan_error(dir_path, format!("Cannot read directory: {}", e));
+            if context.scan_root.as_deref() == Some(dir_path) {
+                context.stats.record_root_error(e);
+            }
+            return Err(error);
         }
     };
 
-    let mut children = Vec::new();
+    // Its own ignore file applies to everything below the directory
+    if entries.iter().any(|entry| entry.file_name() == IGNORE_FILE) {
+        context.load_ignore_file(dir_path);
+    }
 
-    // Use parallel processing if we have multiple threads configured
-    if context.config.threads > 1 {
-        // Collect entries first
-        let dir_entries: Vec<_> = entries
-            .filter_map(|entry| entry.ok())
-            .filter(|entry| should_include_entry(entry, context))
-            .collect();
+    let dir_entries: Vec<_> = entries
+        .into_iter()
+        .filter(|entry| should_include_entry(entry, context))
+        .filter(|entry| !context.skips_for_include_only(entry))
+        .collect();
 
-        // Process in parallel
-        let parallel_children: Vec<Arc<Entry>> = dir_entries
-            .into_par_iter()
-            .map(|dir_entry| scan_entry(&dir_entry.path(), context))
-            .filter_map(|result| match result {
-                Ok(entry) => Some(entry),
-                Err(_) => None, // Errors are handled in scan_entry
-            })
-            .collect();
+    let reports_partial = context.reports_partial(dir_path);
+    if reports_partial {
+        context.report_listing(dir_path, &dir_entries);
+    }
 
-        children = parallel_children;
+    let mut own_time = started.elapsed();
+
+    // Directories that hold nothing `--include-only` keeps come back as None
+    let timed_scan = |dir_entry: DirEntry| {
+        let started = Instant::now();
+        let path = dir_entry.path();
+        let result = scan_entry(&path, context).map(|child| {
+            context
+                .keeps_for_include_only(&path, &child)
+                .then_some(child)
+        });
+        if let (true, Ok(Some(child))) = (reports_partial, &result) {
+            context.report_partial(PartialUpdate::Subtree(child.clone()));
+        }
+        (result, started.elapsed())
+    };
+
+    // Use parallel processing if we have multiple threads configured
+    let results: Vec<_> = if context.config.threads > 1 {
+        dir_entries.into_par_iter().map(timed_scan).collect()
     } else {
-        // Sequential processing
-        for entry in entries {
-            if let Ok(dir_entry) = entry {
-                if should_include_entry(&dir_entry, context) {
-                    match scan_entry(&dir_entry.path(), context) {
-                        Ok(child_entry) => children.push(child_entry),
-                        Err(_) => {} // Errors are handled in scan_entry
-                    }
+        dir_entries.into_iter().map(timed_scan).collect()
+    };
+
+    let mut children = Vec::with_capacity(results.len());
+    for (result, elapsed) in results {
+        match result {
+            Ok(Some(child_entry)) => {
+                if child_entry.entry_type != EntryType::Directory {
+                    own_time += elapsed;
                 }
+                children.push(child_entry);
             }
+            Ok(None) => {}
+            Err(_) => own_time += elapsed, // Errors are handled in scan_entry
         }
     }
+    context.record_directory_time(dir_path, own_time);
+
+    if let Some(max_children) = context.config.max_children {
+        cap_children(&mut children, max_children, &context.config);
+    }
 
     Ok(children)
 }
 
+/// Set a directory's mtime to the newest one among its children
+///
+/// Subdirectories went through this before their parent, so the result is
+/// the newest mtime anywhere below the directory. A directory with nothing
+/// below it that has an mtime keeps its own.
+fn take_newest_child_mtime(dir: &mut Entry) {
+    let newest = dir
+        .children
+        .iter()
+        .filter_map(|child| child.extended.as_ref()?.mtime)
+        .max();
+    if let (Some(info), Some(newest)) = (dir.extended.as_mut(), newest) {
+        info.mtime = Some(newest);
+    }
+}
+
+/// Compile `--exclude`-style glob patterns, naming `kind` if one is invalid
+fn compile_patterns(patterns: &[String], kind: &str) -> Result<Vec<glob::Pattern>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            glob::Pattern::new(pattern).map_err(|e| {
+                RsduError::ConfigError(format!("Invalid {} pattern '{}': {}", kind, pattern, e))
+            })
+        })
+        .collect()
+}
+
+/// Keep only the `max_children` largest of `children`
+///
+/// The dropped children are replaced by a single synthetic entry carrying
+/// their combined size, so directory totals stay exact.
+fn cap_children(children: &mut Vec<Arc<Entry>>, max_children: usize, config: &Config) {
+    if children.len() <= max_children {
+        return;
+    }
+
+    let sizes = SizeFormatter::new(config);
+    // Ranked by whole subtrees, whatever shallow sizes the browser may show
+    children.sort_by_key(|entry| std::cmp::Reverse(sizes.total_value(entry)));
+    let dropped = children.split_off(max_children);
+
+    let size = dropped.iter().map(|entry| entry.total_size()).sum();
+    let blocks = dropped.iter().map(|entry| entry.total_blocks()).sum();
+    children.push(Arc::new(Entry::new(
+        generate_entry_id(),
+        EntryType::Aggregate,
+        format!("(and {} more)", dropped.len()).into(),
+        size,
+        blocks,
+        dropped[0].device,
+        0,
+        1,
+    )));
+}
+
 /// Determine if a directory entry should be included in the scan
 fn should_include_entry(entry: &DirEntry, context: &ScanContext) -> bool {
     let file_name = entry.file_name();
@@ -430,6 +1389,32 @@ fn should_include_entry(entry: &DirEntry, context: &ScanContext) -> bool {
     true
 }
 
+/// `entry` with its size, blocks and extended information read from `path` again
+///
+/// Only the entry itself is looked at: a directory keeps the children it
+/// had. Symlinks are followed and sized as they would be by a scan.
+pub fn restat_entry(entry: &Entry, path: &Path, config: &Config) -> Result<Entry> {
+    let context = ScanContext::new(config.clone(), None)?;
+    let metadata = if entry.entry_type == EntryType::Symlink {
+        context.metadata_source.metadata(path, false)?
+    } else {
+        context.entry_metadata(path)?
+    };
+
+    let mut fresh = entry.clone();
+    (fresh.size, fresh.blocks) = if entry.entry_type == EntryType::Symlink {
+        symlink_size(path, &metadata, &context)
+    } else {
+        (metadata.len(), metadata.blocks())
+    };
+    fresh.nlink = metadata.nlink() as u32;
+    // Imported entries may carry extended information without `--extended`
+    if config.extended || entry.extended.is_some() {
+        fresh.extended = Some(extended_info(&metadata, metadata.modified()));
+    }
+    Ok(fresh)
+}
+
 /// Get metadata for a path, optionally following symlinks
 fn get_metadata(path: &Path, follow_symlinks: bool) -> std::io::Result<Metadata> {
     if follow_symlinks {
@@ -439,6 +1424,48 @@ fn get_metadata(path: &Path, follow_symlinks: bool) -> std::io::Result<Metadata>
     }
 }
 
+/// Size and blocks a symlink counts for, per `config.symlink_size`
+///
+/// A link whose target can't be read counts as the link itself.
+fn symlink_size(path: &Path, metadata: &Metadata, context: &ScanContext) -> (u64, u64) {
+    match context.config.symlink_size {
+        SymlinkSize::None => (0, 0),
+        SymlinkSize::Link => (metadata.len(), metadata.blocks()),
+        SymlinkSize::Target => match context.metadata_source.metadata(path, true) {
+            Ok(target) => (target.len(), target.blocks()),
+            Err(_) => (metadata.len(), metadata.blocks()),
+        },
+    }
+}
+
+/// Extended information for `--extended`
+///
+/// `modified` is the mtime as read from `metadata`, which some platforms
+/// can't supply. Such an mtime, or one chrono can't represent, stays `None`
+/// rather than turning into zero.
+fn extended_info(metadata: &Metadata, modified: io::Result<SystemTime>) -> ExtendedInfo {
+    ExtendedInfo {
+        mtime: modified_time(modified),
+        uid: Some(metadata.uid()),
+        gid: Some(metadata.gid()),
+        mode: Some(metadata.mode()),
+    }
+}
+
+/// Modification time to the second; times before the epoch are kept
+fn modified_time(modified: io::Result<SystemTime>) -> Option<DateTime<Utc>> {
+    let seconds = match modified.ok()?.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(after) => i64::try_from(after.as_secs()).ok()?,
+        Err(before) => {
+            let before = before.duration();
+            let seconds = i64::try_from(before.as_secs()).ok()?;
+            // Round down, as for times after the epoch
+            -seconds - i64::from(before.subsec_nanos() > 0)
+        }
+    };
+    DateTime::from_timestamp(seconds, 0)
+}
+
 /// Determine the entry type from metadata
 fn get_entry_type(metadata: &Metadata, _path: &Path) -> EntryType {
     use std::os::unix::fs::FileTypeExt;
@@ -462,19 +1489,9 @@ fn get_entry_type(metadata: &Metadata, _path: &Path) -> EntryType {
 }
 
 /// Sort entries according to configuration
-fn sort_entries(entries: &mut Vec<Arc<Entry>>, config: &Config) {
-    let sort_col = match config.sort_col {
-        crate::config::SortColumn::Name => SortColumn::Name,
-        crate::config::SortColumn::Blocks => SortColumn::Blocks,
-        crate::config::SortColumn::Size => SortColumn::Size,
-        crate::config::SortColumn::Items => SortColumn::Items,
-        crate::config::SortColumn::Mtime => SortColumn::Mtime,
-    };
-
-    let sort_order = match config.sort_order {
-        crate::config::SortOrder::Asc => SortOrder::Asc,
-        crate::config::SortOrder::Desc => SortOrder::Desc,
-    };
+pub(crate) fn sort_entries(entries: &mut [Arc<Entry>], config: &Config) {
+    let sort_col = SortColumn::from(config.sort_col);
+    let sort_order = SortOrder::from(config.sort_order);
 
     entries.sort_by(|a, b| {
         use std::cmp::Ordering;
@@ -493,69 +1510,17 @@ fn sort_entries(entries: &mut Vec<Arc<Entry>>, config: &Config) {
         }
 
         let cmp = match sort_col {
-            SortColumn::Name => {
-                if config.sort_natural {
-                    natural_sort(&a.name.to_string_lossy(), &b.name.to_string_lossy())
-                } else {
-                    a.name.cmp(&b.name)
-                }
-            }
-            SortColumn::Size => {
-                let a_total_size = calculate_total_entry_size(a);
-                let b_total_size = calculate_total_entry_size(b);
-                a_total_size.cmp(&b_total_size)
-            }
-            SortColumn::Blocks => {
-                let a_total_blocks = calculate_total_entry_blocks(a);
-                let b_total_blocks = calculate_total_entry_blocks(b);
-                a_total_blocks.cmp(&b_total_blocks)
-            }
-            SortColumn::Items => {
-                let a_total_items = calculate_total_entry_items(a);
-                let b_total_items = calculate_total_entry_items(b);
-                a_total_items.cmp(&b_total_items)
-            }
-            SortColumn::Mtime => {
-                let a_mtime = a.extended.as_ref().and_then(|e| e.mtime);
-                let b_mtime = b.extended.as_ref().and_then(|e| e.mtime);
-                a_mtime.cmp(&b_mtime)
+            SortColumn::Name if config.sort_natural => {
+                natural_sort(&a.name.to_string_lossy(), &b.name.to_string_lossy())
             }
+            _ => a.cmp_by(b, sort_col),
         };
 
         match sort_order {
             SortOrder::Asc => cmp,
             SortOrder::Desc => cmp.reverse(),
         }
-    });
-}
-
-/// Calculate total size including all children for an entry
-fn calculate_total_entry_size(entry: &Arc<Entry>) -> u64 {
-    entry.size
-        + entry
-            .children
-            .iter()
-            .map(|child| calculate_total_entry_size(child))
-            .sum::<u64>()
-}
-
-/// Calculate total blocks including all children for an entry
-fn calculate_total_entry_blocks(entry: &Arc<Entry>) -> u64 {
-    entry.blocks
-        + entry
-            .children
-            .iter()
-            .map(|child| calculate_total_entry_blocks(child))
-            .sum::<u64>()
-}
-
-/// Calculate total item count including all children for an entry
-fn calculate_total_entry_items(entry: &Arc<Entry>) -> u64 {
-    1 + entry
-        .children
-        .iter()
-        .map(|child| calculate_total_entry_items(child))
-        .sum::<u64>()
+    });
 }
 
 /// Natural sorting comparison (handles numbers in strings properly)
@@ -612,7 +1577,8 @@ fn extract_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> u64 {
 /// Scan directory using walkdir for deep scanning (alternative implementation)
 #[allow(dead_code)]
 pub fn scan_directory_walkdir(path: &Path, config: &Config) -> Result<Arc<Entry>> {
-    let context = ScanContext::new(config.clone(), None)?;
+    let mut context = ScanContext::new(config.clone(), None)?;
+    context.scan_root = Some(path.to_path_buf());
 
     // Set up walkdir
     let mut walker = WalkDir::new(path).follow_links(config.follow_symlinks);
@@ -643,6 +1609,10 @@ pub fn scan_directory_walkdir(path: &Path, config: &Config) -> Result<Arc<Entry>
     let mut entries_by_parent: HashMap<PathBuf, Vec<Arc<Entry>>> = HashMap::new();
     let mut total_size = 0u64;
     let mut total_blocks = 0u64;
+    // For `--include-only`: directories matching nothing, and the directories
+    // found to hold something that is kept
+    let mut awaiting: Vec<(PathBuf, Arc<Entry>)> = Vec::new();
+    let mut holding: HashSet<PathBuf> = HashSet::new();
 
     for entry_result in walker {
         match entry_result {
@@ -655,6 +1625,21 @@ pub fn scan_directory_walkdir(path: &Path, config: &Config) -> Result<Arc<Entry>
                 }
 
                 if let Some(scanned_entry) = scan_walkdir_entry(&dir_entry, &context)? {
+                    if scanned_entry.entry_type == EntryType::Directory
+                        && context.awaits_include_match(entry_path)
+                    {
+                        awaiting.push((entry_path.to_path_buf(), scanned_entry));
+                        continue;
+                    }
+                    if !context.include_patterns.is_empty() {
+                        holding.extend(
+                            entry_path
+                                .ancestors()
+                                .skip(1)
+                                .take_while(|dir| *dir != path)
+                                .map(Path::to_path_buf),
+                        );
+                    }
                     total_size += scanned_entry.size;
                     total_blocks += scanned_entry.blocks;
 
@@ -666,12 +1651,26 @@ pub fn scan_directory_walkdir(path: &Path, config: &Config) -> Result<Arc<Entry>
                 }
             }
             Err(e) => {
-                context.stats.increment_errors();
+                context.stats.record_error(walkdir_error_category(&e));
                 eprintln!("Error walking directory: {}", e);
             }
         }
     }
 
+    for (dir_path, dir) in awaiting {
+        if !holding.contains(&dir_path) {
+            continue;
+        }
+        context.stats.increment_entries();
+        context.stats.increment_directories();
+        context.stats.add_size(dir.size);
+        context.stats.add_blocks(dir.blocks);
+        total_size += dir.size;
+        total_blocks += dir.blocks;
+        let parent_path = dir_path.parent().unwrap_or(path).to_path_buf();
+        entries_by_parent.entry(parent_path).or_default().push(dir);
+    }
+
     // Update root entry
     root.size = total_size;
     root.blocks = total_blocks;
@@ -687,9 +1686,34 @@ pub fn scan_directory_walkdir(path: &Path, config: &Config) -> Result<Arc<Entry>
     println!("  Directories: {}", stats.get_directories());
     println!("  Files: {}", stats.get_files());
     println!("  Total entries: {}", stats.get_total_entries());
-    println!("  Errors: {}", stats.get_errors());
+    println!("  Errors: {}", format_error_counts(stats));
 
-    Ok(Arc::new(root))
+    Ok(root.into_linked())
+}
+
+/// Classify a walkdir error; loops and other non-I/O failures count as other
+fn walkdir_error_category(error: &walkdir::Error) -> ErrorCategory {
+    error
+        .io_error()
+        .map(ErrorCategory::from_io_error)
+        .unwrap_or(ErrorCategory::Other)
+}
+
+/// Error total for the scan summary, broken down by category
+fn format_error_counts(stats: &ScanStats) -> String {
+    let total = stats.get_errors();
+    if total == 0 {
+        return total.to_string();
+    }
+    format!(
+        "{} ({})",
+        total,
+        format_error_breakdown(
+            stats.get_permission_errors(),
+            stats.get_not_found_errors(),
+            stats.get_other_errors()
+        )
+    )
 }
 
 /// Scan a single walkdir entry
@@ -697,18 +1721,29 @@ fn scan_walkdir_entry(entry: &WalkDirEntry, context: &ScanContext) -> Result<Opt
     let path = entry.path();
 
     // Apply filters
-    if context.is_excluded_by_pattern(path) {
+    if context.is_excluded_by_pattern(path) || context.is_ignored(path) {
         return Ok(None);
     }
+    if entry.file_type().is_dir() {
+        // Walkdir yields a directory before anything inside it
+        context.load_ignore_file(path);
+    }
 
     if context.is_kernel_filesystem(path) {
         return Ok(None);
     }
 
+    // `--include-only` leaves out what matches nothing; directories are
+    // settled by the caller once it knows what lies below them
+    let deferred = context.awaits_include_match(path);
+    if deferred && !entry.file_type().is_dir() {
+        return Ok(None);
+    }
+
     let metadata = match entry.metadata() {
         Ok(meta) => meta,
         Err(e) => {
-            context.stats.increment_errors();
+            context.stats.record_error(walkdir_error_category(&e));
             return Ok(Some(Arc::new(Entry::error(
                 generate_entry_id(),
                 path.file_name().unwrap_or(path.as_os_str()).to_os_string(),
@@ -718,12 +1753,16 @@ fn scan_walkdir_entry(entry: &WalkDirEntry, context: &ScanContext) -> Result<Opt
     };
 
     let entry_type = get_entry_type(&metadata, path);
-    context.stats.increment_entries();
+    if !deferred {
+        context.stats.increment_entries();
+    }
 
     if entry_type == EntryType::Directory {
-        context.stats.increment_directories();
+        if !deferred {
+            context.stats.increment_directories();
+        }
 
-        if context.has_cachedir_tag(path) {
+        if context.has_exclude_marker(path) {
             return Ok(None);
         }
     } else {
@@ -736,28 +1775,20 @@ fn scan_walkdir_entry(entry: &WalkDirEntry, context: &ScanContext) -> Result<Opt
         path.file_name().unwrap_or(path.as_os_str()).to_os_string(),
         metadata.len(),
         metadata.blocks(),
-        metadata.dev() as u32,
+        metadata.dev(),
         metadata.ino(),
         metadata.nlink() as u32,
     );
 
     // Add extended info if requested
     if context.config.extended {
-        scanned_entry.extended = Some(ExtendedInfo {
-            mtime: metadata.modified().ok().and_then(|t| {
-                DateTime::from_timestamp(
-                    t.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs() as i64,
-                    0,
-                )
-            }),
-            uid: Some(metadata.uid()),
-            gid: Some(metadata.gid()),
-            mode: Some(metadata.mode()),
-        });
+        scanned_entry.extended = Some(extended_info(&metadata, metadata.modified()));
     }
 
-    context.stats.add_size(metadata.len());
-    context.stats.add_blocks(metadata.blocks());
+    if !deferred {
+        context.stats.add_size(metadata.len());
+        context.stats.add_blocks(metadata.blocks());
+    }
 
     Ok(Some(Arc::new(scanned_entry)))
 }
@@ -813,11 +1844,1095 @@ mod tests {
         assert_eq!(entry.children.len(), 3);
     }
 
+    #[test]
+    fn test_scanned_parent_chain() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("a/b")).unwrap();
+        std::fs::write(temp_dir.path().join("a/b/file.txt"), "Hello").unwrap();
+
+        let root = scan_directory(temp_dir.path(), &Config::default()).unwrap();
+        assert!(root.parent().is_none());
+
+        let a = root.children[0].clone();
+        let b = a.children[0].clone();
+        let file = b.children[0].clone();
+        assert_eq!(file.parent().unwrap().id, b.id);
+        assert_eq!(b.parent().unwrap().id, a.id);
+        assert_eq!(a.parent().unwrap().id, root.id);
+
+        let root_name = temp_dir.path().file_name().unwrap();
+        assert_eq!(file.full_path(), Path::new(root_name).join("a/b/file.txt"));
+    }
+
+    #[test]
+    fn test_repeated_directory_is_skipped() {
+        let temp_dir = TempDir::new().unwrap();
+        let data = temp_dir.path().join("data");
+        std::fs::create_dir(&data).unwrap();
+        std::fs::write(data.join("file"), vec![0u8; 4096]).unwrap();
+        // Following this link reaches the same device+inode a second time,
+        // just like a bind mount of `data` would
+        std::os::unix::fs::symlink(&data, temp_dir.path().join("mirror")).unwrap();
+
+        let config = Config {
+            follow_symlinks: true,
+            threads: 1,
+            ..Config::default()
+        };
+        let root = scan_directory(temp_dir.path(), &config).unwrap();
+
+        let types: Vec<EntryType> = root.children.iter().map(|c| c.entry_type).collect();
+        assert_eq!(
+            types.iter().filter(|t| **t == EntryType::Directory).count(),
+            1
+        );
+        assert_eq!(
+            types.iter().filter(|t| **t == EntryType::BindMount).count(),
+            1
+        );
+        // The file is only counted once
+        let file_size: u64 = root.children.iter().map(|c| c.total_size()).sum::<u64>()
+            - root.children.iter().map(|c| c.size).sum::<u64>();
+        assert_eq!(file_size, 4096);
+    }
+
+    /// Metadata source that stalls before every call
+    struct SlowMetadata(Duration);
+
+    impl MetadataSource for SlowMetadata {
+        fn metadata(&self, path: &Path, follow_symlinks: bool) -> io::Result<Metadata> {
+            thread::sleep(self.0);
+            get_metadata(path, follow_symlinks)
+        }
+
+        fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+            thread::sleep(self.0);
+            FsMetadata.read_dir(path)
+        }
+
+        fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+            thread::sleep(self.0);
+            FsMetadata.read_link(path)
+        }
+
+        fn filesystem_usage(&self, path: &Path) -> io::Result<FsUsage> {
+            thread::sleep(self.0);
+            FsMetadata.filesystem_usage(path)
+        }
+    }
+
+    /// Fails with the error kind named by the start of the file name
+    struct FailingMetadata;
+
+    impl FailingMetadata {
+        fn failure(path: &Path) -> Option<io::Error> {
+            let name = path.file_name()?.to_string_lossy();
+            let kind = if name.starts_with("denied") {
+                io::ErrorKind::PermissionDenied
+            } else if name.starts_with("missing") {
+                io::ErrorKind::NotFound
+            } else if name.starts_with("broken") {
+                io::ErrorKind::InvalidData
+            } else {
+                return None;
+            };
+            Some(io::Error::new(kind, "injected failure"))
+        }
+    }
+
+    impl MetadataSource for FailingMetadata {
+        fn metadata(&self, path: &Path, follow_symlinks: bool) -> io::Result<Metadata> {
+            match Self::failure(path) {
+                Some(e) if !path.is_dir() => Err(e),
+                _ => get_metadata(path, follow_symlinks),
+            }
+        }
+
+        fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+            match Self::failure(path) {
+                Some(e) => Err(e),
+                None => FsMetadata.read_dir(path),
+            }
+        }
+
+        fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+            FsMetadata.read_link(path)
+        }
+
+        fn filesystem_usage(&self, path: &Path) -> io::Result<FsUsage> {
+            FsMetadata.filesystem_usage(path)
+        }
+    }
+
+    /// Counts the symlinks read through it
+    #[derive(Default)]
+    struct CountingLinks(AtomicU64);
+
+    impl MetadataSource for CountingLinks {
+        fn metadata(&self, path: &Path, follow_symlinks: bool) -> io::Result<Metadata> {
+            get_metadata(path, follow_symlinks)
+        }
+
+        fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+            FsMetadata.read_dir(path)
+        }
+
+        fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            FsMetadata.read_link(path)
+        }
+
+        fn filesystem_usage(&self, path: &Path) -> io::Result<FsUsage> {
+            FsMetadata.filesystem_usage(path)
+        }
+    }
+
+    #[test]
+    fn test_error_categories() {
+        let temp_dir = TempDir::new().unwrap();
+        for name in ["ok", "denied-1", "denied-2", "missing", "broken"] {
+            fs::write(temp_dir.path().join(name), "data").unwrap();
+        }
+        // A directory whose listing fails is counted once, by category
+        fs::create_dir(temp_dir.path().join("denied-dir")).unwrap();
+
+        let config = Config {
+            threads: 1,
+            ..Config::default()
+        };
+        let mut context = ScanContext::new(config, None).unwrap();
+        context.metadata_source = Arc::new(FailingMetadata);
+
+        let root = scan_entry(temp_dir.path(), &context).unwrap();
+        assert_eq!(root.children.len(), 6);
+        assert_eq!(context.stats.get_errors(), 5);
+        assert_eq!(context.stats.get_permission_errors(), 3);
+        assert_eq!(context.stats.get_not_found_errors(), 1);
+        assert_eq!(context.stats.get_other_errors(), 1);
+        assert_eq!(
+            format_error_counts(&context.stats),
+            "5 (3 permission denied, 1 not found, 1 other)"
+        );
+    }
+
+    #[test]
+    fn test_io_timeout() {
+        let temp_dir = TempDir::new().unwrap();
+        let slow: Arc<dyn MetadataSource> = Arc::new(SlowMetadata(Duration::from_millis(200)));
+
+        let guarded = TimeoutMetadata::new(slow.clone(), Duration::from_millis(20));
+        let err = guarded.metadata(temp_dir.path(), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(guarded.read_dir(temp_dir.path()).is_err());
+        assert!(guarded.read_link(temp_dir.path()).is_err());
+
+        let patient = TimeoutMetadata::new(slow, Duration::from_secs(5));
+        assert!(patient.metadata(temp_dir.path(), false).is_ok());
+    }
+
+    #[test]
+    fn test_timed_out_path_becomes_error_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut context = ScanContext::new(Config::default(), None).unwrap();
+        context.metadata_source = Arc::new(TimeoutMetadata::new(
+            Arc::new(SlowMetadata(Duration::from_millis(200))),
+            Duration::from_millis(20),
+        ));
+
+        let entry = scan_entry(temp_dir.path(), &context).unwrap();
+        assert_eq!(entry.entry_type, EntryType::Error);
+        assert!(entry.error.as_deref().unwrap().contains("timed out"));
+    }
+
+    #[test]
+    fn test_follow_arg_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("data.bin"), vec![0u8; 4096]).unwrap();
+        let link = temp_dir.path().join("link");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let scan = |follow: bool| {
+            let config = Config {
+                follow_arg_symlinks: follow,
+                scan_ui: Some(ScanUi::None),
+                ..Config::default()
+            };
+            let root = crate::utils::resolve_scan_root(&link, follow).unwrap();
+            (root.clone(), scan_directory(&root, &config).unwrap())
+        };
+
+        let (path, entry) = scan(true);
+        assert_eq!(path, target.canonicalize().unwrap());
+        assert_eq!(entry.entry_type, EntryType::Directory);
+        assert_eq!(entry.total_size(), 4096 + entry.size);
+
+        let (path, entry) = scan(false);
+        assert_eq!(path, temp_dir.path().canonicalize().unwrap().join("link"));
+        assert_eq!(entry.entry_type, EntryType::Symlink);
+        assert!(entry.children.is_empty());
+    }
+
+    #[test]
+    fn test_symlink_size_modes() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("target.bin"), vec![0u8; 10_000]).unwrap();
+        std::os::unix::fs::symlink("target.bin", temp_dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink("missing", temp_dir.path().join("dangling")).unwrap();
+
+        let sizes = |symlink_size: SymlinkSize| {
+            let config = Config {
+                symlink_size,
+                show_blocks: false,
+                scan_ui: Some(ScanUi::None),
+                ..Config::default()
+            };
+            let root = scan_directory(temp_dir.path(), &config).unwrap();
+            let size_of = |name: &str| {
+                let entry = root.children.iter().find(|c| c.name == name).unwrap();
+                assert_eq!(entry.entry_type, EntryType::Symlink);
+                (entry.size, entry.blocks)
+            };
+            (size_of("link"), size_of("dangling"))
+        };
+
+        let (link, dangling) = sizes(SymlinkSize::Link);
+        assert_eq!(link.0, "target.bin".len() as u64);
+        assert_eq!(dangling.0, "missing".len() as u64);
+
+        assert_eq!(sizes(SymlinkSize::None), ((0, 0), (0, 0)));
+
+        let (link, dangling) = sizes(SymlinkSize::Target);
+        assert_eq!(link.0, 10_000);
+        assert!(link.1 > 0);
+        assert_eq!(dangling.0, "missing".len() as u64);
+    }
+
+    #[test]
+    fn test_follow_symlinks_matching() {
+        // The link targets live outside the scanned directory
+        let targets = TempDir::new().unwrap();
+        for dir in ["farm", "other"] {
+            fs::create_dir(targets.path().join(dir)).unwrap();
+            fs::write(targets.path().join(dir).join("data.bin"), vec![0u8; 4096]).unwrap();
+        }
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let link = |target: &str, name: &str| {
+            std::os::unix::fs::symlink(targets.path().join(target), root.join(name)).unwrap()
+        };
+        link("other", "farm-link");
+        link("farm", "by-target");
+        link("other", "other-link");
+
+        let config = Config {
+            follow_symlink_patterns: vec!["farm-*".to_string(), "*/farm".to_string()],
+            scan_ui: Some(ScanUi::None),
+            ..Config::default()
+        };
+        let entry = scan_directory(root, &config).unwrap();
+        let child = |name: &str| entry.children.iter().find(|c| c.name == name).unwrap();
+
+        // Followed by name and by target
+        assert_eq!(child("farm-link").entry_type, EntryType::Directory);
+        assert_eq!(child("by-target").entry_type, EntryType::Directory);
+        assert_eq!(child("by-target").children.len(), 1);
+        // Left alone
+        assert_eq!(child("other-link").entry_type, EntryType::Symlink);
+        assert!(child("other-link").children.is_empty());
+
+        // Only links not already matched by name are read, through the source
+        fs::write(root.join("plain.bin"), "data").unwrap();
+        let links = Arc::new(CountingLinks::default());
+        let mut context = ScanContext::new(config, None).unwrap();
+        context.metadata_source = links.clone();
+        scan_entry(root, &context).unwrap();
+        assert_eq!(links.0.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_extended_info_without_mtime() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = fs::metadata(temp_dir.path()).unwrap();
+        let unsupported = || Err(io::Error::new(io::ErrorKind::Unsupported, "no mtime"));
+
+        // A missing mtime stays unknown while the rest is still filled in
+        let info = extended_info(&metadata, unsupported());
+        assert_eq!(info.mtime, None);
+        assert_eq!(info.uid, Some(metadata.uid()));
+        assert_eq!(info.mode, Some(metadata.mode()));
+
+        let info = extended_info(&metadata, metadata.modified());
+        assert!(info.mtime.is_some());
+
+        // Times before the epoch are kept, rounded down to the second
+        let before_epoch = SystemTime::UNIX_EPOCH - Duration::from_secs(3600);
+        assert_eq!(modified_time(Ok(before_epoch)).unwrap().timestamp(), -3600);
+        let just_before = SystemTime::UNIX_EPOCH - Duration::from_millis(1500);
+        assert_eq!(modified_time(Ok(just_before)).unwrap().timestamp(), -2);
+
+        // Times chrono can't represent are unknown rather than a panic
+        if let Some(far) = SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(i64::MAX as u64))
+        {
+            assert_eq!(modified_time(Ok(far)), None);
+        }
+    }
+
+    #[test]
+    fn test_restat_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("log");
+        fs::write(&path, vec![0u8; 100]).unwrap();
+        let config = Config {
+            extended: true,
+            scan_ui: Some(ScanUi::None),
+            ..Config::default()
+        };
+        let root = scan_directory(temp_dir.path(), &config).unwrap();
+        let stale = root.children[0].clone();
+        assert_eq!(stale.size, 100);
+
+        let written = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let mut file = fs::File::options().append(true).open(&path).unwrap();
+        file.write_all(&[0u8; 4900]).unwrap();
+        file.set_modified(written).unwrap();
+
+        let fresh = restat_entry(&stale, &path, &config).unwrap();
+        assert_eq!(fresh.id, stale.id);
+        assert_eq!(fresh.name, stale.name);
+        assert_eq!(fresh.size, 5000);
+        assert!(fresh.blocks > 0);
+        let mtime = fresh.extended.as_ref().unwrap().mtime.unwrap();
+        assert_eq!(mtime.timestamp(), 1_000_000_000);
+
+        fs::remove_file(&path).unwrap();
+        assert!(restat_entry(&stale, &path, &config).is_err());
+    }
+
+    #[test]
+    fn test_newest_mtime() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir(root.join("empty")).unwrap();
+        fs::write(root.join("a/old"), "old").unwrap();
+        let newest = SystemTime::now() + Duration::from_secs(86_400);
+        fs::File::options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(root.join("a/b/new"))
+            .unwrap()
+            .set_modified(newest)
+            .unwrap();
+        let newest = DateTime::<Utc>::from(newest).timestamp();
+
+        let scan = |newest_mtime: bool| {
+            let config = Config {
+                extended: true,
+                newest_mtime,
+                scan_ui: Some(ScanUi::None),
+                ..Config::default()
+            };
+            scan_directory(root, &config).unwrap()
+        };
+        let mtime = |entry: &Entry| entry.extended.as_ref().unwrap().mtime.unwrap().timestamp();
+        let child = |entry: &Arc<Entry>, name: &str| {
+            entry
+                .children
+                .iter()
+                .find(|c| c.name == name)
+                .unwrap()
+                .clone()
+        };
+
+        let entry = scan(true);
+        let a = child(&entry, "a");
+        assert_eq!(mtime(&entry), newest);
+        assert_eq!(mtime(&a), newest);
+        assert_eq!(mtime(&child(&a, "b")), newest);
+        // Nothing below it, so an empty directory keeps its own
+        let empty = child(&entry, "empty");
+        assert_eq!(
+            mtime(&empty),
+            DateTime::<Utc>::from(
+                fs::metadata(root.join("empty"))
+                    .unwrap()
+                    .modified()
+                    .unwrap()
+            )
+            .timestamp()
+        );
+
+        let entry = scan(false);
+        assert!(mtime(&child(&entry, "a")) < newest);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_pseudo_fs_classification() {
+        assert!(is_pathological_fs_type(statfs::PROC_SUPER_MAGIC));
+        assert!(is_pathological_fs_type(statfs::SYSFS_MAGIC));
+        assert!(is_pathological_fs_type(statfs::CGROUP2_SUPER_MAGIC));
+        assert!(!is_pathological_fs_type(statfs::EXT4_SUPER_MAGIC));
+        assert!(!is_pathological_fs_type(statfs::TMPFS_MAGIC));
+
+        // Classified by type wherever it's mounted, unless it is the scan root
+        let proc = Path::new("/proc");
+        if let Ok(metadata) = fs::metadata(proc) {
+            let mut context = ScanContext::new(Config::default(), None).unwrap();
+            assert!(context.is_pseudo_filesystem(proc, metadata.dev()));
+
+            context.scan_root_device = Some(metadata.dev());
+            context.pseudo_fs_devices.lock().unwrap().clear();
+            assert!(!context.is_pseudo_filesystem(proc, metadata.dev()));
+        }
+    }
+
+    #[test]
+    fn test_stat_other_fs() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("mnt")).unwrap();
+        fs::create_dir(root.join("bind")).unwrap();
+        fs::write(root.join("mnt/data.bin"), vec![0u8; 4096]).unwrap();
+        fs::write(root.join("file.bin"), vec![0u8; 4096]).unwrap();
+
+        // Pretend everything below the root is on another filesystem
+        let mut context = ScanContext::new(
+            Config {
+                same_fs: true,
+                stat_other_fs: true,
+                ..Config::default()
+            },
+            None,
+        )
+        .unwrap();
+        context.root_device = Some(fs::metadata(root).unwrap().dev() + 1);
+
+        // The mount point is sized by the filesystem's used space
+        let mount = scan_entry(&root.join("mnt"), &context).unwrap();
+        assert_eq!(mount.entry_type, EntryType::OtherFs);
+        assert!(mount.size > 0);
+        assert!(mount.children.is_empty());
+
+        // Another mount of the same device doesn't count the filesystem again
+        let bind = scan_entry(&root.join("bind"), &context).unwrap();
+        assert_eq!(bind.entry_type, EntryType::OtherFs);
+        assert_eq!(bind.size, 0);
+
+        let file = scan_entry(&root.join("file.bin"), &context).unwrap();
+        assert_eq!(file.entry_type, EntryType::OtherFs);
+        assert_eq!(file.size, 4096);
+
+        // A hung filesystem falls back to the mount point's own metadata
+        let metadata = fs::metadata(root.join("bind")).unwrap();
+        context.sized_other_fs.lock().unwrap().clear();
+        context.metadata_source = Arc::new(TimeoutMetadata::new(
+            Arc::new(SlowMetadata(Duration::from_millis(500))),
+            Duration::from_millis(20),
+        ));
+        assert_eq!(
+            context.other_fs_size(&root.join("bind"), &metadata),
+            (metadata.len(), metadata.blocks())
+        );
+
+        context.metadata_source = Arc::new(FsMetadata);
+        context.config.stat_other_fs = false;
+        let mount = scan_entry(&root.join("mnt"), &context).unwrap();
+        assert_eq!(mount.size, 0);
+    }
+
+    #[test]
+    fn test_one_file_system_baseline() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("sub/data.bin"), vec![0u8; 4096]).unwrap();
+        fs::write(root.join("file.bin"), vec![0u8; 4096]).unwrap();
+
+        // The root's device is the baseline, so everything on it is scanned
+        let config = Config {
+            same_fs: true,
+            scan_ui: Some(ScanUi::None),
+            ..Config::default()
+        };
+        let entry = scan_directory(root, &config).unwrap();
+        assert_eq!(entry.entry_type, EntryType::Directory);
+        assert_eq!(entry.children.len(), 2);
+        assert!(entry
+            .children
+            .iter()
+            .all(|child| child.entry_type != EntryType::OtherFs));
+
+        // Children on a device other than the root's are cut off, unscanned
+        let root_device = fs::metadata(root).unwrap().dev();
+        let mut context = ScanContext::new(config.clone(), None).unwrap();
+        context.root_device = Some(root_device + 1);
+        for name in ["sub", "file.bin"] {
+            let child = scan_entry(&root.join(name), &context).unwrap();
+            assert_eq!(child.entry_type, EntryType::OtherFs, "{}", name);
+            assert_eq!(child.size, 0);
+            assert!(child.children.is_empty());
+        }
+
+        // Without --one-file-system there is no boundary at all
+        let context = ScanContext::new(Config::default(), None).unwrap();
+        assert!(!context.is_different_filesystem(root_device + 1));
+    }
+
+    #[test]
+    fn test_parse_mount_sources() {
+        let table = "/dev/nvme0n1p2 / ext4 rw,relatime 0 0\n\
+                     proc /proc proc rw,nosuid 0 0\n\
+                     //server/share\\040one /mnt/my\\040share cifs rw 0 0\n\
+                     malformed-line\n";
+        let mounts = parse_mount_sources(table);
+        assert_eq!(mounts.len(), 3);
+        assert_eq!(
+            mounts[0],
+            MountSource {
+                source: "/dev/nvme0n1p2".to_string(),
+                mount_point: PathBuf::from("/"),
+                fs_type: "ext4".to_string(),
+            }
+        );
+        assert_eq!(mounts[2].source, "//server/share one");
+        assert_eq!(mounts[2].mount_point, PathBuf::from("/mnt/my share"));
+    }
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let table = "22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw\n\
+                     23 22 0:22 / /proc rw,nosuid - proc proc rw\n\
+                     40 22 0:45 / /mnt/my\\040share rw master:3 shared:7 - cifs //server/share rw\n\
+                     41 22 259:2 /srv /srv rw - ext4 /dev/nvme0n1p2 rw\n\
+                     malformed-line\n";
+        let by_device = parse_mountinfo(table);
+        assert_eq!(by_device.len(), 3);
+        assert_eq!(
+            by_device[&nix::sys::stat::makedev(0, 45)],
+            MountSource {
+                source: "//server/share".to_string(),
+                mount_point: PathBuf::from("/mnt/my share"),
+                fs_type: "cifs".to_string(),
+            }
+        );
+        assert_eq!(by_device[&nix::sys::stat::makedev(0, 22)].fs_type, "proc");
+        // A device mounted twice is named by its last mount
+        assert_eq!(
+            by_device[&nix::sys::stat::makedev(259, 2)].mount_point,
+            PathBuf::from("/srv")
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_read_mounts_by_device() {
+        // The table is keyed like the devices recorded while scanning
+        let root_device = fs::metadata("/").unwrap().dev();
+        assert!(read_mounts_by_device().contains_key(&root_device));
+    }
+
+    #[test]
+    fn test_kernel_fs_by_mount_type() {
+        let mounts = parse_mount_table(
+            "/dev/sda1 / ext4 rw,relatime 0 0\n\
+             proc /proc proc rw,nosuid 0 0\n\
+             /dev/sda2 /tmp ext4 rw 0 0\n\
+             tmpfs /mnt/ram\\040disk tmpfs rw 0 0\n",
+        );
+        assert_eq!(mounts[3].0, PathBuf::from("/mnt/ram disk"));
+
+        assert_eq!(
+            mount_fs_type(&mounts, Path::new("/proc/1/stat")),
+            Some("proc")
+        );
+        assert_eq!(mount_fs_type(&mounts, Path::new("/tmp/file")), Some("ext4"));
+        assert_eq!(
+            mount_fs_type(&mounts, Path::new("/mnt/ram disk/a")),
+            Some("tmpfs")
+        );
+        assert_eq!(mount_fs_type(&mounts, Path::new("/procfoo")), Some("ext4"));
+
+        let config = Config {
+            exclude_kernfs: true,
+            ..Config::default()
+        };
+        let mut context = ScanContext::new(config, None).unwrap();
+        context.mounts = Some(mounts);
+        assert!(context.is_kernel_filesystem(Path::new("/mnt/ram disk/a")));
+        assert!(context.is_kernel_filesystem(Path::new("/proc")));
+        assert!(!context.is_kernel_filesystem(Path::new("/tmp/file")));
+        assert!(!context.is_kernel_filesystem(Path::new("/home")));
+    }
+
+    #[test]
+    fn test_profile_scan_timings() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/file"), "data").unwrap();
+
+        // Nothing is collected unless profiling was asked for
+        let context = ScanContext::new(Config::default(), None).unwrap();
+        scan_entry(root, &context).unwrap();
+        assert!(context.slowest_directories(10).is_empty());
+
+        let config = Config {
+            profile_scan: Some(2),
+            ..Config::default()
+        };
+        let context = ScanContext::new(config, None).unwrap();
+        scan_entry(root, &context).unwrap();
+
+        let mut timed: Vec<_> = context
+            .dir_timings
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect();
+        timed.sort();
+        assert_eq!(
+            timed,
+            vec![root.to_path_buf(), root.join("a"), root.join("a/b")]
+        );
+
+        let slowest = context.slowest_directories(2);
+        assert_eq!(slowest.len(), 2);
+        assert!(slowest[0].1 >= slowest[1].1);
+    }
+
+    #[test]
+    fn test_ignore_case_patterns() {
+        let config = Config {
+            exclude_patterns: vec!["*.JPG".to_string()],
+            ..Config::default()
+        };
+        let context = ScanContext::new(config.clone(), None).unwrap();
+        assert!(!context.is_excluded_by_pattern(Path::new("/photos/photo.jpg")));
+        assert!(context.is_excluded_by_pattern(Path::new("/photos/photo.JPG")));
+
+        let context = ScanContext::new(
+            Config {
+                ignore_case_patterns: true,
+                ..config
+            },
+            None,
+        )
+        .unwrap();
+        assert!(context.is_excluded_by_pattern(Path::new("/photos/photo.jpg")));
+        assert!(context.is_excluded_by_pattern(Path::new("/photos/Photo.Jpg")));
+        assert!(!context.is_excluded_by_pattern(Path::new("/photos/photo.png")));
+
+        // `.rsduignore` rules follow the same setting
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join(IGNORE_FILE), "*.LOG\nCache/*\n").unwrap();
+        context.load_ignore_file(root);
+        assert!(context.is_ignored(&root.join("app.log")));
+        assert!(context.is_ignored(&root.join("cache/blob")));
+        assert!(!context.is_ignored(&root.join("app.txt")));
+
+        let context = ScanContext::new(
+            Config {
+                include_patterns: vec!["*.TXT".to_string()],
+                ignore_case_patterns: true,
+                ..Config::default()
+            },
+            None,
+        )
+        .unwrap();
+        assert!(context.matches_include_only(Path::new("/notes/todo.txt")));
+    }
+
+    #[test]
+    fn test_exclude_if_present() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("keep")).unwrap();
+        fs::write(root.join("keep/data"), "data").unwrap();
+        fs::create_dir(root.join("scratch")).unwrap();
+        fs::write(root.join("scratch/.nobackup"), "").unwrap();
+        fs::write(root.join("scratch/data"), "data").unwrap();
+
+        assert!(has_marker_file(&root.join("scratch"), ".nobackup"));
+        assert!(!has_marker_file(&root.join("keep"), ".nobackup"));
+
+        let config = Config {
+            exclude_markers: vec![".nobackup".to_string()],
+            ..Config::default()
+        };
+        let entry = scan_directory(root, &config).unwrap();
+        assert_eq!(entry.entry_type, EntryType::Directory);
+        let child = |name: &str| {
+            entry
+                .children
+                .iter()
+                .find(|c| c.name_str() == name)
+                .unwrap()
+        };
+        assert_eq!(child("keep").entry_type, EntryType::Directory);
+        assert_eq!(child("scratch").entry_type, EntryType::Excluded);
+        assert!(child("scratch").children.is_empty());
+    }
+
+    #[test]
+    fn test_include_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join("notes.txt"), "notes").unwrap();
+        fs::write(root.join("photo.jpg"), "jpeg").unwrap();
+        fs::create_dir_all(root.join("docs/old")).unwrap();
+        fs::write(root.join("docs/old/draft.txt"), "draft").unwrap();
+        fs::write(root.join("docs/old/draft.bak"), "backup").unwrap();
+        fs::create_dir(root.join("media")).unwrap();
+        fs::write(root.join("media/clip.mp4"), "video").unwrap();
+
+        let config = Config {
+            include_patterns: vec!["*.txt".to_string()],
+            scan_ui: Some(ScanUi::None),
+            ..Config::default()
+        };
+        let stats = Arc::new(ScanStats::new());
+        let entry = scan_directory_counting(root, &config, None, None, stats.clone()).unwrap();
+
+        let mut names: Vec<String> = entry.children.iter().map(|c| c.name_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["docs", "notes.txt"]);
+
+        let docs = entry.children.iter().find(|c| c.name == "docs").unwrap();
+        let old = &docs.children[0];
+        assert_eq!(old.name, "old");
+        let old_names: Vec<String> = old.children.iter().map(|c| c.name_str()).collect();
+        assert_eq!(old_names, vec!["draft.txt"]);
+
+        // What was pruned was never counted: the root, docs, docs/old and
+        // the two text files are all there is
+        assert_eq!(stats.get_total_entries(), 5);
+        assert_eq!(stats.get_files(), 2);
+        assert_eq!(stats.get_directories(), 3);
+
+        // The walkdir scan keeps the same entries below the root
+        let walked = scan_directory_walkdir(root, &config).unwrap();
+        let mut names: Vec<String> = walked.children.iter().map(|c| c.name_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["docs", "notes.txt"]);
+    }
+
+    #[test]
+    fn test_include_only_matches_below_root() {
+        let temp_dir = TempDir::new().unwrap();
+        // Only paths below the scan root are matched, not the root's own name
+        let root = temp_dir.path().join("backup.txt");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("photo.jpg"), "jpeg").unwrap();
+        fs::create_dir(root.join("logs")).unwrap();
+        fs::write(root.join("logs/app.log"), "log").unwrap();
+        fs::write(root.join("logs/app.bin"), "bin").unwrap();
+        // An entry that can't be read may hide matches, so it stays
+        std::os::unix::fs::symlink(root.join("missing"), root.join("broken")).unwrap();
+
+        let config = Config {
+            include_patterns: vec!["logs/*.log".to_string()],
+            follow_symlinks: true,
+            scan_ui: Some(ScanUi::None),
+            ..Config::default()
+        };
+        let entry = scan_directory(&root, &config).unwrap();
+
+        let mut names: Vec<String> = entry.children.iter().map(|c| c.name_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["broken", "logs"]);
+        let logs = entry.children.iter().find(|c| c.name == "logs").unwrap();
+        assert_eq!(logs.children.len(), 1);
+        assert_eq!(logs.children[0].name, "app.log");
+        let broken = entry.children.iter().find(|c| c.name == "broken").unwrap();
+        assert!(broken.has_error());
+    }
+
+    #[test]
+    fn test_rsduignore() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("project/build/cache")).unwrap();
+        fs::write(
+            root.join("project/.rsduignore"),
+            "# scratch files\n*.tmp\nbuild/cache\n",
+        )
+        .unwrap();
+        fs::write(root.join("project/keep.txt"), "keep").unwrap();
+        fs::write(root.join("project/build/out.tmp"), "tmp").unwrap();
+        fs::write(root.join("project/build/out.bin"), "bin").unwrap();
+        // Patterns only apply inside the directory holding the ignore file
+        fs::write(root.join("top.tmp"), "tmp").unwrap();
+
+        let config = Config {
+            show_hidden: true,
+            ..Config::default()
+        };
+        let entry = scan_directory(root, &config).unwrap();
+        let find = |dir: &Arc<Entry>, name: &str| {
+            dir.children
+                .iter()
+                .find(|c| c.name_str() == name)
+                .unwrap()
+                .clone()
+        };
+
+        assert_eq!(find(&entry, "top.tmp").entry_type, EntryType::File);
+        let project = find(&entry, "project");
+        assert_eq!(find(&project, "keep.txt").entry_type, EntryType::File);
+        assert_eq!(find(&project, ".rsduignore").entry_type, EntryType::File);
+        let build = find(&project, "build");
+        assert_eq!(find(&build, "out.tmp").entry_type, EntryType::Excluded);
+        assert_eq!(find(&build, "out.bin").entry_type, EntryType::File);
+        assert_eq!(find(&build, "cache").entry_type, EntryType::Excluded);
+    }
+
+    #[test]
+    fn test_max_children() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for (name, len) in [("a", 100), ("b", 4000), ("c", 10), ("d", 3000), ("e", 1)] {
+            fs::write(root.join(name), vec![0u8; len]).unwrap();
+        }
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("sub/f"), vec![0u8; 2000]).unwrap();
+
+        let config = Config {
+            show_blocks: false,
+            ..Config::default()
+        };
+        let full = scan_directory(root, &config).unwrap();
+
+        let config = Config {
+            max_children: Some(3),
+            ..config
+        };
+        let capped = scan_directory(root, &config).unwrap();
+
+        assert_eq!(capped.children.len(), 4);
+        let mut names: Vec<_> = capped.children.iter().map(|c| c.name_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["(and 3 more)", "b", "d", "sub"]);
+        let left_out = capped
+            .children
+            .iter()
+            .find(|c| c.name_str() == "(and 3 more)")
+            .unwrap();
+        assert_eq!(left_out.entry_type, EntryType::Aggregate);
+        assert_eq!(capped.total_size(), full.total_size());
+        assert_eq!(capped.total_blocks(), full.total_blocks());
+    }
+
+    #[test]
+    fn test_max_children_ignores_shallow_sizes() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join("a"), vec![0u8; 9000]).unwrap();
+        fs::write(root.join("b"), vec![0u8; 8500]).unwrap();
+        fs::create_dir_all(root.join("sub/deep")).unwrap();
+        fs::write(root.join("sub/deep/f"), vec![0u8; 20000]).unwrap();
+
+        // Shallow, `sub` would only count itself and `deep`
+        let config = Config {
+            show_blocks: false,
+            shallow_dir_sizes: Some(true),
+            max_children: Some(2),
+            ..Config::default()
+        };
+        let capped = scan_directory(root, &config).unwrap();
+        let mut names: Vec<_> = capped.children.iter().map(|c| c.name_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["(and 1 more)", "a", "sub"]);
+    }
+
+    #[test]
+    fn test_progress_callback() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("sub/file"), "data").unwrap();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        let callback: ProgressCallback = Box::new(move |stats, path| {
+            recorder
+                .lock()
+                .unwrap()
+                .push((path.to_path_buf(), stats.total_entries));
+        });
+        let config = Config {
+            threads: 1,
+            progress_interval: Duration::ZERO,
+            ..Config::default()
+        };
+        scan_directory_with_progress(root, &config, Some(callback)).unwrap();
+
+        let seen = seen.lock().unwrap();
+        let paths: Vec<_> = seen.iter().map(|(path, _)| path.clone()).collect();
+        assert!(paths.contains(&root.join("sub")));
+        assert!(paths.contains(&root.join("sub/file")));
+        // Statistics grow as the scan goes on
+        assert!(seen.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        assert!(seen.last().unwrap().1 > 0);
+    }
+
+    #[test]
+    fn test_progress_interval() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..20 {
+            fs::write(temp_dir.path().join(format!("file{}", i)), "data").unwrap();
+        }
+
+        let reports = |progress_interval: Duration| {
+            let count = Arc::new(Mutex::new(0));
+            let counter = count.clone();
+            let callback: ProgressCallback = Box::new(move |_, _| *counter.lock().unwrap() += 1);
+            let config = Config {
+                threads: 1,
+                progress_interval,
+                ..Config::default()
+            };
+            scan_directory_with_progress(temp_dir.path(), &config, Some(callback)).unwrap();
+            let count = *count.lock().unwrap();
+            count
+        };
+
+        // The start of the scan, the root, each of the 20 files, then the end
+        assert_eq!(reports(Duration::ZERO), 23);
+        // Only the first report fits in an interval longer than the scan,
+        // but the final counts still come through
+        assert_eq!(reports(Duration::from_secs(3600)), 2);
+    }
+
+    #[test]
+    fn test_incremental_scan_reports() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("a/deep")).unwrap();
+        fs::write(root.join("a/deep/file"), vec![0u8; 100]).unwrap();
+        fs::write(root.join("b"), vec![0u8; 50]).unwrap();
+
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let recorder = updates.clone();
+        let partial: PartialCallback = Box::new(move |update| {
+            recorder.lock().unwrap().push(update);
+        });
+        let config = Config {
+            show_blocks: false,
+            ..Config::default()
+        };
+        let complete = scan_directory_incremental(root, &config, None, Some(partial)).unwrap();
+
+        let updates = updates.lock().unwrap();
+        assert_eq!(updates.len(), 3);
+        let PartialUpdate::Listing(ref listing) = updates[0] else {
+            panic!("the listing must come first");
+        };
+        let mut names: Vec<_> = listing.children.iter().map(|c| c.name_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(listing.total_size(), 0);
+
+        // Merging every subtree into the listing yields the complete sizes
+        let mut merged = listing.clone();
+        for update in &updates[1..] {
+            let PartialUpdate::Subtree(subtree) = update else {
+                panic!("only subtrees follow the listing");
+            };
+            crate::progress::merge_subtree(&mut merged, subtree.clone());
+        }
+        assert_eq!(merged.total_size(), complete.total_size() - complete.size);
+    }
+
+    #[test]
+    fn test_incremental_listing_is_incomplete() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("dir")).unwrap();
+        fs::write(root.join("dir/file"), "data").unwrap();
+
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let recorder = updates.clone();
+        let partial: PartialCallback = Box::new(move |update| {
+            recorder.lock().unwrap().push(update);
+        });
+        let complete =
+            scan_directory_incremental(root, &Config::default(), None, Some(partial)).unwrap();
+        assert!(complete.complete);
+
+        let updates = updates.lock().unwrap();
+        let PartialUpdate::Listing(ref listing) = updates[0] else {
+            panic!("the listing must come first");
+        };
+        assert!(!listing.complete);
+        assert!(!listing.children[0].complete);
+
+        // The finished subtree replacing the placeholder is complete
+        let PartialUpdate::Subtree(ref subtree) = updates[1] else {
+            panic!("only subtrees follow the listing");
+        };
+        assert!(subtree.complete);
+        assert!(subtree.children.iter().all(|child| child.complete));
+    }
+
+    #[test]
+    fn test_line_progress_ends_cleanly() {
+        let stats = ScanStats::new();
+        let progress = ProgressStats::from_scan_stats(&stats);
+        let path = Path::new("/data/some/dir");
+
+        // Finishing ends the live line before the summary
+        let mut out = Vec::new();
+        {
+            let mut line = LineProgress::new(&mut out, true, Path::new("/data"));
+            line.update(&progress, path);
+            line.finish(&stats);
+        }
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("Scanning directory: /data\n\r"));
+        assert!(text.contains("/data/some/dir\x1b[K\n\nScan complete:\n"));
+        assert!(text.ends_with(" Total blocks: 0\n"));
+
+        // A scan cut short still ends the line
+        let mut out = Vec::new();
+        {
+            let mut line = LineProgress::new(&mut out, true, Path::new("/data"));
+            line.update(&progress, path);
+        }
+        assert!(String::from_utf8(out).unwrap().ends_with("\x1b[K\n"));
+
+        // Without a terminal only the header and summary are written
+        let mut out = Vec::new();
+        {
+            let mut line = LineProgress::new(&mut out, false, Path::new("/data"));
+            line.update(&progress, path);
+            line.finish(&stats);
+        }
+        let text = String::from_utf8(out).unwrap();
+        assert!(!text.contains('\r'));
+        assert!(text.ends_with('\n'));
+    }
+
+    #[test]
+    fn test_mark_directory_visited() {
+        let context = ScanContext::new(Config::default(), None).unwrap();
+        assert!(context.mark_directory_visited(1, 42));
+        assert!(context.mark_directory_visited(2, 42));
+        assert!(!context.mark_directory_visited(1, 42));
+    }
+
     #[test]
     fn test_should_include_entry() {
         let temp_dir = TempDir::new().unwrap();
         let config = Config::default();
-        let context = ScanContext::new(config).unwrap();
+        let context = ScanContext::new(config, None).unwrap();
 
         // Create test entries
         std::fs::write(temp_dir.path().join("visible.txt"), "test").unwrap();
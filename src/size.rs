@@ -0,0 +1,249 @@
+//! Size metric selection and formatting
+//!
+//! Which number stands for an entry's size depends on several settings:
+//! disk usage or apparent size, the bytes per block, whether directories
+//! count only their immediate entries, and SI or binary prefixes. The
+//! browsers and reports all go through `SizeFormatter` so they agree, and
+//! format sizes and counts with the separators of the configured locale.
+
+use crate::cli::NumberLocale;
+use crate::config::Config;
+use crate::model::{Entry, EntryType};
+use crate::utils::{format_file_size, format_number_with_separator, localize_decimal};
+
+/// Size settings taken from a `Config`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeFormatter {
+    /// Disk usage (blocks) rather than apparent size
+    disk_usage: bool,
+    /// Bytes per block of disk usage
+    block_unit: u64,
+    /// Directories count only their own entries, not deeper levels
+    shallow_dirs: bool,
+    /// SI (base 10) prefixes rather than binary ones
+    si: bool,
+    /// Digit grouping and decimal separators
+    locale: NumberLocale,
+    /// Counts have their digits grouped rather than shown raw
+    group_counts: bool,
+}
+
+impl SizeFormatter {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            disk_usage: config.show_blocks,
+            block_unit: config.block_unit,
+            shallow_dirs: config.shallow_dir_sizes == Some(true),
+            si: config.si,
+            locale: config.locale,
+            group_counts: true,
+        }
+    }
+
+    /// The same settings, measuring disk usage or apparent size
+    pub fn with_disk_usage(self, disk_usage: bool) -> Self {
+        Self { disk_usage, ..self }
+    }
+
+    /// The same settings, with the digits of counts grouped or shown raw
+    pub fn with_grouped_counts(self, group_counts: bool) -> Self {
+        Self {
+            group_counts,
+            ..self
+        }
+    }
+
+    /// Size of the entry by itself, in bytes
+    pub fn own_value(&self, entry: &Entry) -> u64 {
+        if self.disk_usage {
+            entry.blocks.saturating_mul(self.block_unit)
+        } else {
+            entry.size
+        }
+    }
+
+    /// Size of the entry with what it contains, in bytes
+    ///
+    /// Directories sum their whole subtree, or only their immediate entries
+    /// when shallow sizes are on.
+    pub fn metric_value(&self, entry: &Entry) -> u64 {
+        if self.shallow_dirs && entry.entry_type.is_directory() {
+            self.own_value(entry)
+                + entry
+                    .children
+                    .iter()
+                    .map(|child| self.own_value(child))
+                    .sum::<u64>()
+        } else {
+            self.total_value(entry)
+        }
+    }
+
+    /// Size of the entry with its whole subtree, in bytes
+    ///
+    /// Unlike `metric_value` this ignores shallow sizes, for reports whose
+    /// figures must add up like `du`'s.
+    pub fn total_value(&self, entry: &Entry) -> u64 {
+        entry.sum_over_subtree(|e| self.own_value(e))
+    }
+
+    /// The largest regular file below `entry` by this metric, if any
+    pub fn largest_file<'a>(&self, entry: &'a Entry) -> Option<&'a Entry> {
+        entry.largest_file(self.disk_usage)
+    }
+
+    /// Part of `metric_value` held in hardlinked files, whose data other
+    /// links share
+    pub fn shared_value(&self, entry: &Entry) -> u64 {
+        entry.sum_over_subtree(|e| {
+            let linked = matches!(e.entry_type, EntryType::File | EntryType::Hardlink);
+            if linked && e.nlink > 1 {
+                self.own_value(e)
+            } else {
+                0
+            }
+        })
+    }
+
+    /// `metric_value` formatted for display, padded to a fixed width
+    pub fn format(&self, entry: &Entry) -> String {
+        self.format_bytes(self.metric_value(entry))
+    }
+
+    /// A byte count formatted for display, padded to a fixed width
+    pub fn format_bytes(&self, bytes: u64) -> String {
+        localize_decimal(&format_file_size(bytes, self.si), self.locale)
+    }
+
+    /// A count of entries, with its digits grouped unless counts are raw
+    pub fn format_count(&self, count: u64) -> String {
+        if self.group_counts {
+            format_number_with_separator(count, self.locale.thousands_separator())
+        } else {
+            count.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::generate_entry_id;
+    use std::sync::Arc;
+
+    fn entry(entry_type: EntryType, size: u64, blocks: u64, children: Vec<Arc<Entry>>) -> Entry {
+        let mut entry = Entry::new(
+            generate_entry_id(),
+            entry_type,
+            "entry".into(),
+            size,
+            blocks,
+            1,
+            1,
+            1,
+        );
+        entry.children = children;
+        entry
+    }
+
+    #[test]
+    fn test_size_settings() {
+        // dir (4096 bytes, 8 blocks) > sub (4096, 8) > file (1000, 2)
+        let file = Arc::new(entry(EntryType::File, 1000, 2, vec![]));
+        let sub = Arc::new(entry(EntryType::Directory, 4096, 8, vec![file.clone()]));
+        let dir = entry(EntryType::Directory, 4096, 8, vec![sub]);
+
+        let cases = [
+            // (disk usage, block unit, shallow, own, metric)
+            (false, 512, false, 4096, 9192),
+            (false, 512, true, 4096, 8192),
+            (true, 512, false, 4096, 9216),
+            (true, 512, true, 4096, 8192),
+            (true, 1024, false, 8192, 18432),
+            (true, 1024, true, 8192, 16384),
+        ];
+        for (show_blocks, block_unit, shallow_dir_sizes, own, metric) in cases {
+            let sizes = SizeFormatter::new(&Config {
+                show_blocks,
+                block_unit,
+                shallow_dir_sizes: Some(shallow_dir_sizes),
+                ..Config::default()
+            });
+            assert_eq!(sizes.own_value(&dir), own, "{:?}", sizes);
+            assert_eq!(sizes.metric_value(&dir), metric, "{:?}", sizes);
+        }
+
+        // Shallow sizes leave files alone
+        let shallow = SizeFormatter::new(&Config {
+            show_blocks: false,
+            shallow_dir_sizes: Some(true),
+            ..Config::default()
+        });
+        assert_eq!(shallow.metric_value(&file), 1000);
+    }
+
+    #[test]
+    fn test_shared_value() {
+        let mut linked = entry(EntryType::File, 3000, 6, vec![]);
+        linked.nlink = 2;
+        let dir = entry(
+            EntryType::Directory,
+            4096,
+            8,
+            vec![
+                Arc::new(linked),
+                Arc::new(entry(EntryType::File, 1000, 2, vec![])),
+            ],
+        );
+        let sizes = SizeFormatter::new(&Config {
+            show_blocks: false,
+            ..Config::default()
+        });
+        assert_eq!(sizes.shared_value(&dir), 3000);
+        assert_eq!(sizes.metric_value(&dir) - sizes.shared_value(&dir), 5096);
+    }
+
+    #[test]
+    fn test_size_formatting() {
+        let file = entry(EntryType::File, 1000, 2, vec![]);
+        for (show_blocks, si, expected) in [
+            (false, false, format_file_size(1000, false)),
+            (false, true, format_file_size(1000, true)),
+            (true, false, format_file_size(1024, false)),
+            (true, true, format_file_size(1024, true)),
+        ] {
+            let sizes = SizeFormatter::new(&Config {
+                show_blocks,
+                si,
+                ..Config::default()
+            });
+            assert_eq!(sizes.format(&file), expected);
+        }
+        assert_eq!(
+            SizeFormatter::new(&Config::default()).format_bytes(1024),
+            "     1 KiB"
+        );
+    }
+
+    #[test]
+    fn test_locale_formatting() {
+        let cases = [
+            // (locale, size of 1536 bytes, count)
+            (NumberLocale::En, "1.50 KiB", "1,234,567"),
+            (NumberLocale::De, "1,50 KiB", "1.234.567"),
+            (NumberLocale::Fr, "1,50 KiB", "1 234 567"),
+        ];
+        for (locale, size, count) in cases {
+            let sizes = SizeFormatter::new(&Config {
+                locale,
+                ..Config::default()
+            });
+            assert_eq!(sizes.format_bytes(1536).trim_start(), size, "{:?}", locale);
+            assert_eq!(sizes.format_count(1234567), count, "{:?}", locale);
+            assert_eq!(sizes.format_count(999), "999", "{:?}", locale);
+        }
+
+        let raw = SizeFormatter::new(&Config::default()).with_grouped_counts(false);
+        assert_eq!(raw.format_count(1234567), "1234567");
+    }
+}
@@ -6,12 +6,28 @@
 //! - Proper event handling and state management
 //! - Clean transitions between modes
 
-use crate::config::Config;
+use crate::cli::{GraphStyle, SharedColumn};
+use crate::config::{Config, Preference, ScanUi, SortColumn, SortOrder};
+use crate::delete::{delete_path, describe_delete, PATH_PLACEHOLDER};
 use crate::error::{Result, RsduError};
-use crate::model::{Entry, EntryType, ScanStats};
-use crate::utils::format_file_size;
+use crate::keymap;
+use crate::model::{propagate_to_ancestors, DeviceId, Entry, EntryId, EntryType};
+use crate::navigation::{handle_navigation_key, BrowserBackend, NavOutcome};
+pub use crate::progress::ScanMessage;
+use crate::progress::{merge_subtree, PartialUpdate};
+use crate::scanner::{
+    read_mounts_by_device, restat_entry, scan_directory, sort_entries, MountSource,
+};
+use crate::size::SizeFormatter;
+use crate::utils::{
+    create_progress_bar, filesystem_usage, format_error_breakdown, format_mode, format_percentage,
+    group_name, is_world_writable, user_name, FsUsage,
+};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -23,23 +39,38 @@ use ratatui::{
     widgets::{block::Title, Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
     Frame, Terminal,
 };
+use std::collections::{HashMap, HashSet};
 use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{
     atomic::{AtomicBool, AtomicUsize, Ordering},
-    Arc, Mutex,
+    Arc, Mutex, OnceLock,
 };
 use std::time::{Duration, Instant};
-use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
 
 /// TUI application state
-pub struct TuiApp {
-    terminal: Terminal<CrosstermBackend<io::Stdout>>,
+pub struct TuiApp<W: io::Write = io::Stdout> {
+    terminal: Terminal<CrosstermBackend<W>>,
     config: Config,
     mode: AppMode,
+    /// Path being scanned, used to look up filesystem usage
+    scan_path: String,
+    /// Usage of the filesystem holding the scanned directory
+    fs_usage: Option<FsUsage>,
+    /// Updates from a scan still running under an incremental browse
+    scan_updates: Option<Receiver<ScanMessage>>,
+    /// The screen no longer shows the current state and must be drawn
+    dirty: bool,
+    /// Mount table by device id, read the first time entry info is shown
+    mounts: Option<HashMap<DeviceId, MountSource>>,
 }
 
 /// Application modes
+// The app holds a single mode, so the larger browsing state costs nothing
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum AppMode {
     Scanning {
@@ -52,10 +83,143 @@ pub enum AppMode {
         path_stack: Vec<Arc<Entry>>,
         list_state: ListState,
         show_help: bool,
+        bookmarks: HashMap<u8, Bookmark>,
+        pending_bookmark: bool,
+        unfolded_dir: Option<EntryId>,
+        expanded: HashSet<EntryId>,
+        /// Name filter typed with `/`
+        name_filter: Option<NameFilter>,
+        dialog: Option<Dialog>,
+        /// Preferences changed while browsing, offered for saving on exit
+        changed_prefs: HashSet<Preference>,
+        /// Shared column mode, starting from `Config::show_shared`
+        shared_column: SharedColumn,
+        /// Directories are sorted before files, starting from
+        /// `Config::sort_dirs_first`
+        dirs_first: bool,
+        /// Counts have their digits grouped rather than shown raw
+        group_counts: bool,
     },
     Quit,
 }
 
+/// Popup shown over the browsing list, taking all input while open
+#[derive(Debug, Clone)]
+pub enum Dialog {
+    /// Ask before deleting `target`, which lives at `path` on disk
+    ConfirmDelete { target: Arc<Entry>, path: PathBuf },
+    /// Single-line text input
+    Input {
+        purpose: InputPurpose,
+        value: String,
+    },
+    /// Informational message, dismissed by any key
+    Message(String),
+    /// Details of the selected entry, dismissed by any key
+    Info(Vec<String>),
+    /// Offer to save changed preferences before quitting
+    ConfirmQuit,
+}
+
+/// How the user answered the quit prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuitChoice {
+    /// Save the changed preferences, then quit
+    Save,
+    /// Quit without saving
+    Discard,
+    /// Keep browsing
+    Cancel,
+}
+
+/// Interpret a key pressed at the quit prompt, None if it isn't an answer
+fn resolve_quit_prompt(key: KeyCode) -> Option<QuitChoice> {
+    match key {
+        KeyCode::Char('s') | KeyCode::Enter => Some(QuitChoice::Save),
+        KeyCode::Char('d') => Some(QuitChoice::Discard),
+        KeyCode::Char('c') | KeyCode::Esc => Some(QuitChoice::Cancel),
+        _ => None,
+    }
+}
+
+/// What the text typed into an input popup is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputPurpose {
+    /// The command run to delete an entry
+    DeleteCommand,
+    /// The file a size report of the current directory is saved to
+    SaveReport,
+    /// Text that listed names must contain
+    Filter,
+}
+
+/// Text that listed names must contain, typed with `/`
+///
+/// It applies to the directory it was typed in only, like `unfolded_dir`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameFilter {
+    pub dir: EntryId,
+    pub pattern: String,
+}
+
+/// The pattern of `filter`, if it applies to `current_dir`
+fn active_filter<'a>(filter: Option<&'a NameFilter>, current_dir: &Entry) -> Option<&'a str> {
+    filter
+        .filter(|filter| filter.dir == current_dir.id)
+        .map(|filter| filter.pattern.as_str())
+}
+
+/// A row in the browsing list
+#[derive(Debug, Clone)]
+pub enum ListRow {
+    /// The `..` row leading back to the parent directory
+    Parent,
+    /// A child of the current directory
+    Entry(Arc<Entry>),
+    /// Small entries folded into a single aggregate row
+    Folded { count: usize, size: u64 },
+    /// Child of an expanded directory, shown inline and indented by `depth`
+    Preview { entry: Arc<Entry>, depth: usize },
+}
+
+/// Maximum number of children shown beneath an expanded directory
+const PREVIEW_LIMIT: usize = 10;
+
+/// Navigation state saved in a bookmark slot
+#[derive(Debug, Clone)]
+pub struct Bookmark {
+    pub path_stack: Vec<Arc<Entry>>,
+    pub current_dir: Arc<Entry>,
+    pub selected: Option<usize>,
+}
+
+impl Bookmark {
+    /// Capture the current navigation state
+    fn capture(
+        path_stack: &[Arc<Entry>],
+        current_dir: &Arc<Entry>,
+        list_state: &ListState,
+    ) -> Self {
+        Self {
+            path_stack: path_stack.to_vec(),
+            current_dir: current_dir.clone(),
+            selected: list_state.selected(),
+        }
+    }
+
+    /// Restore the saved navigation state
+    fn restore(
+        &self,
+        path_stack: &mut Vec<Arc<Entry>>,
+        current_dir: &mut Arc<Entry>,
+        list_state: &mut ListState,
+    ) {
+        *path_stack = self.path_stack.clone();
+        *current_dir = self.current_dir.clone();
+        list_state.select(self.selected.or(Some(0)));
+    }
+}
+
 /// Scanning progress information
 #[derive(Debug)]
 pub struct ScanProgress {
@@ -64,6 +228,8 @@ pub struct ScanProgress {
     pub directories: AtomicUsize,
     pub files: AtomicUsize,
     pub errors: AtomicUsize,
+    pub permission_errors: AtomicUsize,
+    pub not_found_errors: AtomicUsize,
     pub total_size: AtomicUsize,
     pub is_complete: AtomicBool,
 }
@@ -76,49 +242,14 @@ impl Default for ScanProgress {
             directories: AtomicUsize::new(0),
             files: AtomicUsize::new(0),
             errors: AtomicUsize::new(0),
+            permission_errors: AtomicUsize::new(0),
+            not_found_errors: AtomicUsize::new(0),
             total_size: AtomicUsize::new(0),
             is_complete: AtomicBool::new(false),
         }
     }
 }
 
-/// Simple stats for progress messages
-#[derive(Debug, Clone)]
-pub struct ProgressStats {
-    pub total_entries: u64,
-    pub directories: u64,
-    pub files: u64,
-    pub errors: u64,
-    pub total_size: u64,
-}
-
-impl ProgressStats {
-    pub fn from_scan_stats(stats: &ScanStats) -> Self {
-        Self {
-            total_entries: stats.get_total_entries(),
-            directories: stats.get_directories(),
-            files: stats.get_files(),
-            errors: stats.get_errors(),
-            total_size: stats.get_total_size(),
-        }
-    }
-}
-
-/// Messages sent during scanning
-#[derive(Debug, Clone)]
-pub enum ScanMessage {
-    Progress {
-        current_path: String,
-        stats: ProgressStats,
-    },
-    Complete {
-        root: Arc<Entry>,
-    },
-    Error {
-        message: String,
-    },
-}
-
 impl TuiApp {
     /// Create a new TUI application
     pub fn new(config: Config) -> Result<Self> {
@@ -126,18 +257,30 @@ impl TuiApp {
         enable_raw_mode()
             .map_err(|e| RsduError::UiError(format!("Failed to enable raw mode: {}", e)))?;
         let mut stdout = io::stdout();
-        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)
+        setup_terminal(&mut stdout, config.alt_screen)
             .map_err(|e| RsduError::UiError(format!("Failed to setup terminal: {}", e)))?;
 
         let backend = CrosstermBackend::new(stdout);
         let terminal = Terminal::new(backend)
             .map_err(|e| RsduError::UiError(format!("Failed to create terminal: {}", e)))?;
 
-        Ok(Self {
+        Ok(Self::with_terminal(terminal, config))
+    }
+}
+
+impl<W: io::Write> TuiApp<W> {
+    /// Create the application around a terminal that is already set up
+    fn with_terminal(terminal: Terminal<CrosstermBackend<W>>, config: Config) -> Self {
+        Self {
             terminal,
             config,
             mode: AppMode::Quit, // Will be set when starting scan
-        })
+            scan_path: String::new(),
+            fs_usage: None,
+            scan_updates: None,
+            dirty: true,
+            mounts: None,
+        }
     }
 
     /// Start scanning with progress display
@@ -152,8 +295,9 @@ impl TuiApp {
 
         // Update initial path
         if let Ok(mut current_path) = progress.current_path.lock() {
-            *current_path = scan_path;
+            *current_path = scan_path.clone();
         }
+        self.scan_path = scan_path;
 
         Ok(sender)
     }
@@ -162,27 +306,30 @@ impl TuiApp {
     pub fn run(&mut self) -> Result<()> {
         let mut last_tick = Instant::now();
         let mut last_ui_update = Instant::now();
-        let tick_rate = Duration::from_millis(50); // Faster tick rate for scanning updates
-        let ui_update_rate = Duration::from_millis(100); // UI refresh rate
+        // Scan messages are taken in at a fixed rate; `progress_interval`
+        // only throttles how often the scanner reports progress
+        let tick_rate = Duration::from_millis(50);
 
         loop {
             // Handle updates first
             if last_tick.elapsed() >= tick_rate {
-                self.update()?;
+                self.dirty |= self.update()?;
                 last_tick = Instant::now();
             }
 
-            // Draw the UI at a controlled rate to avoid flickering
-            let should_draw = match &self.mode {
-                AppMode::Scanning { .. } => last_ui_update.elapsed() >= ui_update_rate,
-                _ => true, // Always draw for browsing mode
-            };
-
-            if should_draw {
+            let scanning = matches!(self.mode, AppMode::Scanning { .. });
+            if should_redraw(
+                scanning,
+                self.dirty,
+                last_ui_update.elapsed(),
+                self.config.update_delay,
+            ) {
                 let should_quit = {
                     let mode_ref = &self.mode;
+                    let fs_usage = self.fs_usage.as_ref();
+                    let scanning = self.scan_updates.is_some();
                     self.terminal
-                        .draw(|f| draw_ui_for_mode(f, mode_ref, &self.config))
+                        .draw(|f| draw_ui_for_mode(f, mode_ref, &self.config, fs_usage, scanning))
                         .map_err(|e| RsduError::UiError(format!("Failed to draw: {}", e)))?;
                     matches!(self.mode, AppMode::Quit)
                 };
@@ -191,21 +338,18 @@ impl TuiApp {
                     break;
                 }
                 last_ui_update = Instant::now();
+                self.dirty = false;
             }
 
             // Handle input
-            let timeout = Duration::from_millis(10); // Short timeout for responsiveness
+            let timeout = poll_timeout(scanning || self.scan_updates.is_some());
             if event::poll(timeout)
                 .map_err(|e| RsduError::UiError(format!("Event poll error: {}", e)))?
             {
-                if let Event::Key(key) = event::read()
-                    .map_err(|e| RsduError::UiError(format!("Event read error: {}", e)))?
-                {
-                    if key.kind == KeyEventKind::Press {
-                        if self.handle_key_event(key.code)? {
-                            break;
-                        }
-                    }
+                let event = event::read()
+                    .map_err(|e| RsduError::UiError(format!("Event read error: {}", e)))?;
+                if self.handle_event(event)? {
+                    break;
                 }
             }
         }
@@ -213,10 +357,35 @@ impl TuiApp {
         Ok(())
     }
 
-    /// Update application state
-    fn update(&mut self) -> Result<()> {
+    /// Handle one terminal event, returning whether to quit
+    fn handle_event(&mut self, event: Event) -> Result<bool> {
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => self.handle_key_event(key),
+            Event::Resize(..) => {
+                self.handle_resize();
+                Ok(false)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Redraw right away after a resize, even while the scanning screen is
+    /// rate-limited
+    ///
+    /// The list's scroll offset was fitted to the old height; dropping it lets
+    /// the next frame scroll just far enough to keep the selection visible.
+    fn handle_resize(&mut self) {
+        if let AppMode::Browsing { list_state, .. } = &mut self.mode {
+            *list_state.offset_mut() = 0;
+        }
+        self.dirty = true;
+    }
+
+    /// Update application state, returning whether the screen is out of date
+    fn update(&mut self) -> Result<bool> {
         match &mut self.mode {
             AppMode::Scanning { receiver, progress } => {
+                let mut listing = None;
                 if let Some(rx) = receiver {
                     // Process multiple messages per update but limit to avoid blocking UI
                     let mut processed = 0;
@@ -244,14 +413,28 @@ impl TuiApp {
                                         progress
                                             .errors
                                             .store(stats.errors as usize, Ordering::Relaxed);
+                                        progress.permission_errors.store(
+                                            stats.permission_errors as usize,
+                                            Ordering::Relaxed,
+                                        );
+                                        progress.not_found_errors.store(
+                                            stats.not_found_errors as usize,
+                                            Ordering::Relaxed,
+                                        );
                                         progress
                                             .total_size
                                             .store(stats.total_size as usize, Ordering::Relaxed);
                                     }
+                                    ScanMessage::Partial(PartialUpdate::Listing(root)) => {
+                                        listing = Some(root);
+                                        break;
+                                    }
+                                    // Subtrees only follow the listing
+                                    ScanMessage::Partial(PartialUpdate::Subtree(_)) => {}
                                     ScanMessage::Complete { root } => {
                                         progress.is_complete.store(true, Ordering::Relaxed);
-                                        self.start_browsing(root)?;
-                                        return Ok(());
+                                        self.start_browsing(root, None)?;
+                                        return Ok(true);
                                     }
                                     ScanMessage::Error { message } => {
                                         return Err(RsduError::ScanError {
@@ -265,19 +448,90 @@ impl TuiApp {
                         }
                     }
                 }
+
+                // Browse the listing while the rest of the scan streams in
+                if let Some(root) = listing {
+                    let scan_updates = receiver.take();
+                    self.start_browsing(root, scan_updates)?;
+                    return Ok(true);
+                }
+                // The scanning screen refreshes on a timer instead
+                Ok(false)
             }
-            AppMode::Browsing { .. } => {
-                // Nothing to update in browsing mode
+            AppMode::Browsing {
+                root,
+                current_dir,
+                path_stack,
+                list_state,
+                unfolded_dir,
+                expanded,
+                name_filter,
+                dialog,
+                ..
+            } => {
+                let Some(rx) = &self.scan_updates else {
+                    return Ok(false);
+                };
+                let mut changed = false;
+                let mut finished = false;
+                for msg in rx.try_iter() {
+                    match msg {
+                        ScanMessage::Partial(PartialUpdate::Subtree(subtree)) => {
+                            merge_subtree(root, subtree);
+                            changed = true;
+                        }
+                        ScanMessage::Partial(PartialUpdate::Listing(listing)) => {
+                            *root = listing;
+                            changed = true;
+                        }
+                        ScanMessage::Complete { root: complete } => {
+                            *root = complete;
+                            changed = true;
+                            finished = true;
+                        }
+                        ScanMessage::Error { message } => {
+                            *dialog = Some(Dialog::Message(message));
+                            finished = true;
+                        }
+                        ScanMessage::Progress { .. } => {}
+                    }
+                }
+
+                if changed {
+                    // Follow the user's position into the new tree
+                    let (new_stack, new_dir) = relocate(root, path_stack, current_dir);
+                    *path_stack = new_stack;
+                    *current_dir = new_dir;
+                    let row_count = build_list_rows(
+                        current_dir,
+                        &self.config,
+                        *unfolded_dir,
+                        expanded,
+                        !path_stack.is_empty(),
+                        name_filter.as_ref(),
+                    )
+                    .len();
+                    let selected = list_state.selected().unwrap_or(0);
+                    list_state.select(Some(selected.min(row_count.saturating_sub(1))));
+                }
+                if finished {
+                    self.scan_updates = None;
+                }
+                Ok(changed || finished)
             }
-            AppMode::Quit => {}
+            AppMode::Quit => Ok(true),
         }
-        Ok(())
     }
 
     /// Switch to browsing mode
-    fn start_browsing(&mut self, root: Arc<Entry>) -> Result<()> {
+    fn start_browsing(
+        &mut self,
+        root: Arc<Entry>,
+        scan_updates: Option<Receiver<ScanMessage>>,
+    ) -> Result<()> {
         let mut list_state = ListState::default();
         list_state.select(Some(0));
+        self.fs_usage = filesystem_usage(&self.scan_path);
 
         self.mode = AppMode::Browsing {
             current_dir: root.clone(),
@@ -285,156 +539,794 @@ impl TuiApp {
             path_stack: Vec::new(),
             list_state,
             show_help: false,
+            bookmarks: HashMap::new(),
+            pending_bookmark: false,
+            unfolded_dir: None,
+            expanded: HashSet::new(),
+            name_filter: None,
+            dialog: None,
+            changed_prefs: HashSet::new(),
+            shared_column: self.config.show_shared,
+            dirs_first: self.config.sort_dirs_first,
+            group_counts: true,
         };
+        self.scan_updates = scan_updates;
         Ok(())
     }
 
     /// Handle keyboard events
-    fn handle_key_event(&mut self, key: KeyCode) -> Result<bool> {
+    ///
+    /// Returns whether to quit. Keys that change what is on screen mark it
+    /// dirty; anything else leaves it alone so no frame is drawn.
+    fn handle_key_event(&mut self, key: KeyEvent) -> Result<bool> {
+        let key = match dispatch_key(key) {
+            KeyDispatch::Quit => return Ok(true),
+            KeyDispatch::Ignore => return Ok(false),
+            KeyDispatch::Handle(code) => code,
+        };
+
         match &mut self.mode {
             AppMode::Scanning { .. } => {
-                match key {
-                    KeyCode::Char('q') | KeyCode::Esc | KeyCode::Char('c') => {
-                        return Ok(true); // Quit
-                    }
-                    _ => {}
+                return Ok(matches!(
+                    key,
+                    KeyCode::Char('q') | KeyCode::Esc | KeyCode::Char('c')
+                ));
+            }
+            AppMode::Browsing {
+                dialog: Some(_), ..
+            } => {
+                self.dirty |= self.handle_dialog_key(key);
+                if matches!(self.mode, AppMode::Quit) {
+                    return Ok(true);
                 }
             }
             AppMode::Browsing {
-                current_dir,
-                path_stack,
-                list_state,
-                show_help,
-                ..
+                pending_bookmark, ..
             } => {
-                match key {
-                    KeyCode::Char('q') | KeyCode::Esc => {
-                        if *show_help {
-                            *show_help = false;
-                        } else {
-                            return Ok(true); // Quit
-                        }
+                // A pending bookmark only applies to the key right after 'm'
+                let saving_bookmark = std::mem::take(pending_bookmark);
+
+                // Translate remapped keys into the built-in ones handled below
+                let Some(key) = keymap::resolve(&self.config.keybindings, key) else {
+                    return Ok(false);
+                };
+
+                match handle_navigation_key(self, key) {
+                    NavOutcome::Quit => return Ok(self.request_quit()),
+                    NavOutcome::Handled => self.dirty = true,
+                    NavOutcome::Unhandled => {
+                        self.dirty |= self.handle_browsing_key(key, saving_bookmark)
                     }
-                    KeyCode::Char('?') | KeyCode::F(1) => {
-                        *show_help = !*show_help;
+                }
+            }
+            AppMode::Quit => {}
+        }
+        Ok(false)
+    }
+
+    /// Handle the browsing keys specific to this UI
+    ///
+    /// Returns false if the key did nothing.
+    fn handle_browsing_key(&mut self, key: KeyCode, saving_bookmark: bool) -> bool {
+        let AppMode::Browsing {
+            root,
+            current_dir,
+            path_stack,
+            list_state,
+            show_help,
+            bookmarks,
+            pending_bookmark,
+            unfolded_dir,
+            expanded,
+            name_filter,
+            dialog,
+            changed_prefs,
+            shared_column,
+            dirs_first,
+            group_counts,
+        } = &mut self.mode
+        else {
+            return false;
+        };
+        if *show_help {
+            return false;
+        }
+
+        match key {
+            KeyCode::Char(' ') => {
+                let rows = build_list_rows(
+                    current_dir,
+                    &self.config,
+                    *unfolded_dir,
+                    expanded,
+                    !path_stack.is_empty(),
+                    name_filter.as_ref(),
+                );
+                match list_state.selected().and_then(|index| rows.get(index)) {
+                    Some(ListRow::Entry(entry)) | Some(ListRow::Preview { entry, .. }) => {
+                        toggle_expanded(expanded, entry);
                     }
-                    KeyCode::Up | KeyCode::Char('k') => {
-                        if !*show_help {
-                            self.move_selection(-1);
-                        }
+                    _ => {}
+                }
+            }
+            KeyCode::Char('S') => {
+                self.config.shallow_dir_sizes = Some(self.config.shallow_dir_sizes != Some(true));
+                changed_prefs.insert(Preference::DirSizes);
+                if matches!(self.config.sort_col, SortColumn::Size | SortColumn::Blocks) {
+                    *root = resort_current_dir(
+                        current_dir,
+                        path_stack,
+                        list_state,
+                        *unfolded_dir,
+                        expanded,
+                        name_filter.as_ref(),
+                        &with_dirs_first(&self.config, *dirs_first),
+                    );
+                }
+            }
+            KeyCode::Char('t') => {
+                *dirs_first = !*dirs_first;
+                *root = resort_current_dir(
+                    current_dir,
+                    path_stack,
+                    list_state,
+                    *unfolded_dir,
+                    expanded,
+                    name_filter.as_ref(),
+                    &with_dirs_first(&self.config, *dirs_first),
+                );
+            }
+            KeyCode::Char('u') => {
+                *shared_column = next_shared_column(*shared_column);
+            }
+            KeyCode::Char('#') => {
+                *group_counts = !*group_counts;
+            }
+            KeyCode::Char('R') => {
+                if self.config.can_refresh == Some(false) {
+                    *dialog = Some(Dialog::Message("Refreshing is disabled".to_string()));
+                    return true;
+                }
+                if self.scan_updates.is_some() {
+                    *dialog = Some(Dialog::Message(
+                        "Wait for the scan to finish before refreshing".to_string(),
+                    ));
+                    return true;
+                }
+
+                let scan_root = Path::new(&self.scan_path);
+                let config = with_dirs_first(&self.config, *dirs_first);
+                match rescan_current_dir(scan_root, path_stack, current_dir, &config) {
+                    Ok(new_root) => *root = new_root,
+                    Err(e) => {
+                        *dialog = Some(Dialog::Message(format!(
+                            "Cannot refresh {}: {}",
+                            current_dir_disk_path(scan_root, path_stack, current_dir).display(),
+                            e
+                        )));
+                        return true;
                     }
-                    KeyCode::Down | KeyCode::Char('j') => {
-                        if !*show_help {
-                            self.move_selection(1);
+                }
+                let last = build_list_rows(
+                    current_dir,
+                    &self.config,
+                    *unfolded_dir,
+                    expanded,
+                    !path_stack.is_empty(),
+                    name_filter.as_ref(),
+                )
+                .len()
+                .saturating_sub(1);
+                list_state.select(Some(list_state.selected().unwrap_or(0).min(last)));
+                self.fs_usage = filesystem_usage(&self.scan_path);
+            }
+            KeyCode::Char('d') => {
+                let rows = build_list_rows(
+                    current_dir,
+                    &self.config,
+                    *unfolded_dir,
+                    expanded,
+                    !path_stack.is_empty(),
+                    name_filter.as_ref(),
+                );
+                let Some(ListRow::Entry(target)) =
+                    list_state.selected().and_then(|index| rows.get(index))
+                else {
+                    return false;
+                };
+                if self.config.can_delete == Some(false) {
+                    *dialog = Some(Dialog::Message("Deletion is disabled".to_string()));
+                    return true;
+                }
+                if self.scan_updates.is_some() {
+                    *dialog = Some(Dialog::Message(
+                        "Wait for the scan to finish before deleting".to_string(),
+                    ));
+                    return true;
+                }
+
+                let path =
+                    entry_disk_path(Path::new(&self.scan_path), path_stack, current_dir, target);
+                if let Some(reason) = delete_refusal(
+                    Path::new(&self.scan_path),
+                    path_stack,
+                    current_dir,
+                    target,
+                    &path,
+                ) {
+                    *dialog = Some(Dialog::Message(reason.to_string()));
+                    return true;
+                }
+                let target = target.clone();
+                if self.config.confirm_delete {
+                    *dialog = Some(Dialog::ConfirmDelete { target, path });
+                } else {
+                    self.delete_entry(&target, &path);
+                }
+            }
+            KeyCode::Char('E') => {
+                *dialog = Some(Dialog::Input {
+                    purpose: InputPurpose::DeleteCommand,
+                    value: self.config.delete_command.clone(),
+                });
+            }
+            KeyCode::Char('c') => {
+                *dialog = Some(Dialog::Input {
+                    purpose: InputPurpose::SaveReport,
+                    value: REPORT_FILE_NAME.to_string(),
+                });
+            }
+            KeyCode::Char('/') => {
+                *dialog = Some(Dialog::Input {
+                    purpose: InputPurpose::Filter,
+                    value: active_filter(name_filter.as_ref(), current_dir)
+                        .unwrap_or_default()
+                        .to_string(),
+                });
+            }
+            KeyCode::Char('i') => {
+                let rows = build_list_rows(
+                    current_dir,
+                    &self.config,
+                    *unfolded_dir,
+                    expanded,
+                    !path_stack.is_empty(),
+                    name_filter.as_ref(),
+                );
+                let Some(ListRow::Entry(entry) | ListRow::Preview { entry, .. }) =
+                    list_state.selected().and_then(|index| rows.get(index))
+                else {
+                    return false;
+                };
+                let path =
+                    entry_disk_path(Path::new(&self.scan_path), path_stack, current_dir, entry);
+                let mounts = self.mounts.get_or_insert_with(read_mounts_by_device);
+                let sizes = SizeFormatter::new(&self.config).with_grouped_counts(*group_counts);
+                *dialog = Some(Dialog::Info(entry_info(entry, &path, &sizes, mounts)));
+            }
+            KeyCode::Char('r') => {
+                let rows = build_list_rows(
+                    current_dir,
+                    &self.config,
+                    *unfolded_dir,
+                    expanded,
+                    !path_stack.is_empty(),
+                    name_filter.as_ref(),
+                );
+                let Some(ListRow::Entry(target)) =
+                    list_state.selected().and_then(|index| rows.get(index))
+                else {
+                    return false;
+                };
+                if self.config.can_refresh == Some(false) {
+                    *dialog = Some(Dialog::Message("Refreshing is disabled".to_string()));
+                    return true;
+                }
+                if target.entry_type == EntryType::Aggregate {
+                    *dialog = Some(Dialog::Message(
+                        "Entries that were left out can't be refreshed".to_string(),
+                    ));
+                    return true;
+                }
+                if self.scan_updates.is_some() {
+                    *dialog = Some(Dialog::Message(
+                        "Wait for the scan to finish before refreshing".to_string(),
+                    ));
+                    return true;
+                }
+
+                let path = linked_disk_path(Path::new(&self.scan_path), root, target);
+                match restat_entry(target, &path, &self.config) {
+                    Ok(fresh) => {
+                        let dir = Arc::make_mut(current_dir);
+                        if let Some(slot) = dir.children.iter_mut().find(|c| c.id == fresh.id) {
+                            *slot = fresh.into_linked();
                         }
+                        *root = propagate_to_ancestors(path_stack, current_dir.clone());
                     }
-                    KeyCode::Home | KeyCode::Char('g') => {
-                        if !*show_help {
-                            list_state.select(Some(0));
-                        }
+                    Err(RsduError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                        *dialog = Some(Dialog::Message(format!(
+                            "{} no longer exists",
+                            path.display()
+                        )));
                     }
-                    KeyCode::End | KeyCode::Char('G') => {
-                        if !*show_help && !current_dir.children.is_empty() {
-                            list_state.select(Some(current_dir.children.len() - 1));
-                        }
+                    Err(e) => {
+                        *dialog = Some(Dialog::Message(format!(
+                            "Cannot refresh {}: {}",
+                            path.display(),
+                            e
+                        )));
                     }
-                    KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
-                        if !*show_help {
-                            self.enter_selected()?;
+                }
+            }
+            KeyCode::Char('m') => {
+                *pending_bookmark = true;
+            }
+            KeyCode::Char(slot @ '1'..='9') => {
+                let slot = slot as u8 - b'0';
+                if saving_bookmark {
+                    bookmarks.insert(slot, Bookmark::capture(path_stack, current_dir, list_state));
+                } else if let Some(bookmark) = bookmarks.get(&slot) {
+                    bookmark.restore(path_stack, current_dir, list_state);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl<W: io::Write> TuiApp<W> {
+    /// Quit, or first offer to save changed preferences
+    ///
+    /// Returns whether to quit right away. Otherwise the quit prompt is
+    /// opened, which only happens when saving on exit is enabled and
+    /// preferences were changed since startup.
+    fn request_quit(&mut self) -> bool {
+        let AppMode::Browsing {
+            dialog,
+            changed_prefs,
+            ..
+        } = &mut self.mode
+        else {
+            return true;
+        };
+        if self.config.save_on_exit != Some(true) || changed_prefs.is_empty() {
+            return true;
+        }
+        *dialog = Some(Dialog::ConfirmQuit);
+        self.dirty = true;
+        false
+    }
+
+    /// Handle a key while a dialog is open, returning false if it did nothing
+    fn handle_dialog_key(&mut self, key: KeyCode) -> bool {
+        let AppMode::Browsing {
+            current_dir,
+            dialog,
+            changed_prefs,
+            list_state,
+            name_filter,
+            ..
+        } = &mut self.mode
+        else {
+            return false;
+        };
+        let Some(open) = dialog.take() else {
+            return false;
+        };
+
+        match open {
+            Dialog::ConfirmDelete { target, path } => match key {
+                KeyCode::Char('y') | KeyCode::Enter => self.delete_entry(&target, &path),
+                KeyCode::Char('n') | KeyCode::Esc => {}
+                _ => {
+                    *dialog = Some(Dialog::ConfirmDelete { target, path });
+                    return false;
+                }
+            },
+            Dialog::Input { purpose, mut value } => match key {
+                KeyCode::Enter => match purpose {
+                    InputPurpose::DeleteCommand => {
+                        if self.config.delete_command != value {
+                            changed_prefs.insert(Preference::DeleteCommand);
                         }
+                        self.config.delete_command = value;
+                    }
+                    InputPurpose::SaveReport => self.save_size_report(Path::new(&value)),
+                    InputPurpose::Filter => {
+                        *name_filter = (!value.is_empty()).then(|| NameFilter {
+                            dir: current_dir.id,
+                            pattern: value,
+                        });
+                        list_state.select(Some(0));
                     }
-                    KeyCode::Left | KeyCode::Char('h') | KeyCode::Backspace => {
-                        if !*show_help && !path_stack.is_empty() {
-                            let parent = path_stack.pop().unwrap();
-                            *current_dir = parent;
-                            list_state.select(Some(0));
+                },
+                KeyCode::Esc => {}
+                KeyCode::Backspace => {
+                    value.pop();
+                    *dialog = Some(Dialog::Input { purpose, value });
+                }
+                KeyCode::Char(c) => {
+                    value.push(c);
+                    *dialog = Some(Dialog::Input { purpose, value });
+                }
+                _ => {
+                    *dialog = Some(Dialog::Input { purpose, value });
+                    return false;
+                }
+            },
+            Dialog::ConfirmQuit => match resolve_quit_prompt(key) {
+                Some(QuitChoice::Save) => {
+                    let saved = Config::user_config_path()
+                        .ok_or_else(|| anyhow::anyhow!("no user config directory"))
+                        .and_then(|path| self.config.save_preferences(&path, changed_prefs));
+                    match saved {
+                        Ok(()) => self.mode = AppMode::Quit,
+                        Err(e) => {
+                            *dialog =
+                                Some(Dialog::Message(format!("Cannot save preferences: {:#}", e)))
                         }
                     }
-                    _ => {}
                 }
-            }
-            AppMode::Quit => {}
+                Some(QuitChoice::Discard) => self.mode = AppMode::Quit,
+                Some(QuitChoice::Cancel) => {}
+                None => {
+                    *dialog = Some(Dialog::ConfirmQuit);
+                    return false;
+                }
+            },
+            Dialog::Message(_) | Dialog::Info(_) => {}
         }
-        Ok(false)
+        true
     }
 
-    /// Move selection up or down
-    fn move_selection(&mut self, delta: i32) {
-        if let AppMode::Browsing {
+    /// Write the size report of the current directory to `path`
+    ///
+    /// The outcome is reported in a message dialog.
+    fn save_size_report(&mut self, path: &Path) {
+        let AppMode::Browsing {
+            current_dir,
+            dialog,
+            ..
+        } = &mut self.mode
+        else {
+            return;
+        };
+
+        let report = size_report(current_dir, &self.config);
+        let message = match std::fs::write(path, report) {
+            Ok(()) => format!("Size report saved to {}", path.display()),
+            Err(e) => format!("Cannot write {}: {}", path.display(), e),
+        };
+        *dialog = Some(Dialog::Message(message));
+    }
+
+    /// Delete `target` from disk and drop it from the tree
+    ///
+    /// Ancestor totals are re-linked afterwards, the same way the recalculate
+    /// action does. Failures are reported in a message dialog.
+    fn delete_entry(&mut self, target: &Entry, path: &Path) {
+        let result = delete_path(path, &self.config.delete_command);
+        let AppMode::Browsing {
+            root,
             current_dir,
+            path_stack,
             list_state,
+            unfolded_dir,
+            expanded,
+            name_filter,
+            dialog,
             ..
         } = &mut self.mode
-        {
-            if current_dir.children.is_empty() {
-                return;
-            }
+        else {
+            return;
+        };
 
-            let current = list_state.selected().unwrap_or(0);
-            let max_index = current_dir.children.len() - 1;
+        if let Err(e) = result {
+            *dialog = Some(Dialog::Message(format!(
+                "Cannot delete {}: {}",
+                path.display(),
+                e
+            )));
+            return;
+        }
 
-            let new_index = if delta < 0 {
-                current.saturating_sub((-delta) as usize)
-            } else {
-                (current + delta as usize).min(max_index)
-            };
+        Arc::make_mut(current_dir).remove_child(target.id);
+        *root = propagate_to_ancestors(path_stack, current_dir.clone());
+        let last = build_list_rows(
+            current_dir,
+            &self.config,
+            *unfolded_dir,
+            expanded,
+            !path_stack.is_empty(),
+            name_filter.as_ref(),
+        )
+        .len()
+        .saturating_sub(1);
+        list_state.select(Some(list_state.selected().unwrap_or(0).min(last)));
+        self.fs_usage = filesystem_usage(&self.scan_path);
+    }
+}
+
+impl<W: io::Write> BrowserBackend for TuiApp<W> {
+    fn row_count(&self) -> usize {
+        match &self.mode {
+            AppMode::Browsing {
+                current_dir,
+                path_stack,
+                unfolded_dir,
+                expanded,
+                name_filter,
+                ..
+            } => build_list_rows(
+                current_dir,
+                &self.config,
+                *unfolded_dir,
+                expanded,
+                !path_stack.is_empty(),
+                name_filter.as_ref(),
+            )
+            .len(),
+            _ => 0,
+        }
+    }
+
+    fn selected(&self) -> usize {
+        match &self.mode {
+            AppMode::Browsing { list_state, .. } => list_state.selected().unwrap_or(0),
+            _ => 0,
+        }
+    }
 
-            list_state.select(Some(new_index));
+    fn select(&mut self, index: usize) {
+        if let AppMode::Browsing { list_state, .. } = &mut self.mode {
+            list_state.select(Some(index));
         }
     }
 
-    /// Enter the currently selected directory
-    fn enter_selected(&mut self) -> Result<()> {
+    fn page_height(&self) -> usize {
+        // Header, status line and the list's own borders take 8 rows
+        self.terminal
+            .size()
+            .map(|area| (area.height as usize).saturating_sub(8))
+            .unwrap_or(1)
+    }
+
+    fn enter_selected(&mut self) {
         if let AppMode::Browsing {
             current_dir,
             path_stack,
             list_state,
+            unfolded_dir,
+            expanded,
+            name_filter,
             ..
         } = &mut self.mode
         {
-            if let Some(selected_index) = list_state.selected() {
-                if selected_index < current_dir.children.len() {
-                    let selected = &current_dir.children[selected_index];
-                    if selected.entry_type.is_directory() && selected.entry_type != EntryType::Error
-                    {
-                        path_stack.push(current_dir.clone());
-                        *current_dir = selected.clone();
-                        list_state.select(Some(0));
-                    }
-                }
-            }
+            let rows = build_list_rows(
+                current_dir,
+                &self.config,
+                *unfolded_dir,
+                expanded,
+                !path_stack.is_empty(),
+                name_filter.as_ref(),
+            );
+            let row = list_state.selected().and_then(|index| rows.get(index));
+            enter_list_row(row, current_dir, path_stack, list_state, unfolded_dir);
         }
-        Ok(())
     }
-}
 
-/// Draw UI for the given mode (standalone function to avoid borrowing issues)
-fn draw_ui_for_mode(f: &mut Frame, mode: &AppMode, config: &Config) {
-    match mode {
-        AppMode::Scanning { progress, .. } => {
-            draw_scanning_ui_standalone(f, progress, config);
-        }
-        AppMode::Browsing {
-            show_help: true, ..
-        } => {
-            draw_help_ui_standalone(f);
-        }
-        AppMode::Browsing {
-            root: _,
+    fn go_back(&mut self) {
+        if let AppMode::Browsing {
             current_dir,
             path_stack,
             list_state,
             ..
-        } => {
-            draw_browsing_ui_standalone(f, current_dir, path_stack, list_state, config);
+        } = &mut self.mode
+        {
+            if let Some(parent) = path_stack.pop() {
+                *current_dir = parent;
+                list_state.select(Some(0));
+            }
+        }
+    }
+
+    fn help_visible(&self) -> bool {
+        matches!(
+            &self.mode,
+            AppMode::Browsing {
+                show_help: true,
+                ..
+            }
+        )
+    }
+
+    fn set_help_visible(&mut self, visible: bool) {
+        if let AppMode::Browsing { show_help, .. } = &mut self.mode {
+            *show_help = visible;
         }
-        AppMode::Quit => {}
     }
 }
 
-/// Enhanced scanning UI function with ncdu-like appearance
-fn draw_scanning_ui_standalone(f: &mut Frame, progress: &Arc<ScanProgress>, config: &Config) {
-    let chunks = Layout::default()
+/// Find the directory the user is in within a replacement `root`
+///
+/// Directories are matched by name along the path, since an updated tree has
+/// new nodes. If part of the path no longer exists, the deepest directory
+/// still present is used.
+fn relocate(
+    root: &Arc<Entry>,
+    path_stack: &[Arc<Entry>],
+    current_dir: &Arc<Entry>,
+) -> (Vec<Arc<Entry>>, Arc<Entry>) {
+    let mut new_stack = Vec::new();
+    let mut dir = root.clone();
+    if path_stack.is_empty() {
+        return (new_stack, dir);
+    }
+
+    let names = path_stack[1..].iter().chain(std::iter::once(current_dir));
+    for name in names.map(|entry| &entry.name) {
+        let Some(child) = dir
+            .children
+            .iter()
+            .find(|child| &child.name == name && child.entry_type.is_directory())
+            .cloned()
+        else {
+            break;
+        };
+        new_stack.push(dir);
+        dir = child;
+    }
+    (new_stack, dir)
+}
+
+/// Act on entering `row` of the current listing
+fn enter_list_row(
+    row: Option<&ListRow>,
+    current_dir: &mut Arc<Entry>,
+    path_stack: &mut Vec<Arc<Entry>>,
+    list_state: &mut ListState,
+    unfolded_dir: &mut Option<EntryId>,
+) {
+    match row {
+        Some(ListRow::Parent) => {
+            if let Some(parent) = path_stack.pop() {
+                *current_dir = parent;
+                list_state.select(Some(0));
+            }
+        }
+        Some(ListRow::Entry(selected)) => {
+            if selected.entry_type.is_directory() && selected.entry_type != EntryType::Error {
+                path_stack.push(current_dir.clone());
+                *current_dir = selected.clone();
+                list_state.select(Some(0));
+            }
+        }
+        Some(ListRow::Folded { .. }) => {
+            // Expand the aggregate back into the individual entries
+            *unfolded_dir = Some(current_dir.id);
+        }
+        // Previews are for peeking; navigate from the expanded directory
+        Some(ListRow::Preview { .. }) | None => {}
+    }
+}
+
+/// Event poll timeout while idle in the browser, where nothing changes on its own
+const IDLE_POLL_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Event poll timeout while a scan is feeding the screen
+const ACTIVE_POLL_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// How long to wait for input before looking at the scan again
+fn poll_timeout(scan_running: bool) -> Duration {
+    if scan_running {
+        ACTIVE_POLL_TIMEOUT
+    } else {
+        IDLE_POLL_TIMEOUT
+    }
+}
+
+/// Whether the main loop should draw a frame now
+///
+/// The scanning screen refreshes every `update_delay`. Everywhere else the
+/// screen only changes in response to something, so frames are drawn only
+/// when `needs_redraw` was set by input, a resize or new scan data.
+fn should_redraw(
+    scanning: bool,
+    needs_redraw: bool,
+    since_last_draw: Duration,
+    update_delay: Duration,
+) -> bool {
+    if scanning {
+        needs_redraw || since_last_draw >= update_delay
+    } else {
+        needs_redraw
+    }
+}
+
+/// How a key press is routed before any mode-specific handling
+#[derive(Debug, PartialEq, Eq)]
+enum KeyDispatch {
+    /// Leave the application, whatever is on screen
+    Quit,
+    /// A modifier combination with no binding yet
+    Ignore,
+    /// An ordinary key for the current mode to handle
+    Handle(KeyCode),
+}
+
+/// Route a key press by its modifiers
+///
+/// Ctrl-C quits from every mode. Other Ctrl and Alt combinations are
+/// reserved for future bindings and must not fall through as plain keys;
+/// Shift is already folded into the character, so it passes through.
+fn dispatch_key(key: KeyEvent) -> KeyDispatch {
+    if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+        return KeyDispatch::Quit;
+    }
+    if key
+        .modifiers
+        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+    {
+        return KeyDispatch::Ignore;
+    }
+    KeyDispatch::Handle(key.code)
+}
+
+/// Draw UI for the given mode (standalone function to avoid borrowing issues)
+fn draw_ui_for_mode(
+    f: &mut Frame,
+    mode: &AppMode,
+    config: &Config,
+    fs_usage: Option<&FsUsage>,
+    scanning: bool,
+) {
+    match mode {
+        AppMode::Scanning { progress, .. } => {
+            draw_scanning_ui_standalone(f, progress, config);
+        }
+        AppMode::Browsing {
+            show_help: true, ..
+        } => {
+            draw_help_ui_standalone(f);
+        }
+        AppMode::Browsing {
+            root: _,
+            current_dir,
+            path_stack,
+            list_state,
+            unfolded_dir,
+            expanded,
+            name_filter,
+            dialog,
+            shared_column,
+            group_counts,
+            ..
+        } => {
+            let view = BrowsingView {
+                current_dir,
+                path_stack,
+                list_state,
+                unfolded_dir: *unfolded_dir,
+                expanded,
+                name_filter: name_filter.as_ref(),
+                shared_column: *shared_column,
+                group_counts: *group_counts,
+                scanning,
+            };
+            draw_browsing_ui_standalone(f, &view, config, fs_usage);
+            if let Some(dialog) = dialog {
+                draw_dialog(f, dialog, config);
+            }
+        }
+        AppMode::Quit => {}
+    }
+}
+
+/// Enhanced scanning UI function with ncdu-like appearance
+fn draw_scanning_ui_standalone(f: &mut Frame, progress: &Arc<ScanProgress>, config: &Config) {
+    let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
         .constraints([
@@ -518,13 +1410,16 @@ fn draw_scanning_ui_standalone(f: &mut Frame, progress: &Arc<ScanProgress>, conf
     // Statistics - more detailed like ncdu
     let total_size = progress.total_size.load(Ordering::Relaxed) as u64;
     let errors = progress.errors.load(Ordering::Relaxed);
+    let permission_errors = progress.permission_errors.load(Ordering::Relaxed);
+    let not_found_errors = progress.not_found_errors.load(Ordering::Relaxed);
+    let other_errors = errors.saturating_sub(permission_errors + not_found_errors);
 
     let stats_text = vec![
         Line::from(""),
         Line::from(vec![
             Span::raw("  Total size: "),
             Span::styled(
-                format_file_size(total_size, config.si),
+                SizeFormatter::new(config).format_bytes(total_size),
                 Style::default()
                     .fg(Color::Cyan)
                     .add_modifier(Modifier::BOLD),
@@ -538,6 +1433,14 @@ fn draw_scanning_ui_standalone(f: &mut Frame, progress: &Arc<ScanProgress>, conf
                     errors.to_string(),
                     Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
                 ),
+                Span::raw(format!(
+                    " ({})",
+                    format_error_breakdown(
+                        permission_errors as u64,
+                        not_found_errors as u64,
+                        other_errors as u64
+                    )
+                )),
             ])
         } else {
             Line::from("")
@@ -559,7 +1462,7 @@ fn draw_scanning_ui_standalone(f: &mut Frame, progress: &Arc<ScanProgress>, conf
 
 /// Standalone help UI function
 fn draw_help_ui_standalone(f: &mut Frame) {
-    let help_text = vec![
+    let mut help_text = vec![
         Line::from(Span::styled(
             "rsdu - Help",
             Style::default()
@@ -572,15 +1475,38 @@ fn draw_help_ui_standalone(f: &mut Frame) {
         Line::from("  ↓/j        Move down"),
         Line::from("  ←/h        Go back to parent directory"),
         Line::from("  →/l/Enter  Enter directory"),
+        Line::from("  PgUp/PgDn  Page up/down"),
         Line::from("  Home/g     Go to first item"),
         Line::from("  End/G      Go to last item"),
+        Line::from("  Space      Expand/collapse directory inline"),
+        Line::from(""),
+        Line::from("Bookmarks:"),
+        Line::from("  m1-9       Bookmark current directory"),
+        Line::from("  1-9        Jump to bookmarked directory"),
         Line::from(""),
         Line::from("Other:"),
+        Line::from("  d          Delete selected entry"),
+        Line::from("  E          Edit delete command"),
+        Line::from("  i          Show details of the selected entry"),
+        Line::from("  /          Filter entries by name"),
+        Line::from("  c          Save a size report of this directory"),
+        Line::from("  S          Toggle shallow/recursive directory sizes"),
+        Line::from("  u          Cycle the shared column: shared, unique, off"),
+        Line::from("  t          Toggle directories before files"),
+        Line::from("  #          Toggle grouped/raw item counts"),
+        Line::from("  R          Rescan the current directory"),
+        Line::from("  r          Refresh the selected entry's size and times"),
         Line::from("  ?/F1       Toggle this help"),
         Line::from("  q/Esc      Quit"),
+        Line::from("  Ctrl+C     Quit from anywhere"),
         Line::from(""),
-        Line::from("Press ? or Esc to return to browser"),
+        Line::from("Legend:"),
     ];
+    help_text.extend(type_legend());
+    help_text.extend([
+        Line::from(""),
+        Line::from("Press ? or Esc to return to browser"),
+    ]);
 
     // Center the help dialog
     let area = centered_rect(60, 70, f.size());
@@ -592,14 +1518,168 @@ fn draw_help_ui_standalone(f: &mut Frame) {
     f.render_widget(help_widget, area);
 }
 
+/// One help line per entry type: its marker in its color, and what it means
+fn type_legend() -> Vec<Line<'static>> {
+    EntryType::ALL
+        .into_iter()
+        .map(|entry_type| {
+            let (marker, color) = get_file_type_info(entry_type);
+            let meaning = match entry_type {
+                EntryType::Directory => "Directory",
+                EntryType::File => "Regular file",
+                EntryType::Symlink => "Symbolic link",
+                EntryType::Hardlink => "Hard link counted elsewhere",
+                EntryType::Special => "Device, pipe or socket",
+                EntryType::Error => "Error reading entry",
+                EntryType::Excluded => "Excluded from the scan",
+                EntryType::OtherFs => "Other filesystem, not descended",
+                EntryType::KernelFs => "Kernel filesystem, not scanned",
+                EntryType::BindMount => "Already scanned through another path",
+                EntryType::Aggregate => "Entries left out, summed into one",
+            };
+            Line::from(vec![
+                Span::raw("  "),
+                Span::styled(marker.to_string(), Style::default().fg(color)),
+                Span::raw(format!("          {}", meaning)),
+            ])
+        })
+        .collect()
+}
+
+/// Draw a dialog centered over the browsing list
+fn draw_dialog(f: &mut Frame, dialog: &Dialog, config: &Config) {
+    let (title, lines) = match dialog {
+        Dialog::ConfirmDelete { target, path } => (
+            "Confirm delete",
+            vec![
+                Line::from(format!("Delete {}?", target.name_str())),
+                Line::from(""),
+                Line::from(Span::styled(
+                    describe_delete(&config.delete_command, path),
+                    Style::default().fg(Color::Yellow),
+                )),
+                Line::from(""),
+                Line::from("y/Enter: delete  n/Esc: cancel"),
+            ],
+        ),
+        Dialog::Input {
+            purpose: InputPurpose::DeleteCommand,
+            value,
+        } => (
+            "Delete command",
+            vec![
+                Line::from(format!(
+                    "Command run on delete; {} is replaced by the path.",
+                    PATH_PLACEHOLDER
+                )),
+                Line::from("Leave empty to remove files directly."),
+                Line::from(""),
+                Line::from(Span::styled(
+                    format!("> {}_", value),
+                    Style::default().fg(Color::Yellow),
+                )),
+                Line::from(""),
+                Line::from("Enter: save  Esc: cancel"),
+            ],
+        ),
+        Dialog::Input {
+            purpose: InputPurpose::SaveReport,
+            value,
+        } => (
+            "Save size report",
+            vec![
+                Line::from("File to save a size table of this directory to."),
+                Line::from(""),
+                Line::from(Span::styled(
+                    format!("> {}_", value),
+                    Style::default().fg(Color::Yellow),
+                )),
+                Line::from(""),
+                Line::from("Enter: save  Esc: cancel"),
+            ],
+        ),
+        Dialog::Input {
+            purpose: InputPurpose::Filter,
+            value,
+        } => (
+            "Filter",
+            vec![
+                Line::from("List only entries whose name contains this, in any case."),
+                Line::from("Leave empty to list everything."),
+                Line::from(""),
+                Line::from(Span::styled(
+                    format!("> {}_", value),
+                    Style::default().fg(Color::Yellow),
+                )),
+                Line::from(""),
+                Line::from("Enter: apply  Esc: cancel"),
+            ],
+        ),
+        Dialog::Message(message) => (
+            "rsdu",
+            vec![
+                Line::from(message.as_str()),
+                Line::from(""),
+                Line::from("Press any key to continue"),
+            ],
+        ),
+        Dialog::ConfirmQuit => (
+            "Quit",
+            vec![
+                Line::from("Preferences were changed. Save them to your config file?"),
+                Line::from(""),
+                Line::from("s/Enter: save and quit  d: quit without saving  c/Esc: cancel"),
+            ],
+        ),
+        Dialog::Info(info) => {
+            let mut lines: Vec<Line> = info.iter().map(|line| Line::from(line.as_str())).collect();
+            lines.push(Line::from(""));
+            lines.push(Line::from("Press any key to continue"));
+            ("Info", lines)
+        }
+    };
+
+    let area = centered_rect(60, 30, f.size());
+    f.render_widget(Clear, area);
+    let widget = Paragraph::new(Text::from(lines))
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .wrap(Wrap { trim: false });
+    f.render_widget(widget, area);
+}
+
+/// Browsing state borrowed for drawing the listing
+struct BrowsingView<'a> {
+    current_dir: &'a Arc<Entry>,
+    path_stack: &'a [Arc<Entry>],
+    list_state: &'a ListState,
+    unfolded_dir: Option<EntryId>,
+    expanded: &'a HashSet<EntryId>,
+    name_filter: Option<&'a NameFilter>,
+    shared_column: SharedColumn,
+    group_counts: bool,
+    /// The scan is still filling in sizes
+    scanning: bool,
+}
+
 /// Standalone browsing UI function
 fn draw_browsing_ui_standalone(
     f: &mut Frame,
-    current_dir: &Arc<Entry>,
-    path_stack: &[Arc<Entry>],
-    list_state: &ListState,
+    view: &BrowsingView,
     config: &Config,
+    fs_usage: Option<&FsUsage>,
 ) {
+    let BrowsingView {
+        current_dir,
+        path_stack,
+        list_state,
+        unfolded_dir,
+        expanded,
+        name_filter,
+        shared_column,
+        group_counts,
+        scanning,
+    } = *view;
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
@@ -611,27 +1691,85 @@ fn draw_browsing_ui_standalone(
 
     // Header with current path and total size
     let current_path = build_current_path(path_stack, current_dir);
-    let total_size = calculate_total_size(current_dir);
+    let total_size = calculate_total_size(current_dir, config);
 
-    let header_text = vec![
-        Line::from(vec![
-            Span::raw("Path: "),
-            Span::styled(&current_path, Style::default().fg(Color::Cyan)),
-        ]),
-        Line::from(vec![
-            Span::raw("Total: "),
-            Span::styled(
-                format_file_size(total_size, config.si),
-                Style::default().fg(Color::Yellow),
-            ),
-            Span::raw(" ("),
-            Span::styled(
-                format!("{} items", current_dir.children.len()),
-                Style::default().fg(Color::Green),
+    let mut total_line = vec![
+        Span::raw(if config.shallow_dir_sizes == Some(true) {
+            "Total (shallow): "
+        } else {
+            "Total: "
+        }),
+        Span::styled(
+            SizeFormatter::new(config).format_bytes(total_size),
+            Style::default().fg(Color::Yellow),
+        ),
+        Span::raw(" ("),
+        Span::styled(
+            {
+                let sizes = SizeFormatter::new(config).with_grouped_counts(group_counts);
+                let (dirs, files) = count_dirs_and_files(current_dir);
+                format!(
+                    "{} dirs, {} files",
+                    sizes.format_count(dirs as u64),
+                    sizes.format_count(files as u64)
+                )
+            },
+            Style::default().fg(Color::Green),
+        ),
+        Span::raw(")"),
+    ];
+    if let Some((average, count)) = average_file_size(current_dir, config) {
+        total_line.push(Span::raw("  Avg file: "));
+        total_line.push(Span::styled(
+            SizeFormatter::new(config)
+                .format_bytes(average)
+                .trim_start()
+                .to_string(),
+            Style::default().fg(Color::Yellow),
+        ));
+        total_line.push(Span::raw(format!(" over {}", count)));
+    }
+    if let Some(filter) = active_filter(name_filter, current_dir) {
+        total_line.push(Span::raw("  Filter: "));
+        total_line.push(Span::styled(
+            filter.to_string(),
+            Style::default().fg(Color::Black).bg(Color::Yellow),
+        ));
+    }
+    if let Some(label) = shared_column_label(shared_column) {
+        total_line.push(Span::raw("  Column: "));
+        total_line.push(Span::styled(label, Style::default().fg(Color::Magenta)));
+    }
+    if let Some(usage) = fs_usage {
+        let percent = usage.fullness_percent();
+        total_line.push(Span::raw("  Disk: "));
+        total_line.push(Span::styled(
+            format!(
+                "[{}]",
+                create_progress_bar(percent, 10, graph_style_name(&config.graph_style))
             ),
-            Span::raw(")"),
-        ]),
+            Style::default().fg(fullness_color(percent)),
+        ));
+        total_line.push(Span::raw(format!(
+            " {:.0}% of {}",
+            percent,
+            SizeFormatter::new(config)
+                .format_bytes(usage.total)
+                .trim_start()
+        )));
+    }
+
+    let mut path_line = vec![
+        Span::raw("Path: "),
+        Span::styled(&current_path, Style::default().fg(Color::Cyan)),
     ];
+    if scanning {
+        path_line.push(Span::styled(
+            "  (scanning...)",
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    let header_text = vec![Line::from(path_line), Line::from(total_line)];
 
     let header = Paragraph::new(Text::from(header_text)).block(
         Block::default()
@@ -641,14 +1779,30 @@ fn draw_browsing_ui_standalone(
     f.render_widget(header, chunks[0]);
 
     // File list
-    if current_dir.children.is_empty() {
+    let rows = build_list_rows(
+        current_dir,
+        config,
+        unfolded_dir,
+        expanded,
+        !path_stack.is_empty(),
+        name_filter,
+    );
+    if rows.is_empty() {
         let empty_msg = Paragraph::new("(empty directory)")
             .style(Style::default().fg(Color::Gray))
             .alignment(Alignment::Center)
             .block(Block::default().borders(Borders::ALL));
         f.render_widget(empty_msg, chunks[1]);
     } else {
-        let items = create_file_list_items(current_dir, chunks[1].width as usize, config.si);
+        let items = create_file_list_items(
+            &rows,
+            total_size,
+            chunks[1].width as usize,
+            shared_column,
+            active_filter(name_filter, current_dir),
+            group_counts,
+            config,
+        );
         let file_list = List::new(items)
             .block(Block::default().borders(Borders::ALL))
             .highlight_style(
@@ -661,16 +1815,11 @@ fn draw_browsing_ui_standalone(
     }
 
     // Status line
-    let selected_index = list_state.selected().unwrap_or(0);
-    let status_text = if current_dir.children.is_empty() {
-        "Empty directory | q:quit ?:help".to_string()
-    } else {
-        format!(
-            "{}/{} | q:quit ?:help ↑↓:navigate ←→:dir Enter:enter h:up",
-            selected_index + 1,
-            current_dir.children.len()
-        )
-    };
+    let status_text = status_line(
+        list_state.selected().unwrap_or(0),
+        rows.len(),
+        chunks[2].width as usize,
+    );
 
     let status = Paragraph::new(status_text)
         .style(Style::default().fg(Color::Gray))
@@ -678,72 +1827,356 @@ fn draw_browsing_ui_standalone(
     f.render_widget(status, chunks[2]);
 }
 
-/// Create file list items with proper formatting
-fn create_file_list_items(
-    current_dir: &Arc<Entry>,
-    available_width: usize,
-    use_si: bool,
-) -> Vec<ListItem> {
-    let mut items = Vec::new();
+/// Terminal width below which the status line drops its key hints
+const STATUS_COMPACT_WIDTH: usize = 80;
 
-    // Calculate column widths - set to match the 10-character size padding
-    let size_width = 10;
-    let bar_width = 15;
-    let spacing = 2;
-    let name_width = available_width.saturating_sub(size_width + bar_width + spacing + 4); // 4 for borders
+/// Status line text for a listing of `row_count` rows, fitted to `width`
+fn status_line(selected_index: usize, row_count: usize, width: usize) -> String {
+    let position = if row_count == 0 {
+        "Empty directory".to_string()
+    } else {
+        format!("{}/{}", selected_index + 1, row_count)
+    };
 
-    // Calculate total size for percentage bars
-    let total_size = calculate_total_size(current_dir);
+    if width < STATUS_COMPACT_WIDTH {
+        format!("{} | q:quit ?:help", position)
+    } else {
+        format!(
+            "{} | q:quit ?:help ↑↓:navigate ←→:dir Enter:enter h:up d:delete",
+            position
+        )
+    }
+}
 
-    for entry in &current_dir.children {
-        let entry_size = if entry.entry_type.is_directory() {
-            calculate_directory_size(entry)
-        } else {
-            entry.size
+/// Toggle whether a directory's children are previewed inline
+///
+/// Returns true if the directory is now expanded. Entries without children
+/// cannot be expanded.
+fn toggle_expanded(expanded: &mut HashSet<EntryId>, entry: &Entry) -> bool {
+    if expanded.remove(&entry.id) || entry.children.is_empty() {
+        return false;
+    }
+    expanded.insert(entry.id)
+}
+
+/// Build the rows shown for a directory, including inline previews of
+/// expanded directories
+///
+/// Below the root the listing starts with a `..` row for going up.
+fn build_list_rows(
+    current_dir: &Arc<Entry>,
+    config: &Config,
+    unfolded_dir: Option<EntryId>,
+    expanded: &HashSet<EntryId>,
+    show_parent: bool,
+    name_filter: Option<&NameFilter>,
+) -> Vec<ListRow> {
+    let filter = active_filter(name_filter, current_dir);
+    let rows = build_top_level_rows(current_dir, config, unfolded_dir, filter);
+    let mut with_previews = Vec::with_capacity(rows.len() + 1);
+    if show_parent {
+        with_previews.push(ListRow::Parent);
+    }
+    for row in rows {
+        let expanded_entry = match &row {
+            ListRow::Entry(entry) if expanded.contains(&entry.id) => Some(entry.clone()),
+            _ => None,
         };
+        with_previews.push(row);
+        if let Some(entry) = expanded_entry {
+            push_preview_rows(&mut with_previews, &entry, 1, expanded);
+        }
+    }
+
+    with_previews
+}
+
+/// Append the top children of an expanded directory, recursing into nested
+/// expanded directories
+fn push_preview_rows(
+    rows: &mut Vec<ListRow>,
+    dir: &Arc<Entry>,
+    depth: usize,
+    expanded: &HashSet<EntryId>,
+) {
+    for child in dir.children.iter().take(PREVIEW_LIMIT) {
+        rows.push(ListRow::Preview {
+            entry: child.clone(),
+            depth,
+        });
+        if expanded.contains(&child.id) {
+            push_preview_rows(rows, child, depth + 1, expanded);
+        }
+    }
+}
+
+/// Build the rows for a directory's own children, folding small entries when
+/// configured
+///
+/// Entries below `config.fold_threshold` percent of the directory total are
+/// collapsed into a single `ListRow::Folded` row at the bottom, unless the
+/// directory has been unfolded by entering that row. Children whose name
+/// doesn't contain `filter` are left out altogether.
+fn build_top_level_rows(
+    current_dir: &Arc<Entry>,
+    config: &Config,
+    unfolded_dir: Option<EntryId>,
+    filter: Option<&str>,
+) -> Vec<ListRow> {
+    let shown = |entry: &Entry| match filter {
+        Some(filter) => find_match(&entry.name_str(), filter).is_some(),
+        None => true,
+    };
+    let all_rows = || {
+        current_dir
+            .children
+            .iter()
+            .filter(|child| shown(child))
+            .cloned()
+            .map(ListRow::Entry)
+            .collect()
+    };
+
+    let threshold = match config.fold_threshold {
+        Some(threshold) if unfolded_dir != Some(current_dir.id) => threshold,
+        _ => return all_rows(),
+    };
+
+    let total_size = calculate_total_size(current_dir, config);
+    if total_size == 0 {
+        return all_rows();
+    }
+
+    let sizes = SizeFormatter::new(config);
+    let is_small =
+        |entry: &Entry| (sizes.metric_value(entry) as f64 / total_size as f64 * 100.0) < threshold;
+    let small_count = current_dir
+        .children
+        .iter()
+        .filter(|c| shown(c) && is_small(c))
+        .count();
 
-        // Format size (now properly padded by format_file_size function)
-        let size_str = format_file_size(entry_size, use_si);
+    // Folding a single entry would only hide it behind another row
+    if small_count < 2 {
+        return all_rows();
+    }
 
-        // Create percentage bar
-        let percentage = if total_size > 0 {
-            (entry_size as f64 / total_size as f64 * 100.0) as u8
+    let mut rows = Vec::new();
+    let mut folded_size = 0;
+    for child in current_dir.children.iter().filter(|child| shown(child)) {
+        if is_small(child) {
+            folded_size += sizes.metric_value(child);
         } else {
-            0
+            rows.push(ListRow::Entry(child.clone()));
+        }
+    }
+    rows.push(ListRow::Folded {
+        count: small_count,
+        size: folded_size,
+    });
+
+    rows
+}
+
+/// Create file list items with proper formatting
+fn create_file_list_items<'a>(
+    rows: &[ListRow],
+    total_size: u64,
+    available_width: usize,
+    shared_column: SharedColumn,
+    filter: Option<&str>,
+    group_counts: bool,
+    config: &Config,
+) -> Vec<ListItem<'a>> {
+    let mut items = Vec::new();
+
+    let show_owner = config.extended || config.sort_col == SortColumn::Owner;
+    let show_mode = config.extended || config.sort_col == SortColumn::Mode;
+    let sizes = SizeFormatter::new(config).with_grouped_counts(group_counts);
+    // Shared data per row, summed once; like ncdu, the shared column only
+    // appears where there is some
+    let shared_values: Vec<Option<u64>> = rows
+        .iter()
+        .map(|row| match row {
+            ListRow::Entry(entry) | ListRow::Preview { entry, .. }
+                if shared_column != SharedColumn::Off =>
+            {
+                Some(sizes.shared_value(entry))
+            }
+            _ => None,
+        })
+        .collect();
+    let show_shared = shared_values
+        .iter()
+        .any(|shared| shared.is_some_and(|v| v > 0));
+    let columns = list_columns(available_width, show_owner, show_mode, show_shared, config);
+    let name_width = columns.name_width;
+
+    // Size, the shared column if shown, then the graph bar and percent if
+    // there is room for them
+    let size_columns = |size: u64, entry: Option<&Entry>, shared: Option<u64>| {
+        let texts = match entry {
+            Some(entry) => size_texts(entry, config),
+            None if columns.dual => vec![sizes.format_bytes(size), " ".repeat(SIZE_WIDTH)],
+            None => vec![sizes.format_bytes(size)],
         };
-        let bar = create_percentage_bar(percentage, bar_width.saturating_sub(2));
-
-        // Get file type info
-        let (type_char, color) = get_file_type_info(entry);
-
-        // Format name with type indicator
-        let name_with_type = format!("{}{}", type_char, entry.name_str());
-        let truncated_name = if name_with_type.width() > name_width {
-            let mut truncated = String::new();
-            let mut current_width = 0;
-            for ch in name_with_type.chars() {
-                let char_width = ch.width().unwrap_or(0);
-                if current_width + char_width + 3 > name_width {
-                    // 3 for "..."
-                    truncated.push_str("...");
-                    break;
+        let mut spans = Vec::new();
+        for text in texts {
+            spans.push(Span::styled(text, Style::default().fg(Color::Yellow)));
+            spans.push(Span::raw(" "));
+        }
+        if columns.shared {
+            let text = match (shared, shared_column) {
+                (Some(shared), SharedColumn::Shared) => sizes.format_bytes(shared),
+                (Some(shared), SharedColumn::Unique) => {
+                    sizes.format_bytes(size.saturating_sub(shared))
                 }
-                truncated.push(ch);
-                current_width += char_width;
+                _ => " ".repeat(SIZE_WIDTH),
+            };
+            spans.push(Span::styled(text, Style::default().fg(Color::Magenta)));
+            spans.push(Span::raw(" "));
+        }
+        if columns.graph {
+            let percentage = bar_percentage(size, total_size);
+            let bar = create_percentage_bar(percentage, GRAPH_WIDTH - 2);
+            spans.push(Span::styled(
+                format!("[{}] ", bar),
+                Style::default().fg(Color::Blue),
+            ));
+        }
+        if columns.percent {
+            spans.push(Span::raw(format!(
+                "{:>width$} ",
+                format_percentage(size, total_size, config.locale),
+                width = PERCENT_WIDTH
+            )));
+        }
+        spans
+    };
+
+    for (row, &shared) in rows.iter().zip(&shared_values) {
+        let (entry, depth) = match row {
+            ListRow::Parent => {
+                items.push(ListItem::new(Line::from(vec![
+                    Span::raw(" ".repeat(columns.prefix_width())),
+                    Span::styled("/..", Style::default().fg(Color::Blue)),
+                ])));
+                continue;
+            }
+            ListRow::Entry(entry) => (entry, 0),
+            ListRow::Preview { entry, depth } => (entry, *depth),
+            ListRow::Folded { count, size } => {
+                let label = format!(
+                    "({} other items, total {})",
+                    count,
+                    sizes.format_bytes(*size).trim_start()
+                );
+
+                let mut spans = size_columns(*size, None, None);
+                spans.push(Span::styled(label, Style::default().fg(Color::DarkGray)));
+                items.push(ListItem::new(Line::from(spans)));
+                continue;
+            }
+        };
+
+        let entry_size = sizes.metric_value(entry);
+
+        // Get file type info, optionally colored by extension
+        let (type_char, mut color) = get_file_type_info(entry.entry_type);
+        if config.color_by_extension && entry.entry_type == EntryType::File {
+            if let Some(ext_color) = get_extension_color(&entry.name_str()) {
+                color = ext_color;
+            }
+        }
+
+        // Format name with type indicator, indented under an expanded parent
+        let prefix = format!("{}{}", "  ".repeat(depth), type_char);
+        let name = entry.name_str();
+        let mut name_with_type = format!("{}{}", prefix, name);
+        if config.stat_other_fs && entry.entry_type == EntryType::OtherFs {
+            name_with_type.push_str(" (not descended)");
+        }
+        // Left out until the directory is fully scanned, so a tree still
+        // growing isn't walked for it
+        if config.show_largest_file && entry.entry_type == EntryType::Directory && entry.complete {
+            if let Some(file) = sizes.largest_file(entry) {
+                name_with_type.push_str(&format!(
+                    " (largest: {} {})",
+                    file.name_str(),
+                    sizes.format_bytes(sizes.metric_value(file)).trim_start()
+                ));
+            }
+        }
+        if config.show_excluded_counts {
+            let excluded = excluded_child_count(entry);
+            if excluded > 0 {
+                name_with_type.push_str(&format!(" ({} excluded)", sizes.format_count(excluded)));
             }
-            truncated
+        }
+        // Leave room for the marker of an entry still being scanned
+        let marker_width = if entry.complete {
+            0
         } else {
-            name_with_type
+            SCANNING_MARKER.len() + 1
         };
+        let truncated_name =
+            truncate_to_width(&name_with_type, name_width.saturating_sub(marker_width));
 
         // Create the line
-        let line = Line::from(vec![
-            Span::styled(size_str, Style::default().fg(Color::Yellow)),
-            Span::raw(" "),
-            Span::styled(format!("[{}]", bar), Style::default().fg(Color::Blue)),
-            Span::raw(" "),
-            Span::styled(truncated_name, Style::default().fg(color)),
-        ]);
+        let mut spans = size_columns(entry_size, Some(entry), shared);
+        let mode = entry_mode(entry);
+        let world_writable = mode.is_some_and(is_world_writable);
+        if show_mode {
+            let mode_style = if world_writable {
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::Gray)
+            };
+            spans.push(Span::styled(
+                format!(
+                    "{:<width$} ",
+                    mode.map(format_mode).unwrap_or_default(),
+                    width = MODE_WIDTH
+                ),
+                mode_style,
+            ));
+        }
+        if show_owner {
+            spans.push(Span::styled(
+                format!("{:<width$} ", owner_label(entry), width = OWNER_WIDTH),
+                Style::default().fg(Color::Cyan),
+            ));
+        }
+        let name_style = if world_writable {
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(color)
+        };
+        // The filter is matched against the name only
+        let names = name_range(&truncated_name, &name_with_type, prefix.len(), name.len());
+        let (name_start, name_end) = (names.start, names.end);
+        spans.push(Span::styled(
+            truncated_name[..name_start].to_string(),
+            name_style,
+        ));
+        spans.extend(highlight_match(
+            &truncated_name[name_start..name_end],
+            filter,
+            name_style,
+        ));
+        if name_end < truncated_name.len() {
+            spans.push(Span::styled(
+                truncated_name[name_end..].to_string(),
+                name_style,
+            ));
+        }
+        if !entry.complete {
+            spans.push(Span::styled(
+                format!(" {}", SCANNING_MARKER),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        let line = Line::from(spans);
 
         items.push(ListItem::new(line));
     }
@@ -751,83 +2184,784 @@ fn create_file_list_items(
     items
 }
 
-/// Create a percentage bar string
-fn create_percentage_bar(percentage: u8, width: usize) -> String {
-    if width == 0 {
-        return String::new();
+/// Byte range of the first occurrence of `pattern` in `text`, ignoring case
+fn find_match(text: &str, pattern: &str) -> Option<std::ops::Range<usize>> {
+    if pattern.is_empty() {
+        return None;
     }
-
-    let filled = (percentage as usize * width / 100).min(width);
-    let mut bar = String::new();
-
-    for i in 0..width {
-        if i < filled {
-            bar.push('█');
-        } else {
-            bar.push(' ');
+    text.char_indices().find_map(|(start, _)| {
+        let mut rest = text[start..].chars();
+        let mut end = start;
+        for wanted in pattern.chars() {
+            let found = rest.next()?;
+            if !found.to_lowercase().eq(wanted.to_lowercase()) {
+                return None;
+            }
+            end += found.len_utf8();
         }
-    }
-
-    bar
+        Some(start..end)
+    })
 }
 
-/// Get file type character and color
-fn get_file_type_info(entry: &Entry) -> (char, Color) {
-    match entry.entry_type {
-        EntryType::Directory => ('/', Color::Blue),
-        EntryType::File => (' ', Color::White),
-        EntryType::Symlink => ('@', Color::Cyan),
-        EntryType::Hardlink => ('>', Color::Yellow),
-        EntryType::Special => ('=', Color::Magenta),
-        EntryType::Error => ('!', Color::Red),
-        EntryType::Excluded => ('x', Color::DarkGray),
-        EntryType::OtherFs => ('~', Color::DarkGray),
-        EntryType::KernelFs => ('#', Color::DarkGray),
-    }
+/// Byte range of the entry name within `truncated`, the shown part of `full`
+///
+/// `full` is the name after a `prefix_len` type indicator, followed by any
+/// notes. The notes and the "..." of truncation are left out of the range.
+fn name_range(
+    truncated: &str,
+    full: &str,
+    prefix_len: usize,
+    name_len: usize,
+) -> std::ops::Range<usize> {
+    let kept = if truncated == full {
+        truncated.len()
+    } else {
+        truncated.len() - "...".len()
+    };
+    let end = (prefix_len + name_len).min(kept);
+    prefix_len.min(end)..end
 }
 
-/// Build current path string
-fn build_current_path(path_stack: &[Arc<Entry>], current_dir: &Arc<Entry>) -> String {
-    let mut path_parts = Vec::new();
-    for entry in path_stack {
-        path_parts.push(entry.name_str());
-    }
-    path_parts.push(current_dir.name_str());
-    format!("/{}", path_parts.join("/"))
+/// `text` split into spans with the match of `pattern` highlighted
+fn highlight_match<'a>(text: &str, pattern: Option<&str>, style: Style) -> Vec<Span<'a>> {
+    let Some(range) = pattern.and_then(|pattern| find_match(text, pattern)) else {
+        return vec![Span::styled(text.to_string(), style)];
+    };
+    let highlight = style.fg(Color::Black).bg(Color::Yellow);
+    [
+        (&text[..range.start], style),
+        (&text[range.clone()], highlight),
+        (&text[range.end..], style),
+    ]
+    .into_iter()
+    .filter(|(part, _)| !part.is_empty())
+    .map(|(part, style)| Span::styled(part.to_string(), style))
+    .collect()
 }
 
-/// Calculate total size of current directory
-fn calculate_total_size(dir: &Arc<Entry>) -> u64 {
-    dir.children
+/// Number of direct children of `entry` that were excluded from the scan
+fn excluded_child_count(entry: &Entry) -> u64 {
+    entry
+        .children
         .iter()
-        .map(|entry| {
-            if entry.entry_type.is_directory() {
-                calculate_directory_size(entry)
-            } else {
-                entry.size
-            }
-        })
-        .sum()
+        .filter(|child| child.entry_type == EntryType::Excluded)
+        .count() as u64
 }
 
-/// Calculate directory size (simplified)
-fn calculate_directory_size(entry: &Entry) -> u64 {
-    entry.size
-        + entry
-            .children
-            .iter()
-            .map(|child| {
-                if child.entry_type.is_directory() {
-                    calculate_directory_size(child)
-                } else {
-                    child.size
-                }
-            })
-            .sum::<u64>()
-}
+/// Shown after entries whose scan has not finished yet
+const SCANNING_MARKER: &str = "[scanning]";
 
-/// Create centered rectangle
-fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+/// Width of the size column in the file list
+const SIZE_WIDTH: usize = 10;
+
+/// Width of the graph bar in the file list, brackets included
+const GRAPH_WIDTH: usize = 15;
+
+/// Width of the percent column in the file list
+const PERCENT_WIDTH: usize = 6;
+
+/// Names get at least this many columns before optional columns are dropped
+const MIN_NAME_WIDTH: usize = 30;
+
+/// Which optional columns of the file list are drawn, and the room left for names
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ListColumns {
+    /// Apparent size and disk usage instead of one size, with `dual_size`
+    dual: bool,
+    /// Shared or unique size, after the size
+    shared: bool,
+    graph: bool,
+    percent: bool,
+    name_width: usize,
+}
+
+impl ListColumns {
+    /// Columns taken up before the owner, mode and name columns
+    fn prefix_width(&self) -> usize {
+        SIZE_WIDTH
+            + 1
+            + if self.dual { SIZE_WIDTH + 1 } else { 0 }
+            + if self.shared { SIZE_WIDTH + 1 } else { 0 }
+            + if self.graph { GRAPH_WIDTH + 1 } else { 0 }
+            + if self.percent { PERCENT_WIDTH + 1 } else { 0 }
+    }
+}
+
+/// Text of an entry's size column, or with `dual_size` of its apparent size
+/// and disk usage columns
+fn size_texts(entry: &Entry, config: &Config) -> Vec<String> {
+    let sizes = SizeFormatter::new(config);
+    if config.dual_size {
+        [false, true]
+            .into_iter()
+            .map(|disk_usage| sizes.with_disk_usage(disk_usage).format(entry))
+            .collect()
+    } else {
+        vec![sizes.format(entry)]
+    }
+}
+
+/// Lay out the file list for a list `available_width` columns wide
+///
+/// The graph bar and percent columns follow `show_graph` and `show_percent`,
+/// but on a narrow terminal the graph is dropped first, then the percent,
+/// whenever keeping them would squeeze names below `MIN_NAME_WIDTH`.
+fn list_columns(
+    available_width: usize,
+    show_owner: bool,
+    show_mode: bool,
+    show_shared: bool,
+    config: &Config,
+) -> ListColumns {
+    let owner_width = if show_owner { OWNER_WIDTH + 1 } else { 0 };
+    let mode_width = if show_mode { MODE_WIDTH + 1 } else { 0 };
+    let mut columns = ListColumns {
+        dual: config.dual_size,
+        shared: show_shared,
+        graph: config.show_graph,
+        percent: config.show_percent,
+        name_width: 0,
+    };
+    let name_width = |columns: &ListColumns| {
+        // 4 for borders
+        available_width.saturating_sub(columns.prefix_width() + owner_width + mode_width + 4)
+    };
+
+    if columns.graph && name_width(&columns) < MIN_NAME_WIDTH {
+        columns.graph = false;
+    }
+    if columns.percent && name_width(&columns) < MIN_NAME_WIDTH {
+        columns.percent = false;
+    }
+    columns.name_width = name_width(&columns);
+    columns
+}
+
+/// Columns a grapheme cluster takes up on the terminal
+///
+/// A cluster is measured by its base character, so combining marks and the
+/// zero-width-joined parts of an emoji sequence add nothing. Clusters asking
+/// for emoji presentation and regional indicator pairs (flags) are drawn two
+/// columns wide.
+fn grapheme_width(grapheme: &str) -> usize {
+    let Some(base) = grapheme.chars().next() else {
+        return 0;
+    };
+    let is_flag = ('\u{1F1E6}'..='\u{1F1FF}').contains(&base) && grapheme.chars().count() == 2;
+    if is_flag || grapheme.contains('\u{FE0F}') {
+        return 2;
+    }
+    base.width().unwrap_or(0)
+}
+
+/// Columns `text` takes up on the terminal
+fn display_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+/// Cut `text` to at most `max_width` columns, marking the cut with "..."
+///
+/// Cuts fall between grapheme clusters, never inside one.
+fn truncate_to_width(text: &str, max_width: usize) -> String {
+    if display_width(text) <= max_width {
+        return text.to_string();
+    }
+
+    let mut truncated = String::new();
+    let mut current_width = 0;
+    for grapheme in text.graphemes(true) {
+        let width = grapheme_width(grapheme);
+        // 3 for "..."
+        if current_width + width + 3 > max_width {
+            break;
+        }
+        truncated.push_str(grapheme);
+        current_width += width;
+    }
+    truncated.push_str("...");
+    truncated
+}
+
+/// Width of the permission column in the file list
+const MODE_WIDTH: usize = 9;
+
+/// Permission bits of an entry, if they were scanned
+///
+/// Symlinks are left out since their own mode is always `rwxrwxrwx`.
+fn entry_mode(entry: &Entry) -> Option<u32> {
+    if entry.entry_type == EntryType::Symlink {
+        return None;
+    }
+    entry.extended.as_ref().and_then(|ext| ext.mode)
+}
+
+/// Width of the owner column in the file list
+const OWNER_WIDTH: usize = 17;
+
+/// "user:group" label for an entry, empty when ownership wasn't scanned
+fn owner_label(entry: &Entry) -> String {
+    let Some(ext) = entry.extended.as_ref() else {
+        return String::new();
+    };
+    match (ext.uid, ext.gid) {
+        (Some(uid), Some(gid)) => format!("{}:{}", user_name(uid), group_name(gid)),
+        (Some(uid), None) => user_name(uid),
+        (None, Some(gid)) => format!(":{}", group_name(gid)),
+        (None, None) => String::new(),
+    }
+}
+
+/// The shared column mode after `mode`, for the `u` key
+fn next_shared_column(mode: SharedColumn) -> SharedColumn {
+    match mode {
+        SharedColumn::Off => SharedColumn::Shared,
+        SharedColumn::Shared => SharedColumn::Unique,
+        SharedColumn::Unique => SharedColumn::Off,
+    }
+}
+
+/// Header label of the shared column mode, if the column is on
+fn shared_column_label(mode: SharedColumn) -> Option<&'static str> {
+    match mode {
+        SharedColumn::Off => None,
+        SharedColumn::Shared => Some("shared"),
+        SharedColumn::Unique => Some("unique"),
+    }
+}
+
+/// Name of a graph style as understood by `create_progress_bar`
+fn graph_style_name(style: &GraphStyle) -> &'static str {
+    match style {
+        GraphStyle::Hash => "hash",
+        GraphStyle::HalfBlock => "half-block",
+        GraphStyle::EighthBlock => "eighth-block",
+    }
+}
+
+/// Color of the disk fullness bar, warning as the disk fills up
+fn fullness_color(percent: f64) -> Color {
+    if percent >= 90.0 {
+        Color::Red
+    } else if percent >= 75.0 {
+        Color::Yellow
+    } else {
+        Color::Green
+    }
+}
+
+/// Share of `total` taken by `size`, as a whole percentage for the graph bar
+///
+/// Clamped to 100, since an entry can outweigh a total computed with a
+/// different size metric (e.g. shallow directory sizes).
+fn bar_percentage(size: u64, total: u64) -> u8 {
+    if total == 0 {
+        0
+    } else {
+        (size as f64 / total as f64 * 100.0).clamp(0.0, 100.0) as u8
+    }
+}
+
+/// Create a percentage bar string
+fn create_percentage_bar(percentage: u8, width: usize) -> String {
+    if width == 0 {
+        return String::new();
+    }
+
+    let filled = width * usize::from(percentage.min(100)) / 100;
+    let mut bar = String::new();
+
+    for i in 0..width {
+        if i < filled {
+            bar.push('█');
+        } else {
+            bar.push(' ');
+        }
+    }
+
+    bar
+}
+
+/// Get file type character and color
+fn get_file_type_info(entry_type: EntryType) -> (char, Color) {
+    match entry_type {
+        EntryType::Directory => ('/', Color::Blue),
+        EntryType::File => (' ', Color::White),
+        EntryType::Symlink => ('@', Color::Cyan),
+        EntryType::Hardlink => ('>', Color::Yellow),
+        EntryType::Special => ('=', Color::Magenta),
+        EntryType::Error => ('!', Color::Red),
+        EntryType::Excluded => ('x', Color::DarkGray),
+        EntryType::OtherFs => ('~', Color::DarkGray),
+        EntryType::KernelFs => ('#', Color::DarkGray),
+        EntryType::BindMount => ('^', Color::DarkGray),
+        EntryType::Aggregate => ('+', Color::DarkGray),
+    }
+}
+
+/// Built-in extension to color mapping, grouped by kind of file
+const EXTENSION_COLORS: &[(&[&str], Color)] = &[
+    // Archives and compressed files
+    (
+        &[
+            "7z", "bz2", "deb", "gz", "rar", "rpm", "tar", "tgz", "xz", "zip", "zst",
+        ],
+        Color::Red,
+    ),
+    // Images
+    (
+        &[
+            "bmp", "gif", "ico", "jpeg", "jpg", "png", "svg", "tif", "tiff", "webp",
+        ],
+        Color::Magenta,
+    ),
+    // Audio and video
+    (
+        &[
+            "avi", "flac", "m4a", "mkv", "mov", "mp3", "mp4", "ogg", "wav", "webm",
+        ],
+        Color::LightMagenta,
+    ),
+    // Source code and scripts
+    (
+        &[
+            "c", "cpp", "go", "h", "java", "js", "py", "rb", "rs", "sh", "ts",
+        ],
+        Color::Green,
+    ),
+    // Documents
+    (
+        &[
+            "doc", "docx", "md", "odt", "pdf", "rtf", "txt", "xls", "xlsx",
+        ],
+        Color::LightYellow,
+    ),
+];
+
+/// Get the color for a file name based on its extension
+///
+/// Entries from `LS_COLORS` take precedence over the built-in mapping.
+fn get_extension_color(name: &str) -> Option<Color> {
+    static LS_COLORS: OnceLock<HashMap<String, Color>> = OnceLock::new();
+
+    let ls_colors = LS_COLORS.get_or_init(|| {
+        std::env::var("LS_COLORS")
+            .map(|value| parse_ls_colors(&value))
+            .unwrap_or_default()
+    });
+
+    extension_color(name, ls_colors)
+}
+
+/// Get the color for a file name from the given `LS_COLORS` entries or the built-in mapping
+fn extension_color(name: &str, ls_colors: &HashMap<String, Color>) -> Option<Color> {
+    let extension = Path::new(name).extension()?.to_str()?.to_lowercase();
+    if let Some(color) = ls_colors.get(&extension) {
+        return Some(*color);
+    }
+
+    builtin_extension_color(&extension)
+}
+
+/// Look up a (lowercase) extension in the built-in mapping
+fn builtin_extension_color(extension: &str) -> Option<Color> {
+    EXTENSION_COLORS
+        .iter()
+        .find(|(extensions, _)| extensions.contains(&extension))
+        .map(|(_, color)| *color)
+}
+
+/// Parse the `*.ext=SGR` entries of an `LS_COLORS` value
+fn parse_ls_colors(value: &str) -> HashMap<String, Color> {
+    let mut colors = HashMap::new();
+
+    for item in value.split(':') {
+        let Some((pattern, codes)) = item.split_once('=') else {
+            continue;
+        };
+        let Some(extension) = pattern.strip_prefix("*.") else {
+            continue;
+        };
+        if let Some(color) = parse_sgr_color(codes) {
+            colors.insert(extension.to_lowercase(), color);
+        }
+    }
+
+    colors
+}
+
+/// Extract the foreground color from an SGR sequence such as `01;31` or `38;5;208`
+fn parse_sgr_color(codes: &str) -> Option<Color> {
+    let codes: Vec<&str> = codes.split(';').collect();
+    let mut color = None;
+    let mut i = 0;
+
+    while i < codes.len() {
+        match codes[i].parse::<u8>().ok()? {
+            38 if codes.get(i + 1) == Some(&"5") => {
+                color = Some(Color::Indexed(codes.get(i + 2)?.parse().ok()?));
+                i += 2;
+            }
+            code @ 30..=37 => color = Some(sgr_base_color(code - 30, false)),
+            code @ 90..=97 => color = Some(sgr_base_color(code - 90, true)),
+            _ => {}
+        }
+        i += 1;
+    }
+
+    color
+}
+
+/// Map an ANSI color index (0-7) to a ratatui color
+fn sgr_base_color(index: u8, bright: bool) -> Color {
+    match (index, bright) {
+        (0, false) => Color::Black,
+        (1, false) => Color::Red,
+        (2, false) => Color::Green,
+        (3, false) => Color::Yellow,
+        (4, false) => Color::Blue,
+        (5, false) => Color::Magenta,
+        (6, false) => Color::Cyan,
+        (7, false) => Color::Gray,
+        (0, true) => Color::DarkGray,
+        (1, true) => Color::LightRed,
+        (2, true) => Color::LightGreen,
+        (3, true) => Color::LightYellow,
+        (4, true) => Color::LightBlue,
+        (5, true) => Color::LightMagenta,
+        (6, true) => Color::LightCyan,
+        _ => Color::White,
+    }
+}
+
+/// Build current path string
+fn build_current_path(path_stack: &[Arc<Entry>], current_dir: &Arc<Entry>) -> String {
+    let mut path_parts = Vec::new();
+    for entry in path_stack {
+        path_parts.push(entry.name_str());
+    }
+    path_parts.push(current_dir.name_str());
+    format!("/{}", path_parts.join("/"))
+}
+
+/// Location on disk of `entry`, a child of `current_dir`
+///
+/// The root of the tree is the scanned directory itself, so only the names
+/// below it are joined onto `scan_root`.
+fn entry_disk_path(
+    scan_root: &Path,
+    path_stack: &[Arc<Entry>],
+    current_dir: &Arc<Entry>,
+    entry: &Entry,
+) -> PathBuf {
+    let mut path = scan_root.to_path_buf();
+    if !path_stack.is_empty() {
+        for dir in path_stack.iter().skip(1) {
+            path.push(&dir.name);
+        }
+        path.push(&current_dir.name);
+    }
+    path.push(&entry.name);
+    path
+}
+
+/// Location on disk of `entry`, following its parent links
+///
+/// The top of the tree stands for `scan_root`, so only the part of
+/// `Entry::full_path` below it is joined onto `scan_root`.
+fn linked_disk_path(scan_root: &Path, root: &Entry, entry: &Entry) -> PathBuf {
+    let full_path = entry.full_path();
+    match full_path.strip_prefix(&root.name) {
+        Ok(below) => scan_root.join(below),
+        Err(_) => full_path,
+    }
+}
+
+/// Lines of the info popup for `entry`, found on disk at `path`
+///
+/// The mount line names the device or other source the entry's filesystem
+/// is mounted from, looked up by the device id recorded during the scan.
+fn entry_info(
+    entry: &Entry,
+    path: &Path,
+    sizes: &SizeFormatter,
+    mounts: &HashMap<DeviceId, MountSource>,
+) -> Vec<String> {
+    let mount = match mounts.get(&entry.device) {
+        Some(mount) => format!(
+            "{} on {} ({})",
+            mount.source,
+            mount.mount_point.display(),
+            mount.fs_type
+        ),
+        None => "unknown".to_string(),
+    };
+    vec![
+        format!("Name:  {}", entry.name_str()),
+        format!("Path:  {}", path.display()),
+        format!("Type:  {}", entry.entry_type),
+        format!("Size:  {}", sizes.format(entry).trim_start()),
+        format!("Items: {}", sizes.format_count(entry.total_items())),
+        format!("Mount: {}", mount),
+    ]
+}
+
+/// Where the directory being browsed lives on disk
+fn current_dir_disk_path(
+    scan_root: &Path,
+    path_stack: &[Arc<Entry>],
+    current_dir: &Arc<Entry>,
+) -> PathBuf {
+    match path_stack.split_last() {
+        Some((parent, ancestors)) => entry_disk_path(scan_root, ancestors, parent, current_dir),
+        None => scan_root.to_path_buf(),
+    }
+}
+
+/// Scan the directory being browsed again and link it back into the tree
+///
+/// The fresh subtree takes over the old directory's id and name, so the
+/// ancestors on `path_stack` are rebuilt around it and their totals include
+/// whatever changed on disk. Returns the new root.
+///
+/// The scan runs quietly, as the browser is on screen. The synthetic root of
+/// a `--targets-from` scan has no directory of its own; each target below it
+/// is scanned again instead.
+fn rescan_current_dir(
+    scan_root: &Path,
+    path_stack: &mut [Arc<Entry>],
+    current_dir: &mut Arc<Entry>,
+    config: &Config,
+) -> Result<Arc<Entry>> {
+    let config = Config {
+        scan_ui: Some(ScanUi::None),
+        ..config.clone()
+    };
+    let mut fresh = if path_stack.is_empty() && config.scan_targets.len() > 1 {
+        let mut root = Entry::clone(current_dir);
+        root.children = current_dir
+            .children
+            .iter()
+            .map(|target| {
+                // Targets are named by their full path
+                let mut fresh =
+                    Arc::unwrap_or_clone(scan_directory(Path::new(&target.name), &config)?);
+                fresh.name = target.name.clone();
+                Ok(fresh.into_linked())
+            })
+            .collect::<Result<_>>()?;
+        root
+    } else {
+        let path = current_dir_disk_path(scan_root, path_stack, current_dir);
+        Arc::unwrap_or_clone(scan_directory(&path, &config)?)
+    };
+    fresh.id = current_dir.id;
+    fresh.name = current_dir.name.clone();
+    *current_dir = fresh.into_linked();
+    Ok(propagate_to_ancestors(path_stack, current_dir.clone()))
+}
+
+/// Why deleting `target` (found on disk at `path`) must be refused, if it must
+///
+/// Removing the scan root or a directory the browser is inside would leave
+/// it showing a tree that no longer exists, so those are never deleted, even
+/// when an oddly named entry such as `.` or `..` resolves to one of them.
+fn delete_refusal(
+    scan_root: &Path,
+    path_stack: &[Arc<Entry>],
+    current_dir: &Arc<Entry>,
+    target: &Entry,
+    path: &Path,
+) -> Option<&'static str> {
+    if target.entry_type == EntryType::Aggregate {
+        return Some("Entries that were left out can't be deleted");
+    }
+
+    let root_id = path_stack.first().unwrap_or(current_dir).id;
+    if target.id == root_id || path == scan_root {
+        return Some("Refusing to delete the scan root");
+    }
+
+    let dir_path = current_dir_disk_path(scan_root, path_stack, current_dir);
+    if target.id == current_dir.id
+        || path_stack.iter().any(|dir| dir.id == target.id)
+        || path == dir_path
+        || target.name == ".."
+    {
+        return Some("Refusing to delete the current directory or one of its parents");
+    }
+    None
+}
+
+/// Count the directories and other entries directly inside `dir`
+///
+/// Anything that isn't a directory counts as a file, as in the scan totals.
+fn count_dirs_and_files(dir: &Entry) -> (usize, usize) {
+    let dirs = dir
+        .children
+        .iter()
+        .filter(|child| child.entry_type.is_directory())
+        .count();
+    (dirs, dir.children.len() - dirs)
+}
+
+/// Average size of the regular files directly inside `dir`, with their count
+///
+/// Directories, links and other special entries are left out. Returns None
+/// when there are no files to average.
+fn average_file_size(dir: &Entry, config: &Config) -> Option<(u64, usize)> {
+    let sizes = SizeFormatter::new(config);
+    let (total, count) = dir
+        .children
+        .iter()
+        .filter(|child| child.entry_type == EntryType::File)
+        .fold((0u64, 0usize), |(total, count), child| {
+            (total + sizes.own_value(child), count + 1)
+        });
+    (count > 0).then(|| (total / count as u64, count))
+}
+
+/// File name offered when saving a size report
+const REPORT_FILE_NAME: &str = "rsdu-report.txt";
+
+/// Plain-text table of the children of `dir`: name, size and percent of the total
+///
+/// Rows follow the listing order and sizes the displayed metric, so the
+/// table matches what is on screen. Directory names end in `/`.
+fn size_report(dir: &Arc<Entry>, config: &Config) -> String {
+    let total = calculate_total_size(dir, config);
+    let sizes = SizeFormatter::new(config);
+    let rows: Vec<(String, u64)> = dir
+        .children
+        .iter()
+        .map(|child| {
+            let mut name = child.name_str();
+            if child.entry_type.is_directory() {
+                name.push('/');
+            }
+            (name, sizes.metric_value(child))
+        })
+        .collect();
+    let name_width = rows
+        .iter()
+        .map(|(name, _)| display_width(name))
+        .chain(std::iter::once("Name".len()))
+        .max()
+        .unwrap_or(0);
+
+    let pad = |name: &str| format!("{}{}", name, " ".repeat(name_width - display_width(name)));
+    let mut report = format!("{}  {:>10}  {:>7}\n", pad("Name"), "Size", "Percent");
+    for (name, size) in &rows {
+        report.push_str(&format!(
+            "{}  {}  {:>7}\n",
+            pad(name),
+            sizes.format_bytes(*size),
+            format_percentage(*size, total, config.locale)
+        ));
+    }
+    report.push_str(&format!(
+        "{}  {}  {:>7}\n",
+        pad("Total"),
+        sizes.format_bytes(total),
+        format_percentage(total, total, config.locale)
+    ));
+    report
+}
+
+/// Calculate total size of current directory
+fn calculate_total_size(dir: &Arc<Entry>, config: &Config) -> u64 {
+    let sizes = SizeFormatter::new(config);
+    dir.children
+        .iter()
+        .map(|entry| sizes.metric_value(entry))
+        .sum()
+}
+
+/// Re-sort a directory by the size currently displayed for each entry
+fn sort_by_display_size(dir: &mut Entry, config: &Config) {
+    let sizes = SizeFormatter::new(config);
+    dir.children.sort_by(|a, b| {
+        if config.sort_dirs_first {
+            let dirs_first = b
+                .entry_type
+                .is_directory()
+                .cmp(&a.entry_type.is_directory());
+            if dirs_first != std::cmp::Ordering::Equal {
+                return dirs_first;
+            }
+        }
+
+        let cmp = sizes.metric_value(a).cmp(&sizes.metric_value(b));
+        match config.sort_order {
+            SortOrder::Asc => cmp,
+            SortOrder::Desc => cmp.reverse(),
+        }
+    });
+}
+
+/// `config` with the directory grouping chosen while browsing, for sorting
+fn with_dirs_first(config: &Config, dirs_first: bool) -> Config {
+    Config {
+        sort_dirs_first: dirs_first,
+        ..config.clone()
+    }
+}
+
+/// Re-sort the current directory after a change to how it is sorted,
+/// returning the root that now holds it
+///
+/// The cursor stays on the same entry rather than the same row.
+fn resort_current_dir(
+    current_dir: &mut Arc<Entry>,
+    path_stack: &mut [Arc<Entry>],
+    list_state: &mut ListState,
+    unfolded_dir: Option<EntryId>,
+    expanded: &HashSet<EntryId>,
+    name_filter: Option<&NameFilter>,
+    config: &Config,
+) -> Arc<Entry> {
+    let show_parent = !path_stack.is_empty();
+    let rows = build_list_rows(
+        current_dir,
+        config,
+        unfolded_dir,
+        expanded,
+        show_parent,
+        name_filter,
+    );
+    let selected_id = match list_state.selected().and_then(|index| rows.get(index)) {
+        Some(ListRow::Entry(entry)) => Some(entry.id),
+        _ => None,
+    };
+
+    let dir = Arc::make_mut(current_dir);
+    if matches!(config.sort_col, SortColumn::Size | SortColumn::Blocks) {
+        sort_by_display_size(dir, config);
+    } else {
+        sort_entries(&mut dir.children, config);
+    }
+    let root = propagate_to_ancestors(path_stack, current_dir.clone());
+
+    if let Some(id) = selected_id {
+        let rows = build_list_rows(
+            current_dir,
+            config,
+            unfolded_dir,
+            expanded,
+            show_parent,
+            name_filter,
+        );
+        if let Some(index) = rows
+            .iter()
+            .position(|row| matches!(row, ListRow::Entry(entry) if entry.id == id))
+        {
+            list_state.select(Some(index));
+        }
+    }
+    root
+}
+
+/// Create centered rectangle
+fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
@@ -847,15 +2981,1233 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         .split(popup_layout[1])[1]
 }
 
-impl Drop for TuiApp {
+impl<W: io::Write> Drop for TuiApp<W> {
     fn drop(&mut self) {
         // Cleanup terminal
         let _ = disable_raw_mode();
-        let _ = execute!(
-            self.terminal.backend_mut(),
-            LeaveAlternateScreen,
-            DisableMouseCapture
-        );
+        let _ = restore_terminal(self.terminal.backend_mut(), self.config.alt_screen);
         let _ = self.terminal.show_cursor();
     }
 }
+
+/// Prepare the terminal for the UI, on the alternate screen if `alt_screen`
+///
+/// Without it the UI draws over the normal screen, where its last frame
+/// stays visible after quitting.
+fn setup_terminal(out: &mut impl io::Write, alt_screen: bool) -> io::Result<()> {
+    if alt_screen {
+        execute!(out, EnterAlternateScreen)?;
+    }
+    execute!(out, EnableMouseCapture)
+}
+
+/// Undo `setup_terminal`
+fn restore_terminal(out: &mut impl io::Write, alt_screen: bool) -> io::Result<()> {
+    if alt_screen {
+        execute!(out, LeaveAlternateScreen)?;
+    }
+    execute!(out, DisableMouseCapture)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::generate_entry_id;
+    use crate::utils::format_file_size;
+
+    fn dir_entry(name: &str, children: Vec<Arc<Entry>>) -> Arc<Entry> {
+        let mut entry = Entry::new(
+            generate_entry_id(),
+            EntryType::Directory,
+            name.into(),
+            0,
+            0,
+            1,
+            1,
+            1,
+        );
+        entry.children = children;
+        entry.into_linked()
+    }
+
+    #[test]
+    fn test_bookmark_save_and_restore() {
+        let deep = dir_entry("deep", Vec::new());
+        let sub = dir_entry("sub", vec![deep.clone()]);
+        let root = dir_entry("root", vec![sub.clone()]);
+
+        // Navigate to root/sub/deep and bookmark it
+        let mut path_stack = vec![root.clone(), sub.clone()];
+        let mut current_dir = deep.clone();
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        let bookmark = Bookmark::capture(&path_stack, &current_dir, &list_state);
+
+        // Go back to the root, then jump to the bookmark
+        path_stack.clear();
+        current_dir = root.clone();
+        list_state.select(Some(0));
+        bookmark.restore(&mut path_stack, &mut current_dir, &mut list_state);
+
+        assert!(Arc::ptr_eq(&current_dir, &deep));
+        assert_eq!(path_stack.len(), 2);
+        assert!(Arc::ptr_eq(&path_stack[0], &root));
+        assert!(Arc::ptr_eq(&path_stack[1], &sub));
+        assert_eq!(list_state.selected(), Some(0));
+    }
+
+    fn file_entry(name: &str, size: u64) -> Arc<Entry> {
+        Arc::new(Entry::new(
+            generate_entry_id(),
+            EntryType::File,
+            name.into(),
+            size,
+            0,
+            1,
+            1,
+            1,
+        ))
+    }
+
+    /// App drawing into a fixed-size area, leaving the real terminal alone
+    fn headless_app(config: Config) -> TuiApp<io::Sink> {
+        use ratatui::{TerminalOptions, Viewport};
+
+        let terminal = Terminal::with_options(
+            CrosstermBackend::new(io::sink()),
+            TerminalOptions {
+                viewport: Viewport::Fixed(Rect::new(0, 0, 80, 24)),
+            },
+        )
+        .unwrap();
+        let mut app = TuiApp::with_terminal(terminal, config);
+        app.dirty = false;
+        app
+    }
+
+    #[test]
+    fn test_alt_screen_setup() {
+        let mut out = Vec::new();
+        setup_terminal(&mut out, true).unwrap();
+        restore_terminal(&mut out, true).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("\x1b[?1049h"));
+        assert!(text.contains("\x1b[?1049l"));
+
+        let mut out = Vec::new();
+        setup_terminal(&mut out, false).unwrap();
+        restore_terminal(&mut out, false).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(!text.contains("1049"));
+        assert!(text.contains("\x1b[?1000h"));
+    }
+
+    #[test]
+    fn test_resize_forces_redraw() {
+        let mut app = headless_app(Config::default());
+        app.dirty = false;
+        assert!(!app.handle_event(Event::Resize(100, 30)).unwrap());
+        assert!(app.dirty);
+        // Even a scanning screen that was just drawn is redrawn at once
+        assert!(should_redraw(
+            true,
+            app.dirty,
+            Duration::ZERO,
+            Duration::from_secs(1)
+        ));
+
+        let children = (0..50).map(|i| file_entry(&format!("f{}", i), 1)).collect();
+        app.start_browsing(dir_entry("root", children), None)
+            .unwrap();
+        if let AppMode::Browsing { list_state, .. } = &mut app.mode {
+            list_state.select(Some(40));
+            *list_state.offset_mut() = 30;
+        }
+        app.dirty = false;
+        app.handle_event(Event::Resize(100, 60)).unwrap();
+        assert!(app.dirty);
+        let AppMode::Browsing { list_state, .. } = &app.mode else {
+            panic!("still browsing");
+        };
+        assert_eq!(list_state.offset(), 0);
+        assert_eq!(list_state.selected(), Some(40));
+    }
+
+    #[test]
+    fn test_only_state_changing_keys_dirty() {
+        let press = |app: &mut TuiApp<io::Sink>, code| {
+            app.dirty = false;
+            let quit = app
+                .handle_key_event(KeyEvent::new(code, KeyModifiers::NONE))
+                .unwrap();
+            assert!(!quit);
+            app.dirty
+        };
+
+        let root = dir_entry("root", vec![file_entry("a", 2), file_entry("b", 1)]);
+        let mut app = headless_app(Config::default());
+        app.start_browsing(root, None).unwrap();
+
+        // Unbound keys and chords leave the screen as it is
+        assert!(!press(&mut app, KeyCode::Char('x')));
+        assert!(!press(&mut app, KeyCode::F(5)));
+        app.dirty = false;
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::ALT))
+            .unwrap();
+        assert!(!app.dirty);
+
+        assert!(press(&mut app, KeyCode::Down));
+        assert!(press(&mut app, KeyCode::Char('S')));
+        assert!(press(&mut app, KeyCode::Char('?')));
+
+        // Open the delete command editor: typing edits it, arrows do nothing
+        assert!(press(&mut app, KeyCode::Char('?')));
+        assert!(press(&mut app, KeyCode::Char('E')));
+        assert!(press(&mut app, KeyCode::Char('r')));
+        assert!(!press(&mut app, KeyCode::Up));
+        assert!(press(&mut app, KeyCode::Esc));
+    }
+
+    #[test]
+    fn test_fold_small_entries() {
+        let dir = dir_entry(
+            "dir",
+            vec![
+                file_entry("big", 1000),
+                file_entry("medium", 500),
+                file_entry("tiny1", 10),
+                file_entry("tiny2", 5),
+                file_entry("tiny3", 3),
+            ],
+        );
+        let config = Config {
+            fold_threshold: Some(5.0),
+            show_blocks: false,
+            ..Config::default()
+        };
+
+        let rows = build_list_rows(&dir, &config, None, &HashSet::new(), false, None);
+        assert_eq!(rows.len(), 3);
+        assert!(matches!(&rows[0], ListRow::Entry(e) if e.name_str() == "big"));
+        assert!(matches!(&rows[1], ListRow::Entry(e) if e.name_str() == "medium"));
+        assert!(matches!(rows[2], ListRow::Folded { count: 3, size: 18 }));
+
+        // Entering the aggregate row unfolds the directory
+        let rows = build_list_rows(&dir, &config, Some(dir.id), &HashSet::new(), false, None);
+        assert_eq!(rows.len(), 5);
+
+        // Without a threshold nothing is folded
+        let rows = build_list_rows(&dir, &Config::default(), None, &HashSet::new(), false, None);
+        assert_eq!(rows.len(), 5);
+    }
+
+    #[test]
+    fn test_parent_row() {
+        let sub = dir_entry("sub", vec![file_entry("a", 10)]);
+        let root = dir_entry("root", vec![sub.clone(), file_entry("b", 5)]);
+        let config = Config::default();
+
+        // No way up from the root
+        let rows = build_list_rows(&root, &config, None, &HashSet::new(), false, None);
+        assert!(matches!(&rows[0], ListRow::Entry(e) if e.id == sub.id));
+
+        let mut current_dir = root.clone();
+        let mut path_stack = Vec::new();
+        let mut list_state = ListState::default();
+        let mut unfolded_dir = None;
+        enter_list_row(
+            rows.first(),
+            &mut current_dir,
+            &mut path_stack,
+            &mut list_state,
+            &mut unfolded_dir,
+        );
+        assert_eq!(current_dir.id, sub.id);
+
+        // Below it the listing starts with `..`, and entering it goes back
+        let rows = build_list_rows(&current_dir, &config, None, &HashSet::new(), true, None);
+        assert_eq!(rows.len(), 2);
+        assert!(matches!(rows[0], ListRow::Parent));
+        enter_list_row(
+            rows.first(),
+            &mut current_dir,
+            &mut path_stack,
+            &mut list_state,
+            &mut unfolded_dir,
+        );
+        assert_eq!(current_dir.id, root.id);
+        assert!(path_stack.is_empty());
+    }
+
+    #[test]
+    fn test_relocate_into_updated_tree() {
+        let inner = dir_entry("inner", vec![file_entry("old", 1)]);
+        let sub = dir_entry("sub", vec![inner.clone()]);
+        let root = dir_entry("root", vec![sub.clone()]);
+
+        // The same layout with fresh nodes and new contents
+        let new_inner = dir_entry("inner", vec![file_entry("new", 2)]);
+        let new_sub = dir_entry("sub", vec![new_inner.clone()]);
+        let new_root = dir_entry("root", vec![new_sub.clone(), file_entry("extra", 3)]);
+
+        let (stack, dir) = relocate(&new_root, &[root.clone(), sub.clone()], &inner);
+        assert_eq!(dir.id, new_inner.id);
+        assert_eq!(
+            stack.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![new_root.id, new_sub.id]
+        );
+
+        // Vanished directories fall back to the deepest surviving one
+        let bare_root = dir_entry("root", vec![new_sub.clone()]);
+        let gone = dir_entry("gone", vec![]);
+        let (stack, dir) = relocate(&bare_root, std::slice::from_ref(&root), &gone);
+        assert_eq!(dir.id, bare_root.id);
+        assert!(stack.is_empty());
+
+        let (stack, dir) = relocate(&new_root, &[], &root);
+        assert_eq!(dir.id, new_root.id);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_idle_browser_does_not_redraw() {
+        let delay = Duration::from_millis(100);
+        let long_ago = Duration::from_secs(60);
+
+        // Browsing: only events cause frames, however long it has been
+        assert!(!should_redraw(false, false, long_ago, delay));
+        assert!(should_redraw(false, true, Duration::ZERO, delay));
+
+        // Scanning keeps its timed refresh
+        assert!(!should_redraw(
+            true,
+            false,
+            Duration::from_millis(10),
+            delay
+        ));
+        assert!(should_redraw(true, false, delay, delay));
+
+        assert!(poll_timeout(false) > poll_timeout(true));
+    }
+
+    #[test]
+    fn test_shared_column_cycles() {
+        let mut app = headless_app(Config::default());
+        app.start_browsing(dir_entry("root", vec![file_entry("a", 1)]), None)
+            .unwrap();
+        let mode = |app: &TuiApp<_>| match &app.mode {
+            AppMode::Browsing { shared_column, .. } => *shared_column,
+            _ => panic!("not browsing"),
+        };
+        assert_eq!(mode(&app), SharedColumn::Off);
+
+        let mut labels = Vec::new();
+        for _ in 0..3 {
+            app.dirty = false;
+            app.handle_key_event(KeyEvent::new(KeyCode::Char('u'), KeyModifiers::NONE))
+                .unwrap();
+            assert!(app.dirty);
+            labels.push(shared_column_label(mode(&app)));
+        }
+        assert_eq!(labels, vec![Some("shared"), Some("unique"), None]);
+        assert_eq!(mode(&app), SharedColumn::Off);
+        // Cycling is a view change and leaves the configured mode alone
+        assert_eq!(app.config.show_shared, SharedColumn::Off);
+
+        // The column takes a size-wide slot out of the names when shown
+        let config = Config::default();
+        let without = list_columns(120, false, false, false, &config);
+        let with = list_columns(120, false, false, true, &config);
+        assert_eq!(without.name_width - with.name_width, SIZE_WIDTH + 1);
+    }
+
+    #[test]
+    fn test_refresh_entry_key() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("grown"), vec![0u8; 5000]).unwrap();
+
+        let mut app = headless_app(Config {
+            show_blocks: false,
+            ..Config::default()
+        });
+        let root = dir_entry("root", vec![file_entry("grown", 10), file_entry("gone", 1)]);
+        app.start_browsing(root, None).unwrap();
+        app.scan_path = temp_dir.path().display().to_string();
+        let press_r = |app: &mut TuiApp<io::Sink>| {
+            app.handle_key_event(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::NONE))
+                .unwrap();
+        };
+
+        press_r(&mut app);
+        let AppMode::Browsing {
+            root,
+            current_dir,
+            list_state,
+            dialog,
+            ..
+        } = &mut app.mode
+        else {
+            panic!("not browsing");
+        };
+        assert!(dialog.is_none());
+        assert_eq!(current_dir.children[0].size, 5000);
+        assert_eq!(root.total_size(), 5001 + root.size);
+
+        // The other entry was deleted since the scan
+        list_state.select(Some(1));
+        press_r(&mut app);
+        let AppMode::Browsing { dialog, .. } = &app.mode else {
+            panic!("not browsing");
+        };
+        assert!(
+            matches!(dialog, Some(Dialog::Message(message)) if message.ends_with("no longer exists"))
+        );
+    }
+
+    #[test]
+    fn test_excluded_child_count() {
+        let excluded = |name: &str| {
+            let mut entry = (*file_entry(name, 0)).clone();
+            entry.entry_type = EntryType::Excluded;
+            Arc::new(entry)
+        };
+        let nested = dir_entry("nested", vec![excluded("deeper.o")]);
+        let dir = dir_entry(
+            "build",
+            vec![
+                excluded("a.o"),
+                file_entry("main.c", 100),
+                excluded("b.o"),
+                nested.clone(),
+            ],
+        );
+
+        // Only direct children count
+        assert_eq!(excluded_child_count(&dir), 2);
+        assert_eq!(excluded_child_count(&nested), 1);
+        assert_eq!(excluded_child_count(&file_entry("main.c", 100)), 0);
+    }
+
+    #[test]
+    fn test_group_counts_toggle() {
+        let mut app = headless_app(Config::default());
+        let files = (0..1234)
+            .map(|i| file_entry(&format!("f{}", i), 1))
+            .collect();
+        app.start_browsing(dir_entry("root", vec![dir_entry("big", files)]), None)
+            .unwrap();
+        let press = |app: &mut TuiApp<io::Sink>, code: KeyCode| {
+            app.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE))
+                .unwrap();
+        };
+        let items = |app: &mut TuiApp<io::Sink>| {
+            press(app, KeyCode::Char('i'));
+            let AppMode::Browsing { dialog, .. } = &mut app.mode else {
+                panic!("not browsing");
+            };
+            let Some(Dialog::Info(lines)) = dialog.take() else {
+                panic!("no info popup");
+            };
+            lines
+                .into_iter()
+                .find(|line| line.starts_with("Items:"))
+                .unwrap()
+        };
+        assert_eq!(items(&mut app), "Items: 1,235");
+
+        app.dirty = false;
+        press(&mut app, KeyCode::Char('#'));
+        assert!(app.dirty);
+        assert_eq!(items(&mut app), "Items: 1235");
+    }
+
+    #[test]
+    fn test_highlight_match() {
+        let style = Style::default().fg(Color::White);
+        let parts = |text: &str, pattern: Option<&str>| {
+            highlight_match(text, pattern, style)
+                .into_iter()
+                .map(|span| (span.content.to_string(), span.style != style))
+                .collect::<Vec<_>>()
+        };
+
+        // The match is found in any case and keeps the name's own case
+        assert_eq!(
+            parts("Photo.JPG", Some("jpg")),
+            vec![("Photo.".to_string(), false), ("JPG".to_string(), true)]
+        );
+        assert_eq!(
+            parts("café menu", Some("É m")),
+            vec![
+                ("caf".to_string(), false),
+                ("é m".to_string(), true),
+                ("enu".to_string(), false)
+            ]
+        );
+        assert_eq!(parts("data", None), vec![("data".to_string(), false)]);
+        assert_eq!(find_match("data", "x"), None);
+
+        // Only the name is searched, not notes after it or the truncation
+        let full = "/logs (3 excluded)";
+        assert_eq!(name_range(full, full, 1, 4), 1..5);
+        assert_eq!(name_range("/logs (3...", full, 1, 4), 1..5);
+        assert_eq!(name_range("/lo...", full, 1, 4), 1..3);
+    }
+
+    #[test]
+    fn test_filter_key() {
+        let mut app = headless_app(Config::default());
+        let root = dir_entry(
+            "root",
+            vec![
+                dir_entry("photo-album", vec![file_entry("cover.png", 1000)]),
+                file_entry("Photo.JPG", 300),
+                file_entry("notes.txt", 200),
+                file_entry("photo-2.jpg", 100),
+            ],
+        );
+        app.start_browsing(root, None).unwrap();
+        let press = |app: &mut TuiApp<io::Sink>, code: KeyCode| {
+            app.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE))
+                .unwrap();
+        };
+        let names = |app: &TuiApp<io::Sink>| {
+            let AppMode::Browsing {
+                current_dir,
+                name_filter,
+                ..
+            } = &app.mode
+            else {
+                panic!("not browsing");
+            };
+            build_list_rows(
+                current_dir,
+                &app.config,
+                None,
+                &HashSet::new(),
+                false,
+                name_filter.as_ref(),
+            )
+            .iter()
+            .filter_map(|row| match row {
+                ListRow::Entry(entry) => Some(entry.name_str().to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+        };
+
+        press(&mut app, KeyCode::Char('/'));
+        for c in "PHOTO".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(names(&app), vec!["photo-album", "Photo.JPG", "photo-2.jpg"]);
+
+        // The filter belongs to the directory it was typed in
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(names(&app), vec!["cover.png"]);
+        press(&mut app, KeyCode::Left);
+        assert_eq!(names(&app).len(), 3);
+
+        // Clearing the filter lists everything again
+        press(&mut app, KeyCode::Char('/'));
+        for _ in 0..5 {
+            press(&mut app, KeyCode::Backspace);
+        }
+        press(&mut app, KeyCode::Enter);
+        let AppMode::Browsing { name_filter, .. } = &app.mode else {
+            panic!("not browsing");
+        };
+        assert_eq!(name_filter, &None);
+        assert_eq!(names(&app).len(), 4);
+    }
+
+    #[test]
+    fn test_dual_size_columns() {
+        // 1 MiB apparent, but only 8 blocks allocated
+        let sparse = Entry::new(
+            generate_entry_id(),
+            EntryType::File,
+            "sparse.img".into(),
+            1 << 20,
+            8,
+            1,
+            1,
+            1,
+        );
+        let config = Config {
+            dual_size: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            size_texts(&sparse, &config),
+            vec![
+                format_file_size(1 << 20, false),
+                format_file_size(4096, false)
+            ]
+        );
+
+        let single = Config {
+            show_blocks: false,
+            ..Config::default()
+        };
+        assert_eq!(
+            size_texts(&sparse, &single),
+            vec![format_file_size(1 << 20, false)]
+        );
+
+        let without = list_columns(120, false, false, false, &single);
+        let with = list_columns(120, false, false, false, &config);
+        assert_eq!(without.name_width - with.name_width, SIZE_WIDTH + 1);
+    }
+
+    #[test]
+    fn test_remapped_quit_key() {
+        let mut config = Config::default();
+        config
+            .keybindings
+            .insert(keymap::Action::Quit, vec![KeyCode::Char('x')]);
+        let mut app = headless_app(config);
+        app.start_browsing(dir_entry("root", vec![file_entry("a", 1)]), None)
+            .unwrap();
+
+        let press = |app: &mut TuiApp<io::Sink>, code| {
+            app.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE))
+                .unwrap()
+        };
+        assert!(!press(&mut app, KeyCode::Char('q')));
+        assert!(!press(&mut app, KeyCode::Down));
+        assert!(press(&mut app, KeyCode::Char('x')));
+    }
+
+    #[test]
+    fn test_ctrl_c_quits() {
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+
+        assert_eq!(dispatch_key(ctrl('c')), KeyDispatch::Quit);
+        // Other chords don't leak through as their plain key, e.g. 'd' for delete
+        assert_eq!(dispatch_key(ctrl('d')), KeyDispatch::Ignore);
+        assert_eq!(
+            dispatch_key(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::ALT)),
+            KeyDispatch::Ignore
+        );
+
+        assert_eq!(
+            dispatch_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::NONE)),
+            KeyDispatch::Handle(KeyCode::Char('c'))
+        );
+        assert_eq!(
+            dispatch_key(KeyEvent::new(KeyCode::Char('S'), KeyModifiers::SHIFT)),
+            KeyDispatch::Handle(KeyCode::Char('S'))
+        );
+    }
+
+    #[test]
+    fn test_status_line_width() {
+        assert_eq!(status_line(2, 10, 40), "3/10 | q:quit ?:help");
+        assert_eq!(
+            status_line(2, 10, 120),
+            "3/10 | q:quit ?:help ↑↓:navigate ←→:dir Enter:enter h:up d:delete"
+        );
+        assert_eq!(status_line(0, 0, 40), "Empty directory | q:quit ?:help");
+    }
+
+    #[test]
+    fn test_count_dirs_and_files() {
+        let link = Entry::new(
+            generate_entry_id(),
+            EntryType::Symlink,
+            "link".into(),
+            0,
+            0,
+            1,
+            1,
+            1,
+        );
+        let dir = dir_entry(
+            "mixed",
+            vec![
+                dir_entry("src", vec![file_entry("main.rs", 10)]),
+                dir_entry("docs", vec![]),
+                file_entry("README.md", 5),
+                file_entry("Cargo.toml", 3),
+                Arc::new(link),
+            ],
+        );
+        assert_eq!(count_dirs_and_files(&dir), (2, 3));
+        assert_eq!(count_dirs_and_files(&dir_entry("empty", vec![])), (0, 0));
+    }
+
+    #[test]
+    fn test_average_file_size() {
+        let link = Entry::new(
+            generate_entry_id(),
+            EntryType::Symlink,
+            "link".into(),
+            4000,
+            0,
+            1,
+            1,
+            1,
+        );
+        let dir = dir_entry(
+            "mixed",
+            vec![
+                dir_entry("src", vec![file_entry("main.rs", 9000)]),
+                file_entry("a", 100),
+                file_entry("b", 300),
+                Arc::new(link),
+            ],
+        );
+        let config = Config {
+            show_blocks: false,
+            ..Config::default()
+        };
+        assert_eq!(average_file_size(&dir, &config), Some((200, 2)));
+        assert_eq!(
+            average_file_size(&dir_entry("empty", vec![]), &config),
+            None
+        );
+    }
+
+    #[test]
+    fn test_grapheme_widths() {
+        // Family emoji: three people joined by zero-width joiners
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        assert_eq!(display_width(family), 2);
+        assert_eq!(display_width(&format!("{}.png", family)), 6);
+
+        // "café" spelled with a combining acute accent
+        let cafe = "cafe\u{0301}";
+        assert_eq!(display_width(cafe), 4);
+        assert_eq!(truncate_to_width(cafe, 4), cafe);
+
+        // Truncation never splits a cluster
+        let name = format!("{}{}{}", family, family, family);
+        assert_eq!(truncate_to_width(&name, 5), format!("{}...", family));
+        assert_eq!(truncate_to_width("cafe\u{0301}teria", 7), "cafe\u{0301}...");
+
+        // Flags are a pair of regional indicators
+        assert_eq!(display_width("\u{1F1EB}\u{1F1F7}"), 2);
+    }
+
+    #[test]
+    fn test_size_report() {
+        let dir = dir_entry(
+            "project",
+            vec![
+                dir_entry("src", vec![file_entry("main.rs", 768)]),
+                file_entry("README.md", 256),
+            ],
+        );
+        let config = Config {
+            show_blocks: false,
+            ..Config::default()
+        };
+
+        assert_eq!(
+            size_report(&dir, &config),
+            "Name             Size  Percent\n\
+             src/            768 B    75.0%\n\
+             README.md       256 B    25.0%\n\
+             Total           1 KiB   100.0%\n"
+        );
+    }
+
+    #[test]
+    fn test_entry_disk_path() {
+        let file = file_entry("core.dump", 10);
+        let logs = dir_entry("logs", vec![file.clone()]);
+        let var = dir_entry("var", vec![logs.clone()]);
+        let root = dir_entry("data", vec![var.clone()]);
+        let scan_root = Path::new("/mnt/data");
+
+        assert_eq!(
+            entry_disk_path(scan_root, &[], &root, &var),
+            PathBuf::from("/mnt/data/var")
+        );
+        assert_eq!(
+            entry_disk_path(scan_root, &[root.clone(), var.clone()], &logs, &file),
+            PathBuf::from("/mnt/data/var/logs/core.dump")
+        );
+    }
+
+    #[test]
+    fn test_refresh_updates_ancestor_totals() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let scan_root = temp_dir.path();
+        std::fs::create_dir_all(scan_root.join("var/logs")).unwrap();
+        std::fs::write(scan_root.join("var/logs/big.log"), vec![0u8; 40_000]).unwrap();
+        std::fs::write(scan_root.join("small"), vec![0u8; 100]).unwrap();
+
+        let config = Config {
+            show_blocks: false,
+            ..Config::default()
+        };
+        let root = scan_directory(scan_root, &config).unwrap();
+        let var = root
+            .children
+            .iter()
+            .find(|c| c.name_str() == "var")
+            .unwrap()
+            .clone();
+        let mut current_dir = var.children[0].clone();
+        let before = [
+            root.total_size(),
+            var.total_size(),
+            current_dir.total_size(),
+        ];
+        let mut path_stack = vec![root.clone(), var];
+
+        std::fs::write(scan_root.join("var/logs/big.log"), vec![0u8; 1_000]).unwrap();
+        let new_root =
+            rescan_current_dir(scan_root, &mut path_stack, &mut current_dir, &config).unwrap();
+
+        // Only the file shrank, so every total drops by the same amount
+        let after = [
+            new_root.total_size(),
+            path_stack[1].total_size(),
+            current_dir.total_size(),
+        ];
+        assert_eq!(after.map(|total| total + 39_000), before);
+        assert_eq!(new_root.id, root.id);
+    }
+
+    #[test]
+    fn test_refresh_multi_target_root() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let targets = [temp_dir.path().join("a"), temp_dir.path().join("b")];
+        for target in &targets {
+            std::fs::create_dir(target).unwrap();
+            std::fs::write(target.join("file"), vec![0u8; 1_000]).unwrap();
+        }
+
+        let config = Config {
+            show_blocks: false,
+            scan_targets: targets.to_vec(),
+            ..Config::default()
+        };
+        let children = targets
+            .iter()
+            .map(|target| {
+                let mut scanned = Arc::unwrap_or_clone(scan_directory(target, &config).unwrap());
+                scanned.name = target.as_os_str().to_os_string();
+                Arc::new(scanned)
+            })
+            .collect();
+        let mut root = Entry::clone(&dir_entry("targets.txt", Vec::new()));
+        root.children = children;
+        let mut current_dir = Arc::new(root);
+        let (root_id, before) = (current_dir.id, current_dir.total_size());
+
+        std::fs::write(targets[1].join("more"), vec![0u8; 500]).unwrap();
+        let new_root =
+            rescan_current_dir(Path::new("targets.txt"), &mut [], &mut current_dir, &config)
+                .unwrap();
+
+        assert_eq!(new_root.id, root_id);
+        assert_eq!(new_root.name_str(), "targets.txt");
+        assert_eq!(new_root.children.len(), 2);
+        assert_eq!(new_root.children[1].name, targets[1].as_os_str());
+        assert_eq!(new_root.total_size(), before + 500);
+
+        // The rescanned targets hang off the new root
+        let target = &new_root.children[1];
+        assert!(Arc::ptr_eq(&target.parent().unwrap(), &new_root));
+        for file in &target.children {
+            assert!(Arc::ptr_eq(&file.parent().unwrap(), target));
+        }
+    }
+
+    #[test]
+    fn test_type_legend_covers_every_type() {
+        let legend = type_legend();
+        assert_eq!(legend.len(), EntryType::ALL.len());
+        for (line, entry_type) in legend.iter().zip(EntryType::ALL) {
+            let (marker, color) = get_file_type_info(entry_type);
+            assert_eq!(line.spans[1].content, marker.to_string());
+            assert_eq!(line.spans[1].style.fg, Some(color));
+        }
+
+        let text: String = legend
+            .iter()
+            .flat_map(|line| line.spans.iter().map(|span| span.content.as_ref()))
+            .collect();
+        for marker in ['/', '@', '>', '=', '!', 'x', '~', '#', '^'] {
+            assert!(text.contains(marker), "legend is missing {}", marker);
+        }
+    }
+
+    #[test]
+    fn test_narrow_list_drops_graph_then_percent() {
+        let config = Config {
+            show_percent: true,
+            ..Config::default()
+        };
+        let layout = |width| {
+            let columns = list_columns(width, false, false, false, &config);
+            (columns.graph, columns.percent)
+        };
+
+        assert_eq!(layout(120), (true, true));
+        assert_eq!(layout(60), (false, true));
+        assert_eq!(layout(40), (false, false));
+
+        let wide = list_columns(120, false, false, false, &config);
+        assert_eq!(wide.name_width, 120 - (11 + 16 + 7 + 4));
+        let narrow = list_columns(60, false, false, false, &config);
+        assert_eq!(narrow.name_width, 60 - (11 + 7 + 4));
+
+        // Explicitly hidden columns stay hidden however wide the list
+        let config = Config {
+            show_graph: false,
+            ..Config::default()
+        };
+        let columns = list_columns(200, false, false, false, &config);
+        assert!(!columns.graph && !columns.percent);
+    }
+
+    #[test]
+    fn test_entry_info_names_mount() {
+        let file = file_entry("disk.img", 2048);
+        let mounts = HashMap::from([(
+            file.device,
+            MountSource {
+                source: "/dev/sdb1".to_string(),
+                mount_point: PathBuf::from("/srv"),
+                fs_type: "xfs".to_string(),
+            },
+        )]);
+        let sizes = SizeFormatter::new(&Config {
+            show_blocks: false,
+            ..Config::default()
+        });
+
+        let info = entry_info(&file, Path::new("/srv/disk.img"), &sizes, &mounts);
+        assert_eq!(info[0], "Name:  disk.img");
+        assert_eq!(info[1], "Path:  /srv/disk.img");
+        assert_eq!(info[5], "Mount: /dev/sdb1 on /srv (xfs)");
+
+        let info = entry_info(&file, Path::new("/srv/disk.img"), &sizes, &HashMap::new());
+        assert_eq!(info[5], "Mount: unknown");
+    }
+
+    #[test]
+    fn test_quit_prompt_choices() {
+        let key = KeyCode::Char;
+        assert_eq!(resolve_quit_prompt(key('s')), Some(QuitChoice::Save));
+        assert_eq!(resolve_quit_prompt(KeyCode::Enter), Some(QuitChoice::Save));
+        assert_eq!(resolve_quit_prompt(key('d')), Some(QuitChoice::Discard));
+        assert_eq!(resolve_quit_prompt(key('c')), Some(QuitChoice::Cancel));
+        assert_eq!(resolve_quit_prompt(KeyCode::Esc), Some(QuitChoice::Cancel));
+        assert_eq!(resolve_quit_prompt(key('x')), None);
+        assert_eq!(resolve_quit_prompt(key('y')), None);
+    }
+
+    #[test]
+    fn test_quit_offers_to_save_changed_preferences() {
+        let press = |app: &mut TuiApp<io::Sink>, code| {
+            app.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE))
+                .unwrap()
+        };
+        let root = || dir_entry("root", vec![file_entry("a", 1)]);
+        let config = Config {
+            save_on_exit: Some(true),
+            ..Config::default()
+        };
+
+        // Nothing changed, so quitting needs no answer
+        let mut app = headless_app(config.clone());
+        app.start_browsing(root(), None).unwrap();
+        assert!(press(&mut app, KeyCode::Char('q')));
+
+        let mut app = headless_app(config);
+        app.start_browsing(root(), None).unwrap();
+        press(&mut app, KeyCode::Char('S'));
+        assert!(!press(&mut app, KeyCode::Char('q')));
+        assert!(matches!(
+            app.mode,
+            AppMode::Browsing {
+                dialog: Some(Dialog::ConfirmQuit),
+                ..
+            }
+        ));
+        // Cancelling keeps browsing; discarding quits without writing anything
+        assert!(!press(&mut app, KeyCode::Esc));
+        assert!(!press(&mut app, KeyCode::Char('q')));
+        assert!(press(&mut app, KeyCode::Char('d')));
+    }
+
+    #[test]
+    fn test_delete_refuses_root_and_current_dir() {
+        let file = file_entry("core.dump", 10);
+        let logs = dir_entry("logs", vec![file.clone()]);
+        let root = dir_entry("data", vec![logs.clone()]);
+        let scan_root = Path::new("/mnt/data");
+        let in_root = [root.clone()];
+        let refusal = |stack: &[Arc<Entry>], dir: &Arc<Entry>, target: &Entry| {
+            let path = entry_disk_path(scan_root, stack, dir, target);
+            delete_refusal(scan_root, stack, dir, target, &path)
+        };
+
+        assert!(refusal(&[], &root, &logs).is_none());
+        assert!(refusal(&in_root, &logs, &file).is_none());
+
+        assert_eq!(
+            refusal(&[], &root, &root),
+            Some("Refusing to delete the scan root")
+        );
+        assert_eq!(
+            refusal(&[], &root, &file_entry(".", 0)),
+            Some("Refusing to delete the scan root")
+        );
+        assert!(refusal(&in_root, &logs, &logs).is_some());
+        assert!(refusal(&in_root, &logs, &file_entry("..", 0)).is_some());
+
+        let mut left_out = Entry::clone(&file_entry("(and 3 more)", 10));
+        left_out.entry_type = EntryType::Aggregate;
+        assert!(refusal(&in_root, &logs, &left_out).is_some());
+    }
+
+    #[test]
+    fn test_expand_collapse_inline() {
+        let sub = dir_entry("sub", vec![file_entry("a", 10), file_entry("b", 5)]);
+        let dir = dir_entry("dir", vec![sub.clone(), file_entry("c", 1)]);
+        let config = Config::default();
+        let mut expanded = HashSet::new();
+
+        // Files have nothing to preview
+        assert!(!toggle_expanded(&mut expanded, &dir.children[1]));
+        assert!(expanded.is_empty());
+
+        assert!(toggle_expanded(&mut expanded, &sub));
+        assert!(expanded.contains(&sub.id));
+        let rows = build_list_rows(&dir, &config, None, &expanded, false, None);
+        assert_eq!(rows.len(), 4);
+        assert!(matches!(&rows[0], ListRow::Entry(e) if e.id == sub.id));
+        assert!(
+            matches!(&rows[1], ListRow::Preview { entry, depth: 1 } if entry.name_str() == "a")
+        );
+        assert!(
+            matches!(&rows[2], ListRow::Preview { entry, depth: 1 } if entry.name_str() == "b")
+        );
+        assert!(matches!(&rows[3], ListRow::Entry(e) if e.name_str() == "c"));
+
+        // Collapsing restores the flat list
+        assert!(!toggle_expanded(&mut expanded, &sub));
+        assert!(expanded.is_empty());
+        assert_eq!(
+            build_list_rows(&dir, &config, None, &expanded, false, None).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_shallow_vs_deep_sizes() {
+        let nested = dir_entry("nested", vec![file_entry("deep", 1000)]);
+        let sub = dir_entry("sub", vec![file_entry("direct", 10), nested]);
+        let dir = dir_entry("dir", vec![sub.clone(), file_entry("loose", 100)]);
+
+        let mut config = Config {
+            show_blocks: false,
+            ..Config::default()
+        };
+        assert_eq!(SizeFormatter::new(&config).metric_value(&sub), 1010);
+        assert_eq!(calculate_total_size(&dir, &config), 1110);
+
+        // Shallow sizes only count the directory's immediate entries
+        config.shallow_dir_sizes = Some(true);
+        assert_eq!(SizeFormatter::new(&config).metric_value(&sub), 10);
+        assert_eq!(calculate_total_size(&dir, &config), 110);
+
+        // Re-sorting by the shallow basis moves the loose file first
+        config.sort_dirs_first = false;
+        config.sort_order = SortOrder::Desc;
+        let mut dir = (*dir).clone();
+        sort_by_display_size(&mut dir, &config);
+        assert_eq!(dir.children[0].name_str(), "loose");
+    }
+
+    #[test]
+    fn test_dirs_first_toggle() {
+        let sub = dir_entry("sub", vec![file_entry("inner", 10)]);
+        let root = dir_entry(
+            "root",
+            vec![file_entry("big", 1000), sub, file_entry("small", 1)],
+        );
+        let mut app = headless_app(Config {
+            show_blocks: false,
+            sort_col: SortColumn::Size,
+            sort_order: SortOrder::Desc,
+            sort_dirs_first: false,
+            ..Config::default()
+        });
+        app.start_browsing(root, None).unwrap();
+        let names = |app: &TuiApp<io::Sink>| {
+            let AppMode::Browsing { current_dir, .. } = &app.mode else {
+                panic!("not browsing");
+            };
+            current_dir
+                .children
+                .iter()
+                .map(|child| child.name_str().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&app), vec!["big", "sub", "small"]);
+
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('t'), KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(names(&app), vec!["sub", "big", "small"]);
+        // The grouping is part of the view, not the configuration
+        assert!(!app.config.sort_dirs_first);
+        assert!(matches!(
+            app.mode,
+            AppMode::Browsing {
+                dirs_first: true,
+                ..
+            }
+        ));
+
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('t'), KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(names(&app), vec!["big", "sub", "small"]);
+    }
+
+    #[test]
+    fn test_shallow_resort_keeps_selection() {
+        let nested = dir_entry("nested", vec![file_entry("deep", 1000)]);
+        let sub = dir_entry("sub", vec![file_entry("direct", 10), nested]);
+        let root = dir_entry("root", vec![sub.clone(), file_entry("loose", 100)]);
+
+        let mut app = headless_app(Config {
+            show_blocks: false,
+            sort_col: SortColumn::Size,
+            sort_order: SortOrder::Desc,
+            sort_dirs_first: false,
+            ..Config::default()
+        });
+        app.start_browsing(root, None).unwrap();
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('S'), KeyModifiers::NONE))
+            .unwrap();
+
+        let AppMode::Browsing {
+            current_dir,
+            list_state,
+            ..
+        } = &app.mode
+        else {
+            panic!("not browsing");
+        };
+        assert_eq!(current_dir.children[0].name_str(), "loose");
+        assert_eq!(list_state.selected(), Some(1));
+        assert_eq!(current_dir.children[1].id, sub.id);
+    }
+
+    #[test]
+    fn test_bar_percentage_clamped() {
+        assert_eq!(bar_percentage(50, 200), 25);
+        assert_eq!(bar_percentage(5, 0), 0);
+        // Larger than the total, even by more than a u8 can hold
+        assert_eq!(bar_percentage(150, 100), 100);
+        assert_eq!(bar_percentage(u64::MAX, 1), 100);
+
+        let full = "█".repeat(13);
+        assert_eq!(create_percentage_bar(bar_percentage(1000, 3), 13), full);
+        assert_eq!(create_percentage_bar(u8::MAX, 13), full);
+        assert_eq!(create_percentage_bar(50, 4), "██  ");
+    }
+
+    #[test]
+    fn test_bar_follows_size_metric() {
+        // Small apparent size but a whole 4 KiB block on disk
+        let small = Arc::new(Entry::new(
+            generate_entry_id(),
+            EntryType::File,
+            "small".into(),
+            100,
+            8,
+            1,
+            1,
+            1,
+        ));
+        // Sparse: large apparent size, little on disk
+        let sparse = Arc::new(Entry::new(
+            generate_entry_id(),
+            EntryType::File,
+            "sparse".into(),
+            900,
+            1,
+            1,
+            2,
+            1,
+        ));
+        let dir = dir_entry("dir", vec![small.clone(), sparse]);
+
+        let apparent = Config {
+            show_blocks: false,
+            ..Config::default()
+        };
+        let total = calculate_total_size(&dir, &apparent);
+        assert_eq!(total, 1000);
+        assert_eq!(
+            bar_percentage(SizeFormatter::new(&apparent).metric_value(&small), total),
+            10
+        );
+
+        let disk_usage = Config {
+            show_blocks: true,
+            ..Config::default()
+        };
+        let total = calculate_total_size(&dir, &disk_usage);
+        assert_eq!(total, 4608);
+        assert_eq!(
+            bar_percentage(SizeFormatter::new(&disk_usage).metric_value(&small), total),
+            88
+        );
+    }
+
+    #[test]
+    fn test_builtin_extension_colors() {
+        assert_eq!(builtin_extension_color("zip"), Some(Color::Red));
+        assert_eq!(builtin_extension_color("jpg"), Some(Color::Magenta));
+        assert_eq!(builtin_extension_color("mkv"), Some(Color::LightMagenta));
+        assert_eq!(builtin_extension_color("rs"), Some(Color::Green));
+        assert_eq!(builtin_extension_color("pdf"), Some(Color::LightYellow));
+        assert_eq!(builtin_extension_color("unknown"), None);
+    }
+
+    #[test]
+    fn test_extension_color_lookup() {
+        let no_ls_colors = HashMap::new();
+        assert_eq!(
+            extension_color("backup.tar.gz", &no_ls_colors),
+            Some(Color::Red)
+        );
+        assert_eq!(
+            extension_color("PHOTO.JPG", &no_ls_colors),
+            Some(Color::Magenta)
+        );
+        assert_eq!(extension_color("Makefile", &no_ls_colors), None);
+
+        // LS_COLORS entries win over the built-in mapping
+        let ls_colors = parse_ls_colors("*.gz=01;34:*.txt=01;32");
+        assert_eq!(
+            extension_color("backup.tar.gz", &ls_colors),
+            Some(Color::Blue)
+        );
+        assert_eq!(extension_color("notes.TXT", &ls_colors), Some(Color::Green));
+        assert_eq!(
+            extension_color("photo.jpg", &ls_colors),
+            Some(Color::Magenta)
+        );
+    }
+
+    #[test]
+    fn test_parse_ls_colors() {
+        let colors = parse_ls_colors("di=01;34:*.tar=01;31:*.PNG=38;5;208:*.log=00");
+        assert_eq!(colors.get("tar"), Some(&Color::Red));
+        assert_eq!(colors.get("png"), Some(&Color::Indexed(208)));
+        assert_eq!(colors.get("log"), None);
+        assert_eq!(colors.len(), 2);
+    }
+}
@@ -3,10 +3,14 @@
 //! This module contains various helper functions and utilities used
 //! throughout the application.
 
+use crate::cli::NumberLocale;
 use crate::error::{Result, RsduError};
 use humansize::{format_size, BINARY, DECIMAL};
+use nix::unistd::{Gid, Group, Uid, User};
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Format file size in human-readable format with consistent width
@@ -22,19 +26,179 @@ pub fn format_file_size(size: u64, use_si: bool) -> String {
     format!("{:>10}", formatted)
 }
 
-/// Format block count in human-readable format
-pub fn format_blocks(blocks: u64, use_si: bool) -> String {
-    format_file_size(blocks * 512, use_si)
+/// Format block count in human-readable format, given the bytes per block
+pub fn format_blocks(blocks: u64, block_unit: u64, use_si: bool) -> String {
+    format_file_size(blocks.saturating_mul(block_unit), use_si)
+}
+
+/// Parse a human-readable size such as `512`, `10K`, `1.5MiB` or `2G`
+///
+/// Suffixes are binary multiples; a trailing `B` or `iB` is accepted.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let trimmed = s.trim();
+    let unit_start = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(unit_start);
+
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        "P" | "PB" | "PIB" => 1 << 50,
+        _ => {
+            return Err(RsduError::ConfigError(format!(
+                "Invalid size unit in '{}'",
+                s
+            )))
+        }
+    };
+
+    let value: f64 = number
+        .parse()
+        .map_err(|_| RsduError::ConfigError(format!("Invalid size '{}'", s)))?;
+
+    Ok((value * multiplier as f64) as u64)
+}
+
+/// Space usage of a mounted filesystem, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsUsage {
+    pub total: u64,
+    pub free: u64,
+    /// Free space available to unprivileged users
+    pub available: u64,
+}
+
+impl FsUsage {
+    /// Bytes in use
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.free)
+    }
+
+    /// How full the filesystem is, as `df` reports it
+    ///
+    /// Space reserved for root is left out, so a filesystem that only root
+    /// can still write to shows as 100% full.
+    pub fn fullness_percent(&self) -> f64 {
+        let usable = self.used() + self.available;
+        if usable == 0 {
+            0.0
+        } else {
+            self.used() as f64 / usable as f64 * 100.0
+        }
+    }
+}
+
+/// Query space usage of the filesystem containing `path`
+pub fn filesystem_usage<P: AsRef<Path>>(path: P) -> Option<FsUsage> {
+    let stat = nix::sys::statvfs::statvfs(path.as_ref()).ok()?;
+    let fragment_size = stat.fragment_size() as u64;
+
+    Some(FsUsage {
+        total: stat.blocks() as u64 * fragment_size,
+        free: stat.blocks_free() as u64 * fragment_size,
+        available: stat.blocks_available() as u64 * fragment_size,
+    })
+}
+
+/// Look up a name by numeric id, remembering every result
+///
+/// Falls back to the number itself for ids without an entry.
+fn cached_name_lookup(
+    cache: &OnceLock<Mutex<HashMap<u32, String>>>,
+    id: u32,
+    lookup: impl FnOnce(u32) -> Option<String>,
+) -> String {
+    let mut names = cache
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap();
+    names
+        .entry(id)
+        .or_insert_with(|| lookup(id).unwrap_or_else(|| id.to_string()))
+        .clone()
+}
+
+/// Resolve a uid to a user name
+pub fn user_name(uid: u32) -> String {
+    static USER_NAMES: OnceLock<Mutex<HashMap<u32, String>>> = OnceLock::new();
+    cached_name_lookup(&USER_NAMES, uid, |uid| {
+        User::from_uid(Uid::from_raw(uid))
+            .ok()
+            .flatten()
+            .map(|user| user.name)
+    })
+}
+
+/// Resolve a gid to a group name
+pub fn group_name(gid: u32) -> String {
+    static GROUP_NAMES: OnceLock<Mutex<HashMap<u32, String>>> = OnceLock::new();
+    cached_name_lookup(&GROUP_NAMES, gid, |gid| {
+        Group::from_gid(Gid::from_raw(gid))
+            .ok()
+            .flatten()
+            .map(|group| group.name)
+    })
+}
+
+/// Format permission bits as the classic `rwxr-xr-x` string
+///
+/// Setuid, setgid and sticky bits show up as `s`/`S` and `t`/`T` in the
+/// execute positions, like `ls -l` does.
+pub fn format_mode(mode: u32) -> String {
+    let special = [(0o4000, 's', 'S'), (0o2000, 's', 'S'), (0o1000, 't', 'T')];
+    let mut out = String::with_capacity(9);
+
+    for (class, (special_bit, set_exec, set_noexec)) in special.iter().enumerate() {
+        let bits = (mode >> (6 - 3 * class)) & 0o7;
+        out.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        out.push(match (bits & 0o1 != 0, mode & special_bit != 0) {
+            (true, true) => *set_exec,
+            (false, true) => *set_noexec,
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+
+    out
+}
+
+/// Whether the permission bits let anyone write to the file
+pub fn is_world_writable(mode: u32) -> bool {
+    mode & 0o002 != 0
+}
+
+/// Describe error counts by category, leaving out empty categories
+pub fn format_error_breakdown(permission_denied: u64, not_found: u64, other: u64) -> String {
+    [
+        (permission_denied, "permission denied"),
+        (not_found, "not found"),
+        (other, "other"),
+    ]
+    .iter()
+    .filter(|(count, _)| *count > 0)
+    .map(|(count, label)| format!("{} {}", count, label))
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+/// Swap the `.` of a number formatted by Rust for the locale's decimal separator
+pub fn localize_decimal(formatted: &str, locale: NumberLocale) -> String {
+    formatted.replace('.', &locale.decimal_separator().to_string())
 }
 
 /// Format percentage
-pub fn format_percentage(part: u64, total: u64) -> String {
-    if total == 0 {
-        "0.0%".to_string()
+pub fn format_percentage(part: u64, total: u64, locale: NumberLocale) -> String {
+    let percentage = if total == 0 {
+        0.0
     } else {
-        let percentage = (part as f64 / total as f64) * 100.0;
-        format!("{:.1}%", percentage)
-    }
+        (part as f64 / total as f64) * 100.0
+    };
+    localize_decimal(&format!("{:.1}%", percentage), locale)
 }
 
 /// Format number with thousands separator
@@ -82,6 +246,26 @@ pub fn expand_user_path<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
     Ok(path.to_path_buf())
 }
 
+/// Make the scan root absolute, dereferencing it only if `follow_symlink` is set
+///
+/// When a symlinked root isn't followed, only its parent is canonicalized so
+/// the returned path still names the link itself.
+pub fn resolve_scan_root(path: &Path, follow_symlink: bool) -> std::io::Result<PathBuf> {
+    let is_symlink = std::fs::symlink_metadata(path)?.file_type().is_symlink();
+    if follow_symlink || !is_symlink {
+        return path.canonicalize();
+    }
+
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize()?,
+        _ => std::env::current_dir()?,
+    };
+    Ok(match path.file_name() {
+        Some(name) => parent.join(name),
+        None => parent,
+    })
+}
+
 /// Convert SystemTime to timestamp
 pub fn system_time_to_timestamp(time: SystemTime) -> u64 {
     time.duration_since(UNIX_EPOCH)
@@ -289,17 +473,88 @@ pub fn get_cpu_count() -> usize {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_fs_usage_fullness() {
+        let usage = FsUsage {
+            total: 1000,
+            free: 250,
+            available: 250,
+        };
+        assert_eq!(usage.used(), 750);
+        assert_eq!(usage.fullness_percent(), 75.0);
+
+        // Reserved blocks don't count as usable space
+        let usage = FsUsage {
+            total: 1000,
+            free: 100,
+            available: 0,
+        };
+        assert_eq!(usage.fullness_percent(), 100.0);
+
+        let empty = FsUsage {
+            total: 0,
+            free: 0,
+            available: 0,
+        };
+        assert_eq!(empty.fullness_percent(), 0.0);
+
+        assert!(filesystem_usage("/").is_some());
+    }
+
+    #[test]
+    fn test_owner_names() {
+        assert_eq!(user_name(0), "root");
+        assert_eq!(group_name(0), "root");
+        // Unknown ids fall back to the number
+        assert_eq!(user_name(4_000_000_000), "4000000000");
+    }
+
+    #[test]
+    fn test_format_mode() {
+        assert_eq!(format_mode(0o644), "rw-r--r--");
+        assert_eq!(format_mode(0o755), "rwxr-xr-x");
+        assert_eq!(format_mode(0o777), "rwxrwxrwx");
+        assert_eq!(format_mode(0o100600), "rw-------");
+        assert_eq!(format_mode(0o4755), "rwsr-xr-x");
+        assert_eq!(format_mode(0o1777), "rwxrwxrwt");
+        assert_eq!(format_mode(0o1666), "rw-rw-rwT");
+
+        assert!(is_world_writable(0o777));
+        assert!(!is_world_writable(0o755));
+    }
+
+    #[test]
+    fn test_format_blocks_with_unit() {
+        assert_eq!(format_blocks(8, 512, false), format_file_size(4096, false));
+        assert_eq!(
+            format_blocks(8, 4096, false),
+            format_file_size(32768, false)
+        );
+        assert_eq!(format_blocks(3, 1000, true), format_file_size(3000, true));
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("10K").unwrap(), 10 * 1024);
+        assert_eq!(parse_size("1.5MiB").unwrap(), 3 * 512 * 1024);
+        assert_eq!(parse_size("2 gb").unwrap(), 2 << 30);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("10X").is_err());
+    }
+
     #[test]
     fn test_format_file_size() {
-        assert_eq!(format_file_size(1024, false), "1 KiB");
-        assert_eq!(format_file_size(1000, true), "1 kB");
+        assert_eq!(format_file_size(1024, false), "     1 KiB");
+        assert_eq!(format_file_size(1000, true), "      1 kB");
     }
 
     #[test]
     fn test_format_percentage() {
-        assert_eq!(format_percentage(25, 100), "25.0%");
-        assert_eq!(format_percentage(1, 3), "33.3%");
-        assert_eq!(format_percentage(0, 0), "0.0%");
+        assert_eq!(format_percentage(25, 100, NumberLocale::En), "25.0%");
+        assert_eq!(format_percentage(1, 3, NumberLocale::En), "33.3%");
+        assert_eq!(format_percentage(0, 0, NumberLocale::En), "0.0%");
+        assert_eq!(format_percentage(1, 3, NumberLocale::De), "33,3%");
     }
 
     #[test]
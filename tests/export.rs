@@ -0,0 +1,108 @@
+//! End-to-end tests of exporting a scan from the command line
+
+use std::fs;
+use std::process::{Command, Stdio};
+
+#[test]
+fn test_export_json_from_command_line() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let scan_dir = temp_dir.path().join("data");
+    fs::create_dir_all(scan_dir.join("sub")).unwrap();
+    fs::write(scan_dir.join("sub/file"), "data").unwrap();
+    let out = temp_dir.path().join("out.json");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rsdu"))
+        .arg("--ignore-config")
+        .arg("-o")
+        .arg(&out)
+        .arg(&scan_dir)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "rsdu failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let json = fs::read_to_string(&out).unwrap();
+    assert!(!json.is_empty());
+    let exported: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(exported["name"], "data");
+    assert_eq!(exported["children"][0]["name"], "sub");
+    assert_eq!(exported["children"][0]["children"][0]["name"], "file");
+    assert_eq!(exported["children"][0]["children"][0]["size"], 4);
+}
+
+#[test]
+fn test_record_ignores_rsduignore() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let scan_dir = temp_dir.path().join("data");
+    fs::create_dir_all(&scan_dir).unwrap();
+    fs::write(scan_dir.join(".rsduignore"), "*.log\n").unwrap();
+    fs::write(scan_dir.join("kept.log"), "data").unwrap();
+    let modified = std::time::UNIX_EPOCH + std::time::Duration::new(1_700_000_000, 123_456_789);
+    fs::File::options()
+        .write(true)
+        .open(scan_dir.join("kept.log"))
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    let out = temp_dir.path().join("snapshot.json");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rsdu"))
+        .arg("--ignore-config")
+        .arg("--record")
+        .arg(&out)
+        .arg(&scan_dir)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "rsdu failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let exported: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&out).unwrap()).unwrap();
+    let children = exported["children"].as_array().unwrap();
+    let log = children
+        .iter()
+        .find(|child| child["name"] == "kept.log")
+        .expect("the ignored file is recorded");
+    assert_eq!(log["size"], 4);
+    // The scanner keeps mtimes to the second
+    assert_eq!(log["extended"]["mtime"], "2023-11-14T22:13:20Z");
+}
+
+#[test]
+fn test_config_compress_level_compresses_export() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let scan_dir = temp_dir.path().join("data");
+    fs::create_dir_all(&scan_dir).unwrap();
+    fs::write(scan_dir.join("file"), "data").unwrap();
+    let config_dir = temp_dir.path().join("config");
+    fs::create_dir_all(config_dir.join("rsdu")).unwrap();
+    fs::write(config_dir.join("rsdu/config"), "compress-level = 10\n").unwrap();
+    let out = temp_dir.path().join("out.json");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rsdu"))
+        .env("XDG_CONFIG_HOME", &config_dir)
+        .arg("--ignore-system-config")
+        .arg("-o")
+        .arg(&out)
+        .arg(&scan_dir)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "rsdu failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // zstd frame magic number
+    let written = fs::read(&out).unwrap();
+    assert!(written.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]));
+}
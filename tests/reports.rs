@@ -0,0 +1,53 @@
+//! End-to-end tests of the plain-text reports from the command line
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Run rsdu with `args`, returning its stdout
+fn rsdu(args: &[&Path]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_rsdu"))
+        .arg("--ignore-config")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "rsdu failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_targets_from_report_paths() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let data = temp_dir.path().join("data");
+    fs::create_dir_all(data.join("sub")).unwrap();
+    fs::write(data.join("top.bin"), vec![0u8; 8192]).unwrap();
+    fs::write(data.join("sub/inner.bin"), vec![0u8; 4096]).unwrap();
+    let list = temp_dir.path().join("list.txt");
+    let largest = [
+        Path::new("--largest"),
+        Path::new("10"),
+        Path::new("--targets-from"),
+        &list,
+    ];
+
+    // A lone target is the root the report's paths start from
+    fs::write(&list, format!("{}\n", data.display())).unwrap();
+    let report = rsdu(&largest);
+    assert!(report.contains(&data.join("sub/inner.bin").display().to_string()));
+    assert!(!report.contains("list.txt"));
+
+    // A target inside another one isn't counted again
+    fs::write(
+        &list,
+        format!("{}\n{}\n", data.display(), data.join("sub").display()),
+    )
+    .unwrap();
+    let report = rsdu(&largest);
+    assert_eq!(report.matches("inner.bin").count(), 1, "{}", report);
+    assert_eq!(report.lines().count(), 2, "{}", report);
+}